@@ -0,0 +1,297 @@
+// Achievements: goals defined in data, unlocked per profile, announced with toasts
+// Definitions live in assets/achievements.json; each has a condition checked against the
+// running match while it is played and again against the result once it ends.
+
+use crate::hud::{Anchor, Layout, COLUMN_GAP, HEADING_SIZE, LINE_GAP, LIST_INDENT, MENU_INDENT};
+use crate::profile::{MatchMode, MatchOutcome, Profile, ProfileStore};
+use crate::stats::MatchStats;
+use crate::text::Align;
+use crate::theme;
+use crate::{Difficulty, Side};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const BUILTIN_ACHIEVEMENTS: &str = include_str!("../assets/achievements.json");
+// Seconds each toast stays on screen
+pub const TOAST_DURATION: f32 = 3.0;
+const TOAST_TITLE_SIZE: f32 = 20.0;
+const TOAST_TEXT_SIZE: f32 = 18.0;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Condition {
+    Win,
+    // Win without conceding a point
+    Shutout,
+    Rally { hits: u32 },
+    BeatDifficulty { difficulty: Difficulty },
+    // Returns off the paddle edge in one match
+    EdgeReturns { count: u32 },
+    // A return made while the ball travels at least this fast
+    BallSpeed { speed: f32 },
+    // A return made at the rule set's top speed
+    TopSpeed,
+    WinStreak { count: u32 },
+    Matches { count: u32 },
+    Rating { rating: f32 },
+    PlayMode { mode: MatchMode },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Achievement {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub condition: Condition,
+}
+
+// What conditions are checked against: the match so far and, once it is over, its result
+#[derive(Clone, Copy, Debug)]
+pub struct Progress<'a> {
+    pub profile: &'a Profile,
+    pub stats: &'a MatchStats,
+    pub side: Side,
+    // Hits in the rally being played
+    pub rally_length: u32,
+    // The fastest the match's rules let the ball go
+    pub top_speed: f32,
+    pub outcome: Option<&'a MatchOutcome>,
+}
+
+impl Condition {
+    pub fn is_met(&self, progress: &Progress) -> bool {
+        let won = progress.outcome.is_some_and(|outcome| outcome.won);
+        let own = progress.stats.side(progress.side);
+        let profile = progress.profile;
+        match self {
+            Condition::Win => won,
+            Condition::Shutout => {
+                won && progress
+                    .outcome
+                    .is_some_and(|outcome| outcome.points_against == 0)
+            }
+            Condition::Rally { hits } => {
+                progress.stats.longest_rally.max(progress.rally_length) >= *hits
+            }
+            Condition::BeatDifficulty { difficulty } => {
+                won && progress
+                    .outcome
+                    .is_some_and(|outcome| outcome.difficulty == Some(*difficulty))
+            }
+            Condition::EdgeReturns { count } => own.edge_hits >= *count,
+            Condition::BallSpeed { speed } => own.fastest_return >= *speed,
+            // Allow for rounding in the speed taken from the ball's velocity
+            Condition::TopSpeed => own.fastest_return >= progress.top_speed * 0.999,
+            Condition::WinStreak { count } => profile.current_streak >= *count,
+            Condition::Matches { count } => profile.matches >= *count,
+            Condition::Rating { rating } => profile.rating >= *rating,
+            Condition::PlayMode { mode } => profile.by_mode.contains_key(mode),
+        }
+    }
+}
+
+pub fn parse_achievements(json: &str) -> Result<Vec<Achievement>, String> {
+    let achievements: Vec<Achievement> =
+        serde_json::from_str(json).map_err(|err| format!("invalid achievements: {}", err))?;
+    let mut ids = HashSet::new();
+    for achievement in &achievements {
+        if !ids.insert(achievement.id.as_str()) {
+            return Err(format!("duplicate achievement id: {}", achievement.id));
+        }
+    }
+    Ok(achievements)
+}
+
+pub fn builtin_achievements() -> Vec<Achievement> {
+    parse_achievements(BUILTIN_ACHIEVEMENTS).expect("built-in achievements are valid")
+}
+
+// Achievements whose conditions hold but which the profile hasn't unlocked yet
+pub fn newly_met<'a>(achievements: &'a [Achievement], progress: &Progress) -> Vec<&'a Achievement> {
+    achievements
+        .iter()
+        .filter(|achievement| !progress.profile.achievements.contains_key(&achievement.id))
+        .filter(|achievement| achievement.condition.is_met(progress))
+        .collect()
+}
+
+fn now_seconds() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+// Unlock everything newly met by the active profile; returns what was unlocked
+pub fn unlock(
+    store: &mut ProfileStore,
+    achievements: &[Achievement],
+    stats: &MatchStats,
+    side: Side,
+    rally_length: u32,
+    top_speed: f32,
+    outcome: Option<&MatchOutcome>,
+) -> Vec<Achievement> {
+    let Some(profile) = store.active_mut() else {
+        return Vec::new();
+    };
+    let progress = Progress {
+        profile,
+        stats,
+        side,
+        rally_length,
+        top_speed,
+        outcome,
+    };
+    let unlocked: Vec<Achievement> = newly_met(achievements, &progress)
+        .into_iter()
+        .cloned()
+        .collect();
+
+    let time = now_seconds();
+    for achievement in &unlocked {
+        profile.achievements.insert(achievement.id.clone(), time);
+    }
+    unlocked
+}
+
+struct Toast {
+    title: String,
+    text: String,
+    remaining: f32,
+}
+
+// Unlock notifications, shown one after another in the top right corner
+#[derive(Default)]
+pub struct Toasts {
+    queue: VecDeque<Toast>,
+}
+
+impl Toasts {
+    pub fn push(&mut self, title: &str, text: &str) {
+        self.queue.push_back(Toast {
+            title: title.to_owned(),
+            text: text.to_owned(),
+            remaining: TOAST_DURATION,
+        });
+    }
+
+    pub fn push_unlocked(&mut self, achievement: &Achievement) {
+        self.push(
+            &format!("Achievement unlocked: {}", achievement.name),
+            &achievement.description,
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        if let Some(toast) = self.queue.front_mut() {
+            toast.remaining -= dt;
+            if toast.remaining <= 0.0 {
+                self.queue.pop_front();
+            }
+        }
+    }
+
+    pub fn draw(&self) {
+        let Some(toast) = self.queue.front() else {
+            return;
+        };
+        let theme = theme::active();
+        let layout = Layout::current();
+        let padding = 12.0;
+        let width = layout
+            .column_width([toast.title.as_str()], TOAST_TITLE_SIZE)
+            .max(layout.column_width([toast.text.as_str()], TOAST_TEXT_SIZE))
+            + 2.0 * padding;
+        let height = layout.line_height(TOAST_TITLE_SIZE)
+            + LINE_GAP
+            + layout.line_height(TOAST_TEXT_SIZE)
+            + 2.0 * padding;
+        // Slide in over the first and out over the last quarter second, from off the right edge
+        let shown = (TOAST_DURATION - toast.remaining).min(toast.remaining);
+        let slide = (shown / 0.25).clamp(0.0, 1.0);
+        let size = TextDimensions {
+            width,
+            height,
+            offset_y: 0.0,
+        };
+        let offset = vec2((width + layout.margin) * (1.0 - slide), 0.0);
+        let corner = layout.place(Anchor::TopRight, size, offset);
+
+        draw_rectangle(
+            corner.x,
+            corner.y,
+            width,
+            height,
+            theme.background.with_alpha(0.9),
+        );
+        draw_rectangle_lines(corner.x, corner.y, width, height, 2.0, theme.accent);
+        let title = layout.line(
+            &toast.title,
+            corner.x + padding,
+            corner.y + padding,
+            TOAST_TITLE_SIZE,
+            theme.accent,
+            Align::Left,
+        );
+        layout.below(title, &toast.text, TOAST_TEXT_SIZE, theme.text);
+    }
+}
+
+// Every achievement with the profile's unlock state
+pub fn draw_achievement_list(profile: &Profile, achievements: &[Achievement]) {
+    let theme = theme::active();
+    let unlocked = achievements
+        .iter()
+        .filter(|achievement| profile.achievements.contains_key(&achievement.id))
+        .count();
+    let layout = Layout::current();
+    let heading = layout.heading("ACHIEVEMENTS", HEADING_SIZE, theme.text);
+    let summary = format!(
+        "{}: {} of {} unlocked",
+        profile.name,
+        unlocked,
+        achievements.len()
+    );
+    let mut above = layout.below(heading, &summary, 20.0, theme.text_dim);
+
+    let names: Vec<String> = achievements
+        .iter()
+        .map(|achievement| {
+            let done = profile.achievements.contains_key(&achievement.id);
+            format!("{} {}", if done { "*" } else { "-" }, achievement.name)
+        })
+        .collect();
+    let description_x = MENU_INDENT
+        + LIST_INDENT
+        + layout.column_width(names.iter().map(String::as_str), 22.0)
+        + COLUMN_GAP;
+    for (name, achievement) in names.iter().zip(achievements) {
+        let color = if profile.achievements.contains_key(&achievement.id) {
+            theme.accent
+        } else {
+            theme.text_dim
+        };
+        above = layout.row(above, name, 22.0, color);
+        // Descriptions are smaller, so they share the row's baseline rather than its top
+        let top = above.y + layout.line_height(22.0) - layout.line_height(18.0);
+        layout.line(
+            &achievement.description,
+            description_x,
+            top,
+            18.0,
+            theme.text_dim,
+            Align::Left,
+        );
+    }
+    layout.footer("ESC to go back", theme.text_dim);
+}
@@ -0,0 +1,333 @@
+// Shot analytics: where the ball meets paddles, walls and goal lines
+// Every paddle hit, wall hit and goal event is kept as a raw shot for CSV export and binned into
+// heatmaps for the post-game heatmap screen.
+
+use crate::events::{EventListener, GameEvent};
+use crate::hud::{Layout, HEADING_SIZE, LINE_GAP};
+use crate::text::Align;
+use crate::theme;
+use crate::{Side, WINDOW_HEIGHT, WINDOW_WIDTH};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub const PADDLE_BINS: usize = 16;
+pub const WALL_BINS: usize = 20;
+pub const GOAL_BINS: usize = 12;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShotKind {
+    PaddleContact,
+    WallBounce,
+    Goal,
+}
+
+impl ShotKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            ShotKind::PaddleContact => "paddle_contact",
+            ShotKind::WallBounce => "wall_bounce",
+            ShotKind::Goal => "goal",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ShotEvent {
+    // Seconds of play since the match started
+    pub time: f32,
+    pub kind: ShotKind,
+    // The paddle that made a contact, or the side whose goal line the ball crossed
+    pub side: Option<Side>,
+    // Ball centre when the event happened
+    pub x: f32,
+    pub y: f32,
+    // Paddle contacts only: -1 at the top edge, 0 in the centre, 1 at the bottom edge
+    pub offset: Option<f32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatchAnalytics {
+    pub events: Vec<ShotEvent>,
+}
+
+impl MatchAnalytics {
+    pub fn record_contact(&mut self, time: f32, side: Side, x: f32, y: f32, offset: f32) {
+        self.events.push(ShotEvent {
+            time,
+            kind: ShotKind::PaddleContact,
+            side: Some(side),
+            x,
+            y,
+            offset: Some(offset),
+        });
+    }
+
+    pub fn record_wall_bounce(&mut self, time: f32, x: f32, y: f32) {
+        self.events.push(ShotEvent {
+            time,
+            kind: ShotKind::WallBounce,
+            side: None,
+            x,
+            y,
+            offset: None,
+        });
+    }
+
+    // `conceding` is the side whose goal line the ball crossed
+    pub fn record_goal(&mut self, time: f32, conceding: Side, x: f32, y: f32) {
+        self.events.push(ShotEvent {
+            time,
+            kind: ShotKind::Goal,
+            side: Some(conceding),
+            x,
+            y,
+            offset: None,
+        });
+    }
+
+    fn values<'a>(
+        &'a self,
+        kind: ShotKind,
+        side: Option<Side>,
+        value: impl Fn(&ShotEvent) -> Option<f32> + 'a,
+    ) -> impl Iterator<Item = f32> + 'a {
+        self.events
+            .iter()
+            .filter(move |event| event.kind == kind && (side.is_none() || event.side == side))
+            .filter_map(value)
+    }
+
+    // Contact offsets along one paddle, top to bottom
+    pub fn paddle_heatmap(&self, side: Side) -> Heatmap {
+        let offsets = self.values(ShotKind::PaddleContact, Some(side), |event| event.offset);
+        Heatmap::from_values(offsets, -1.0, 1.0, PADDLE_BINS)
+    }
+
+    // Bounce positions along the top (`top` true) or bottom wall, left to right
+    pub fn wall_heatmap(&self, top: bool) -> Heatmap {
+        let xs = self.values(ShotKind::WallBounce, None, move |event| {
+            (top == (event.y < WINDOW_HEIGHT / 2.0)).then_some(event.x)
+        });
+        Heatmap::from_values(xs, 0.0, WINDOW_WIDTH, WALL_BINS)
+    }
+
+    // Where the ball crossed one side's goal line, top to bottom
+    pub fn goal_heatmap(&self, conceding: Side) -> Heatmap {
+        let ys = self.values(ShotKind::Goal, Some(conceding), |event| Some(event.y));
+        Heatmap::from_values(ys, 0.0, WINDOW_HEIGHT, GOAL_BINS)
+    }
+
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writeln!(writer, "time,kind,side,x,y,offset")?;
+        for event in &self.events {
+            let side = match event.side {
+                Some(Side::Left) => "left",
+                Some(Side::Right) => "right",
+                None => "",
+            };
+            let offset = event
+                .offset
+                .map_or(String::new(), |offset| format!("{:.4}", offset));
+            writeln!(
+                writer,
+                "{:.4},{},{},{:.2},{:.2},{}",
+                event.time,
+                event.kind.name(),
+                side,
+                event.x,
+                event.y,
+                offset
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn export_csv(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        self.write_csv(io::BufWriter::new(fs::File::create(path)?))
+    }
+}
+
+impl EventListener for MatchAnalytics {
+    fn on_event(&mut self, event: &GameEvent, time: f32) {
+        match *event {
+            GameEvent::PaddleHit {
+                side,
+                contact,
+                position,
+                ..
+            } => self.record_contact(time, side, position.x, position.y, contact),
+            GameEvent::WallHit { position } => {
+                self.record_wall_bounce(time, position.x, position.y)
+            }
+            GameEvent::Goal {
+                scorer, position, ..
+            } => self.record_goal(time, scorer.opponent(), position.x, position.y),
+            GameEvent::Serve { .. } | GameEvent::MatchEnd { .. } => {}
+        }
+    }
+}
+
+// The CSV of shot events written next to an exported match-<time>.json
+pub fn events_path(stats_path: &Path) -> PathBuf {
+    let stem = stats_path
+        .file_stem()
+        .map_or_else(|| "match".into(), |stem| stem.to_string_lossy());
+    stats_path.with_file_name(format!("{}-events.csv", stem))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heatmap {
+    pub bins: Vec<u32>,
+}
+
+impl Heatmap {
+    // Count `values` into `bins` equal buckets between `min` and `max`; outliers go to the ends
+    pub fn from_values(
+        values: impl IntoIterator<Item = f32>,
+        min: f32,
+        max: f32,
+        bins: usize,
+    ) -> Self {
+        let mut counts = vec![0; bins.max(1)];
+        let last = counts.len() - 1;
+        for value in values {
+            let position = (value - min) / (max - min) * counts.len() as f32;
+            let index = (position.max(0.0) as usize).min(last);
+            counts[index] += 1;
+        }
+        Self { bins: counts }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.bins.iter().sum()
+    }
+
+    pub fn peak(&self) -> u32 {
+        self.bins.iter().copied().max().unwrap_or(0)
+    }
+
+    // Share of the busiest bin, from 0 to 1
+    pub fn intensity(&self, bin: usize) -> f32 {
+        match self.peak() {
+            0 => 0.0,
+            peak => self.bins[bin] as f32 / peak as f32,
+        }
+    }
+
+    // Cold blue through to hot red
+    pub fn color(intensity: f32) -> Color {
+        let t = intensity.clamp(0.0, 1.0);
+        Color::new(t, 0.2 * (1.0 - t), 1.0 - t, 0.25 + 0.75 * t)
+    }
+
+    // Draw as a strip of cells, laid out across (`horizontal`) or down the given rectangle
+    pub fn draw(&self, x: f32, y: f32, width: f32, height: f32, horizontal: bool) {
+        let count = self.bins.len() as f32;
+        for bin in 0..self.bins.len() {
+            let color = Self::color(self.intensity(bin));
+            if horizontal {
+                let cell = width / count;
+                draw_rectangle(x + bin as f32 * cell, y, cell - 1.0, height, color);
+            } else {
+                let cell = height / count;
+                draw_rectangle(x, y + bin as f32 * cell, width, cell - 1.0, color);
+            }
+        }
+    }
+}
+
+// Heatmaps around a half-size court: wall bounces above and below, goal crossings on the goal
+// lines and paddle contacts outside them
+pub fn draw_heatmaps(analytics: &MatchAnalytics) {
+    let theme = theme::active();
+    let layout = Layout::current();
+    let scale = 0.5;
+    let court_width = WINDOW_WIDTH * scale;
+    let court_height = WINDOW_HEIGHT * scale;
+    let left = (WINDOW_WIDTH - court_width) / 2.0;
+    let top = 150.0;
+    let strip = 14.0;
+
+    let heading = layout.heading("SHOT HEATMAPS", HEADING_SIZE, theme.text);
+    let recorded = format!("{} events recorded", analytics.events.len());
+    layout.below(heading, &recorded, 20.0, theme.text_dim);
+
+    draw_rectangle_lines(left, top, court_width, court_height, 2.0, theme.text_dim);
+    draw_line(
+        left + court_width / 2.0,
+        top,
+        left + court_width / 2.0,
+        top + court_height,
+        1.0,
+        theme.text_dim,
+    );
+
+    analytics
+        .wall_heatmap(true)
+        .draw(left, top - strip - 4.0, court_width, strip, true);
+    analytics
+        .wall_heatmap(false)
+        .draw(left, top + court_height + 4.0, court_width, strip, true);
+    analytics
+        .goal_heatmap(Side::Left)
+        .draw(left + 2.0, top, strip, court_height, false);
+    analytics.goal_heatmap(Side::Right).draw(
+        left + court_width - strip - 2.0,
+        top,
+        strip,
+        court_height,
+        false,
+    );
+
+    // Paddle strips are drawn taller than the scaled paddle so each bin stays readable
+    let paddle_height = court_height * 0.6;
+    let paddle_top = top + (court_height - paddle_height) / 2.0;
+    let left_paddle_x = left - 60.0;
+    let right_paddle_x = left + court_width + 60.0 - strip;
+    analytics.paddle_heatmap(Side::Left).draw(
+        left_paddle_x,
+        paddle_top,
+        strip,
+        paddle_height,
+        false,
+    );
+    analytics.paddle_heatmap(Side::Right).draw(
+        right_paddle_x,
+        paddle_top,
+        strip,
+        paddle_height,
+        false,
+    );
+
+    // Labels are centred over the paddle strips and the court
+    let label_top = paddle_top - LINE_GAP - layout.line_height(18.0);
+    for (label, x) in [
+        ("Left paddle", left_paddle_x),
+        ("Right paddle", right_paddle_x),
+    ] {
+        let center = x + strip / 2.0;
+        layout.line(
+            label,
+            center,
+            label_top,
+            18.0,
+            theme.text_dim,
+            Align::Center,
+        );
+    }
+    layout.line(
+        "Walls above and below, goal lines at the ends; red is busiest",
+        layout.width / 2.0,
+        top + court_height + 4.0 + strip + LINE_GAP,
+        18.0,
+        theme.text_dim,
+        Align::Center,
+    );
+}
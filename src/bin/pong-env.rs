@@ -0,0 +1,29 @@
+// Reinforcement-learning environment driven over stdin/stdout
+
+use pong::env::serve_env;
+use std::io;
+
+const USAGE: &str = "Usage: pong-env
+
+Reads one JSON request per line from stdin and answers each with one JSON line on stdout:
+  {\"cmd\":\"configure\",\"config\":{...}}  Replace the environment configuration
+  {\"cmd\":\"reset\",\"seed\":N}             Start a new episode
+  {\"cmd\":\"step\",\"action\":\"Up\"}         Advance one step with Up, Down or Stay
+  {\"cmd\":\"close\"}                        Exit";
+
+fn main() {
+    if std::env::args()
+        .skip(1)
+        .any(|arg| arg == "-h" || arg == "--help")
+    {
+        println!("{}", USAGE);
+        return;
+    }
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    if let Err(err) = serve_env(stdin.lock(), stdout.lock()) {
+        eprintln!("pong-env stopped: {}", err);
+        std::process::exit(1);
+    }
+}
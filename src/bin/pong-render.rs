@@ -0,0 +1,30 @@
+// Render a replay or a simulated match to PNG frames or an animated GIF, without a window
+
+use pong::export::{export, parse_export_args, EXPORT_USAGE};
+
+fn main() {
+    let options = match parse_export_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, EXPORT_USAGE);
+            std::process::exit(2);
+        }
+    };
+    if options.help {
+        println!("{}", EXPORT_USAGE);
+        return;
+    }
+
+    match export(&options) {
+        Ok(frames) => eprintln!(
+            "Rendered {} frame{} to {}",
+            frames,
+            if frames == 1 { "" } else { "s" },
+            options.output.display()
+        ),
+        Err(err) => {
+            eprintln!("Export failed: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+// Headless authoritative match server
+
+use pong::server::{Server, DEFAULT_SERVER_PORT};
+
+const USAGE: &str = "Usage: pong-server [--bind <ADDR>]
+
+Options:
+  --bind <ADDR>  Address to listen on (default: 0.0.0.0:7100)
+  -h, --help     Print this help";
+
+fn main() {
+    let mut bind = format!("0.0.0.0:{}", DEFAULT_SERVER_PORT);
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bind" => match args.next() {
+                Some(addr) => bind = addr,
+                None => {
+                    eprintln!("--bind expects a value\n\n{}", USAGE);
+                    std::process::exit(2);
+                }
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => {
+                eprintln!("unknown argument: {}\n\n{}", arg, USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let server = match Server::bind(&bind) {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("Failed to bind {}: {}", bind, err);
+            std::process::exit(1);
+        }
+    };
+
+    if let Ok(addr) = server.local_addr() {
+        println!("pong-server listening on {}", addr);
+    }
+    if let Err(err) = server.run() {
+        eprintln!("Server stopped: {}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,61 @@
+// Headless batch simulation of bot-vs-bot matches
+
+use pong::sim::{
+    parse_sim_args, run_batch, write_csv, write_json, write_text, OutputFormat, Summary, SIM_USAGE,
+};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::Instant;
+
+fn main() {
+    let options = match parse_sim_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, SIM_USAGE);
+            std::process::exit(2);
+        }
+    };
+    if options.help {
+        println!("{}", SIM_USAGE);
+        return;
+    }
+
+    let started = Instant::now();
+    let records = match run_batch(&options.config) {
+        Ok(records) => records,
+        Err(err) => {
+            eprintln!("Simulation failed: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let elapsed = started.elapsed().as_secs_f64();
+    eprintln!(
+        "Simulated {} matches in {:.2}s on {} threads ({:.0} matches/s)",
+        records.len(),
+        elapsed,
+        options.config.threads,
+        records.len() as f64 / elapsed.max(f64::EPSILON)
+    );
+
+    let mut writer: Box<dyn Write> = match &options.output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(err) => {
+                eprintln!("Failed to create {}: {}", path.display(), err);
+                std::process::exit(1);
+            }
+        },
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+
+    let summary = Summary::from_records(&records);
+    let result = match options.format {
+        OutputFormat::Text => write_text(&mut writer, &options.config, &summary),
+        OutputFormat::Csv => write_csv(&mut writer, &records),
+        OutputFormat::Json => write_json(&mut writer, &options.config, &summary, &records),
+    };
+    if let Err(err) = result.and_then(|_| writer.flush()) {
+        eprintln!("Failed to write report: {}", err);
+        std::process::exit(1);
+    }
+}
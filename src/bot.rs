@@ -0,0 +1,352 @@
+// Paddle controllers, including bots running as external programs
+// A bot is any executable that reads one JSON message per line on stdin and, for every
+// `state` message, answers with a line holding the state's tick and `up`, `down` or `stay`,
+// such as `12 up`. Answers that are late, malformed or missing because the bot crashed all
+// count as `stay`, and answers to an earlier tick are thrown away.
+
+use crate::cli::BotOptions;
+use crate::netplay::TICK_DT;
+use crate::{AiSettings, Game, GameState, PaddleCommand, RuleSet, Side, Vec2D};
+use ::rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_TIME_BUDGET: Duration = Duration::from_millis(10);
+// Headless matches that somehow never end are stopped after this many ticks
+pub const MAX_MATCH_TICKS: u32 = 60 * 60 * 30;
+// How long a dropped bot gets to read its last messages, such as `end`, and exit by itself
+const EXIT_GRACE: Duration = Duration::from_millis(200);
+
+// Something that decides a paddle's move each tick
+pub trait Controller {
+    fn command(&mut self, game: &Game, side: Side) -> PaddleCommand;
+
+    // Called once when the match is over
+    fn finish(&mut self, _winner: Side) {}
+
+    // A problem worth showing on screen, such as a script error
+    fn status(&self) -> Option<String> {
+        None
+    }
+}
+
+pub struct BuiltinAi;
+
+impl Controller for BuiltinAi {
+    fn command(&mut self, game: &Game, side: Side) -> PaddleCommand {
+        game.ai_command(side)
+    }
+}
+
+// The built-in AI with its own handicap, for sides the game itself doesn't slow down.
+// Reduced speed is approximated by moving on only that fraction of ticks.
+pub struct HandicappedAi {
+    pub settings: AiSettings,
+    rng: ChaCha8Rng,
+}
+
+impl HandicappedAi {
+    pub fn new(settings: AiSettings, seed: u64) -> Self {
+        Self {
+            settings,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Controller for HandicappedAi {
+    fn command(&mut self, game: &Game, side: Side) -> PaddleCommand {
+        let command = game.tracking_command(side, self.settings.dead_zone);
+        if self.rng.gen::<f32>() < self.settings.speed_factor {
+            command
+        } else {
+            PaddleCommand::Stay
+        }
+    }
+}
+
+// The match as seen by one paddle
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BotState {
+    pub tick: u32,
+    pub side: Side,
+    pub ball_position: Vec2D,
+    pub ball_velocity: Vec2D,
+    pub paddle_y: f32,
+    pub opponent_paddle_y: f32,
+    pub score: i32,
+    pub opponent_score: i32,
+}
+
+impl BotState {
+    pub fn capture(game: &Game, side: Side, tick: u32) -> Self {
+        let (score, opponent_score) = match side {
+            Side::Left => (game.player_score, game.ai_score),
+            Side::Right => (game.ai_score, game.player_score),
+        };
+        Self {
+            tick,
+            side,
+            ball_position: game.ball.position,
+            ball_velocity: game.ball.velocity,
+            paddle_y: game.paddle(side).position.y,
+            opponent_paddle_y: game.paddle(side.opponent()).position.y,
+            score,
+            opponent_score,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotMessage {
+    Start {
+        side: Side,
+        rules: RuleSet,
+        tick_seconds: f32,
+        time_budget_ms: u64,
+    },
+    State(BotState),
+    End {
+        winner: Side,
+    },
+}
+
+// A reply's tick and command, from a line like `12 up`
+pub fn parse_reply(line: &str) -> Option<(u32, PaddleCommand)> {
+    let mut parts = line.split_whitespace();
+    let tick = parts.next()?.parse().ok()?;
+    let command = match parts.next()?.to_ascii_lowercase().as_str() {
+        "up" => PaddleCommand::Up,
+        "down" => PaddleCommand::Down,
+        "stay" => PaddleCommand::Stay,
+        _ => return None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((tick, command))
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BotStats {
+    pub replies: u32,
+    pub timeouts: u32,
+    pub invalid: u32,
+    // Replies that arrived after their tick's budget had run out
+    pub late: u32,
+}
+
+pub struct BotController {
+    child: Child,
+    // Lines for the bot's stdin, written on a separate thread so a bot that stops reading
+    // can't block the game
+    input: Option<Sender<Vec<u8>>>,
+    writer: Option<JoinHandle<()>>,
+    replies: Receiver<String>,
+    budget: Duration,
+    tick: u32,
+    started: bool,
+    stats: BotStats,
+}
+
+impl BotController {
+    // Start `command_line`, split on whitespace into the program and its arguments
+    pub fn spawn(command_line: &str, budget: Duration) -> io::Result<Self> {
+        let mut parts = command_line.split_whitespace();
+        let program = parts
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty bot command"))?;
+        let mut command = Command::new(program);
+        command.args(parts);
+        Self::from_command(command, budget)
+    }
+
+    pub fn from_command(mut command: Command, budget: Duration) -> io::Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("bot stdin is piped");
+        let stdout = child.stdout.take().expect("bot stdout is piped");
+
+        let (input, lines) = channel::<Vec<u8>>();
+        let writer = thread::spawn(move || {
+            for line in lines {
+                if stdin.write_all(&line).and_then(|_| stdin.flush()).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (tx, replies) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            child,
+            input: Some(input),
+            writer: Some(writer),
+            replies,
+            budget,
+            tick: 0,
+            started: false,
+            stats: BotStats::default(),
+        })
+    }
+
+    pub fn stats(&self) -> BotStats {
+        self.stats
+    }
+
+    fn send(&mut self, message: &BotMessage) -> bool {
+        let Some(input) = self.input.as_ref() else {
+            return false;
+        };
+        let mut line = match serde_json::to_vec(message) {
+            Ok(line) => line,
+            Err(_) => return false,
+        };
+        line.push(b'\n');
+        if input.send(line).is_err() {
+            // The bot has exited; it stays put from now on
+            self.input = None;
+            return false;
+        }
+        true
+    }
+}
+
+impl Controller for BotController {
+    fn command(&mut self, game: &Game, side: Side) -> PaddleCommand {
+        if !self.started {
+            self.started = true;
+            let start = BotMessage::Start {
+                side,
+                rules: game.rules,
+                tick_seconds: TICK_DT,
+                time_budget_ms: self.budget.as_millis() as u64,
+            };
+            self.send(&start);
+        }
+
+        // Anything still queued answers an earlier tick
+        while self.replies.try_recv().is_ok() {
+            self.stats.late += 1;
+        }
+
+        self.tick += 1;
+        if !self.send(&BotMessage::State(BotState::capture(game, side, self.tick))) {
+            return PaddleCommand::Stay;
+        }
+
+        // A slow bot's answer to an earlier tick can still arrive while waiting for this one
+        let deadline = Instant::now() + self.budget;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.replies.recv_timeout(remaining) {
+                Ok(line) => match parse_reply(&line) {
+                    Some((tick, _)) if tick < self.tick => self.stats.late += 1,
+                    Some((tick, command)) if tick == self.tick => {
+                        self.stats.replies += 1;
+                        return command;
+                    }
+                    _ => {
+                        self.stats.invalid += 1;
+                        return PaddleCommand::Stay;
+                    }
+                },
+                Err(RecvTimeoutError::Timeout) => {
+                    self.stats.timeouts += 1;
+                    return PaddleCommand::Stay;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.input = None;
+                    return PaddleCommand::Stay;
+                }
+            }
+        }
+    }
+
+    fn finish(&mut self, winner: Side) {
+        self.send(&BotMessage::End { winner });
+    }
+}
+
+impl Drop for BotController {
+    fn drop(&mut self) {
+        // Closing the channel lets the writer deliver what's queued, then close the bot's stdin
+        self.input = None;
+        let deadline = Instant::now() + EXIT_GRACE;
+        if let Some(writer) = self.writer.take() {
+            while !writer.is_finished() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(1));
+            }
+            if writer.is_finished() {
+                let _ = writer.join();
+            }
+        }
+        while Instant::now() < deadline {
+            match self.child.try_wait() {
+                Ok(None) => thread::sleep(Duration::from_millis(1)),
+                Ok(Some(_)) | Err(_) => break,
+            }
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// A fresh game for a bot match. The built-in AI keeps its usual handicap when it plays a bot,
+// but a windowed match's left side without a bot belongs to the keyboard, not the AI.
+pub fn bot_game(options: &BotOptions) -> Game {
+    let mut game = Game::with_seed(options.seed);
+    let builtin_left = options.left.is_none() && options.headless;
+    let builtin_right = options.right.is_none();
+    game.ai_side = match (builtin_left, builtin_right) {
+        (true, false) => Some(Side::Left),
+        (false, true) => Some(Side::Right),
+        _ => None,
+    };
+    game
+}
+
+// Play `game` to the end at the fixed tick rate; returns the winner, if the match finished
+pub fn play_headless(
+    game: &mut Game,
+    left: &mut dyn Controller,
+    right: &mut dyn Controller,
+    max_ticks: u32,
+) -> Option<Side> {
+    for _ in 0..max_ticks {
+        if game.game_state != GameState::Playing {
+            break;
+        }
+        let left_command = left.command(game, Side::Left);
+        let right_command = right.command(game, Side::Right);
+        game.step(TICK_DT, left_command, right_command);
+        // Nobody sees the effects, so don't let them pile up
+        game.particles.clear();
+    }
+
+    let winner = match game.game_state {
+        GameState::GameOver if game.player_score > game.ai_score => Side::Left,
+        GameState::GameOver => Side::Right,
+        _ => return None,
+    };
+    left.finish(winner);
+    right.finish(winner);
+    Some(winner)
+}
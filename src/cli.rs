@@ -0,0 +1,358 @@
+// Command-line options for the pong binary
+
+use crate::bot::DEFAULT_TIME_BUDGET;
+use crate::learned::{default_weights_path, TrainConfig};
+use crate::mods::{parse_mod_list, ModConfig};
+use crate::netplay::{LinkConditions, NetConfig};
+use crate::text::is_font_name;
+use crate::theme::THEME_NAMES;
+use crate::{Difficulty, Side};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+pub const USAGE: &str = "Usage: pong [OPTIONS]
+       pong train [TRAIN OPTIONS]
+
+Options:
+  --netplay <LOCAL_ADDR> <PEER_ADDR>  Play a peer-to-peer match over UDP
+  --side <left|right>                 Paddle controlled by this peer (default: left)
+  --seed <N>                          Match seed; netplay peers must agree on it (default: 0)
+  --input-delay <TICKS>               Local input delay in ticks (default: 2)
+  --sim-loss <0.0-1.0>                Simulated outgoing packet loss
+  --sim-latency <MS>                  Simulated outgoing latency
+  --sim-jitter <MS>                   Simulated extra random latency
+  --connect <ADDR>                    Play on a pong-server
+  --room <NAME>                       Room to join on the server
+  --create                            Create the room instead of joining it
+  --name <NAME>                       Player name shown to others
+  --spectator                         Watch the server room instead of playing
+  --broadcast <ADDR>                  Let spectators watch this local or netplay match
+  --spectate <ADDR>                   Watch a match shared with --broadcast
+  --lan-discovery <ADDR>              Address LAN games are announced on (default: broadcast)
+  --left-bot <CMD>                    Let an external program or a .rhai script play the left paddle
+  --right-bot <CMD>                   Let an external program or a .rhai script play the right paddle
+  --bot-budget <MS>                   Time a bot has to answer each tick (default: 10)
+  --headless                          Play the bot match without a window and print the result
+  --difficulty <LEVEL>                Computer paddle: easy, normal, hard or learned (default: normal)
+  --ai-weights <FILE>                 Network for the learned difficulty (default: data dir)
+  --mods <LIST>                       Rule mods or .rhai game modes for local matches, e.g.
+                                      gravity_ball,shrinking_paddles (default: mods.json in the data dir)
+  --theme <NAME|FILE>                 classic, neon, amber, high_contrast, custom (theme.json in the
+                                      data dir) or a theme file (default: settings.json)
+  --font <NAME|FILE>                  pixel (built in), default (macroquad's font) or a .ttf/.otf
+                                      file (default: settings.json)
+  --reduced-motion                    Turn off screen shake, hit-stop and the other game-feel effects
+  --tui                               Play against the computer in the terminal (needs the tui feature)
+  -h, --help                          Print this help
+
+Train options (evolve the learned AI and save it):
+  --generations <N>                   Generations to evolve (default: 100)
+  --population <N>                    Networks per generation (default: 48)
+  --hidden <N>                        Hidden neurons (default: 8)
+  --seed <N>                          Training seed (default: 0)
+  --threads <N>                       Worker threads (default: all cores)
+  --out <FILE>                        Where to save the weights (default: data dir)";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Options {
+    pub netplay: Option<NetplayOptions>,
+    pub online: Option<OnlineOptions>,
+    pub broadcast: Option<String>,
+    pub spectate: Option<String>,
+    pub lan_discovery: Option<SocketAddr>,
+    pub bots: Option<BotOptions>,
+    pub difficulty: Option<Difficulty>,
+    pub ai_weights: Option<PathBuf>,
+    pub mods: Option<Vec<ModConfig>>,
+    pub theme: Option<String>,
+    pub font: Option<String>,
+    pub reduced_motion: bool,
+    pub tui: bool,
+    pub train: Option<TrainOptions>,
+    pub help: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrainOptions {
+    pub config: TrainConfig,
+    pub output: PathBuf,
+}
+
+// Sides without a bot are played by the keyboard (left, with a window) or the built-in AI
+#[derive(Clone, Debug, PartialEq)]
+pub struct BotOptions {
+    pub left: Option<String>,
+    pub right: Option<String>,
+    pub budget: Duration,
+    pub headless: bool,
+    pub seed: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OnlineOptions {
+    pub server: String,
+    pub room: String,
+    pub create: bool,
+    pub name: String,
+    pub spectator: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetplayOptions {
+    pub local_addr: SocketAddr,
+    pub peer_addr: SocketAddr,
+    pub side: Side,
+    pub seed: u64,
+    pub config: NetConfig,
+    pub link: LinkConditions,
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} expects a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+fn parse_side(value: &str) -> Result<Side, String> {
+    match value {
+        "left" => Ok(Side::Left),
+        "right" => Ok(Side::Right),
+        _ => Err(format!("invalid side: {} (expected left or right)", value)),
+    }
+}
+
+fn parse_train_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut config = TrainConfig::default();
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--generations" => config.generations = parse_value("--generations", args.next())?,
+            "--population" => config.population = parse_value("--population", args.next())?,
+            "--hidden" => config.hidden = parse_value("--hidden", args.next())?,
+            "--seed" => config.seed = parse_value("--seed", args.next())?,
+            "--threads" => config.threads = parse_value("--threads", args.next())?,
+            "--out" => output = Some(parse_value("--out", args.next())?),
+            "-h" | "--help" => options.help = true,
+            _ => return Err(format!("unknown train argument: {}", arg)),
+        }
+    }
+
+    if config.population < 2 || config.hidden == 0 || config.threads == 0 {
+        return Err(
+            "--population must be at least 2, --hidden and --threads at least 1".to_owned(),
+        );
+    }
+    // Without a generation there is no champion worth saving
+    if config.generations == 0 {
+        return Err("--generations must be at least 1".to_owned());
+    }
+    options.train = Some(TrainOptions {
+        config,
+        output: output.unwrap_or_else(default_weights_path),
+    });
+    Ok(options)
+}
+
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut args = args.into_iter().peekable();
+    if args.peek().map(String::as_str) == Some("train") {
+        args.next();
+        return parse_train_args(args);
+    }
+
+    let mut options = Options::default();
+    let mut addrs: Option<(SocketAddr, SocketAddr)> = None;
+    let mut side = Side::Left;
+    let mut seed = 0;
+    let mut config = NetConfig::default();
+    let mut link = LinkConditions::default();
+    let mut netplay_flag_used = false;
+    let mut server: Option<String> = None;
+    let mut room: Option<String> = None;
+    let mut create = false;
+    let mut name: Option<String> = None;
+    let mut spectator = false;
+    let mut left_bot: Option<String> = None;
+    let mut right_bot: Option<String> = None;
+    let mut budget: Option<Duration> = None;
+    let mut headless = false;
+    let mut seed_used = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--netplay" => {
+                let local = parse_value("--netplay", args.next())?;
+                let peer = parse_value("--netplay", args.next())?;
+                addrs = Some((local, peer));
+            }
+            "--side" => {
+                side = parse_side(&parse_value::<String>("--side", args.next())?)?;
+                netplay_flag_used = true;
+            }
+            "--seed" => {
+                seed = parse_value("--seed", args.next())?;
+                seed_used = true;
+            }
+            "--input-delay" => {
+                config.input_delay = parse_value("--input-delay", args.next())?;
+                netplay_flag_used = true;
+            }
+            "--sim-loss" => {
+                link.loss = parse_value("--sim-loss", args.next())?;
+                if !(0.0..=1.0).contains(&link.loss) {
+                    return Err("--sim-loss must be between 0.0 and 1.0".to_owned());
+                }
+                netplay_flag_used = true;
+            }
+            "--sim-latency" => {
+                link.latency = Duration::from_millis(parse_value("--sim-latency", args.next())?);
+                netplay_flag_used = true;
+            }
+            "--sim-jitter" => {
+                link.jitter = Duration::from_millis(parse_value("--sim-jitter", args.next())?);
+                netplay_flag_used = true;
+            }
+            "--connect" => server = Some(parse_value("--connect", args.next())?),
+            "--room" => room = Some(parse_value("--room", args.next())?),
+            "--create" => create = true,
+            "--name" => name = Some(parse_value("--name", args.next())?),
+            "--spectator" => spectator = true,
+            "--broadcast" => options.broadcast = Some(parse_value("--broadcast", args.next())?),
+            "--spectate" => options.spectate = Some(parse_value("--spectate", args.next())?),
+            "--lan-discovery" => {
+                options.lan_discovery = Some(parse_value("--lan-discovery", args.next())?);
+            }
+            "--left-bot" => left_bot = Some(parse_value("--left-bot", args.next())?),
+            "--right-bot" => right_bot = Some(parse_value("--right-bot", args.next())?),
+            "--bot-budget" => {
+                budget = Some(Duration::from_millis(parse_value(
+                    "--bot-budget",
+                    args.next(),
+                )?));
+            }
+            "--headless" => headless = true,
+            "--difficulty" => {
+                let value: String = parse_value("--difficulty", args.next())?;
+                options.difficulty = Some(value.parse()?);
+            }
+            "--ai-weights" => options.ai_weights = Some(parse_value("--ai-weights", args.next())?),
+            "--mods" => {
+                let list: String = parse_value("--mods", args.next())?;
+                options.mods = Some(parse_mod_list(&list)?);
+            }
+            "--theme" => {
+                let name: String = parse_value("--theme", args.next())?;
+                if !THEME_NAMES.contains(&name.as_str()) && !name.ends_with(".json") {
+                    return Err(format!("unknown theme: {}", name));
+                }
+                options.theme = Some(name);
+            }
+            "--font" => {
+                let name: String = parse_value("--font", args.next())?;
+                if !is_font_name(&name) {
+                    return Err(format!("unknown font: {}", name));
+                }
+                options.font = Some(name);
+            }
+            "--reduced-motion" => options.reduced_motion = true,
+            "--tui" => options.tui = true,
+            "-h" | "--help" => options.help = true,
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    if left_bot.is_some() || right_bot.is_some() {
+        options.bots = Some(BotOptions {
+            left: left_bot,
+            right: right_bot,
+            budget: budget.unwrap_or(DEFAULT_TIME_BUDGET),
+            headless,
+            seed,
+        });
+    } else if headless || budget.is_some() {
+        return Err("--headless and --bot-budget require --left-bot or --right-bot".to_owned());
+    }
+
+    match addrs {
+        Some((local_addr, peer_addr)) => {
+            options.netplay = Some(NetplayOptions {
+                local_addr,
+                peer_addr,
+                side,
+                seed,
+                config,
+                link,
+            });
+        }
+        None if netplay_flag_used || (seed_used && options.bots.is_none()) => {
+            return Err("netplay options require --netplay <LOCAL_ADDR> <PEER_ADDR>".to_owned());
+        }
+        None => {}
+    }
+
+    match (server, room) {
+        (Some(server), Some(room)) => {
+            let name = name
+                .or_else(|| std::env::var("USER").ok())
+                .unwrap_or_else(|| "player".to_owned());
+            options.online = Some(OnlineOptions {
+                server,
+                room,
+                create,
+                name,
+                spectator,
+            });
+        }
+        (Some(_), None) => return Err("--connect requires --room <NAME>".to_owned()),
+        (None, _) if create || spectator || name.is_some() => {
+            return Err(
+                "--room, --create, --name and --spectator require --connect <ADDR>".to_owned(),
+            );
+        }
+        (None, _) => {}
+    }
+
+    let modes = [
+        options.netplay.is_some(),
+        options.online.is_some(),
+        options.spectate.is_some(),
+        options.bots.is_some(),
+    ];
+    if modes.iter().filter(|&&mode| mode).count() > 1 {
+        return Err("--netplay, --connect, --spectate and bots cannot be combined".to_owned());
+    }
+    if options.broadcast.is_some() && (modes[1] || modes[2] || modes[3]) {
+        return Err("--broadcast only applies to local and netplay matches".to_owned());
+    }
+    if options.lan_discovery.is_some() && modes.contains(&true) {
+        return Err("--lan-discovery only applies to the local title menu".to_owned());
+    }
+    if (options.difficulty.is_some() || options.ai_weights.is_some()) && modes.contains(&true) {
+        return Err("--difficulty and --ai-weights only apply to local matches".to_owned());
+    }
+    if options.mods.is_some() && modes.contains(&true) {
+        return Err("--mods only applies to local matches".to_owned());
+    }
+    if options.tui && modes.contains(&true) {
+        return Err("--tui only plays local matches against the computer".to_owned());
+    }
+    if options.tui && (options.broadcast.is_some() || options.lan_discovery.is_some()) {
+        return Err("--tui cannot be combined with --broadcast or --lan-discovery".to_owned());
+    }
+    if options.tui && !cfg!(feature = "tui") {
+        return Err("--tui needs pong built with `--features tui`".to_owned());
+    }
+    if options.ai_weights.is_some() {
+        match options.difficulty {
+            None => options.difficulty = Some(Difficulty::Learned),
+            Some(Difficulty::Learned) => {}
+            Some(_) => return Err("--ai-weights requires the learned difficulty".to_owned()),
+        }
+    }
+
+    Ok(options)
+}
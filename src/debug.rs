@@ -0,0 +1,367 @@
+// Debug overlay: frame timing, simulation state and collision boxes drawn over the court, with a
+// panel of tunables for editing speeds and sizes mid-match
+// The module only exists with the `debug-overlay` feature, so normal builds carry none of it.
+// F3 shows or hides the overlay; the tunable keys only work while it is showing.
+
+use crate::text;
+use crate::theme::Theme;
+use crate::{view, Game, Side, WINDOW_HEIGHT};
+use macroquad::prelude::*;
+use std::collections::VecDeque;
+
+pub const TOGGLE_KEY: KeyCode = KeyCode::F3;
+// Frames kept for the frame time readout, about two seconds at 60 FPS
+pub const FRAME_HISTORY: usize = 120;
+// The velocity arrow ends where the ball will be this far in the future, in seconds
+const VELOCITY_LOOKAHEAD: f32 = 0.25;
+const PANEL_X: f32 = 50.0;
+const PANEL_Y: f32 = 80.0;
+const PANEL_WIDTH: f32 = 300.0;
+const LINE_HEIGHT: f32 = 18.0;
+const TEXT_SIZE: f32 = 16.0;
+
+// A value the panel can edit while a match is running
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tunable {
+    BallSpeed,
+    PaddleSpeed,
+    HitSpeedup,
+    MaxSpeedup,
+    AiSpeed,
+    AiDeadZone,
+    LeftPaddleHeight,
+    RightPaddleHeight,
+}
+
+impl Tunable {
+    pub const ALL: [Tunable; 8] = [
+        Tunable::BallSpeed,
+        Tunable::PaddleSpeed,
+        Tunable::HitSpeedup,
+        Tunable::MaxSpeedup,
+        Tunable::AiSpeed,
+        Tunable::AiDeadZone,
+        Tunable::LeftPaddleHeight,
+        Tunable::RightPaddleHeight,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tunable::BallSpeed => "Ball speed",
+            Tunable::PaddleSpeed => "Paddle speed",
+            Tunable::HitSpeedup => "Speed-up per hit",
+            Tunable::MaxSpeedup => "Max speed-up",
+            Tunable::AiSpeed => "AI speed factor",
+            Tunable::AiDeadZone => "AI dead zone",
+            Tunable::LeftPaddleHeight => "Left paddle height",
+            Tunable::RightPaddleHeight => "Right paddle height",
+        }
+    }
+
+    // Change per key press
+    pub fn step(self) -> f32 {
+        match self {
+            Tunable::BallSpeed | Tunable::PaddleSpeed => 25.0,
+            Tunable::HitSpeedup => 0.01,
+            Tunable::MaxSpeedup => 0.25,
+            Tunable::AiSpeed => 0.05,
+            Tunable::AiDeadZone => 2.0,
+            Tunable::LeftPaddleHeight | Tunable::RightPaddleHeight => 5.0,
+        }
+    }
+
+    pub fn range(self) -> (f32, f32) {
+        match self {
+            Tunable::BallSpeed | Tunable::PaddleSpeed => (25.0, 2000.0),
+            Tunable::HitSpeedup => (1.0, 1.5),
+            Tunable::MaxSpeedup => (1.0, 5.0),
+            Tunable::AiSpeed => (0.0, 2.0),
+            Tunable::AiDeadZone => (0.0, 100.0),
+            Tunable::LeftPaddleHeight | Tunable::RightPaddleHeight => (5.0, WINDOW_HEIGHT / 2.0),
+        }
+    }
+
+    pub fn get(self, game: &Game) -> f32 {
+        match self {
+            Tunable::BallSpeed => game.ball.speed,
+            Tunable::PaddleSpeed => game.rules.paddle_speed,
+            Tunable::HitSpeedup => game.rules.hit_speedup,
+            Tunable::MaxSpeedup => game.rules.max_speedup,
+            Tunable::AiSpeed => game.ai.speed_factor,
+            Tunable::AiDeadZone => game.ai.dead_zone,
+            Tunable::LeftPaddleHeight => game.player_paddle.height,
+            Tunable::RightPaddleHeight => game.ai_paddle.height,
+        }
+    }
+
+    // Set the value, clamped to its range
+    pub fn set(self, game: &mut Game, value: f32) {
+        let (min, max) = self.range();
+        let value = value.clamp(min, max);
+        match self {
+            Tunable::BallSpeed => {
+                // The ball keeps its heading, and later serves and restarts use the new speed
+                let velocity = game.ball.velocity.normalize();
+                game.ball.velocity.x = velocity.x * value;
+                game.ball.velocity.y = velocity.y * value;
+                game.ball.speed = value;
+                game.rules.ball_speed = value;
+            }
+            Tunable::PaddleSpeed => game.rules.paddle_speed = value,
+            Tunable::HitSpeedup => game.rules.hit_speedup = value,
+            Tunable::MaxSpeedup => game.rules.max_speedup = value,
+            Tunable::AiSpeed => game.ai.speed_factor = value,
+            Tunable::AiDeadZone => game.ai.dead_zone = value,
+            Tunable::LeftPaddleHeight => game.player_paddle.resize(value),
+            Tunable::RightPaddleHeight => game.ai_paddle.resize(value),
+        }
+    }
+}
+
+// Recent frame times, in seconds
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameTimes {
+    samples: VecDeque<f32>,
+}
+
+impl FrameTimes {
+    pub fn push(&mut self, dt: f32) {
+        if self.samples.len() == FRAME_HISTORY {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(dt);
+    }
+
+    pub fn average(&self) -> f32 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.samples.iter().sum::<f32>() / self.samples.len() as f32
+    }
+
+    pub fn worst(&self) -> f32 {
+        self.samples.iter().copied().fold(0.0, f32::max)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugOverlay {
+    pub visible: bool,
+    // Index into Tunable::ALL
+    pub selected: usize,
+    pub frame_times: FrameTimes,
+    // Each tunable's value when the overlay was created, restored by the reset key
+    defaults: [f32; Tunable::ALL.len()],
+}
+
+impl DebugOverlay {
+    // A hidden overlay that resets tunables to the values `game` has now
+    pub fn new(game: &Game) -> Self {
+        Self {
+            visible: false,
+            selected: 0,
+            frame_times: FrameTimes::default(),
+            defaults: Tunable::ALL.map(|tunable| tunable.get(game)),
+        }
+    }
+
+    pub fn tunable(&self) -> Tunable {
+        Tunable::ALL[self.selected]
+    }
+
+    // Move the selection by `offset` places, wrapping around the list
+    pub fn select(&mut self, offset: isize) {
+        let count = Tunable::ALL.len() as isize;
+        self.selected = (self.selected as isize + offset).rem_euclid(count) as usize;
+    }
+
+    // Change the selected tunable by `steps` of its step size
+    pub fn adjust(&self, game: &mut Game, steps: f32) {
+        let tunable = self.tunable();
+        tunable.set(game, tunable.get(game) + tunable.step() * steps);
+    }
+
+    pub fn reset(&self, game: &mut Game) {
+        self.tunable().set(game, self.defaults[self.selected]);
+    }
+
+    // Record the frame and handle the overlay's keys; returns whether a tunable changed
+    pub fn update(&mut self, game: &mut Game, dt: f32) -> bool {
+        self.frame_times.push(dt);
+        if is_key_pressed(TOGGLE_KEY) {
+            self.visible = !self.visible;
+        }
+        if !self.visible {
+            return false;
+        }
+
+        if is_key_pressed(KeyCode::LeftBracket) {
+            self.select(-1);
+        } else if is_key_pressed(KeyCode::RightBracket) {
+            self.select(1);
+        }
+        let before = self.tunable().get(game);
+        if is_key_pressed(KeyCode::Minus) {
+            self.adjust(game, -1.0);
+        } else if is_key_pressed(KeyCode::Equal) {
+            self.adjust(game, 1.0);
+        } else if is_key_pressed(KeyCode::Key0) {
+            self.reset(game);
+        }
+        self.tunable().get(game) != before
+    }
+
+    // The readouts and tunables, one line each
+    pub fn lines(&self, game: &Game) -> Vec<String> {
+        let average = self.frame_times.average();
+        let fps = if average > 0.0 { 1.0 / average } else { 0.0 };
+        let ball = &game.ball;
+        let ai = match (game.ai_side, &game.learned_ai) {
+            (None, _) => "none",
+            (Some(_), Some(_)) => "learned network",
+            (Some(_), None) => "tracking",
+        };
+        let mut lines = vec![
+            format!(
+                "FPS {:.0}  frame {:.1} ms (worst {:.1})",
+                fps,
+                average * 1000.0,
+                self.frame_times.worst() * 1000.0
+            ),
+            format!(
+                "Tick {}  match time {:.2} s",
+                game.ticks, game.stats.time_played
+            ),
+            format!(
+                "Ball ({:.0}, {:.0})  v ({:+.0}, {:+.0})",
+                ball.position.x, ball.position.y, ball.velocity.x, ball.velocity.y
+            ),
+            format!(
+                "Speed {:.0}  rally {}",
+                ball.velocity.length(),
+                game.rally_length
+            ),
+            format!(
+                "Particles {}/{}",
+                game.particles.len(),
+                game.particles.budget()
+            ),
+            format!(
+                "Seed {:#x}  RNG word {}",
+                game.seed,
+                game.rng.get_word_pos()
+            ),
+            format!("AI {}  state {:?}", ai, game.game_state),
+            String::new(),
+            "[ ] select  - = change  0 reset".to_owned(),
+        ];
+        for (i, tunable) in Tunable::ALL.into_iter().enumerate() {
+            let marker = if i == self.selected { ">" } else { " " };
+            lines.push(format!(
+                "{} {} {}",
+                marker,
+                tunable.name(),
+                format_value(tunable.get(game))
+            ));
+        }
+        lines
+    }
+
+    pub fn draw(&self, game: &Game, theme: &Theme) {
+        if !self.visible {
+            return;
+        }
+
+        // Boxes follow the court when it shakes so they sit on what was drawn
+        let shake = game.feel.shake_offset();
+        if shake != Vec2::ZERO {
+            view::offset_camera(shake);
+        }
+        draw_collision_boxes(game);
+        draw_velocity(game);
+        draw_ai_target(game);
+        if shake != Vec2::ZERO {
+            view::offset_camera(Vec2::ZERO);
+        }
+
+        let lines = self.lines(game);
+        let height = lines.len() as f32 * LINE_HEIGHT + 12.0;
+        draw_rectangle(
+            PANEL_X,
+            PANEL_Y,
+            PANEL_WIDTH,
+            height,
+            theme.background.with_alpha(0.75),
+        );
+        let tunables_start = lines.len() - Tunable::ALL.len();
+        for (i, line) in lines.iter().enumerate() {
+            let color = if i >= tunables_start && i - tunables_start == self.selected {
+                theme.text
+            } else {
+                theme.text_dim
+            };
+            let y = PANEL_Y + 6.0 + (i + 1) as f32 * LINE_HEIGHT - 4.0;
+            text::draw_text(line, PANEL_X + 8.0, y, TEXT_SIZE, color);
+        }
+    }
+}
+
+// Whole numbers for speeds and sizes, two decimals for factors
+fn format_value(value: f32) -> String {
+    if value.fract() == 0.0 || value.abs() >= 100.0 {
+        format!("{:.0}", value)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+// The rectangles collisions are tested against, which squash and glow leave unchanged
+fn draw_collision_boxes(game: &Game) {
+    for rect in [
+        game.player_paddle.get_rect(),
+        game.ai_paddle.get_rect(),
+        game.ball.get_rect(),
+    ] {
+        draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 1.0, YELLOW);
+    }
+}
+
+fn draw_velocity(game: &Game) {
+    let start = game.ball.center();
+    let start = vec2(start.x, start.y);
+    let velocity = vec2(game.ball.velocity.x, game.ball.velocity.y);
+    let end = start + velocity * VELOCITY_LOOKAHEAD;
+    draw_line(start.x, start.y, end.x, end.y, 2.0, GREEN);
+
+    // Arrow head
+    let direction = velocity.normalize_or_zero();
+    let back = end - direction * 8.0;
+    let across = direction.perp() * 5.0;
+    draw_triangle(end, back + across, back - across, GREEN);
+}
+
+// Where the simple AI is heading, and the dead zone around its paddle where it rests
+fn draw_ai_target(game: &Game) {
+    let side = match (game.ai_side, &game.learned_ai) {
+        (Some(side), None) => side,
+        _ => return,
+    };
+    let paddle = game.paddle(side).get_rect();
+    let x = paddle.center().x;
+    let center = paddle.center().y;
+    let target = game.ai_target_y();
+    let dead_zone = game.ai.dead_zone;
+    draw_rectangle(
+        paddle.x - 6.0,
+        center - dead_zone,
+        paddle.w + 12.0,
+        dead_zone * 2.0,
+        SKYBLUE.with_alpha(0.25),
+    );
+    draw_line(x, center, x, target, 1.0, SKYBLUE);
+    let toward_court = match side {
+        Side::Left => 1.0,
+        Side::Right => -1.0,
+    };
+    draw_line(x, target, x + toward_court * 40.0, target, 1.0, SKYBLUE);
+}
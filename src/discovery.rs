@@ -0,0 +1,375 @@
+// LAN game discovery over UDP broadcast
+// A host announces its open game every ANNOUNCE_INTERVAL to the discovery address; browsers
+// listening there collect the announcements, measure ping with small probes sent straight
+// to each host, and join by sending the UDP port they will play netplay on.
+
+use crate::netplay::{NetConfig, RollbackSession, Transport, MAX_PACKET_SIZE};
+use crate::{RuleSet, Side};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+pub const DISCOVERY_PORT: u16 = 7199;
+pub const DISCOVERY_VERSION: u32 = 2;
+pub const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+pub const PING_INTERVAL: Duration = Duration::from_millis(500);
+// Games that have not announced themselves for this long are dropped from the list
+pub const GAME_TIMEOUT: Duration = Duration::from_secs(4);
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DiscoveryMessage {
+    Announce {
+        version: u32,
+        host_name: String,
+        game_port: u16,
+        rules: RuleSet,
+    },
+    Ping {
+        nonce: u64,
+    },
+    Pong {
+        nonce: u64,
+    },
+    Join {
+        name: String,
+        game_port: u16,
+    },
+    Accept {
+        seed: u64,
+        side: Side,
+        rules: RuleSet,
+        game_port: u16,
+        host_name: String,
+    },
+    Reject {
+        reason: String,
+    },
+}
+
+// Where browsers listen by default
+pub fn default_listen_addr() -> SocketAddr {
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), DISCOVERY_PORT)
+}
+
+// Hosts announce to the broadcast address unless browsers listen on a specific interface
+pub fn announce_addr(listen: SocketAddr) -> SocketAddr {
+    if listen.ip().is_unspecified() {
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::BROADCAST), listen.port())
+    } else {
+        listen
+    }
+}
+
+fn send_message(socket: &UdpSocket, message: &DiscoveryMessage, to: SocketAddr) -> io::Result<()> {
+    let packet = serde_json::to_vec(message)?;
+    match socket.send_to(&packet, to) {
+        Ok(_) => Ok(()),
+        // Discovery is best-effort; an unreachable network behaves like a lost packet
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock
+                    | io::ErrorKind::ConnectionRefused
+                    | io::ErrorKind::NetworkUnreachable
+                    | io::ErrorKind::PermissionDenied
+            ) =>
+        {
+            Ok(())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+// Next well-formed message, skipping traffic from anything that is not a pong peer
+fn recv_message(socket: &UdpSocket) -> io::Result<Option<(DiscoveryMessage, SocketAddr)>> {
+    let mut buf = [0u8; MAX_PACKET_SIZE];
+    loop {
+        match socket.recv_from(&mut buf) {
+            Ok((len, from)) => {
+                if let Ok(message) = serde_json::from_slice(&buf[..len]) {
+                    return Ok(Some((message, from)));
+                }
+            }
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+            Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// The peer and settings of a match agreed through discovery
+#[derive(Clone, Debug, PartialEq)]
+pub struct LanMatch {
+    pub peer: SocketAddr,
+    pub peer_name: String,
+    pub seed: u64,
+    pub side: Side,
+    pub rules: RuleSet,
+}
+
+impl LanMatch {
+    // A rollback session for this match over `transport`, played under the host's rules
+    pub fn session<T: Transport>(&self, transport: T) -> RollbackSession<T> {
+        RollbackSession::with_rules(
+            self.seed,
+            self.side,
+            NetConfig::default(),
+            self.rules,
+            transport,
+        )
+    }
+}
+
+// Announces an open game and accepts the first player that asks to join
+pub struct LanHost {
+    socket: UdpSocket,
+    announce_to: SocketAddr,
+    host_name: String,
+    game_port: u16,
+    rules: RuleSet,
+    seed: u64,
+    last_announce: Option<Instant>,
+    guest: Option<SocketAddr>,
+}
+
+impl LanHost {
+    pub fn bind(
+        announce_to: SocketAddr,
+        host_name: &str,
+        game_port: u16,
+        rules: RuleSet,
+        seed: u64,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            announce_to,
+            host_name: host_name.to_owned(),
+            game_port,
+            rules,
+            seed,
+            last_announce: None,
+            guest: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    // Announce when due and answer browsers; returns the match once a player has joined.
+    // Keep polling after that so a joiner whose Accept was lost is answered again.
+    pub fn poll(&mut self) -> io::Result<Option<LanMatch>> {
+        let announce_due = self
+            .last_announce
+            .is_none_or(|at| at.elapsed() >= ANNOUNCE_INTERVAL);
+        if self.guest.is_none() && announce_due {
+            let announce = DiscoveryMessage::Announce {
+                version: DISCOVERY_VERSION,
+                host_name: self.host_name.clone(),
+                game_port: self.game_port,
+                rules: self.rules,
+            };
+            send_message(&self.socket, &announce, self.announce_to)?;
+            self.last_announce = Some(Instant::now());
+        }
+
+        let mut joined = None;
+        while let Some((message, from)) = recv_message(&self.socket)? {
+            match message {
+                DiscoveryMessage::Ping { nonce } => {
+                    send_message(&self.socket, &DiscoveryMessage::Pong { nonce }, from)?;
+                }
+                DiscoveryMessage::Join { name, game_port } => {
+                    let peer = SocketAddr::new(from.ip(), game_port);
+                    let reply = match self.guest {
+                        Some(guest) if guest != peer => DiscoveryMessage::Reject {
+                            reason: "game is full".to_owned(),
+                        },
+                        _ => {
+                            if self.guest.is_none() {
+                                self.guest = Some(peer);
+                                joined = Some(LanMatch {
+                                    peer,
+                                    peer_name: name,
+                                    seed: self.seed,
+                                    side: Side::Left,
+                                    rules: self.rules,
+                                });
+                            }
+                            DiscoveryMessage::Accept {
+                                seed: self.seed,
+                                side: Side::Right,
+                                rules: self.rules,
+                                game_port: self.game_port,
+                                host_name: self.host_name.clone(),
+                            }
+                        }
+                    };
+                    send_message(&self.socket, &reply, from)?;
+                }
+                _ => {}
+            }
+        }
+        Ok(joined)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscoveredGame {
+    // Where the host answers pings and join requests
+    pub addr: SocketAddr,
+    pub host_name: String,
+    pub game_port: u16,
+    pub rules: RuleSet,
+    pub ping: Option<Duration>,
+    pub last_seen: Instant,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BrowserEvent {
+    Accepted(LanMatch),
+    Rejected(String),
+}
+
+// Listens for announcements and keeps a list of open games
+pub struct LanBrowser {
+    socket: UdpSocket,
+    games: Vec<DiscoveredGame>,
+    pings: HashMap<u64, (SocketAddr, Instant)>,
+    next_nonce: u64,
+    last_ping: Option<Instant>,
+    // Host being joined, with our name and netplay port, resent until it answers
+    joining: Option<(SocketAddr, String, u16)>,
+}
+
+impl LanBrowser {
+    pub fn bind(listen: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(listen)?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            games: Vec::new(),
+            pings: HashMap::new(),
+            next_nonce: 0,
+            last_ping: None,
+            joining: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn games(&self) -> &[DiscoveredGame] {
+        &self.games
+    }
+
+    // Ask to join the game announced from `host`; the answer arrives through `poll`
+    pub fn join(&mut self, host: SocketAddr, name: &str, game_port: u16) -> io::Result<()> {
+        self.joining = Some((host, name.to_owned(), game_port));
+        self.send_join()
+    }
+
+    fn send_join(&self) -> io::Result<()> {
+        if let Some((host, name, game_port)) = &self.joining {
+            let join = DiscoveryMessage::Join {
+                name: name.clone(),
+                game_port: *game_port,
+            };
+            send_message(&self.socket, &join, *host)?;
+        }
+        Ok(())
+    }
+
+    pub fn poll(&mut self) -> io::Result<Option<BrowserEvent>> {
+        let mut event = None;
+        while let Some((message, from)) = recv_message(&self.socket)? {
+            let from_joined_host = self
+                .joining
+                .as_ref()
+                .is_some_and(|(host, ..)| *host == from);
+            match message {
+                DiscoveryMessage::Announce {
+                    version,
+                    host_name,
+                    game_port,
+                    rules,
+                } if version == DISCOVERY_VERSION => {
+                    let now = Instant::now();
+                    match self.games.iter_mut().find(|game| game.addr == from) {
+                        Some(game) => {
+                            game.host_name = host_name;
+                            game.game_port = game_port;
+                            game.rules = rules;
+                            game.last_seen = now;
+                        }
+                        None => self.games.push(DiscoveredGame {
+                            addr: from,
+                            host_name,
+                            game_port,
+                            rules,
+                            ping: None,
+                            last_seen: now,
+                        }),
+                    }
+                }
+                DiscoveryMessage::Pong { nonce } => {
+                    if let Some((host, sent)) = self.pings.remove(&nonce) {
+                        if let Some(game) = self.games.iter_mut().find(|game| game.addr == host) {
+                            game.ping = Some(sent.elapsed());
+                        }
+                    }
+                }
+                DiscoveryMessage::Accept {
+                    seed,
+                    side,
+                    rules,
+                    game_port,
+                    host_name,
+                } if from_joined_host => {
+                    self.joining = None;
+                    event = Some(BrowserEvent::Accepted(LanMatch {
+                        peer: SocketAddr::new(from.ip(), game_port),
+                        peer_name: host_name,
+                        seed,
+                        side,
+                        rules,
+                    }));
+                }
+                DiscoveryMessage::Reject { reason } if from_joined_host => {
+                    self.joining = None;
+                    event = Some(BrowserEvent::Rejected(reason));
+                }
+                _ => {}
+            }
+        }
+
+        self.games
+            .retain(|game| game.last_seen.elapsed() < GAME_TIMEOUT);
+
+        if self
+            .last_ping
+            .is_none_or(|at| at.elapsed() >= PING_INTERVAL)
+        {
+            self.last_ping = Some(Instant::now());
+            self.pings
+                .retain(|_, (_, sent)| sent.elapsed() < GAME_TIMEOUT);
+            for game in &self.games {
+                let nonce = self.next_nonce;
+                self.next_nonce += 1;
+                self.pings.insert(nonce, (game.addr, Instant::now()));
+                send_message(&self.socket, &DiscoveryMessage::Ping { nonce }, game.addr)?;
+            }
+            self.send_join()?;
+        }
+
+        Ok(event)
+    }
+}
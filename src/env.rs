@@ -0,0 +1,392 @@
+// Reinforcement-learning environment around the headless simulation
+// One agent controls a paddle through `step`, the opponent is driven by the built-in AI or
+// stands still, and each step advances the exact same `Game::step` the game itself runs.
+// Trainers in other languages can drive it through `serve_env`, a JSON lines protocol.
+
+use crate::netplay::TICK_DT;
+use crate::{
+    Game, GameState, PaddleCommand, RuleSet, Side, BALL_SIZE, WINDOW_HEIGHT, WINDOW_WIDTH,
+};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, Write};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Opponent {
+    #[default]
+    BuiltinAi,
+    Idle,
+}
+
+// Which values make up the observation vector, in this order
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ObservationConfig {
+    pub ball_position: bool,
+    pub ball_velocity: bool,
+    pub paddles: bool,
+    pub scores: bool,
+    // Scale positions and velocities to roughly -1..1
+    pub normalize: bool,
+    // Flip the court so the agent always appears to defend the left side
+    pub mirror: bool,
+}
+
+impl Default for ObservationConfig {
+    fn default() -> Self {
+        Self {
+            ball_position: true,
+            ball_velocity: true,
+            paddles: true,
+            scores: false,
+            normalize: true,
+            mirror: true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RewardConfig {
+    pub point_won: f32,
+    pub point_lost: f32,
+    pub paddle_hit: f32,
+    // Added every step, e.g. a small negative value to encourage short rallies
+    pub step: f32,
+    // Scaled by the vertical distance between paddle centre and ball, as a fraction of the court
+    pub tracking: f32,
+}
+
+impl Default for RewardConfig {
+    fn default() -> Self {
+        Self {
+            point_won: 1.0,
+            point_lost: -1.0,
+            paddle_hit: 0.0,
+            step: 0.0,
+            tracking: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnvConfig {
+    pub agent_side: Side,
+    pub opponent: Opponent,
+    pub rules: RuleSet,
+    pub observation: ObservationConfig,
+    pub reward: RewardConfig,
+    // Simulation ticks per step; the action is repeated for each of them
+    pub frame_skip: u32,
+    // Episodes are cut off after this many steps, 0 for no limit
+    pub max_steps: u32,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        Self {
+            agent_side: Side::Left,
+            opponent: Opponent::BuiltinAi,
+            rules: RuleSet::default(),
+            observation: ObservationConfig::default(),
+            reward: RewardConfig::default(),
+            frame_skip: 1,
+            max_steps: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct StepInfo {
+    pub steps: u32,
+    pub agent_score: i32,
+    pub opponent_score: i32,
+    pub rally_length: u32,
+    pub winner: Option<Side>,
+    // The episode ended because max_steps was reached rather than by winning
+    pub truncated: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Step {
+    pub observation: Vec<f32>,
+    pub reward: f32,
+    pub done: bool,
+    pub info: StepInfo,
+}
+
+pub struct PongEnv {
+    config: EnvConfig,
+    game: Game,
+    steps: u32,
+}
+
+impl PongEnv {
+    pub fn new(config: EnvConfig) -> Self {
+        let mut env = Self {
+            config,
+            game: Game::with_seed(0),
+            steps: 0,
+        };
+        env.reset(0);
+        env
+    }
+
+    pub fn config(&self) -> &EnvConfig {
+        &self.config
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    // Labels for each entry of the observation vector
+    pub fn observation_names(&self) -> Vec<&'static str> {
+        let observation = &self.config.observation;
+        let mut names = Vec::new();
+        if observation.ball_position {
+            names.extend(["ball_x", "ball_y"]);
+        }
+        if observation.ball_velocity {
+            names.extend(["ball_vx", "ball_vy"]);
+        }
+        if observation.paddles {
+            names.extend(["agent_paddle_y", "opponent_paddle_y"]);
+        }
+        if observation.scores {
+            names.extend(["agent_score", "opponent_score"]);
+        }
+        names
+    }
+
+    pub fn reset(&mut self, seed: u64) -> Vec<f32> {
+        let mut game = Game::with_seed(seed);
+        game.rules = self.config.rules;
+        game.ai_side = match self.config.opponent {
+            Opponent::BuiltinAi => Some(self.config.agent_side.opponent()),
+            Opponent::Idle => None,
+        };
+        game.reset();
+
+        self.game = game;
+        self.steps = 0;
+        self.observe()
+    }
+
+    pub fn observe(&self) -> Vec<f32> {
+        observe(&self.game, self.config.agent_side, &self.config.observation)
+    }
+
+    fn scores(&self) -> (i32, i32) {
+        match self.config.agent_side {
+            Side::Left => (self.game.player_score, self.game.ai_score),
+            Side::Right => (self.game.ai_score, self.game.player_score),
+        }
+    }
+
+    // Who won, once the match is over
+    fn winner(&self) -> Option<Side> {
+        if self.game.game_state == GameState::Playing {
+            return None;
+        }
+        let (agent_score, opponent_score) = self.scores();
+        let agent = self.config.agent_side;
+        Some(if agent_score > opponent_score {
+            agent
+        } else {
+            agent.opponent()
+        })
+    }
+
+    fn result(&self, reward: f32, truncated: bool) -> Step {
+        let winner = self.winner();
+        let (agent_score, opponent_score) = self.scores();
+        Step {
+            observation: self.observe(),
+            reward,
+            done: winner.is_some() || truncated,
+            info: StepInfo {
+                steps: self.steps,
+                agent_score,
+                opponent_score,
+                rally_length: self.game.rally_length,
+                winner,
+                truncated,
+            },
+        }
+    }
+
+    pub fn step(&mut self, action: PaddleCommand) -> Step {
+        // A finished match stays finished, with nothing more to earn, until the next reset
+        if self.game.game_state != GameState::Playing {
+            return self.result(0.0, false);
+        }
+
+        let agent = self.config.agent_side;
+        let reward_config = self.config.reward;
+        let mut reward = 0.0;
+
+        for _ in 0..self.config.frame_skip.max(1) {
+            let opponent = match self.config.opponent {
+                Opponent::BuiltinAi => self.game.ai_command(agent.opponent()),
+                Opponent::Idle => PaddleCommand::Stay,
+            };
+            let (left, right) = match agent {
+                Side::Left => (action, opponent),
+                Side::Right => (opponent, action),
+            };
+
+            let scores_before = self.scores();
+            let rally_before = self.game.rally_length;
+            let ball_x_velocity = self.game.ball.velocity.x;
+
+            self.game.step(TICK_DT, left, right);
+            // Nothing is ever drawn, so don't let effects accumulate
+            self.game.particles.clear();
+
+            let (agent_score, opponent_score) = self.scores();
+            reward += (agent_score - scores_before.0) as f32 * reward_config.point_won;
+            reward += (opponent_score - scores_before.1) as f32 * reward_config.point_lost;
+
+            // A new hit by the agent reverses the ball away from its side
+            let towards_agent = match agent {
+                Side::Left => ball_x_velocity < 0.0,
+                Side::Right => ball_x_velocity > 0.0,
+            };
+            if self.game.rally_length > rally_before && towards_agent {
+                reward += reward_config.paddle_hit;
+            }
+
+            if self.game.game_state != GameState::Playing {
+                break;
+            }
+        }
+
+        let distance = (self.game.paddle(agent).get_center_y()
+            - (self.game.ball.position.y + BALL_SIZE / 2.0))
+            .abs();
+        reward += reward_config.step - reward_config.tracking * distance / WINDOW_HEIGHT;
+
+        self.steps += 1;
+        let truncated = self.game.game_state == GameState::Playing
+            && self.config.max_steps > 0
+            && self.steps >= self.config.max_steps;
+        self.result(reward, truncated)
+    }
+}
+
+// The observation vector for the paddle on `agent`'s side
+pub fn observe(game: &Game, agent: Side, observation: &ObservationConfig) -> Vec<f32> {
+    let mirrored = observation.mirror && agent == Side::Right;
+
+    // Normalising centres the court on the origin so values fall in -1..1
+    let scale_x = |x: f32| {
+        if observation.normalize {
+            (x - WINDOW_WIDTH / 2.0) * 2.0 / WINDOW_WIDTH
+        } else {
+            x
+        }
+    };
+    let scale_y = |y: f32| {
+        if observation.normalize {
+            (y - WINDOW_HEIGHT / 2.0) * 2.0 / WINDOW_HEIGHT
+        } else {
+            y
+        }
+    };
+    let speed_scale = if observation.normalize {
+        1.0 / game.rules.ball_speed.max(1.0)
+    } else {
+        1.0
+    };
+    let flip = |value: f32| if mirrored { -value } else { value };
+
+    let mut values = Vec::with_capacity(8);
+    if observation.ball_position {
+        let x = game.ball.position.x + BALL_SIZE / 2.0;
+        values.push(scale_x(if mirrored { WINDOW_WIDTH - x } else { x }));
+        values.push(scale_y(game.ball.position.y + BALL_SIZE / 2.0));
+    }
+    if observation.ball_velocity {
+        values.push(flip(game.ball.velocity.x) * speed_scale);
+        values.push(game.ball.velocity.y * speed_scale);
+    }
+    if observation.paddles {
+        values.push(scale_y(game.paddle(agent).get_center_y()));
+        values.push(scale_y(game.paddle(agent.opponent()).get_center_y()));
+    }
+    if observation.scores {
+        let (agent_score, opponent_score) = match agent {
+            Side::Left => (game.player_score, game.ai_score),
+            Side::Right => (game.ai_score, game.player_score),
+        };
+        values.push(agent_score as f32);
+        values.push(opponent_score as f32);
+    }
+    values
+}
+
+// JSON lines protocol: one request per line on input, one response per line on output
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum EnvRequest {
+    Configure {
+        #[serde(default)]
+        config: EnvConfig,
+    },
+    Reset {
+        #[serde(default)]
+        seed: u64,
+    },
+    Step {
+        action: PaddleCommand,
+    },
+    Close,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EnvResponse {
+    Configured { observation_names: Vec<String> },
+    Reset { observation: Vec<f32> },
+    Step(Step),
+    Error { message: String },
+}
+
+pub fn serve_env<R: BufRead, W: Write>(reader: R, mut writer: W) -> io::Result<()> {
+    let mut env = PongEnv::new(EnvConfig::default());
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<EnvRequest>(&line) {
+            Ok(EnvRequest::Configure { config }) => {
+                env = PongEnv::new(config);
+                EnvResponse::Configured {
+                    observation_names: env
+                        .observation_names()
+                        .into_iter()
+                        .map(str::to_owned)
+                        .collect(),
+                }
+            }
+            Ok(EnvRequest::Reset { seed }) => EnvResponse::Reset {
+                observation: env.reset(seed),
+            },
+            Ok(EnvRequest::Step { action }) => EnvResponse::Step(env.step(action)),
+            Ok(EnvRequest::Close) => break,
+            Err(err) => EnvResponse::Error {
+                message: err.to_string(),
+            },
+        };
+
+        serde_json::to_writer(&mut writer, &response)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,82 @@
+// Game events: what the simulation did during a step
+// Physics only emits events into the queue; sound, particles, statistics, analytics and any
+// subscribed listeners react to them once the step's physics is done.
+
+use crate::{Side, Vec2D};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GameEvent {
+    // The ball was launched from the centre, away from `server`
+    Serve {
+        server: Side,
+        velocity: Vec2D,
+    },
+    // `contact` is where the ball met the paddle, from -1 (top) to 1 (bottom)
+    PaddleHit {
+        side: Side,
+        contact: f32,
+        position: Vec2D,
+        speed: f32,
+    },
+    WallHit {
+        position: Vec2D,
+    },
+    // `rally_length` counts the paddle hits of the rally that just ended
+    Goal {
+        scorer: Side,
+        position: Vec2D,
+        speed: f32,
+        rally_length: u32,
+    },
+    MatchEnd {
+        winner: Side,
+    },
+}
+
+// Anything that wants to hear about game events, such as a user plugin
+// `time` is the match time in seconds; listeners must be Send as servers run games on threads
+pub trait EventListener: Send {
+    fn on_event(&mut self, event: &GameEvent, time: f32);
+}
+
+// Events emitted by the latest step (or reset), in the order they happened
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EventQueue {
+    events: Vec<GameEvent>,
+}
+
+impl EventQueue {
+    pub fn push(&mut self, event: GameEvent) {
+        self.events.push(event);
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, GameEvent> {
+        self.events.iter()
+    }
+
+    pub fn as_slice(&self) -> &[GameEvent] {
+        &self.events
+    }
+}
+
+impl<'a> IntoIterator for &'a EventQueue {
+    type Item = &'a GameEvent;
+    type IntoIter = std::slice::Iter<'a, GameEvent>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.events.iter()
+    }
+}
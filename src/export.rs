@@ -0,0 +1,475 @@
+// Frame export: render a replay or a simulated match to PNG images or an animated GIF
+// Frames come from the software rasteriser, so exports work on machines with no display or GPU.
+// Frames are taken at a fixed rate of match time, whatever time steps the match was played at.
+
+use crate::bot::Controller;
+use crate::netplay::TICK_DT;
+use crate::raster::{self, Frame};
+use crate::replay::{default_replay_path, Replay, ReplayStep};
+use crate::sim::{PlayerSpec, SimConfig};
+use crate::theme::{self, Theme};
+use crate::{Game, GameState, RuleSet, Side};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const EXPORT_USAGE: &str = "Usage: pong-render [OPTIONS]
+
+Options:
+  --replay <FILE>        Render a recorded match; `last` is the last local match
+  -a, --player-a <SPEC>  Left player of a simulated match, as in pong-sim (default: ai:0.5)
+  -b, --player-b <SPEC>  Right player of a simulated match (default: ai:0.4)
+  --seed <N>             Seed of the simulated match (default: 0)
+  --winning-score <N>    Points needed to win the simulated match (default: 5)
+  --max-ticks <N>        Stop the simulated match after this many ticks (default: 36000)
+  --bot-budget <MS>      Time a bot has to answer each tick (default: 10)
+  --from <SECONDS>       Match time of the first frame (default: 0)
+  --duration <SECONDS>   Match time to render (default: until the match ends)
+  --fps <N>              Frames per second of match time (default: 25)
+  --scale <FACTOR>       Image size as a multiple of 800x600 (default: 1, or 0.5 for GIFs)
+  --theme <NAME|FILE>    Colours, as for pong --theme (default: classic)
+  -o, --output <PATH>    A .gif file, a .png file for the single frame at --from, or a
+                         directory for numbered PNG frames (default: frames)
+  -h, --help             Print this help";
+
+// Frame delays in GIFs are whole hundredths of a second
+pub const MAX_FPS: u32 = 50;
+pub const MAX_SCALE: f32 = 4.0;
+// How hard the GIF encoder works on each frame's palette, from 1 (best) to 30 (fastest)
+const GIF_QUANTIZE_SPEED: i32 = 10;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExportSource {
+    Replay(PathBuf),
+    Simulated {
+        left: PlayerSpec,
+        right: PlayerSpec,
+        seed: u64,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    // Numbered frames in a directory
+    PngSequence,
+    Png,
+    Gif,
+}
+
+impl ExportFormat {
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("gif") => ExportFormat::Gif,
+            Some(extension) if extension.eq_ignore_ascii_case("png") => ExportFormat::Png,
+            _ => ExportFormat::PngSequence,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportOptions {
+    pub source: ExportSource,
+    pub rules: RuleSet,
+    pub max_ticks: u32,
+    pub bot_budget: Duration,
+    pub from: f32,
+    pub duration: Option<f32>,
+    pub fps: u32,
+    pub scale: Option<f32>,
+    pub theme: Option<String>,
+    pub output: PathBuf,
+    pub help: bool,
+}
+
+// Simulated matches default to pong-sim's players and limits
+impl Default for ExportOptions {
+    fn default() -> Self {
+        let sim = SimConfig::default();
+        Self {
+            source: ExportSource::Simulated {
+                left: sim.player_a,
+                right: sim.player_b,
+                seed: sim.seed,
+            },
+            rules: sim.rules,
+            max_ticks: sim.max_ticks,
+            bot_budget: sim.bot_budget,
+            from: 0.0,
+            duration: None,
+            fps: 25,
+            scale: None,
+            theme: None,
+            output: PathBuf::from("frames"),
+            help: false,
+        }
+    }
+}
+
+impl ExportOptions {
+    pub fn format(&self) -> ExportFormat {
+        ExportFormat::for_path(&self.output)
+    }
+
+    // GIFs default to half size to keep files small
+    pub fn scale(&self) -> f32 {
+        self.scale.unwrap_or(match self.format() {
+            ExportFormat::Gif => 0.5,
+            _ => 1.0,
+        })
+    }
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} expects a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+pub fn parse_export_args<I: IntoIterator<Item = String>>(args: I) -> Result<ExportOptions, String> {
+    let mut options = ExportOptions::default();
+    let sim = SimConfig::default();
+    let (mut left, mut right, mut seed) = (sim.player_a, sim.player_b, sim.seed);
+    let mut replay: Option<PathBuf> = None;
+    let mut simulation_flag_used = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--replay" => {
+                let path: String = parse_value("--replay", args.next())?;
+                replay = Some(match path.as_str() {
+                    "last" => default_replay_path(),
+                    _ => PathBuf::from(path),
+                });
+            }
+            "-a" | "--player-a" => {
+                left = args.next().ok_or("--player-a expects a value")?.parse()?;
+                simulation_flag_used = true;
+            }
+            "-b" | "--player-b" => {
+                right = args.next().ok_or("--player-b expects a value")?.parse()?;
+                simulation_flag_used = true;
+            }
+            "--seed" => {
+                seed = parse_value("--seed", args.next())?;
+                simulation_flag_used = true;
+            }
+            "--winning-score" => {
+                options.rules.winning_score = parse_value("--winning-score", args.next())?;
+                simulation_flag_used = true;
+            }
+            "--max-ticks" => {
+                options.max_ticks = parse_value("--max-ticks", args.next())?;
+                simulation_flag_used = true;
+            }
+            "--bot-budget" => {
+                options.bot_budget =
+                    Duration::from_millis(parse_value("--bot-budget", args.next())?);
+                simulation_flag_used = true;
+            }
+            "--from" => options.from = parse_value("--from", args.next())?,
+            "--duration" => options.duration = Some(parse_value("--duration", args.next())?),
+            "--fps" => options.fps = parse_value("--fps", args.next())?,
+            "--scale" => options.scale = Some(parse_value("--scale", args.next())?),
+            "--theme" => options.theme = Some(parse_value("--theme", args.next())?),
+            "-o" | "--output" => options.output = parse_value("--output", args.next())?,
+            "-h" | "--help" => options.help = true,
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    options.source = match replay {
+        Some(_) if simulation_flag_used => {
+            return Err("--replay cannot be combined with simulated match options".to_owned());
+        }
+        Some(path) => ExportSource::Replay(path),
+        None => ExportSource::Simulated { left, right, seed },
+    };
+    if options.fps == 0 || options.fps > MAX_FPS {
+        return Err(format!("--fps must be between 1 and {}", MAX_FPS));
+    }
+    if options
+        .scale
+        .is_some_and(|scale| scale.is_nan() || scale <= 0.0 || scale > MAX_SCALE)
+    {
+        return Err(format!("--scale must be above 0 and at most {}", MAX_SCALE));
+    }
+    if options.from.is_nan() || options.from < 0.0 {
+        return Err("--from must not be negative".to_owned());
+    }
+    if options
+        .duration
+        .is_some_and(|duration| duration.is_nan() || duration <= 0.0)
+    {
+        return Err("--duration must be positive".to_owned());
+    }
+    if options.rules.winning_score < 1 {
+        return Err("--winning-score must be at least 1".to_owned());
+    }
+
+    Ok(options)
+}
+
+enum Steps {
+    Replay {
+        steps: Vec<ReplayStep>,
+        next: usize,
+    },
+    Simulated {
+        left: Box<dyn Controller>,
+        right: Box<dyn Controller>,
+        ticks_left: u32,
+    },
+}
+
+// Steps a game through a replay, or through a match played by two controllers
+pub struct Playback {
+    pub game: Game,
+    // Match time played so far, in seconds
+    pub time: f32,
+    steps: Steps,
+}
+
+impl Playback {
+    pub fn replay(replay: &Replay) -> Self {
+        Self {
+            game: replay.game(),
+            time: 0.0,
+            steps: Steps::Replay {
+                steps: replay.steps.clone(),
+                next: 0,
+            },
+        }
+    }
+
+    // A match at the fixed tick rate, set up as pong-sim sets up its matches
+    pub fn simulate(
+        left: &PlayerSpec,
+        right: &PlayerSpec,
+        seed: u64,
+        rules: RuleSet,
+        max_ticks: u32,
+        bot_budget: Duration,
+    ) -> io::Result<Self> {
+        let mut game = Game::with_seed(seed);
+        game.rules = rules;
+        game.ai_side = None;
+        game.reset();
+        Ok(Self {
+            game,
+            time: 0.0,
+            steps: Steps::Simulated {
+                left: left.controller(seed, bot_budget)?,
+                right: right.controller(seed ^ 0x5eed, bot_budget)?,
+                ticks_left: max_ticks,
+            },
+        })
+    }
+
+    pub fn from_options(options: &ExportOptions) -> io::Result<Self> {
+        match &options.source {
+            ExportSource::Replay(path) => match Replay::read_from(path) {
+                Ok(replay) => Ok(Self::replay(&replay)),
+                Err(err) => Err(io::Error::new(
+                    err.kind(),
+                    format!("failed to read {}: {}", path.display(), err),
+                )),
+            },
+            ExportSource::Simulated { left, right, seed } => Self::simulate(
+                left,
+                right,
+                *seed,
+                options.rules,
+                options.max_ticks,
+                options.bot_budget,
+            ),
+        }
+    }
+
+    // Play one step; false once the match is over or the replay has run out
+    pub fn advance(&mut self) -> bool {
+        if self.game.game_state != GameState::Playing {
+            return false;
+        }
+        match &mut self.steps {
+            Steps::Replay { steps, next } => {
+                let Some(step) = steps.get(*next) else {
+                    return false;
+                };
+                *next += 1;
+                self.game.step(step.dt, step.left, step.right);
+                self.time += step.dt;
+            }
+            Steps::Simulated {
+                left,
+                right,
+                ticks_left,
+            } => {
+                if *ticks_left == 0 {
+                    return false;
+                }
+                *ticks_left -= 1;
+                let left = left.command(&self.game, Side::Left);
+                let right = right.command(&self.game, Side::Right);
+                self.game.step(TICK_DT, left, right);
+                self.time += TICK_DT;
+            }
+        }
+        true
+    }
+}
+
+// Render a frame every 1/fps seconds of match time from `from`, handing each to `sink`, and a
+// last one showing how the match ended; returns how many were rendered
+pub fn render_frames(
+    playback: &mut Playback,
+    theme: &Theme,
+    options: &ExportOptions,
+    mut sink: impl FnMut(Frame) -> io::Result<()>,
+) -> io::Result<u32> {
+    let scale = options.scale();
+    let interval = 1.0 / options.fps as f32;
+    let end = match options.format() {
+        ExportFormat::Png => Some(options.from),
+        _ => options.duration.map(|duration| options.from + duration),
+    };
+    // Allow for rounding in the summed time steps
+    let slack = 1e-4;
+    let in_range = |time: f32| end.is_none_or(|end| time <= end + slack);
+
+    let mut next = options.from;
+    let mut rendered = 0;
+    let mut last_rendered = None;
+    loop {
+        while playback.time + slack >= next && in_range(next) {
+            sink(raster::render(&playback.game, theme, scale))?;
+            rendered += 1;
+            last_rendered = Some(playback.time);
+            next += interval;
+        }
+        if !in_range(next) {
+            break;
+        }
+        if !playback.advance() {
+            // The final step usually lands between frames
+            if playback.time >= options.from && last_rendered != Some(playback.time) {
+                sink(raster::render(&playback.game, theme, scale))?;
+                rendered += 1;
+            }
+            break;
+        }
+    }
+    Ok(rendered)
+}
+
+pub fn write_png(writer: impl Write, frame: &Frame) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, frame.width, frame.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&frame.pixels)?;
+    writer.finish()?;
+    Ok(())
+}
+
+fn gif_error(err: gif::EncodingError) -> io::Error {
+    match err {
+        gif::EncodingError::Io(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+// An animated GIF that loops forever; each frame gets its own palette
+pub struct GifWriter<W: Write> {
+    encoder: gif::Encoder<W>,
+    // Hundredths of a second each frame is shown for
+    delay: u16,
+}
+
+impl<W: Write> GifWriter<W> {
+    pub fn new(writer: W, width: u32, height: u32, fps: u32) -> io::Result<Self> {
+        let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "image too large for a GIF");
+        let width = u16::try_from(width).map_err(|_| too_large())?;
+        let height = u16::try_from(height).map_err(|_| too_large())?;
+        let mut encoder = gif::Encoder::new(writer, width, height, &[]).map_err(gif_error)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(gif_error)?;
+        Ok(Self {
+            encoder,
+            delay: (100.0 / fps as f32).round() as u16,
+        })
+    }
+
+    pub fn write(&mut self, frame: &Frame) -> io::Result<()> {
+        let mut gif_frame = gif::Frame::from_rgb_speed(
+            frame.width as u16,
+            frame.height as u16,
+            &frame.pixels,
+            GIF_QUANTIZE_SPEED,
+        );
+        gif_frame.delay = self.delay;
+        self.encoder.write_frame(&gif_frame).map_err(gif_error)
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        self.encoder.into_inner()
+    }
+}
+
+// Frame `index` of a PNG sequence
+pub fn sequence_path(directory: &Path, index: u32) -> PathBuf {
+    directory.join(format!("frame_{:05}.png", index))
+}
+
+// Render and write everything `options` asks for; returns how many frames were written
+pub fn export(options: &ExportOptions) -> io::Result<u32> {
+    let theme = match &options.theme {
+        Some(name) => theme::resolve(name)?,
+        None => Theme::CLASSIC,
+    };
+    let mut playback = Playback::from_options(options)?;
+    let output = &options.output;
+
+    let rendered = match options.format() {
+        ExportFormat::Gif => {
+            let (width, height) = raster::frame_size(options.scale());
+            let file = BufWriter::new(File::create(output)?);
+            let mut gif = GifWriter::new(file, width, height, options.fps)?;
+            let rendered =
+                render_frames(&mut playback, &theme, options, |frame| gif.write(&frame))?;
+            gif.finish()?.flush()?;
+            rendered
+        }
+        ExportFormat::Png => {
+            let mut last = None;
+            let rendered = render_frames(&mut playback, &theme, options, |frame| {
+                last = Some(frame);
+                Ok(())
+            })?;
+            if let Some(frame) = last {
+                write_png(BufWriter::new(File::create(output)?), &frame)?;
+            }
+            rendered.min(1)
+        }
+        ExportFormat::PngSequence => {
+            fs::create_dir_all(output)?;
+            let mut index = 0;
+            render_frames(&mut playback, &theme, options, |frame| {
+                let file = BufWriter::new(File::create(sequence_path(output, index))?);
+                index += 1;
+                write_png(file, &frame)
+            })?
+        }
+    };
+    if rendered == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the match ended before {}s", options.from),
+        ));
+    }
+    Ok(rendered)
+}
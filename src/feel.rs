@@ -0,0 +1,175 @@
+// Game feel: screen shake, hit-stop, paddle squash, goal flash and score pops
+// Effects are triggered by game events and only change how the match looks, except hit-stop,
+// which pauses local matches for a few frames. Each effect can be switched off in the settings
+// for players sensitive to motion.
+
+use crate::{GameEvent, Side, BALL_SPEED};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+
+// Largest shake, in logical pixels
+pub const SHAKE_DISTANCE: f32 = 12.0;
+// Shake lost per second, as a fraction of the strongest shake
+const SHAKE_DECAY: f32 = 1.6;
+// Shake added by a goal at the serve speed; faster balls shake harder
+const GOAL_SHAKE: f32 = 0.45;
+// Hits this far from the paddle centre are hard returns and pause the game briefly
+pub const HARD_CONTACT: f32 = 0.6;
+const HIT_STOP_TIME: f32 = 0.05;
+const MAX_HIT_STOP_TIME: f32 = 0.12;
+const SQUASH_TIME: f32 = 0.15;
+const FLASH_TIME: f32 = 0.3;
+const POP_TIME: f32 = 0.4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeelSettings {
+    pub screen_shake: bool,
+    pub hit_stop: bool,
+    pub squash_stretch: bool,
+    pub goal_flash: bool,
+    pub score_pop: bool,
+}
+
+impl FeelSettings {
+    pub const ALL: FeelSettings = FeelSettings {
+        screen_shake: true,
+        hit_stop: true,
+        squash_stretch: true,
+        goal_flash: true,
+        score_pop: true,
+    };
+
+    // What --reduced-motion uses
+    pub const OFF: FeelSettings = FeelSettings {
+        screen_shake: false,
+        hit_stop: false,
+        squash_stretch: false,
+        goal_flash: false,
+        score_pop: false,
+    };
+}
+
+impl Default for FeelSettings {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+static SETTINGS: RwLock<FeelSettings> = RwLock::new(FeelSettings::ALL);
+
+pub fn settings() -> FeelSettings {
+    *SETTINGS.read().unwrap_or_else(|err| err.into_inner())
+}
+
+pub fn set_settings(settings: FeelSettings) {
+    *SETTINGS.write().unwrap_or_else(|err| err.into_inner()) = settings;
+}
+
+fn side_index(side: Side) -> usize {
+    match side {
+        Side::Left => 0,
+        Side::Right => 1,
+    }
+}
+
+// The running effects of one game; timers count down in seconds
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Feel {
+    // Shake strength from 0 to 1; the offset grows with its square so small shakes stay subtle
+    pub trauma: f32,
+    pub hit_stop: f32,
+    pub squash: [f32; 2],
+    pub flash: f32,
+    pub flash_side: Option<Side>,
+    pub score_pop: [f32; 2],
+    // Drives the shake pattern
+    time: f32,
+}
+
+impl Feel {
+    // Start the effects an event calls for; `ball_speed` is the speed of the ball in play
+    pub fn on_event(&mut self, event: &GameEvent, ball_speed: f32, settings: &FeelSettings) {
+        let speed_factor = ball_speed / BALL_SPEED;
+        match *event {
+            GameEvent::PaddleHit { side, contact, .. } => {
+                if settings.squash_stretch {
+                    self.squash[side_index(side)] = SQUASH_TIME;
+                }
+                if settings.hit_stop && contact.abs() >= HARD_CONTACT {
+                    self.hit_stop = (HIT_STOP_TIME * speed_factor).min(MAX_HIT_STOP_TIME);
+                }
+            }
+            GameEvent::Goal { scorer, .. } => {
+                if settings.screen_shake {
+                    self.trauma = (self.trauma + GOAL_SHAKE * speed_factor).min(1.0);
+                }
+                if settings.goal_flash {
+                    self.flash = FLASH_TIME;
+                    self.flash_side = Some(scorer);
+                }
+                if settings.score_pop {
+                    self.score_pop[side_index(scorer)] = POP_TIME;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Use up hit-stop time; returns whether the game should stay paused this frame
+    pub fn hold(&mut self, dt: f32) -> bool {
+        if self.hit_stop <= 0.0 {
+            return false;
+        }
+        self.hit_stop = (self.hit_stop - dt).max(0.0);
+        true
+    }
+
+    // Let the effects run out while the match is stopped, such as after the winning goal
+    pub fn settle(&mut self, dt: f32) {
+        self.hit_stop = 0.0;
+        self.update(dt);
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.time += dt;
+        self.trauma = (self.trauma - SHAKE_DECAY * dt).max(0.0);
+        self.flash = (self.flash - dt).max(0.0);
+        if self.flash == 0.0 {
+            self.flash_side = None;
+        }
+        for timer in self.squash.iter_mut().chain(&mut self.score_pop) {
+            *timer = (*timer - dt).max(0.0);
+        }
+    }
+
+    // How far to move the court this frame
+    pub fn shake_offset(&self) -> Vec2 {
+        if self.trauma <= 0.0 {
+            return Vec2::ZERO;
+        }
+        let t = self.time;
+        let wobble = vec2(
+            (t * 53.0).sin() + 0.5 * (t * 31.0).sin(),
+            (t * 47.0).cos() + 0.5 * (t * 29.0).cos(),
+        ) / 1.5;
+        wobble * self.trauma * self.trauma * SHAKE_DISTANCE
+    }
+
+    // From 0 (at rest) to 1 (just hit)
+    pub fn squash(&self, side: Side) -> f32 {
+        self.squash[side_index(side)] / SQUASH_TIME
+    }
+
+    // From 0 (gone) to 1 (just scored)
+    pub fn flash_strength(&self) -> f32 {
+        self.flash / FLASH_TIME
+    }
+
+    // Scale of a score, briefly larger after it changes
+    pub fn score_scale(&self, side: Side) -> f32 {
+        let pop = self.score_pop[side_index(side)] / POP_TIME;
+        1.0 + 0.6 * pop * pop
+    }
+}
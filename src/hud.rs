@@ -0,0 +1,245 @@
+// HUD layout: where scores, hints, clocks and banners go on the court
+// Text is placed from its measured size and an anchor instead of hand-tuned offsets, so it stays
+// where it belongs whatever the string or font. Text is never drawn smaller on screen than
+// MIN_TEXT_PIXELS, keeping hints readable when the window is small.
+
+use crate::text::{self, Align};
+use crate::view::Viewport;
+use crate::{Side, WINDOW_HEIGHT, WINDOW_WIDTH};
+use macroquad::prelude::*;
+
+pub const MARGIN: f32 = 20.0;
+// Left edge of headings and lists on menu screens
+pub const MENU_INDENT: f32 = 40.0;
+// How far list rows sit in from a menu's headings
+pub const LIST_INDENT: f32 = 20.0;
+// Space between stacked lines of text, and between the columns of a table
+pub const LINE_GAP: f32 = 14.0;
+pub const COLUMN_GAP: f32 = 40.0;
+pub const MIN_TEXT_PIXELS: f32 = 9.0;
+pub const SCORE_SIZE: f32 = 48.0;
+pub const HEADING_SIZE: f32 = 36.0;
+// The message on waiting and connecting screens
+pub const STATUS_SIZE: f32 = 24.0;
+pub const HINT_SIZE: f32 = 20.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Layout {
+    pub width: f32,
+    pub height: f32,
+    pub margin: f32,
+    // Window points per logical unit
+    pub scale: f32,
+}
+
+impl Layout {
+    pub fn new(viewport: &Viewport) -> Self {
+        Self {
+            width: WINDOW_WIDTH,
+            height: WINDOW_HEIGHT,
+            margin: MARGIN,
+            scale: viewport.scale,
+        }
+    }
+
+    pub fn current() -> Self {
+        Self::new(&Viewport::current())
+    }
+
+    // `size`, grown if needed so it is at least MIN_TEXT_PIXELS on screen
+    pub fn text_size(&self, size: f32) -> f32 {
+        size.max(MIN_TEXT_PIXELS / self.scale)
+    }
+
+    // Where to draw text of size `dimensions` so its box sits at `anchor`, inside the margin
+    // Returns the start of the baseline, as draw_text expects
+    pub fn place(&self, anchor: Anchor, dimensions: TextDimensions, offset: Vec2) -> Vec2 {
+        let x = match anchor {
+            Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => self.margin,
+            Anchor::Top | Anchor::Center | Anchor::Bottom => (self.width - dimensions.width) / 2.0,
+            Anchor::TopRight | Anchor::Right | Anchor::BottomRight => {
+                self.width - self.margin - dimensions.width
+            }
+        };
+        let top = match anchor {
+            Anchor::TopLeft | Anchor::Top | Anchor::TopRight => self.margin,
+            Anchor::Left | Anchor::Center | Anchor::Right => {
+                (self.height - dimensions.height) / 2.0
+            }
+            Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight => {
+                self.height - self.margin - dimensions.height
+            }
+        };
+        vec2(x, top + dimensions.offset_y) + offset
+    }
+
+    // Draw text at an anchor; returns the box it covers
+    pub fn text(&self, text: &str, anchor: Anchor, offset: Vec2, size: f32, color: Color) -> Rect {
+        let size = self.text_size(size);
+        let dimensions = text::measure(text, size);
+        let baseline = self.place(anchor, dimensions, offset);
+        text::draw_text(text, baseline.x, baseline.y, size, color);
+        Rect::new(
+            baseline.x,
+            baseline.y - dimensions.offset_y,
+            dimensions.width,
+            dimensions.height,
+        )
+    }
+
+    // Text centred on the court `offset_y` below its middle
+    pub fn centered(&self, text: &str, offset_y: f32, size: f32, color: Color) -> Rect {
+        self.text(text, Anchor::Center, vec2(0.0, offset_y), size, color)
+    }
+
+    // Height of a line of text, the same for every string so stacked lines stay evenly spaced
+    pub fn line_height(&self, size: f32) -> f32 {
+        text::measure("M", self.text_size(size)).height
+    }
+
+    // The box of a line of text with its top at `top`, aligned on `x` as `align` says
+    pub fn line_box(&self, text: &str, x: f32, top: f32, size: f32, align: Align) -> Rect {
+        let size = self.text_size(size);
+        let left = text::aligned_x(text, x, size, align);
+        let width = text::measure(text, size).width;
+        Rect::new(left, top, width, self.line_height(size))
+    }
+
+    // Draw a line of text placed as line_box places it; returns its box
+    pub fn line(
+        &self,
+        text: &str,
+        x: f32,
+        top: f32,
+        size: f32,
+        color: Color,
+        align: Align,
+    ) -> Rect {
+        let rect = self.line_box(text, x, top, size, align);
+        let size = self.text_size(size);
+        let ascent = text::measure("M", size).offset_y;
+        text::draw_text(text, rect.x, rect.y + ascent, size, color);
+        rect
+    }
+
+    // Left-aligned text LINE_GAP under `above`
+    pub fn below(&self, above: Rect, text: &str, size: f32, color: Color) -> Rect {
+        self.line(
+            text,
+            above.x,
+            above.bottom() + LINE_GAP,
+            size,
+            color,
+            Align::Left,
+        )
+    }
+
+    // The first line of a menu or status screen, at its top left
+    pub fn heading(&self, text: &str, size: f32, color: Color) -> Rect {
+        let offset = vec2(MENU_INDENT - self.margin, self.margin);
+        self.text(text, Anchor::TopLeft, offset, size, color)
+    }
+
+    // A menu list row LINE_GAP under `above`, indented from the headings
+    pub fn row(&self, above: Rect, text: &str, size: f32, color: Color) -> Rect {
+        let x = MENU_INDENT + LIST_INDENT;
+        self.line(text, x, above.bottom() + LINE_GAP, size, color, Align::Left)
+    }
+
+    // The width of the widest of `texts`, for lining up a column
+    pub fn column_width<'a>(&self, texts: impl IntoIterator<Item = &'a str>, size: f32) -> f32 {
+        let size = self.text_size(size);
+        texts
+            .into_iter()
+            .map(|text| text::measure(text, size).width)
+            .fold(0.0, f32::max)
+    }
+
+    // Offset from a bottom anchor up to the `lines`th line of hints above the instructions
+    pub fn lines_up(&self, lines: usize) -> Vec2 {
+        vec2(
+            0.0,
+            -(self.line_height(HINT_SIZE) + LINE_GAP) * lines as f32,
+        )
+    }
+
+    // Both scores, centred over each half; `pops` scales each one around its centre
+    pub fn scoreboard(&self, scores: [i32; 2], pops: [f32; 2], colors: [Color; 2]) {
+        for ((score, baseline, size), color) in
+            self.score_placements(scores, pops).into_iter().zip(colors)
+        {
+            text::draw_text(&score, baseline.x, baseline.y, size, color);
+        }
+    }
+
+    // Each score's text, baseline and size, as the scoreboard draws them
+    pub fn score_placements(&self, scores: [i32; 2], pops: [f32; 2]) -> [(String, Vec2, f32); 2] {
+        let base = text::measure("0", SCORE_SIZE);
+        let middle = self.margin + base.height;
+        [(0, Side::Left), (1, Side::Right)].map(|(i, side)| {
+            let score = scores[i].to_string();
+            let size = SCORE_SIZE * pops[i];
+            let dimensions = text::measure(&score, size);
+            let center_x = match side {
+                Side::Left => self.width / 4.0,
+                Side::Right => 3.0 * self.width / 4.0,
+            };
+            let baseline = vec2(
+                center_x - dimensions.width / 2.0,
+                middle + base.height / 2.0 - dimensions.height / 2.0 + dimensions.offset_y,
+            );
+            (score, baseline, size)
+        })
+    }
+
+    // The match clock between the scores
+    pub fn clock(&self, seconds: f32, color: Color) -> Rect {
+        self.text(&format_clock(seconds), Anchor::Top, Vec2::ZERO, 18.0, color)
+    }
+
+    // Controls for the screen, centred along the bottom
+    pub fn instructions(&self, text: &str, color: Color) -> Rect {
+        self.text(text, Anchor::Bottom, Vec2::ZERO, HINT_SIZE, color)
+    }
+
+    // Keys for a menu screen, along the bottom under its list
+    pub fn footer(&self, text: &str, color: Color) -> Rect {
+        let offset = vec2(MENU_INDENT - self.margin, 0.0);
+        self.text(text, Anchor::BottomLeft, offset, HINT_SIZE, color)
+    }
+
+    // Centred text on a strip across the court, such as the winner
+    pub fn banner(&self, text: &str, offset_y: f32, size: f32, color: Color, strip: Color) -> Rect {
+        let size = self.text_size(size);
+        let dimensions = text::measure(text, size);
+        let baseline = self.place(Anchor::Center, dimensions, vec2(0.0, offset_y));
+        let padding = dimensions.height / 2.0;
+        draw_rectangle(
+            0.0,
+            baseline.y - dimensions.offset_y - padding,
+            self.width,
+            dimensions.height + padding * 2.0,
+            strip,
+        );
+        self.centered(text, offset_y, size, color)
+    }
+}
+
+// Minutes and seconds, as "m:ss"
+pub fn format_clock(seconds: f32) -> String {
+    let total = seconds.max(0.0) as u32;
+    format!("{}:{:02}", total / 60, total % 60)
+}
@@ -0,0 +1,419 @@
+// Learned AI: a small feed-forward network evolved by a genetic algorithm
+// Networks see the same observation vector as the training environment and choose Up, Down
+// or Stay. Training runs headless self-play: every genome plays the best network found so
+// far and a handicapped built-in AI, and the fittest genomes survive, cross over and mutate.
+
+use crate::bot::{Controller, HandicappedAi};
+use crate::env::{observe, ObservationConfig};
+use crate::netplay::{TICK_DT, TICK_RATE};
+use crate::save::data_dir;
+use crate::{AiSettings, Game, GameState, PaddleCommand, RuleSet, Side};
+use ::rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+pub const WEIGHTS_VERSION: u32 = 1;
+pub const WEIGHTS_FILE_NAME: &str = "ai_weights.json";
+// Outputs are scored in this order
+const ACTIONS: [PaddleCommand; 3] = [PaddleCommand::Up, PaddleCommand::Down, PaddleCommand::Stay];
+
+pub fn default_weights_path() -> PathBuf {
+    data_dir().join(WEIGHTS_FILE_NAME)
+}
+
+fn observation_config() -> ObservationConfig {
+    ObservationConfig::default()
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Network {
+    // Neurons per layer, from inputs to outputs
+    pub layers: Vec<usize>,
+    // Each layer's weight matrix (row per output neuron) followed by its biases
+    pub weights: Vec<f32>,
+}
+
+impl Network {
+    pub fn parameter_count(layers: &[usize]) -> usize {
+        layers.windows(2).map(|pair| (pair[0] + 1) * pair[1]).sum()
+    }
+
+    pub fn random(layers: &[usize], rng: &mut impl Rng) -> Self {
+        let weights = (0..Self::parameter_count(layers))
+            .map(|_| rng.gen_range(-1.0..1.0))
+            .collect();
+        Self {
+            layers: layers.to_vec(),
+            weights,
+        }
+    }
+
+    // Default shape: the standard observation, one hidden layer, one output per action
+    pub fn default_layers(hidden: usize) -> Vec<usize> {
+        let inputs = observe(&Game::with_seed(0), Side::Left, &observation_config()).len();
+        vec![inputs, hidden, ACTIONS.len()]
+    }
+
+    // tanh on hidden layers, linear outputs
+    pub fn forward(&self, input: &[f32]) -> Vec<f32> {
+        let mut activations = input.to_vec();
+        let mut offset = 0;
+        let last = self.layers.len().saturating_sub(2);
+
+        for (index, pair) in self.layers.windows(2).enumerate() {
+            let (inputs, outputs) = (pair[0], pair[1]);
+            let matrix = &self.weights[offset..offset + inputs * outputs];
+            let biases = &self.weights[offset + inputs * outputs..offset + (inputs + 1) * outputs];
+            offset += (inputs + 1) * outputs;
+
+            activations = (0..outputs)
+                .map(|neuron| {
+                    let row = &matrix[neuron * inputs..(neuron + 1) * inputs];
+                    let sum = row
+                        .iter()
+                        .zip(&activations)
+                        .map(|(w, a)| w * a)
+                        .sum::<f32>()
+                        + biases[neuron];
+                    if index < last {
+                        sum.tanh()
+                    } else {
+                        sum
+                    }
+                })
+                .collect();
+        }
+        activations
+    }
+
+    pub fn decide(&self, game: &Game, side: Side) -> PaddleCommand {
+        let outputs = self.forward(&observe(game, side, &observation_config()));
+        let best =
+            outputs.iter().enumerate().fold(
+                0,
+                |best, (i, value)| if *value > outputs[best] { i } else { best },
+            );
+        ACTIONS[best]
+    }
+
+    pub fn to_json(&self, generation: u32, fitness: f32) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&WeightsFile {
+            version: WEIGHTS_VERSION,
+            generation,
+            fitness,
+            network: self.clone(),
+        })
+    }
+
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let file: WeightsFile = serde_json::from_str(json)?;
+        if file.version != WEIGHTS_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported weights version {}", file.version),
+            ));
+        }
+        let network = file.network;
+        if network.layers.len() < 2
+            || network.weights.len() != Self::parameter_count(&network.layers)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "weights do not match the network shape",
+            ));
+        }
+        let inputs = observe(&Game::with_seed(0), Side::Left, &observation_config()).len();
+        if network.layers[0] != inputs || network.layers[network.layers.len() - 1] != ACTIONS.len()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "network expects {} inputs and {} outputs",
+                    inputs,
+                    ACTIONS.len()
+                ),
+            ));
+        }
+        Ok(network)
+    }
+
+    pub fn save(&self, path: &Path, generation: u32, fitness: f32) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, self.to_json(generation, fitness)?)?;
+        fs::rename(&tmp_path, path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct WeightsFile {
+    version: u32,
+    generation: u32,
+    fitness: f32,
+    network: Network,
+}
+
+pub struct NeuralAi {
+    pub network: Network,
+}
+
+impl Controller for NeuralAi {
+    fn command(&mut self, game: &Game, side: Side) -> PaddleCommand {
+        self.network.decide(game, side)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrainConfig {
+    pub population: usize,
+    pub generations: u32,
+    pub hidden: usize,
+    // Matches each genome plays against each opponent per generation
+    pub matches: u32,
+    // Genomes copied unchanged into the next generation
+    pub elite: usize,
+    pub mutation_rate: f32,
+    pub mutation_strength: f32,
+    pub seed: u64,
+    pub threads: usize,
+    // Shorter matches keep generations quick
+    pub winning_score: i32,
+    pub max_ticks: u32,
+}
+
+impl Default for TrainConfig {
+    fn default() -> Self {
+        Self {
+            population: 48,
+            generations: 100,
+            hidden: 8,
+            matches: 2,
+            elite: 4,
+            mutation_rate: 0.15,
+            mutation_strength: 0.4,
+            seed: 0,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            winning_score: 3,
+            max_ticks: 90 * TICK_RATE,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GenerationReport {
+    pub generation: u32,
+    pub best_fitness: f32,
+    pub mean_fitness: f32,
+}
+
+// Fitness of `genome` over one match against `opponent`: points difference dominates,
+// returned balls break ties so early generations that never score can still improve
+fn play_match(
+    genome: &Network,
+    opponent: &mut dyn Controller,
+    side: Side,
+    config: &TrainConfig,
+    seed: u64,
+) -> f32 {
+    let mut game = Game::with_seed(seed);
+    game.rules = RuleSet {
+        winning_score: config.winning_score,
+        ..RuleSet::default()
+    };
+    game.ai_side = None;
+    game.reset();
+
+    let mut returns = 0;
+    for _ in 0..config.max_ticks {
+        if game.game_state != GameState::Playing {
+            break;
+        }
+        let own = genome.decide(&game, side);
+        let other = opponent.command(&game, side.opponent());
+        let (left, right) = match side {
+            Side::Left => (own, other),
+            Side::Right => (other, own),
+        };
+
+        let velocity_before = game.ball.velocity.x;
+        let rally = game.rally_length;
+        game.step(TICK_DT, left, right);
+        game.particles.clear();
+
+        // A hit that sends the ball away from our side
+        let towards_us = match side {
+            Side::Left => velocity_before < 0.0,
+            Side::Right => velocity_before > 0.0,
+        };
+        if towards_us && game.rally_length > rally {
+            returns += 1;
+        }
+    }
+
+    let (own_score, other_score) = match side {
+        Side::Left => (game.player_score, game.ai_score),
+        Side::Right => (game.ai_score, game.player_score),
+    };
+    (own_score - other_score) as f32 * 10.0 + returns as f32
+}
+
+pub struct Trainer {
+    config: TrainConfig,
+    rng: ChaCha8Rng,
+    population: Vec<Network>,
+    champion: Network,
+    champion_fitness: f32,
+    generation: u32,
+}
+
+impl Trainer {
+    pub fn new(config: TrainConfig) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(config.seed);
+        let layers = Network::default_layers(config.hidden);
+        let population: Vec<Network> = (0..config.population.max(2))
+            .map(|_| Network::random(&layers, &mut rng))
+            .collect();
+        let champion = population[0].clone();
+        Self {
+            config,
+            rng,
+            population,
+            champion,
+            champion_fitness: f32::NEG_INFINITY,
+            generation: 0,
+        }
+    }
+
+    pub fn champion(&self) -> &Network {
+        &self.champion
+    }
+
+    pub fn champion_fitness(&self) -> f32 {
+        self.champion_fitness
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    fn evaluate(&self, genome: &Network, index: usize) -> f32 {
+        let config = &self.config;
+        let base_seed = config
+            .seed
+            .wrapping_mul(0x9e37_79b9_7f4a_7c15)
+            .wrapping_add(u64::from(self.generation) << 32)
+            .wrapping_add(index as u64 * 64);
+        let mut total = 0.0;
+
+        for game in 0..config.matches {
+            // Both sides, against the best network so far and against a fallible built-in AI
+            for (round, side) in [Side::Left, Side::Right].into_iter().enumerate() {
+                let seed = base_seed + u64::from(game) * 4 + round as u64 * 2;
+                let mut champion = NeuralAi {
+                    network: self.champion.clone(),
+                };
+                total += play_match(genome, &mut champion, side, config, seed);
+
+                let settings = AiSettings {
+                    speed_factor: 0.5,
+                    ..AiSettings::default()
+                };
+                let mut ai = HandicappedAi::new(settings, seed);
+                total += play_match(genome, &mut ai, side, config, seed + 1);
+            }
+        }
+        total / (config.matches * 4) as f32
+    }
+
+    // Fitness of every genome, computed in parallel; results don't depend on thread count
+    fn evaluate_population(&self) -> Vec<f32> {
+        let next = AtomicUsize::new(0);
+        let fitness = Mutex::new(vec![0.0; self.population.len()]);
+
+        thread::scope(|scope| {
+            for _ in 0..self.config.threads.max(1) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(genome) = self.population.get(index) else {
+                        break;
+                    };
+                    let value = self.evaluate(genome, index);
+                    fitness.lock().unwrap()[index] = value;
+                });
+            }
+        });
+        fitness.into_inner().unwrap()
+    }
+
+    fn pick_parent<'a>(&mut self, ranked: &'a [(f32, Network)]) -> &'a Network {
+        // Tournament selection between three random genomes
+        let best = (0..3)
+            .map(|_| self.rng.gen_range(0..ranked.len()))
+            .min()
+            .unwrap_or(0);
+        &ranked[best].1
+    }
+
+    fn breed(&mut self, ranked: &[(f32, Network)]) -> Network {
+        let mother = self.pick_parent(ranked).clone();
+        let father = self.pick_parent(ranked);
+        let mut child = mother;
+        for (weight, other) in child.weights.iter_mut().zip(&father.weights) {
+            if self.rng.gen_bool(0.5) {
+                *weight = *other;
+            }
+            if self.rng.gen::<f32>() < self.config.mutation_rate {
+                *weight += self.rng.gen_range(-1.0..1.0) * self.config.mutation_strength;
+            }
+        }
+        child
+    }
+
+    // Evaluate the current population, then replace it with the next generation
+    pub fn step_generation(&mut self) -> GenerationReport {
+        let fitness = self.evaluate_population();
+        let mut ranked: Vec<(f32, Network)> = fitness
+            .iter()
+            .copied()
+            .zip(self.population.drain(..))
+            .collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let best_fitness = ranked[0].0;
+        let mean_fitness = fitness.iter().sum::<f32>() / fitness.len() as f32;
+        // Fitness is measured against the current champion, so the best genome of each
+        // generation takes over as the benchmark for the next one
+        self.champion = ranked[0].1.clone();
+        self.champion_fitness = best_fitness;
+
+        let elite = self.config.elite.min(ranked.len());
+        let mut next: Vec<Network> = ranked[..elite]
+            .iter()
+            .map(|(_, genome)| genome.clone())
+            .collect();
+        while next.len() < ranked.len() {
+            let child = self.breed(&ranked);
+            next.push(child);
+        }
+        self.population = next;
+
+        self.generation += 1;
+        GenerationReport {
+            generation: self.generation,
+            best_fitness,
+            mean_fitness,
+        }
+    }
+}
@@ -1,9 +1,49 @@
 // Pong Game Library
 // This exposes the core game components for testing and modular organization
 
-use macroquad::prelude::*;
+use ::rand::{Rng, SeedableRng};
 use macroquad::audio::{load_sound_from_bytes, play_sound_once, Sound};
-use ::rand::Rng;
+use macroquad::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+pub mod achievements;
+pub mod analytics;
+pub mod bot;
+pub mod cli;
+#[cfg(feature = "debug-overlay")]
+pub mod debug;
+pub mod discovery;
+pub mod env;
+pub mod events;
+pub mod export;
+pub mod feel;
+pub mod hud;
+pub mod learned;
+pub mod mods;
+pub mod netplay;
+pub mod particles;
+pub mod postfx;
+pub mod profile;
+pub mod raster;
+pub mod replay;
+pub mod save;
+pub mod script;
+pub mod server;
+pub mod settings;
+pub mod sim;
+pub mod spectator;
+pub mod stats;
+pub mod text;
+pub mod theme;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod view;
+
+pub use events::{EventListener, EventQueue, GameEvent};
+pub use particles::{Particle, ParticleSystem};
+pub use save::{default_save_path, SaveState};
 
 // Game Constants
 pub const WINDOW_WIDTH: f32 = 800.0;
@@ -13,8 +53,134 @@ pub const PADDLE_HEIGHT: f32 = 80.0;
 pub const PADDLE_SPEED: f32 = 300.0;
 pub const BALL_SIZE: f32 = 15.0;
 pub const BALL_SPEED: f32 = 350.0;
+// Default rule set speed-up: the ball keeps its serve speed, and rule sets that raise
+// BALL_SPEEDUP speed each return up to at most MAX_SPEEDUP times the serve speed
+pub const BALL_SPEEDUP: f32 = 1.0;
+pub const MAX_SPEEDUP: f32 = 2.0;
 pub const WINNING_SCORE: i32 = 5;
 
+// Rule set for a match
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RuleSet {
+    pub winning_score: i32,
+    pub ball_speed: f32,
+    pub paddle_speed: f32,
+    // Factor applied to the ball speed on every paddle hit, 1.0 for a constant speed
+    #[serde(default = "default_hit_speedup")]
+    pub hit_speedup: f32,
+    // The ball never goes faster than this many times `ball_speed`
+    #[serde(default = "default_max_speedup")]
+    pub max_speedup: f32,
+}
+
+fn default_hit_speedup() -> f32 {
+    BALL_SPEEDUP
+}
+
+fn default_max_speedup() -> f32 {
+    MAX_SPEEDUP
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self {
+            winning_score: WINNING_SCORE,
+            ball_speed: BALL_SPEED,
+            paddle_speed: PADDLE_SPEED,
+            hit_speedup: BALL_SPEEDUP,
+            max_speedup: MAX_SPEEDUP,
+        }
+    }
+}
+
+impl RuleSet {
+    // The fastest a rally can get: the serve speed, unless returns speed the ball up
+    pub fn top_speed(&self) -> f32 {
+        if self.hit_speedup > 1.0 {
+            self.ball_speed * self.max_speedup.max(1.0)
+        } else {
+            self.ball_speed
+        }
+    }
+}
+
+// Tuning for the computer paddle
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AiSettings {
+    pub speed_factor: f32,
+    pub dead_zone: f32,
+}
+
+impl Default for AiSettings {
+    fn default() -> Self {
+        Self {
+            speed_factor: 0.8, // Make AI slightly slower for fairness
+            dead_zone: 10.0,
+        }
+    }
+}
+
+// Named strengths for the computer paddle
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+    // A network evolved by `pong train`, which always moves at full speed
+    Learned,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 4] = [
+        Difficulty::Easy,
+        Difficulty::Normal,
+        Difficulty::Hard,
+        Difficulty::Learned,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+            Difficulty::Learned => "learned",
+        }
+    }
+
+    pub fn ai_settings(self) -> AiSettings {
+        let speed_factor = match self {
+            Difficulty::Easy => 0.6,
+            Difficulty::Normal => 0.8,
+            Difficulty::Hard | Difficulty::Learned => 1.0,
+        };
+        AiSettings {
+            speed_factor,
+            ..AiSettings::default()
+        }
+    }
+}
+
+impl std::str::FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "easy" => Ok(Difficulty::Easy),
+            "normal" => Ok(Difficulty::Normal),
+            "hard" => Ok(Difficulty::Hard),
+            "learned" => Ok(Difficulty::Learned),
+            _ => Err(format!(
+                "invalid difficulty: {} (expected easy, normal, hard or learned)",
+                value
+            )),
+        }
+    }
+}
+
 // Audio generation functions
 pub fn generate_paddle_hit_sound() -> Vec<u8> {
     let sample_rate = 44100u32;
@@ -118,8 +284,43 @@ fn create_wav_file(audio_data: &[u8], sample_rate: u32) -> Vec<u8> {
     wav_file
 }
 
+// Which side of the court a paddle defends
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    pub fn opponent(self) -> Self {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        }
+    }
+}
+
+// Per-tick movement request for a paddle, from a player, the AI or a remote peer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PaddleCommand {
+    Up,
+    Down,
+    #[default]
+    Stay,
+}
+
+impl PaddleCommand {
+    pub fn direction(self) -> f32 {
+        match self {
+            PaddleCommand::Up => -1.0,
+            PaddleCommand::Down => 1.0,
+            PaddleCommand::Stay => 0.0,
+        }
+    }
+}
+
 // Math utilities
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Vec2D {
     pub x: f32,
     pub y: f32,
@@ -151,53 +352,18 @@ impl Vec2D {
     }
 }
 
-// Particle system
-pub struct Particle {
-    pub position: Vec2D,
-    pub velocity: Vec2D,
-    pub lifetime: f32,
-    pub max_lifetime: f32,
-}
-
-impl Particle {
-    pub fn new(x: f32, y: f32) -> Self {
-        let mut rng = ::rand::thread_rng();
-        let angle = rng.gen::<f32>() * 2.0 * std::f32::consts::PI;
-        let speed = rng.gen_range(50.0..200.0);
-        let lifetime = rng.gen_range(0.5..2.0);
-
-        Self {
-            position: Vec2D::new(x, y),
-            velocity: Vec2D::new(angle.cos() * speed, angle.sin() * speed),
-            lifetime,
-            max_lifetime: lifetime,
-        }
-    }
-
-    pub fn update(&mut self, dt: f32) -> bool {
-        self.position.x += self.velocity.x * dt;
-        self.position.y += self.velocity.y * dt;
-        self.lifetime -= dt;
-
-        self.lifetime > 0.0
-    }
-
-    pub fn draw(&self) {
-        let alpha = self.lifetime / self.max_lifetime;
-        let size = 3.0 * alpha;
-        draw_circle(
-            self.position.x,
-            self.position.y,
-            size,
-            Color::new(1.0, 1.0, 1.0, alpha),
-        );
-    }
-}
-
 // Paddle entity
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Paddle {
     pub position: Vec2D,
     pub velocity: f32,
+    // PADDLE_HEIGHT unless a mod has resized it
+    #[serde(default = "default_paddle_height")]
+    pub height: f32,
+}
+
+fn default_paddle_height() -> f32 {
+    PADDLE_HEIGHT
 }
 
 impl Paddle {
@@ -205,77 +371,106 @@ impl Paddle {
         Self {
             position: Vec2D::new(x, y),
             velocity: 0.0,
+            height: PADDLE_HEIGHT,
         }
     }
 
+    // Change the height while keeping the paddle centred where it was
+    pub fn resize(&mut self, height: f32) {
+        let center = self.get_center_y();
+        self.height = height;
+        self.position.y = (center - height / 2.0).clamp(0.0, WINDOW_HEIGHT - height);
+    }
+
     pub fn update(&mut self, dt: f32) {
         self.position.y += self.velocity * dt;
 
         // Keep paddle within window bounds
-        if self.position.y < 0.0 {
-            self.position.y = 0.0;
-        } else if self.position.y > WINDOW_HEIGHT - PADDLE_HEIGHT {
-            self.position.y = WINDOW_HEIGHT - PADDLE_HEIGHT;
-        }
+        self.position.y = self.position.y.clamp(0.0, WINDOW_HEIGHT - self.height);
     }
 
-    pub fn draw(&self) {
+    pub fn draw(&self, color: Color) {
+        self.draw_squashed(color, 0.0);
+    }
+
+    // Squeezed sideways and stretched tall by `squash`, from 0 to 1, around the paddle's centre
+    pub fn draw_squashed(&self, color: Color, squash: f32) {
+        let Rect {
+            x,
+            y,
+            w: width,
+            h: height,
+        } = self.squashed_rect(squash);
+
         // Draw main paddle
-        draw_rectangle(
-            self.position.x,
-            self.position.y,
-            PADDLE_WIDTH,
-            PADDLE_HEIGHT,
-            WHITE,
-        );
+        draw_rectangle(x, y, width, height, color);
 
-        // Draw glow effect
+        // Stacked outlines fake a glow unless the bloom shader is running
+        if postfx::shaders_active() {
+            return;
+        }
         let glow_intensity = 0.3;
         for i in 1..=3 {
             let offset = i as f32 * 2.0;
             let alpha = glow_intensity / (i as f32);
             draw_rectangle_lines(
-                self.position.x - offset,
-                self.position.y - offset,
-                PADDLE_WIDTH + offset * 2.0,
-                PADDLE_HEIGHT + offset * 2.0,
+                x - offset,
+                y - offset,
+                width + offset * 2.0,
+                height + offset * 2.0,
                 2.0,
-                Color::new(1.0, 1.0, 1.0, alpha),
+                color.with_alpha(alpha),
             );
         }
     }
 
+    // Where draw_squashed puts the paddle
+    pub fn squashed_rect(&self, squash: f32) -> Rect {
+        let width = PADDLE_WIDTH * (1.0 - 0.4 * squash);
+        let height = self.height * (1.0 + 0.15 * squash);
+        let x = self.position.x + (PADDLE_WIDTH - width) / 2.0;
+        Rect::new(x, self.get_center_y() - height / 2.0, width, height)
+    }
+
     pub fn get_center_y(&self) -> f32 {
-        self.position.y + PADDLE_HEIGHT / 2.0
+        self.position.y + self.height / 2.0
     }
 
     pub fn get_rect(&self) -> Rect {
-        Rect::new(self.position.x, self.position.y, PADDLE_WIDTH, PADDLE_HEIGHT)
+        Rect::new(self.position.x, self.position.y, PADDLE_WIDTH, self.height)
     }
 }
 
 // Ball entity
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Ball {
     pub position: Vec2D,
     pub velocity: Vec2D,
+    pub speed: f32,
+}
+
+// Random serve angle, aimed at either side within 45 degrees of horizontal
+fn serve_angle(rng: &mut impl Rng) -> f32 {
+    if rng.gen::<bool>() {
+        rng.gen_range(-std::f32::consts::PI / 4.0..std::f32::consts::PI / 4.0)
+    } else {
+        rng.gen_range(3.0 * std::f32::consts::PI / 4.0..5.0 * std::f32::consts::PI / 4.0)
+    }
 }
 
 impl Ball {
     pub fn new(x: f32, y: f32) -> Self {
-        let mut rng = ::rand::thread_rng();
-        let angle = if rng.gen::<bool>() {
-            rng.gen_range(-std::f32::consts::PI / 4.0..std::f32::consts::PI / 4.0)
-        } else {
-            rng.gen_range(3.0 * std::f32::consts::PI / 4.0..5.0 * std::f32::consts::PI / 4.0)
-        };
+        let angle = serve_angle(&mut ::rand::thread_rng());
 
         Self {
             position: Vec2D::new(x, y),
             velocity: Vec2D::new(BALL_SPEED * angle.cos(), BALL_SPEED * angle.sin()),
+            speed: BALL_SPEED,
         }
     }
 
-    pub fn update(&mut self, dt: f32) {
+    // Returns whether the ball bounced off the top or bottom wall
+    pub fn update(&mut self, dt: f32) -> bool {
         self.position.x += self.velocity.x * dt;
         self.position.y += self.velocity.y * dt;
 
@@ -283,13 +478,31 @@ impl Ball {
         if self.position.y <= 0.0 || self.position.y >= WINDOW_HEIGHT - BALL_SIZE {
             self.velocity.y = -self.velocity.y;
             self.position.y = self.position.y.clamp(0.0, WINDOW_HEIGHT - BALL_SIZE);
+            return true;
         }
+        false
     }
 
-    pub fn draw(&self) {
-        draw_rectangle(self.position.x, self.position.y, BALL_SIZE, BALL_SIZE, WHITE);
+    pub fn center(&self) -> Vec2D {
+        Vec2D::new(
+            self.position.x + BALL_SIZE / 2.0,
+            self.position.y + BALL_SIZE / 2.0,
+        )
+    }
+
+    pub fn draw(&self, color: Color) {
+        draw_rectangle(
+            self.position.x,
+            self.position.y,
+            BALL_SIZE,
+            BALL_SIZE,
+            color,
+        );
 
-        // Draw glow effect
+        // Stacked outlines fake a glow unless the bloom shader is running
+        if postfx::shaders_active() {
+            return;
+        }
         let glow_intensity = 0.4;
         for i in 1..=3 {
             let offset = i as f32 * 1.5;
@@ -300,7 +513,7 @@ impl Ball {
                 BALL_SIZE + offset * 2.0,
                 BALL_SIZE + offset * 2.0,
                 1.0,
-                Color::new(1.0, 1.0, 1.0, alpha),
+                color.with_alpha(alpha),
             );
         }
     }
@@ -310,34 +523,34 @@ impl Ball {
     }
 
     pub fn reset(&mut self) {
-        self.position = Vec2D::new(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0);
+        self.serve(&mut ::rand::thread_rng());
+    }
 
-        let mut rng = ::rand::thread_rng();
-        let angle = if rng.gen::<bool>() {
-            rng.gen_range(-std::f32::consts::PI / 4.0..std::f32::consts::PI / 4.0)
-        } else {
-            rng.gen_range(3.0 * std::f32::consts::PI / 4.0..5.0 * std::f32::consts::PI / 4.0)
-        };
+    // Re-centre the ball and launch it using the given RNG
+    pub fn serve(&mut self, rng: &mut impl Rng) {
+        self.position = Vec2D::new(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0);
 
-        self.velocity = Vec2D::new(BALL_SPEED * angle.cos(), BALL_SPEED * angle.sin());
+        let angle = serve_angle(rng);
+        self.velocity = Vec2D::new(self.speed * angle.cos(), self.speed * angle.sin());
     }
 
-    pub fn handle_paddle_collision(&mut self, paddle: &Paddle) {
+    // Returns where the ball met the paddle, from -1 (top edge) to 1 (bottom edge)
+    pub fn handle_paddle_collision(&mut self, paddle: &Paddle) -> Option<f32> {
         let ball_rect = self.get_rect();
         let paddle_rect = paddle.get_rect();
 
         if ball_rect.overlaps(&paddle_rect) {
             // Calculate relative intersection point (between -1.0 and 1.0)
             let intersect_y = (self.position.y + BALL_SIZE / 2.0) - paddle.get_center_y();
-            let normalized_intersect = intersect_y / (PADDLE_HEIGHT / 2.0);
+            let normalized_intersect = intersect_y / (paddle.height / 2.0);
 
             // Calculate new angle based on intersection point
             let angle = normalized_intersect * std::f32::consts::PI / 4.0;
 
             // Reverse horizontal direction and apply angle
             let direction = if self.velocity.x > 0.0 { -1.0 } else { 1.0 };
-            self.velocity.x = BALL_SPEED * angle.cos() * direction;
-            self.velocity.y = BALL_SPEED * angle.sin();
+            self.velocity.x = self.speed * angle.cos() * direction;
+            self.velocity.y = self.speed * angle.sin();
 
             // Move ball away from paddle to prevent multiple collisions
             if direction < 0.0 {
@@ -345,13 +558,16 @@ impl Ball {
             } else {
                 self.position.x = paddle_rect.x + PADDLE_WIDTH;
             }
+            return Some(normalized_intersect);
         }
+        None
     }
 }
 
 // Game states
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum GameState {
+    Title,
     Playing,
     GameOver,
 }
@@ -364,161 +580,405 @@ pub struct Game {
     pub player_score: i32,
     pub ai_score: i32,
     pub game_state: GameState,
-    pub particles: Vec<Particle>,
+    pub particles: ParticleSystem,
     pub ball_trail: Vec<Vec2D>,
     pub paddle_hit_sound: Option<Sound>,
     pub wall_hit_sound: Option<Sound>,
     pub score_sound: Option<Sound>,
+    pub rules: RuleSet,
+    pub ai: AiSettings,
+    pub rng: ChaCha8Rng,
+    // What `rng` was seeded with when the game was built
+    pub seed: u64,
+    pub ai_side: Option<Side>,
+    // Plays the right paddle instead of the built-in AI when set
+    pub learned_ai: Option<learned::Network>,
+    // Paddle hits since the last serve
+    pub rally_length: u32,
+    // Steps played since the match started or was resumed
+    pub ticks: u64,
+    pub stats: stats::MatchStats,
+    // Result of the last statistics export, shown on the game over screen
+    pub stats_export: Option<String>,
+    pub analytics: analytics::MatchAnalytics,
+    // Game over screen shows the shot heatmaps instead of the summary table
+    pub show_heatmaps: bool,
+    // Events from the latest step, kept until the next one starts
+    pub events: EventQueue,
+    // Shake, hit-stop and other effects started by the events
+    pub feel: feel::Feel,
+    pub listeners: Vec<Box<dyn EventListener>>,
+    pub mods: Vec<Box<dyn mods::GameMod>>,
+    // Records every step while set
+    pub replay: Option<replay::Replay>,
+    pub save_path: PathBuf,
+    pub has_save: bool,
 }
 
 impl Game {
     pub async fn new() -> Self {
+        let mut game = Self::with_seed(::rand::thread_rng().gen());
+        game.load_sounds().await;
+
+        game.has_save = game.save_path.exists();
+        game.game_state = GameState::Title;
+        game
+    }
+
+    pub async fn load_sounds(&mut self) {
         // Load sounds asynchronously
-        let paddle_hit_sound = load_sound_from_bytes(&generate_paddle_hit_sound()).await.ok();
-        let wall_hit_sound = load_sound_from_bytes(&generate_wall_hit_sound()).await.ok();
-        let score_sound = load_sound_from_bytes(&generate_score_sound()).await.ok();
+        self.paddle_hit_sound = load_sound_from_bytes(&generate_paddle_hit_sound())
+            .await
+            .ok();
+        self.wall_hit_sound = load_sound_from_bytes(&generate_wall_hit_sound()).await.ok();
+        self.score_sound = load_sound_from_bytes(&generate_score_sound()).await.ok();
+    }
 
-        Self {
+    // Build a silent game whose serves are fully determined by `seed`
+    pub fn with_seed(seed: u64) -> Self {
+        let rules = RuleSet::default();
+        let mut ball = Ball::new(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0);
+        ball.speed = rules.ball_speed;
+
+        let mut game = Self {
             player_paddle: Paddle::new(30.0, WINDOW_HEIGHT / 2.0 - PADDLE_HEIGHT / 2.0),
             ai_paddle: Paddle::new(
                 WINDOW_WIDTH - 30.0 - PADDLE_WIDTH,
                 WINDOW_HEIGHT / 2.0 - PADDLE_HEIGHT / 2.0,
             ),
-            ball: Ball::new(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0),
+            ball,
             player_score: 0,
             ai_score: 0,
             game_state: GameState::Playing,
-            particles: Vec::new(),
+            particles: ParticleSystem::new(particles::PARTICLE_BUDGET, seed),
             ball_trail: Vec::new(),
-            paddle_hit_sound,
-            wall_hit_sound,
-            score_sound,
+            paddle_hit_sound: None,
+            wall_hit_sound: None,
+            score_sound: None,
+            rules,
+            ai: AiSettings::default(),
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            seed,
+            ai_side: Some(Side::Right),
+            learned_ai: None,
+            rally_length: 0,
+            ticks: 0,
+            stats: stats::MatchStats::default(),
+            stats_export: None,
+            analytics: analytics::MatchAnalytics::default(),
+            show_heatmaps: false,
+            events: EventQueue::default(),
+            feel: feel::Feel::default(),
+            listeners: Vec::new(),
+            mods: Vec::new(),
+            replay: None,
+            save_path: default_save_path(),
+            has_save: false,
+        };
+        game.serve();
+        game.dispatch_events();
+        game
+    }
+
+    pub fn paddle(&self, side: Side) -> &Paddle {
+        match side {
+            Side::Left => &self.player_paddle,
+            Side::Right => &self.ai_paddle,
+        }
+    }
+
+    pub fn paddle_mut(&mut self, side: Side) -> &mut Paddle {
+        match side {
+            Side::Left => &mut self.player_paddle,
+            Side::Right => &mut self.ai_paddle,
         }
     }
 
     pub fn update(&mut self, dt: f32) {
         if self.game_state != GameState::Playing {
+            self.feel.settle(dt);
             return;
         }
 
-        // Handle player input
-        self.player_paddle.velocity = 0.0;
-        if is_key_down(KeyCode::W) || is_key_down(KeyCode::Up) {
-            self.player_paddle.velocity = -PADDLE_SPEED;
+        // Hard returns freeze the match for a moment
+        if self.feel.hold(dt) {
+            return;
         }
-        if is_key_down(KeyCode::S) || is_key_down(KeyCode::Down) {
-            self.player_paddle.velocity = PADDLE_SPEED;
+
+        let player_command = keyboard_command();
+        let ai_command = self.opponent_command();
+        self.step(dt, player_command, ai_command);
+    }
+
+    // The computer's move for the right paddle: the learned network if loaded, else the simple AI
+    pub fn opponent_command(&self) -> PaddleCommand {
+        match &self.learned_ai {
+            Some(network) => network.decide(self, Side::Right),
+            None => self.ai_command(Side::Right),
         }
+    }
+
+    // Simple AI: chase the ball, resting inside a small dead zone
+    pub fn ai_command(&self, side: Side) -> PaddleCommand {
+        self.tracking_command(side, self.ai.dead_zone)
+    }
 
-        // Simple AI for the computer paddle
-        let ball_center_y = self.ball.position.y + BALL_SIZE / 2.0;
-        let ai_center_y = self.ai_paddle.get_center_y();
-        let ai_speed = PADDLE_SPEED * 0.8; // Make AI slightly slower for fairness
+    pub fn tracking_command(&self, side: Side, dead_zone: f32) -> PaddleCommand {
+        let ball_center_y = self.ai_target_y();
+        let ai_center_y = self.paddle(side).get_center_y();
 
-        if ball_center_y < ai_center_y - 10.0 {
-            self.ai_paddle.velocity = -ai_speed;
-        } else if ball_center_y > ai_center_y + 10.0 {
-            self.ai_paddle.velocity = ai_speed;
+        if ball_center_y < ai_center_y - dead_zone {
+            PaddleCommand::Up
+        } else if ball_center_y > ai_center_y + dead_zone {
+            PaddleCommand::Down
         } else {
-            self.ai_paddle.velocity = 0.0;
+            PaddleCommand::Stay
         }
+    }
 
-        // Update game objects
-        self.player_paddle.update(dt);
-        self.ai_paddle.update(dt);
+    // Height the simple AI moves its paddle's centre towards
+    pub fn ai_target_y(&self) -> f32 {
+        self.ball.position.y + BALL_SIZE / 2.0
+    }
 
-        // Check for wall collision before updating ball
-        let old_ball_y = self.ball.position.y;
-        self.ball.update(dt);
+    fn paddle_speed(&self, side: Side) -> f32 {
+        if self.ai_side == Some(side) {
+            self.rules.paddle_speed * self.ai.speed_factor
+        } else {
+            self.rules.paddle_speed
+        }
+    }
 
-        // Play wall hit sound if ball bounced off top or bottom
-        if (old_ball_y <= 0.0 || old_ball_y >= WINDOW_HEIGHT - BALL_SIZE) &&
-           (self.ball.position.y <= 0.0 || self.ball.position.y >= WINDOW_HEIGHT - BALL_SIZE) {
-            if let Some(sound) = &self.wall_hit_sound {
-                play_sound_once(sound);
-            }
+    // Advance the simulation by `dt` with the given paddle commands
+    // Gameplay state depends only on the current state, `dt` and the commands
+    pub fn step(&mut self, dt: f32, left: PaddleCommand, right: PaddleCommand) {
+        if self.game_state != GameState::Playing {
+            return;
+        }
+        if let Some(replay) = &mut self.replay {
+            replay.push(dt, left, right);
         }
+        self.ticks += 1;
 
-        // Handle paddle-ball collisions
-        let old_ball_velocity = self.ball.velocity;
-        self.ball.handle_paddle_collision(&self.player_paddle);
-        self.ball.handle_paddle_collision(&self.ai_paddle);
-
-        // Create particles on paddle hit
-        if (self.ball.velocity.x > 0.0) != (old_ball_velocity.x > 0.0) {
-            // Play paddle hit sound
-            if let Some(sound) = &self.paddle_hit_sound {
-                play_sound_once(sound);
-            }
+        self.player_paddle.velocity = left.direction() * self.paddle_speed(Side::Left);
+        self.ai_paddle.velocity = right.direction() * self.paddle_speed(Side::Right);
 
-            for _ in 0..8 {
-                self.particles.push(Particle::new(
-                    self.ball.position.x + BALL_SIZE / 2.0,
-                    self.ball.position.y + BALL_SIZE / 2.0,
-                ));
-            }
+        self.events.clear();
+
+        // Update game objects
+        self.player_paddle.update(dt);
+        self.ai_paddle.update(dt);
+        if self.ball.update(dt) {
+            self.events.push(GameEvent::WallHit {
+                position: self.ball.center(),
+            });
         }
 
-        // Update particles
-        self.particles.retain_mut(|particle| particle.update(dt));
+        // Handle paddle-ball collisions
+        let left_contact = self.ball.handle_paddle_collision(&self.player_paddle);
+        let right_contact = self.ball.handle_paddle_collision(&self.ai_paddle);
+        let hit = match (left_contact, right_contact) {
+            (Some(contact), _) => Some((Side::Left, contact)),
+            (None, Some(contact)) => Some((Side::Right, contact)),
+            (None, None) => None,
+        };
+        if let Some((side, contact)) = hit {
+            self.rally_length += 1;
+            self.speed_up_ball();
+            self.events.push(GameEvent::PaddleHit {
+                side,
+                contact,
+                position: self.ball.center(),
+                speed: self.ball.velocity.length(),
+            });
+        }
 
         // Update ball trail
-        self.ball_trail.push(Vec2D::new(
-            self.ball.position.x + BALL_SIZE / 2.0,
-            self.ball.position.y + BALL_SIZE / 2.0,
-        ));
+        self.ball_trail.push(self.ball.center());
         if self.ball_trail.len() > 10 {
             self.ball_trail.remove(0);
         }
 
         // Check for scoring
-        if self.ball.position.x < 0.0 {
-            self.ai_score += 1;
+        let scorer = if self.ball.position.x < 0.0 {
+            Some(Side::Right)
+        } else if self.ball.position.x > WINDOW_WIDTH {
+            Some(Side::Left)
+        } else {
+            None
+        };
+        if let Some(scorer) = scorer {
+            self.events.push(GameEvent::Goal {
+                scorer,
+                position: self.ball.center(),
+                speed: self.ball.velocity.length(),
+                rally_length: self.rally_length,
+            });
+            let score = match scorer {
+                Side::Left => &mut self.player_score,
+                Side::Right => &mut self.ai_score,
+            };
+            *score += 1;
+            let won = *score >= self.rules.winning_score;
 
-            // Play score sound
-            if let Some(sound) = &self.score_sound {
-                play_sound_once(sound);
+            // Every point is served at the rule set's speed
+            self.ball.speed = self.rules.ball_speed;
+            self.serve();
+            self.ball_trail.clear();
+            self.rally_length = 0;
+            if won {
+                self.end_match(scorer);
             }
+        }
 
-            // Create score particles
-            for _ in 0..15 {
-                self.particles.push(Particle::new(
-                    self.ball.position.x + BALL_SIZE / 2.0,
-                    self.ball.position.y + BALL_SIZE / 2.0,
-                ));
-            }
+        self.dispatch_events();
+        self.run_mods(dt);
+        self.stats.record_tick(dt, &self.ball);
 
-            self.ball.reset();
-            self.ball_trail.clear();
-            if self.ai_score >= WINNING_SCORE {
-                self.game_state = GameState::GameOver;
-            }
-        } else if self.ball.position.x > WINDOW_WIDTH {
-            self.player_score += 1;
+        // Update particles
+        self.particles.update(dt);
+        self.feel.update(dt);
+    }
+
+    // Returns get faster through a rally, keeping the ball's heading
+    fn speed_up_ball(&mut self) {
+        let limit = self.rules.top_speed();
+        if self.ball.speed < limit {
+            self.ball.speed = (self.ball.speed * self.rules.hit_speedup.max(1.0)).min(limit);
+        }
+        let heading = self.ball.velocity.normalize();
+        self.ball.velocity = Vec2D::new(heading.x * self.ball.speed, heading.y * self.ball.speed);
+    }
+
+    // Subscribe a listener to every event from now on
+    pub fn subscribe(&mut self, listener: Box<dyn EventListener>) {
+        self.listeners.push(listener);
+    }
+
+    // Add a mod after the ones already enabled; its rule changes apply from the next reset
+    pub fn add_mod(&mut self, game_mod: Box<dyn mods::GameMod>) {
+        game_mod.modify_config(&mut self.rules);
+        self.mods.push(game_mod);
+    }
 
-            // Play score sound
-            if let Some(sound) = &self.score_sound {
-                play_sound_once(sound);
+    pub fn apply_mods(&mut self, configs: &[mods::ModConfig]) {
+        for config in configs {
+            self.add_mod(config.build());
+        }
+    }
+
+    // Give each mod this step's paddle hits and goals, then its tick
+    fn run_mods(&mut self, dt: f32) {
+        let mut mods = std::mem::take(&mut self.mods);
+        let events = self.events.clone();
+        for game_mod in &mut mods {
+            for event in &events {
+                match *event {
+                    GameEvent::PaddleHit { side, .. } => game_mod.on_paddle_hit(self, side),
+                    GameEvent::Goal { scorer, .. } => game_mod.on_goal(self, scorer),
+                    _ => {}
+                }
             }
+            game_mod.on_tick(self, dt);
+        }
+        self.mods = mods;
+    }
+
+    // Launch the ball from the centre and announce the serve
+    fn serve(&mut self) {
+        self.ball.serve(&mut self.rng);
+        let server = if self.ball.velocity.x > 0.0 {
+            Side::Left
+        } else {
+            Side::Right
+        };
+        self.events.push(GameEvent::Serve {
+            server,
+            velocity: self.ball.velocity,
+        });
+    }
 
-            // Create score particles
-            for _ in 0..15 {
-                self.particles.push(Particle::new(
-                    self.ball.position.x + BALL_SIZE / 2.0,
-                    self.ball.position.y + BALL_SIZE / 2.0,
-                ));
+    // Hand the queued events to statistics, analytics, sound, particles, effects and listeners
+    fn dispatch_events(&mut self) {
+        let time = self.stats.time_played;
+        let feel_settings = feel::settings();
+        let events = std::mem::take(&mut self.events);
+        for event in &events {
+            // Effects scale with how fast the ball was going when it was hit or scored
+            let speed = match *event {
+                GameEvent::PaddleHit { speed, .. } | GameEvent::Goal { speed, .. } => speed,
+                _ => self.ball.velocity.length(),
+            };
+            self.feel.on_event(event, speed, &feel_settings);
+            self.stats.on_event(event, time);
+            self.analytics.on_event(event, time);
+            self.play_event_sound(event);
+            self.spawn_event_particles(event);
+            for listener in &mut self.listeners {
+                listener.on_event(event, time);
             }
+        }
+        self.events = events;
+    }
 
-            self.ball.reset();
-            self.ball_trail.clear();
-            if self.player_score >= WINNING_SCORE {
-                self.game_state = GameState::GameOver;
+    fn play_event_sound(&self, event: &GameEvent) {
+        let sound = match event {
+            GameEvent::PaddleHit { .. } => &self.paddle_hit_sound,
+            GameEvent::WallHit { .. } => &self.wall_hit_sound,
+            GameEvent::Goal { .. } => &self.score_sound,
+            GameEvent::Serve { .. } | GameEvent::MatchEnd { .. } => &None,
+        };
+        if let Some(sound) = sound {
+            play_sound_once(sound);
+        }
+    }
+
+    fn spawn_event_particles(&mut self, event: &GameEvent) {
+        use particles::{Emitter, ParticlePreset};
+
+        // Towards the right for the left side
+        let facing = |side: Side| match side {
+            Side::Left => Vec2D::new(1.0, 0.0),
+            Side::Right => Vec2D::new(-1.0, 0.0),
+        };
+        match *event {
+            GameEvent::PaddleHit { side, position, .. } => {
+                // Sparks fly back the way the ball is now going
+                Emitter::new(ParticlePreset::SPARK, position)
+                    .aimed(facing(side))
+                    .burst(&mut self.particles);
             }
+            GameEvent::WallHit { position } => {
+                Emitter::new(ParticlePreset::SMOKE, position).burst(&mut self.particles);
+            }
+            GameEvent::Goal {
+                scorer, position, ..
+            } => {
+                // The ball is already past the goal line, so burst from the line itself
+                let goal_line = Vec2D::new(position.x.clamp(0.0, WINDOW_WIDTH), position.y);
+                Emitter::new(ParticlePreset::GOAL_BURST, goal_line)
+                    .aimed(facing(scorer.opponent()))
+                    .burst(&mut self.particles);
+                Emitter::new(ParticlePreset::CONFETTI, goal_line).burst(&mut self.particles);
+            }
+            GameEvent::Serve { .. } | GameEvent::MatchEnd { .. } => {}
         }
     }
 
     pub fn draw(&self) {
-        clear_background(BLACK);
+        let theme = theme::active();
+        draw_rectangle(0.0, 0.0, WINDOW_WIDTH, WINDOW_HEIGHT, theme.background);
+
+        if self.game_state == GameState::Title {
+            self.draw_title(&theme);
+            return;
+        }
+
+        // Everything on the court moves with the screen shake
+        let shake = self.feel.shake_offset();
+        if shake != Vec2::ZERO {
+            view::offset_camera(shake);
+        }
 
         // Draw center line
         for i in 0..20 {
@@ -529,7 +989,7 @@ impl Game {
                     y,
                     4.0,
                     WINDOW_HEIGHT / 20.0,
-                    WHITE,
+                    theme.center_line,
                 );
             }
         }
@@ -537,103 +997,228 @@ impl Game {
         // Draw ball trail
         for (i, trail_pos) in self.ball_trail.iter().enumerate() {
             let alpha = (i as f32 / self.ball_trail.len() as f32) * 0.3;
-            draw_circle(trail_pos.x, trail_pos.y, 3.0, Color::new(1.0, 1.0, 1.0, alpha));
+            draw_circle(
+                trail_pos.x,
+                trail_pos.y,
+                3.0,
+                theme.trail.with_alpha(theme.trail.a * alpha),
+            );
         }
 
         // Draw game objects
-        self.player_paddle.draw();
-        self.ai_paddle.draw();
-        self.ball.draw();
+        self.player_paddle
+            .draw_squashed(theme.left_paddle, self.feel.squash(Side::Left));
+        self.ai_paddle
+            .draw_squashed(theme.right_paddle, self.feel.squash(Side::Right));
+        self.ball.draw(theme.ball);
 
         // Draw particles
-        for particle in &self.particles {
-            particle.draw();
+        self.particles.draw(theme.particles);
+
+        for game_mod in &self.mods {
+            game_mod.on_draw(self);
         }
 
-        // Draw scores
-        let font_size = 48.0;
-        let player_score_text = format!("{}", self.player_score);
-        let ai_score_text = format!("{}", self.ai_score);
+        // A goal flashes the court in the scorer's colour
+        if let Some(scorer) = self.feel.flash_side {
+            let color = theme.paddle(scorer);
+            let alpha = 0.35 * self.feel.flash_strength();
+            draw_rectangle(
+                0.0,
+                0.0,
+                WINDOW_WIDTH,
+                WINDOW_HEIGHT,
+                color.with_alpha(color.a * alpha),
+            );
+        }
 
-        draw_text(
-            &player_score_text,
-            WINDOW_WIDTH / 4.0 - 20.0,
-            80.0,
-            font_size,
-            WHITE,
-        );
-        draw_text(
-            &ai_score_text,
-            3.0 * WINDOW_WIDTH / 4.0 - 20.0,
-            80.0,
-            font_size,
-            WHITE,
+        // Draw scores, growing from their centre when they pop
+        let layout = hud::Layout::current();
+        layout.scoreboard(
+            [self.player_score, self.ai_score],
+            [
+                self.feel.score_scale(Side::Left),
+                self.feel.score_scale(Side::Right),
+            ],
+            [theme.left_paddle, theme.right_paddle],
         );
+        if shake != Vec2::ZERO {
+            view::offset_camera(Vec2::ZERO);
+        }
 
-        // Draw instructions
+        // Draw the clock and instructions
         if self.game_state == GameState::Playing {
-            let instructions = "W/S or Up/Down arrows to move";
-            draw_text(
-                instructions,
-                WINDOW_WIDTH / 2.0 - 150.0,
-                WINDOW_HEIGHT - 30.0,
-                20.0,
-                GRAY,
-            );
+            layout.clock(self.stats.time_played, theme.text_dim);
+            layout.instructions("W/S or Up/Down arrows to move", theme.text_dim);
         }
 
         // Draw game over screen
         if self.game_state == GameState::GameOver {
-            let winner = if self.player_score >= WINNING_SCORE {
+            let winner = if self.player_score >= self.rules.winning_score {
                 "PLAYER WINS!"
             } else {
                 "AI WINS!"
             };
 
-            draw_text(
-                winner,
-                WINDOW_WIDTH / 2.0 - 100.0,
-                WINDOW_HEIGHT / 2.0 - 50.0,
-                36.0,
-                WHITE,
+            let strip = theme.background.with_alpha(0.8);
+            layout.banner(winner, -180.0, 36.0, theme.text, strip);
+
+            if self.show_heatmaps {
+                analytics::draw_heatmaps(&self.analytics);
+            } else {
+                stats::draw_match_summary(&self.stats, WINDOW_HEIGHT / 2.0 - 120.0);
+            }
+
+            // The export result sits on the line above the instructions
+            let above = layout.lines_up(1);
+            if let Some(message) = &self.stats_export {
+                layout.text(
+                    message,
+                    hud::Anchor::BottomLeft,
+                    above,
+                    18.0,
+                    theme.text_dim,
+                );
+            }
+            layout.instructions(
+                "R to restart, E to export stats, TAB for heatmaps, ESC to quit",
+                theme.text_dim,
             );
+        }
+    }
+
+    fn draw_title(&self, theme: &theme::Theme) {
+        let layout = hud::Layout::current();
+        layout.centered("PONG", -110.0, 72.0, theme.text);
+        layout.centered("Press SPACE to start a new match", 0.0, 20.0, theme.text);
+        if self.has_save {
+            layout.centered("Press C to resume", 30.0, 20.0, theme.text);
+        }
+        layout.centered(
+            "H to host a LAN game, L to find one",
+            60.0,
+            20.0,
+            theme.text_dim,
+        );
 
-            draw_text(
-                "Press R to restart or ESC to quit",
-                WINDOW_WIDTH / 2.0 - 150.0,
-                WINDOW_HEIGHT / 2.0,
-                20.0,
-                GRAY,
+        if !self.mods.is_empty() {
+            let names: Vec<&str> = self.mods.iter().map(|game_mod| game_mod.name()).collect();
+            layout.text(
+                &format!("Mods: {}", names.join(", ")),
+                hud::Anchor::BottomLeft,
+                vec2(0.0, -30.0),
+                18.0,
+                theme.text_dim,
             );
         }
+
+        layout.instructions("ESC to quit", theme.text_dim);
     }
 
     pub fn handle_input(&mut self) {
-        if self.game_state == GameState::GameOver {
-            if is_key_pressed(KeyCode::R) {
-                self.reset();
+        match self.game_state {
+            GameState::Title => {
+                if is_key_pressed(KeyCode::Space) {
+                    self.reset();
+                } else if self.has_save && is_key_pressed(KeyCode::C) {
+                    self.resume();
+                }
             }
+            GameState::GameOver => {
+                if is_key_pressed(KeyCode::R) {
+                    self.reset();
+                } else if is_key_pressed(KeyCode::Tab) {
+                    self.show_heatmaps = !self.show_heatmaps;
+                } else if is_key_pressed(KeyCode::E) {
+                    self.stats_export = Some(self.export_stats());
+                }
+            }
+            GameState::Playing => {}
+        }
+    }
+
+    // Load the save file, falling back to a fresh match if it is unreadable
+    pub fn resume(&mut self) {
+        match SaveState::read_from(&self.save_path) {
+            Ok(state) => self.restore(state),
+            Err(_) => self.reset(),
+        }
+    }
+
+    // Write the in-progress match to the save file so it can be resumed later
+    pub fn save(&self) -> std::io::Result<()> {
+        if self.game_state != GameState::Playing {
+            return Ok(());
+        }
+        self.snapshot().write_to(&self.save_path)
+    }
+
+    // Export the match statistics as JSON and the raw shot events as CSV next to them
+    fn export_stats(&self) -> String {
+        let path = match self.stats.export(&stats::default_stats_dir()) {
+            Ok(path) => path,
+            Err(err) => return format!("Failed to export stats: {}", err),
+        };
+        let events = analytics::events_path(&path);
+        match self.analytics.export_csv(&events) {
+            Ok(()) => format!("Saved stats to {} and {}", path.display(), events.display()),
+            Err(err) => format!("Failed to export shot events: {}", err),
+        }
+    }
+
+    fn end_match(&mut self, winner: Side) {
+        self.game_state = GameState::GameOver;
+        self.events.push(GameEvent::MatchEnd { winner });
+
+        // A finished match can no longer be resumed
+        if self.has_save {
+            let _ = std::fs::remove_file(&self.save_path);
+            self.has_save = false;
         }
     }
 
     pub fn reset(&mut self) {
         self.player_score = 0;
         self.ai_score = 0;
-        self.ball.reset();
-        self.player_paddle.position.y = WINDOW_HEIGHT / 2.0 - PADDLE_HEIGHT / 2.0;
-        self.ai_paddle.position.y = WINDOW_HEIGHT / 2.0 - PADDLE_HEIGHT / 2.0;
+        self.ball.speed = self.rules.ball_speed;
+        self.rally_length = 0;
+        self.ticks = 0;
+        self.stats = stats::MatchStats::default();
+        self.stats_export = None;
+        self.analytics = analytics::MatchAnalytics::default();
+        self.events.clear();
+        self.serve();
+        self.dispatch_events();
+        self.show_heatmaps = false;
+        for paddle in [&mut self.player_paddle, &mut self.ai_paddle] {
+            paddle.height = PADDLE_HEIGHT;
+            paddle.position.y = WINDOW_HEIGHT / 2.0 - PADDLE_HEIGHT / 2.0;
+        }
         self.game_state = GameState::Playing;
         self.particles.clear();
         self.ball_trail.clear();
     }
 }
 
+// Handle player input
+pub fn keyboard_command() -> PaddleCommand {
+    let mut command = PaddleCommand::Stay;
+    if is_key_down(KeyCode::W) || is_key_down(KeyCode::Up) {
+        command = PaddleCommand::Up;
+    }
+    if is_key_down(KeyCode::S) || is_key_down(KeyCode::Down) {
+        command = PaddleCommand::Down;
+    }
+    command
+}
+
 pub fn window_conf() -> Conf {
     Conf {
         window_title: "Pong - Rust Edition".to_owned(),
         window_width: WINDOW_WIDTH as i32,
         window_height: WINDOW_HEIGHT as i32,
-        window_resizable: false,
+        window_resizable: true,
+        high_dpi: true,
         ..Default::default()
     }
-}
\ No newline at end of file
+}
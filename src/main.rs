@@ -1,22 +1,165 @@
-use macroquad::prelude::*;
+mod modes;
+mod screens;
+
+use modes::bots::{run_bots, run_bots_headless};
+use modes::local::run_local;
+use modes::netplay::run_netplay;
+use modes::online::{run_online, run_spectate};
+use modes::train::run_train;
+use pong::cli::{self, Options};
+use pong::discovery;
+use pong::feel::FeelSettings;
+use pong::learned::{default_weights_path, Network};
+use pong::mods::{default_mods_path, ModConfig, ModsFile};
+use pong::settings::{default_settings_path, Settings};
+use pong::text;
 use pong::*;
 
-#[macroquad::main(window_conf)]
-async fn main() {
-    let mut game = Game::new().await;
+fn main() {
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+
+    if let Some(train) = &options.train {
+        std::process::exit(run_train(train));
+    }
+    if let Some(bots) = options.bots.as_ref().filter(|bots| bots.headless) {
+        std::process::exit(run_bots_headless(bots));
+    }
+    #[cfg(feature = "tui")]
+    if options.tui {
+        std::process::exit(modes::tui::run_tui(options));
+    }
+
+    macroquad::Window::from_config(window_conf(), run(options));
+}
 
-    loop {
-        clear_background(BLACK);
+async fn run(options: Options) {
+    let settings = load_settings(&options);
+    let font = match (&options.font, &settings) {
+        (Some(name), _) | (None, Ok(Settings { font: name, .. })) => name.as_str(),
+        (None, Err(_)) => "pixel",
+    };
+    apply_font(font);
+    if let Ok(settings) = &settings {
+        postfx::set_settings(settings.postfx);
+        feel::set_settings(settings.feel);
+    }
+    // Like --theme, this is not written back to the settings file
+    if options.reduced_motion {
+        feel::set_settings(FeelSettings::OFF);
+    }
 
-        game.handle_input();
-        game.update(get_frame_time());
-        game.draw();
+    if let Some(netplay) = options.netplay {
+        run_netplay(netplay, options.broadcast).await;
+    } else if let Some(online) = options.online {
+        run_online(online).await;
+    } else if let Some(addr) = options.spectate {
+        run_spectate(addr).await;
+    } else if let Some(bots) = options.bots {
+        run_bots(bots).await;
+    } else {
+        let discovery = options
+            .lan_discovery
+            .unwrap_or_else(discovery::default_listen_addr);
+        let difficulty = options.difficulty.unwrap_or_default();
+        let Some((learned_ai, mods)) = load_opponent(&options) else {
+            return;
+        };
+        run_local(
+            options.broadcast,
+            discovery,
+            difficulty,
+            learned_ai,
+            mods,
+            settings.ok(),
+        )
+        .await;
+    }
+}
 
-        // Handle quit
-        if is_key_pressed(KeyCode::Escape) {
-            break;
+// Settings for this run, with the theme applied; --theme overrides the saved theme
+fn load_settings(options: &Options) -> std::io::Result<Settings> {
+    let mut settings = Settings::load(&default_settings_path());
+    if let Err(err) = &settings {
+        eprintln!("Failed to load settings: {}", err);
+    }
+    // --theme applies to this run; it is only saved if the player changes theme in game
+    if let (Ok(settings), Some(name)) = (settings.as_mut(), &options.theme) {
+        settings.theme = name.clone();
+    }
+    match (&settings, &options.theme) {
+        (_, Some(name)) | (Ok(Settings { theme: name, .. }), None) => apply_theme(name),
+        (Err(_), None) => {}
+    }
+    settings
+}
+
+// The learned network, if that difficulty was chosen, and the mods for a local match
+// Failures are reported here; None means the match can't start
+fn load_opponent(options: &Options) -> Option<(Option<Network>, Vec<ModConfig>)> {
+    let learned_ai = match options.difficulty.unwrap_or_default() {
+        Difficulty::Learned => {
+            let path = options
+                .ai_weights
+                .clone()
+                .unwrap_or_else(default_weights_path);
+            match Network::load(&path) {
+                Ok(network) => Some(network),
+                Err(err) => {
+                    eprintln!(
+                        "Failed to load AI weights from {}: {} (run `pong train` first)",
+                        path.display(),
+                        err
+                    );
+                    return None;
+                }
+            }
+        }
+        _ => None,
+    };
+    let mods = match &options.mods {
+        Some(mods) => mods.clone(),
+        None => {
+            let path = default_mods_path();
+            match ModsFile::load(&path) {
+                Ok(file) => file.mods,
+                Err(err) => {
+                    eprintln!("Failed to load mods from {}: {}", path.display(), err);
+                    return None;
+                }
+            }
         }
+    };
+    Some((learned_ai, mods))
+}
+
+// Make a theme active, keeping the current one if it can't be loaded
+fn apply_theme(name: &str) {
+    match theme::resolve(name) {
+        Ok(theme) => theme::set_active(theme),
+        Err(err) => eprintln!("Failed to load theme {}: {}", name, err),
+    }
+}
+
+fn apply_font(name: &str) {
+    match text::load(name) {
+        Ok(font) => text::set_font(font),
+        Err(err) => eprintln!("Failed to load font {}: {}", name, err),
+    }
+}
 
-        next_frame().await;
+fn save_settings(settings: &Settings) {
+    if let Err(err) = settings.write_to(&default_settings_path()) {
+        eprintln!("Failed to save settings: {}", err);
     }
 }
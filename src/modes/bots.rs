@@ -0,0 +1,153 @@
+// Matches between bots, scripts, the built-in AI and the keyboard, in a window or headless
+
+use macroquad::prelude::*;
+use pong::bot::{bot_game, play_headless, BotController, BuiltinAi, Controller, MAX_MATCH_TICKS};
+use pong::cli::BotOptions;
+use pong::hud::{Anchor, Layout};
+use pong::netplay::{MAX_TICKS_PER_FRAME, TICK_DT};
+use pong::script::ScriptController;
+use pong::*;
+use std::path::Path;
+
+pub struct Keyboard;
+
+impl Controller for Keyboard {
+    fn command(&mut self, _game: &Game, _side: Side) -> PaddleCommand {
+        keyboard_command()
+    }
+}
+
+// Controllers for a bot match; sides without a bot fall back to `fallback`
+pub fn bot_controllers(
+    options: &BotOptions,
+    fallback: fn(Side) -> Box<dyn Controller>,
+) -> Result<[Box<dyn Controller>; 2], String> {
+    let controller =
+        |command: &Option<String>, side: Side| -> Result<Box<dyn Controller>, String> {
+            match command {
+                // Scripts run inside the game rather than as a separate program
+                Some(path) if path.ends_with(".rhai") => {
+                    Ok(Box::new(ScriptController::load(Path::new(path))))
+                }
+                Some(command) => BotController::spawn(command, options.budget)
+                    .map(|bot| Box::new(bot) as Box<dyn Controller>)
+                    .map_err(|err| format!("Failed to start bot '{}': {}", command, err)),
+                None => Ok(fallback(side)),
+            }
+        };
+    Ok([
+        controller(&options.left, Side::Left)?,
+        controller(&options.right, Side::Right)?,
+    ])
+}
+
+pub fn run_bots_headless(options: &BotOptions) -> i32 {
+    let [mut left, mut right] = match bot_controllers(options, |_| Box::new(BuiltinAi)) {
+        Ok(controllers) => controllers,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 1;
+        }
+    };
+
+    let mut game = bot_game(options);
+    match play_headless(&mut game, left.as_mut(), right.as_mut(), MAX_MATCH_TICKS) {
+        Some(winner) => {
+            println!(
+                "{:?} wins {} - {}",
+                winner, game.player_score, game.ai_score
+            );
+            0
+        }
+        None => {
+            println!(
+                "No winner after {} ticks ({} - {})",
+                MAX_MATCH_TICKS, game.player_score, game.ai_score
+            );
+            1
+        }
+    }
+}
+
+pub async fn run_bots(options: BotOptions) {
+    let fallback = |side| -> Box<dyn Controller> {
+        match side {
+            Side::Left => Box::new(Keyboard),
+            Side::Right => Box::new(BuiltinAi),
+        }
+    };
+    let [mut left, mut right] = match bot_controllers(&options, fallback) {
+        Ok(controllers) => controllers,
+        Err(err) => {
+            eprintln!("{}", err);
+            return;
+        }
+    };
+
+    let mut game = bot_game(&options);
+    game.load_sounds().await;
+    let mut accumulator = 0.0;
+    let mut finished = false;
+    #[cfg(feature = "debug-overlay")]
+    let mut debug_overlay = pong::debug::DebugOverlay::new(&game);
+
+    loop {
+        view::begin_frame();
+
+        // Bots see the game at the same fixed tick rate as headless and networked matches;
+        // slow bots can take longer than a tick to answer, so the backlog is capped
+        accumulator = f32::min(
+            accumulator + get_frame_time(),
+            MAX_TICKS_PER_FRAME as f32 * TICK_DT,
+        );
+        while accumulator >= TICK_DT {
+            accumulator -= TICK_DT;
+            let left_command = left.command(&game, Side::Left);
+            let right_command = right.command(&game, Side::Right);
+            game.step(TICK_DT, left_command, right_command);
+        }
+
+        if game.game_state == GameState::GameOver && !finished {
+            finished = true;
+            let winner = if game.player_score > game.ai_score {
+                Side::Left
+            } else {
+                Side::Right
+            };
+            left.finish(winner);
+            right.finish(winner);
+        }
+        game.handle_input();
+        if finished && game.game_state == GameState::Playing {
+            finished = false;
+            // The bots were told the last match is over, so a restart gets fresh ones
+            [left, right] = match bot_controllers(&options, fallback) {
+                Ok(controllers) => controllers,
+                Err(err) => {
+                    eprintln!("{}", err);
+                    return;
+                }
+            };
+        }
+        #[cfg(feature = "debug-overlay")]
+        debug_overlay.update(&mut game, get_frame_time());
+        game.draw();
+        // Script problems sit on the two lines above the instructions, left side first
+        let layout = Layout::current();
+        for (lines_up, status) in [(2, left.status()), (1, right.status())] {
+            if let Some(status) = status {
+                let line: String = status.chars().take(90).collect();
+                let offset = layout.lines_up(lines_up);
+                layout.text(&line, Anchor::BottomLeft, offset, 16.0, RED);
+            }
+        }
+        #[cfg(feature = "debug-overlay")]
+        debug_overlay.draw(&game, &theme::active());
+
+        if is_key_pressed(KeyCode::Escape) {
+            break;
+        }
+
+        view::next_frame().await;
+    }
+}
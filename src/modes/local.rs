@@ -0,0 +1,172 @@
+// A local match against the computer, with the title screen's theme, profile and LAN keys
+
+use crate::modes::netplay::play_lan_match;
+use crate::modes::{save_replay, start_broadcast};
+use crate::screens::lan::{browse_lan, host_lan};
+use crate::screens::profiles::{choose_profile, Profiles};
+use crate::{apply_theme, save_settings};
+use macroquad::prelude::*;
+use pong::hud::{Anchor, Layout};
+use pong::learned::Network;
+use pong::mods::ModConfig;
+use pong::profile::{MatchMode, MatchOutcome};
+use pong::replay::Replay;
+use pong::server::StateSnapshot;
+use pong::settings::Settings;
+use pong::spectator::MatchInfo;
+use pong::*;
+use std::net::SocketAddr;
+
+pub async fn run_local(
+    broadcast: Option<String>,
+    discovery: SocketAddr,
+    difficulty: Difficulty,
+    learned_ai: Option<Network>,
+    mods: Vec<ModConfig>,
+    // None when the settings file couldn't be read, so it is never saved over
+    mut settings: Option<Settings>,
+) {
+    let mut game = Game::new().await;
+    game.ai = difficulty.ai_settings();
+    game.learned_ai = learned_ai;
+    game.apply_mods(&mods);
+    let mut profiles = Profiles::load();
+    let info = MatchInfo {
+        left_name: profiles.name(),
+        right_name: "AI".to_owned(),
+    };
+    let mut broadcaster = start_broadcast(broadcast.clone(), info);
+    let mut frame = 0;
+    let mut rating_change: Option<f32> = None;
+    #[cfg(feature = "debug-overlay")]
+    let mut debug_overlay = pong::debug::DebugOverlay::new(&game);
+
+    // Intercept window close so an in-progress match can be saved first
+    prevent_quit();
+
+    loop {
+        view::begin_frame();
+        let theme = theme::active();
+
+        let hosting = is_key_pressed(KeyCode::H);
+        if game.game_state == GameState::Title && (hosting || is_key_pressed(KeyCode::L)) {
+            let lan_game = if hosting {
+                host_lan(discovery, profiles.name()).await
+            } else {
+                browse_lan(discovery, profiles.name())
+                    .await
+                    .map(|(lan_match, socket)| (lan_match, socket, None))
+            };
+            if let Some((lan_match, socket, host)) = lan_game {
+                play_lan_match(lan_match, socket, broadcast.clone(), host, &mut profiles).await;
+            }
+            // The ESC that closed the LAN screens must not also quit the game
+            view::next_frame().await;
+            continue;
+        }
+        if game.game_state == GameState::Title && is_key_pressed(KeyCode::T) {
+            let current = settings.as_ref().map_or("classic", |s| s.theme.as_str());
+            let next = theme::next_theme_name(current);
+            apply_theme(next);
+            if let Some(settings) = settings.as_mut() {
+                settings.theme = next.to_owned();
+                save_settings(settings);
+            }
+        }
+        if game.game_state == GameState::Title && is_key_pressed(KeyCode::V) {
+            let mut effects = postfx::settings();
+            effects.enabled = !effects.enabled;
+            postfx::set_settings(effects);
+            if let Some(settings) = settings.as_mut() {
+                settings.postfx = effects;
+                save_settings(settings);
+            }
+        }
+        if game.game_state == GameState::Title && is_key_pressed(KeyCode::P) {
+            choose_profile(&mut profiles).await;
+            view::next_frame().await;
+            continue;
+        }
+
+        let was_over = game.game_state == GameState::GameOver;
+        let was_playing = game.game_state == GameState::Playing;
+        game.handle_input();
+        // Record each match from when it starts or resumes
+        if game.game_state == GameState::Playing && !was_playing {
+            game.replay = Some(Replay::start(&game, &mods));
+        }
+        // Tuned values aren't steps, so a tuned match is recorded again from the change
+        #[cfg(feature = "debug-overlay")]
+        if debug_overlay.update(&mut game, get_frame_time()) && game.replay.is_some() {
+            game.replay = Some(Replay::start(&game, &mods));
+        }
+        game.update(get_frame_time());
+        if game.game_state == GameState::GameOver && !was_over {
+            let outcome =
+                MatchOutcome::from_game(&game, Side::Left, MatchMode::VsAi, Some(difficulty));
+            rating_change = profiles.record(&game, &outcome, Side::Left);
+            save_replay(&mut game);
+        } else if game.game_state == GameState::Playing {
+            profiles.check_achievements(&game, Side::Left, None);
+        }
+        game.draw();
+
+        let layout = Layout::current();
+        let profile_line = match (game.game_state, profiles.store.active()) {
+            (GameState::Title, Some(profile)) => format!(
+                "Profile: {} ({:.0})  P to change",
+                profile.name, profile.rating
+            ),
+            (GameState::Title, None) => "P to choose a profile".to_owned(),
+            (GameState::GameOver, Some(profile)) => match rating_change {
+                Some(change) => format!(
+                    "{} rating {:.0} ({:+.0})",
+                    profile.name, profile.rating, change
+                ),
+                None => String::new(),
+            },
+            _ => String::new(),
+        };
+        let profile_line = layout.text(
+            &profile_line,
+            Anchor::TopLeft,
+            Vec2::ZERO,
+            20.0,
+            theme.text_dim,
+        );
+        if game.game_state == GameState::Title {
+            let name = settings.as_ref().map_or("classic", |s| s.theme.as_str());
+            let effects = if postfx::settings().enabled {
+                "on"
+            } else {
+                "off"
+            };
+            let line = format!(
+                "Theme: {}  T to change   CRT effects: {}  V to toggle",
+                name, effects
+            );
+            layout.below(profile_line, &line, 20.0, theme.text_dim);
+        }
+        #[cfg(feature = "debug-overlay")]
+        debug_overlay.draw(&game, &theme);
+        profiles.draw_toasts();
+
+        if let Some(broadcaster) = broadcaster.as_mut() {
+            if game.game_state != GameState::Title {
+                frame += 1;
+                broadcaster.publish(&StateSnapshot::capture(&game, frame));
+            }
+        }
+
+        // Handle quit
+        if is_key_pressed(KeyCode::Escape) || is_quit_requested() {
+            if let Err(err) = game.save() {
+                eprintln!("Failed to save match: {}", err);
+            }
+            save_replay(&mut game);
+            break;
+        }
+
+        view::next_frame().await;
+    }
+}
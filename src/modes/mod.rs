@@ -0,0 +1,34 @@
+// Ways to play, each run until the player leaves it
+// Helpers several modes share live here; menu screens are in `screens`.
+
+pub mod bots;
+pub mod local;
+pub mod netplay;
+pub mod online;
+pub mod train;
+#[cfg(feature = "tui")]
+pub mod tui;
+
+use pong::replay::default_replay_path;
+use pong::spectator::{MatchInfo, SpectatorBroadcaster};
+use pong::*;
+
+// Keep the match just played for pong-render
+pub fn save_replay(game: &mut Game) {
+    if let Some(replay) = game.replay.take() {
+        if let Err(err) = replay.write_to(&default_replay_path()) {
+            eprintln!("Failed to save replay: {}", err);
+        }
+    }
+}
+
+pub fn start_broadcast(addr: Option<String>, info: MatchInfo) -> Option<SpectatorBroadcaster> {
+    let addr = addr?;
+    match SpectatorBroadcaster::bind(&addr, info) {
+        Ok(broadcaster) => Some(broadcaster),
+        Err(err) => {
+            eprintln!("Failed to start spectator broadcast on {}: {}", addr, err);
+            None
+        }
+    }
+}
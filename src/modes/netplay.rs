@@ -0,0 +1,168 @@
+// Peer-to-peer matches, started directly or through LAN discovery
+
+use crate::modes::start_broadcast;
+use crate::screens::profiles::Profiles;
+use macroquad::prelude::*;
+use pong::cli::NetplayOptions;
+use pong::discovery::{LanHost, LanMatch};
+use pong::hud::{Anchor, Layout};
+use pong::netplay::{
+    AdvanceResult, RollbackSession, SimulatedLink, Transport, UdpTransport, MAX_TICKS_PER_FRAME,
+    TICK_DT,
+};
+use pong::profile::{MatchMode, MatchOutcome};
+use pong::server::StateSnapshot;
+use pong::spectator::MatchInfo;
+use pong::*;
+use std::net::UdpSocket;
+
+pub async fn run_netplay(options: NetplayOptions, broadcast: Option<String>) {
+    let transport = match UdpTransport::bind(options.local_addr, options.peer_addr) {
+        Ok(transport) => transport,
+        Err(err) => {
+            eprintln!("Failed to bind {}: {}", options.local_addr, err);
+            return;
+        }
+    };
+    let link = SimulatedLink::new(transport, options.link, options.seed);
+    let session = RollbackSession::new(options.seed, options.side, options.config, link);
+    let mut profiles = Profiles::load();
+    play_netplay(
+        session,
+        broadcast,
+        None,
+        None,
+        MatchMode::Netplay,
+        &mut profiles,
+    )
+    .await;
+}
+
+// Run a peer-to-peer match until ESC; a LAN host keeps answering late join retries meanwhile.
+// `peer_name` is the name the peer joined a LAN game with, until its packets say otherwise.
+pub async fn play_netplay<T: Transport>(
+    mut session: RollbackSession<T>,
+    broadcast: Option<String>,
+    mut lan_host: Option<LanHost>,
+    peer_name: Option<String>,
+    mode: MatchMode,
+    profiles: &mut Profiles,
+) {
+    session.game.load_sounds().await;
+    session.set_rating(profiles.store.active().map(|profile| profile.rating));
+    let local_name = profiles.name();
+    session.set_name(Some(local_name.clone()));
+    let mut recorded = false;
+
+    let match_info = |session: &RollbackSession<T>| {
+        let remote_name = session
+            .remote_name()
+            .map(str::to_owned)
+            .or_else(|| peer_name.clone())
+            .unwrap_or_else(|| "Opponent".to_owned());
+        let (left_name, right_name) = match session.local_side() {
+            Side::Left => (local_name.clone(), remote_name),
+            Side::Right => (remote_name, local_name.clone()),
+        };
+        MatchInfo {
+            left_name,
+            right_name,
+        }
+    };
+    let mut broadcaster = start_broadcast(broadcast, match_info(&session));
+    let mut accumulator = 0.0;
+    let mut error: Option<String> = None;
+
+    loop {
+        view::begin_frame();
+        let theme = theme::active();
+        if let Some(host) = lan_host.as_mut() {
+            let _ = host.poll();
+        }
+
+        // Run the simulation at a fixed tick rate regardless of frame rate
+        accumulator = f32::min(
+            accumulator + get_frame_time(),
+            MAX_TICKS_PER_FRAME as f32 * TICK_DT,
+        );
+        let mut ticks = 0;
+        while error.is_none() && accumulator >= TICK_DT {
+            accumulator -= TICK_DT;
+            ticks += 1;
+            match session.advance(keyboard_command()) {
+                Ok(AdvanceResult::Advanced) => {
+                    if let Some(broadcaster) = broadcaster.as_mut() {
+                        broadcaster.set_info(match_info(&session));
+                        let state = StateSnapshot::capture(&session.game, session.tick());
+                        broadcaster.publish(&state);
+                    }
+                }
+                Ok(AdvanceResult::Stalled) => {}
+                Err(err) => error = Some(err.to_string()),
+            }
+        }
+        // Fast displays have frames without a tick; lost commands still have to be resent
+        if ticks == 0 && error.is_none() {
+            if let Err(err) = session.poll() {
+                error = Some(err.to_string());
+            }
+        }
+
+        // Only a confirmed result counts; a predicted one could still be rolled back
+        let confirmed = session.confirmed_tick() >= session.tick();
+        if !recorded && confirmed && session.game.game_state == GameState::GameOver {
+            recorded = true;
+            let side = session.local_side();
+            let mut outcome = MatchOutcome::from_game(&session.game, side, mode, None);
+            outcome.opponent_rating = session.remote_rating();
+            profiles.record(&session.game, &outcome, side);
+        }
+
+        session.game.draw();
+        profiles.draw_toasts();
+
+        let status = match &error {
+            Some(err) => format!("Netplay stopped: {}", err),
+            None => format!(
+                "tick {}  confirmed {}  rollbacks {}",
+                session.tick(),
+                session.confirmed_tick(),
+                session.rollback_count()
+            ),
+        };
+        Layout::current().text(&status, Anchor::TopLeft, Vec2::ZERO, 18.0, theme.text_dim);
+
+        if is_key_pressed(KeyCode::Escape) {
+            break;
+        }
+
+        view::next_frame().await;
+    }
+}
+
+pub async fn play_lan_match(
+    lan_match: LanMatch,
+    socket: UdpSocket,
+    broadcast: Option<String>,
+    host: Option<LanHost>,
+    profiles: &mut Profiles,
+) {
+    let transport = match UdpTransport::new(socket, lan_match.peer) {
+        Ok(transport) => transport,
+        Err(err) => {
+            eprintln!("Failed to start LAN match: {}", err);
+            return;
+        }
+    };
+    let session = lan_match.session(transport);
+    let peer_name = Some(lan_match.peer_name);
+    play_netplay(
+        session,
+        broadcast,
+        host,
+        peer_name,
+        MatchMode::Lan,
+        profiles,
+    )
+    .await;
+}
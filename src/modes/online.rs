@@ -0,0 +1,226 @@
+// Matches and spectating through a pong-server or a spectator broadcast
+
+use crate::screens::profiles::Profiles;
+use macroquad::prelude::*;
+use pong::cli::OnlineOptions;
+use pong::hud::{Layout, STATUS_SIZE};
+use pong::netplay::{MAX_TICKS_PER_FRAME, TICK_DT};
+use pong::profile::{MatchMode, MatchOutcome};
+use pong::server::{ClientMessage, ClientPredictor, PlayerInfo, ServerConnection, ServerMessage};
+use pong::spectator::{draw_spectator_hud, SpectatorClient, SpectatorView};
+use pong::*;
+
+pub async fn run_online(options: OnlineOptions) {
+    let mut connection = match ServerConnection::connect(&options.server) {
+        Ok(connection) => connection,
+        Err(err) => {
+            eprintln!("Failed to connect to {}: {}", options.server, err);
+            return;
+        }
+    };
+
+    let room = options.room.clone();
+    let join = if options.spectator {
+        ClientMessage::Spectate { room }
+    } else if options.create {
+        ClientMessage::CreateRoom { room }
+    } else {
+        ClientMessage::JoinRoom { room }
+    };
+    let mut profiles = Profiles::load();
+    let hello = ClientMessage::Hello {
+        name: options.name.clone(),
+        rating: profiles.store.active().map(|profile| profile.rating),
+    };
+    if let Err(err) = connection.send(&hello).and_then(|_| connection.send(&join)) {
+        eprintln!("Failed to talk to {}: {}", options.server, err);
+        return;
+    }
+
+    // Only used for drawing; the server owns the real simulation
+    let mut view = Game::with_seed(0);
+    view.load_sounds().await;
+    let mut side = Side::Left;
+    let mut players: Vec<PlayerInfo> = Vec::new();
+    let mut predictor: Option<ClientPredictor> = None;
+    let mut spectator_view: Option<SpectatorView> = None;
+    let mut status = format!("Connecting to {}...", options.server);
+    let mut accumulator = 0.0;
+
+    loop {
+        view::begin_frame();
+        let theme = theme::active();
+        while let Some(message) = connection.try_recv() {
+            match message {
+                ServerMessage::Joined { room, side: seat } => {
+                    side = seat;
+                    status = format!("Room {}: press SPACE when ready", room);
+                }
+                ServerMessage::Spectating { room } => {
+                    spectator_view = Some(SpectatorView::new());
+                    status = format!("Watching room {}: waiting for a match", room);
+                }
+                ServerMessage::Spectator { message } => {
+                    if let Some(spectator) = spectator_view.as_mut() {
+                        spectator.apply(message, get_time());
+                    }
+                }
+                ServerMessage::RoomUpdate { players: list, .. } => players = list,
+                ServerMessage::MatchStart { rules, .. } => {
+                    view.rules = rules;
+                    predictor = Some(ClientPredictor::new(side, rules));
+                    accumulator = 0.0;
+                }
+                ServerMessage::Snapshot { state, last_input } => {
+                    if let Some(predictor) = predictor.as_mut() {
+                        predictor.apply_snapshot(state, last_input, get_time());
+                    }
+                }
+                ServerMessage::MatchEnd { winner, stats } => {
+                    // The server's result is final, so players record it straight away
+                    if predictor.take().is_some() && spectator_view.is_none() {
+                        let mut outcome = MatchOutcome::from_stats(
+                            &stats,
+                            side,
+                            winner == side,
+                            MatchMode::Online,
+                        );
+                        outcome.opponent_rating = players
+                            .iter()
+                            .find(|player| player.side != side)
+                            .and_then(|player| player.rating);
+                        view.stats = stats;
+                        profiles.record(&view, &outcome, side);
+                    }
+                    status = if let Some(spectator) = spectator_view.as_mut() {
+                        let name = match (&spectator.info, winner) {
+                            (Some(info), Side::Left) => info.left_name.clone(),
+                            (Some(info), Side::Right) => info.right_name.clone(),
+                            (None, _) => format!("{:?}", winner),
+                        };
+                        *spectator = SpectatorView::new();
+                        format!("{} wins! Waiting for the next match", name)
+                    } else if winner == side {
+                        "You win! Press SPACE to play again".to_owned()
+                    } else {
+                        "You lose! Press SPACE to play again".to_owned()
+                    };
+                }
+                ServerMessage::Error { message } => status = format!("Server: {}", message),
+                ServerMessage::Welcome { .. } | ServerMessage::RoomList { .. } => {}
+            }
+        }
+
+        if let Some(spectator) = spectator_view.as_ref() {
+            if !draw_spectator_frame(spectator, &mut view) {
+                Layout::current().heading(&status, STATUS_SIZE, theme.text);
+            }
+            if is_key_pressed(KeyCode::Escape) {
+                let _ = connection.send(&ClientMessage::Leave);
+                break;
+            }
+            view::next_frame().await;
+            continue;
+        }
+
+        match predictor.as_mut() {
+            Some(predictor) => {
+                accumulator = f32::min(
+                    accumulator + get_frame_time(),
+                    MAX_TICKS_PER_FRAME as f32 * TICK_DT,
+                );
+                while accumulator >= TICK_DT {
+                    accumulator -= TICK_DT;
+                    let command = keyboard_command();
+                    let tick = predictor.record_input(command);
+                    if connection
+                        .send(&ClientMessage::Input { tick, command })
+                        .is_err()
+                    {
+                        status = "Lost connection to server".to_owned();
+                    }
+                }
+
+                if let Some(state) = predictor.render_state(get_time()) {
+                    state.apply_to(&mut view);
+                }
+                view.draw();
+            }
+            None => {
+                let layout = Layout::current();
+                let mut above = layout.heading(&status, STATUS_SIZE, theme.text);
+                for player in &players {
+                    let line = format!(
+                        "{:?}: {}{}",
+                        player.side,
+                        player.name,
+                        if player.ready { " (ready)" } else { "" }
+                    );
+                    above = layout.below(above, &line, 22.0, theme.text_dim);
+                }
+
+                if is_key_pressed(KeyCode::Space) && connection.send(&ClientMessage::Ready).is_err()
+                {
+                    status = "Lost connection to server".to_owned();
+                }
+            }
+        }
+
+        profiles.draw_toasts();
+
+        if is_key_pressed(KeyCode::Escape) {
+            let _ = connection.send(&ClientMessage::Leave);
+            break;
+        }
+
+        view::next_frame().await;
+    }
+}
+
+// Draw what a spectator sees; returns false while no keyframe has arrived yet
+pub fn draw_spectator_frame(spectator: &SpectatorView, shell: &mut Game) -> bool {
+    let state = match spectator.render_state(get_time()) {
+        Some(state) => state,
+        None => return false,
+    };
+
+    state.apply_to(shell);
+    shell.draw();
+    if let Some(info) = &spectator.info {
+        draw_spectator_hud(info, &state);
+    }
+    true
+}
+
+pub async fn run_spectate(addr: String) {
+    let client = match SpectatorClient::connect(&addr) {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("Failed to connect to {}: {}", addr, err);
+            return;
+        }
+    };
+
+    // Only used for drawing
+    let mut shell = Game::with_seed(0);
+    let mut spectator = SpectatorView::new();
+
+    loop {
+        view::begin_frame();
+        let theme = theme::active();
+        while let Some(message) = client.try_recv() {
+            spectator.apply(message, get_time());
+        }
+
+        if !draw_spectator_frame(&spectator, &mut shell) {
+            let waiting = format!("Waiting for {}...", addr);
+            Layout::current().heading(&waiting, STATUS_SIZE, theme.text);
+        }
+
+        if is_key_pressed(KeyCode::Escape) {
+            break;
+        }
+
+        view::next_frame().await;
+    }
+}
@@ -0,0 +1,39 @@
+// Training the learned AI from the command line
+
+use pong::cli::TrainOptions;
+use pong::learned::Trainer;
+
+pub fn run_train(options: &TrainOptions) -> i32 {
+    let config = options.config;
+    println!(
+        "Training {} networks for {} generations on {} threads",
+        config.population, config.generations, config.threads
+    );
+    let mut trainer = Trainer::new(config);
+    for _ in 0..config.generations {
+        let report = trainer.step_generation();
+        println!(
+            "Generation {:>3}: best {:>7.2}, mean {:>7.2}",
+            report.generation, report.best_fitness, report.mean_fitness
+        );
+    }
+
+    match trainer.champion().save(
+        &options.output,
+        trainer.generation(),
+        trainer.champion_fitness(),
+    ) {
+        Ok(()) => {
+            println!("Saved weights to {}", options.output.display());
+            0
+        }
+        Err(err) => {
+            eprintln!(
+                "Failed to save weights to {}: {}",
+                options.output.display(),
+                err
+            );
+            1
+        }
+    }
+}
@@ -0,0 +1,30 @@
+// A local match against the computer drawn in the terminal, with no window
+// Only built with the `tui` feature.
+
+use crate::{load_opponent, load_settings};
+use pong::cli::Options;
+use pong::feel::FeelSettings;
+use pong::*;
+
+pub fn run_tui(options: Options) -> i32 {
+    let _ = load_settings(&options);
+    if options.reduced_motion {
+        feel::set_settings(FeelSettings::OFF);
+    }
+    let Some((learned_ai, mods)) = load_opponent(&options) else {
+        return 1;
+    };
+    let mut game = Game::with_seed(::rand::random());
+    game.ai = options.difficulty.unwrap_or_default().ai_settings();
+    game.learned_ai = learned_ai;
+    game.apply_mods(&mods);
+    // Mod rules apply from the next serve, so start over with them in place
+    game.reset();
+    match pong::tui::run(game, theme::active()) {
+        Ok(()) => 0,
+        Err(err) => {
+            eprintln!("Terminal error: {}", err);
+            1
+        }
+    }
+}
@@ -0,0 +1,238 @@
+// Game mods: rule variations layered on top of a match without forking the game
+// Anything that has to survive saves and rollback lives in the game (paddle heights, ball
+// velocity), so the built-in mods are just sets of hooks; script mods only add the message they
+// show and the rules their script asked for. Several mods can be enabled at once and run in the
+// order they were added.
+
+use crate::save::data_dir;
+use crate::script::ScriptMod;
+use crate::theme;
+use crate::{Game, RuleSet, Side, PADDLE_HEIGHT, WINDOW_HEIGHT, WINDOW_WIDTH};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+pub const MODS_VERSION: u32 = 1;
+pub const MODS_FILE_NAME: &str = "mods.json";
+
+pub fn default_mods_path() -> PathBuf {
+    data_dir().join(MODS_FILE_NAME)
+}
+
+pub trait GameMod: Send {
+    fn name(&self) -> &'static str;
+
+    // Adjust the rules when the mod is added; they apply from the next reset
+    fn modify_config(&self, _rules: &mut RuleSet) {}
+
+    // Called at the end of every simulation step, after the paddle-hit and goal hooks
+    fn on_tick(&mut self, _game: &mut Game, _dt: f32) {}
+
+    fn on_paddle_hit(&mut self, _game: &mut Game, _side: Side) {}
+
+    fn on_goal(&mut self, _game: &mut Game, _scorer: Side) {}
+
+    // Drawn over the court and ball, below the scores
+    fn on_draw(&self, _game: &Game) {}
+}
+
+// Pulls the ball towards the bottom wall
+pub struct GravityBall {
+    // Downward acceleration in pixels per second squared
+    pub strength: f32,
+}
+
+impl GameMod for GravityBall {
+    fn name(&self) -> &'static str {
+        "gravity ball"
+    }
+
+    fn on_tick(&mut self, game: &mut Game, dt: f32) {
+        game.ball.velocity.y += self.strength * dt;
+    }
+}
+
+// Every return shrinks the paddle that made it; a goal restores both
+pub struct ShrinkingPaddles {
+    // Height kept after each hit, as a fraction of the height before it
+    pub factor: f32,
+    pub min_height: f32,
+}
+
+impl GameMod for ShrinkingPaddles {
+    fn name(&self) -> &'static str {
+        "shrinking paddles"
+    }
+
+    fn on_paddle_hit(&mut self, game: &mut Game, side: Side) {
+        let paddle = game.paddle_mut(side);
+        let height = (paddle.height * self.factor).max(self.min_height);
+        paddle.resize(height);
+    }
+
+    fn on_goal(&mut self, game: &mut Game, _scorer: Side) {
+        game.paddle_mut(Side::Left).resize(PADDLE_HEIGHT);
+        game.paddle_mut(Side::Right).resize(PADDLE_HEIGHT);
+    }
+}
+
+// The ball disappears while it crosses the middle of the court
+pub struct InvisibleBall {
+    // Width of the hidden band around the centre line
+    pub width: f32,
+    // The ball is slowed down to keep the game fair
+    pub ball_speed_factor: f32,
+}
+
+impl GameMod for InvisibleBall {
+    fn name(&self) -> &'static str {
+        "invisible ball"
+    }
+
+    fn modify_config(&self, rules: &mut RuleSet) {
+        rules.ball_speed *= self.ball_speed_factor;
+    }
+
+    fn on_draw(&self, _game: &Game) {
+        let theme = theme::active();
+        let left = WINDOW_WIDTH / 2.0 - self.width / 2.0;
+        draw_rectangle(left, 0.0, self.width, WINDOW_HEIGHT, theme.background);
+        for x in [left, left + self.width] {
+            draw_line(x, 0.0, x, WINDOW_HEIGHT, 1.0, theme.text_dim);
+        }
+    }
+}
+
+fn default_gravity() -> f32 {
+    300.0
+}
+
+fn default_shrink_factor() -> f32 {
+    0.9
+}
+
+fn default_min_height() -> f32 {
+    30.0
+}
+
+fn default_hidden_width() -> f32 {
+    240.0
+}
+
+fn default_hidden_speed_factor() -> f32 {
+    0.85
+}
+
+// A mod and its parameters, as written in mods.json or named on the command line
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "name", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ModConfig {
+    GravityBall {
+        #[serde(default = "default_gravity")]
+        strength: f32,
+    },
+    ShrinkingPaddles {
+        #[serde(default = "default_shrink_factor")]
+        factor: f32,
+        #[serde(default = "default_min_height")]
+        min_height: f32,
+    },
+    InvisibleBall {
+        #[serde(default = "default_hidden_width")]
+        width: f32,
+        #[serde(default = "default_hidden_speed_factor")]
+        ball_speed_factor: f32,
+    },
+    // A Rhai game-mode script
+    Script {
+        path: PathBuf,
+    },
+}
+
+impl ModConfig {
+    pub fn build(&self) -> Box<dyn GameMod> {
+        match *self {
+            ModConfig::Script { ref path } => Box::new(ScriptMod::load(path)),
+            ModConfig::GravityBall { strength } => Box::new(GravityBall { strength }),
+            ModConfig::ShrinkingPaddles { factor, min_height } => {
+                Box::new(ShrinkingPaddles { factor, min_height })
+            }
+            ModConfig::InvisibleBall {
+                width,
+                ball_speed_factor,
+            } => Box::new(InvisibleBall {
+                width,
+                ball_speed_factor,
+            }),
+        }
+    }
+}
+
+// A bare mod name with default parameters, or the path of a .rhai script
+impl FromStr for ModConfig {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.trim();
+        if name.ends_with(".rhai") {
+            return Ok(ModConfig::Script {
+                path: PathBuf::from(name),
+            });
+        }
+        serde_json::from_value(serde_json::json!({ "name": name }))
+            .map_err(|_| format!("unknown mod: {}", name))
+    }
+}
+
+// Parse a comma-separated list of mod names
+pub fn parse_mod_list(list: &str) -> Result<Vec<ModConfig>, String> {
+    list.split(',')
+        .filter(|name| !name.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModsFile {
+    pub version: u32,
+    pub mods: Vec<ModConfig>,
+}
+
+impl Default for ModsFile {
+    fn default() -> Self {
+        Self {
+            version: MODS_VERSION,
+            mods: Vec::new(),
+        }
+    }
+}
+
+impl ModsFile {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let file: ModsFile = serde_json::from_str(json)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if file.version != MODS_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported mods file version {}", file.version),
+            ));
+        }
+        Ok(file)
+    }
+
+    // A missing file enables no mods
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(json) => Self::from_json(&json),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+}
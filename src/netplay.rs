@@ -0,0 +1,669 @@
+// Peer-to-peer netplay with rollback
+// Both peers run the full simulation at a fixed tick rate and exchange only paddle commands.
+// Remote commands that have not arrived yet are predicted; when the real command turns out
+// to differ, the game is rolled back to that tick and re-simulated with the correct inputs.
+
+use crate::stats::MatchStats;
+use crate::{save, Ball, Game, GameEvent, GameState, Paddle, PaddleCommand, RuleSet, Side};
+use ::rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+pub const TICK_RATE: u32 = 60;
+pub const TICK_DT: f32 = 1.0 / TICK_RATE as f32;
+// A windowed loop that falls further behind than this drops the extra time rather than
+// running ever more ticks per frame and never drawing again
+pub const MAX_TICKS_PER_FRAME: u32 = 4;
+pub const MAX_PACKET_SIZE: usize = 2048;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NetConfig {
+    // Local commands are applied this many ticks after they are read
+    pub input_delay: u32,
+    // How far the simulation may run ahead of the last confirmed remote command
+    pub max_rollback: u32,
+    // Confirmed states are checksummed and compared every this many ticks
+    pub checksum_interval: u32,
+    pub disconnect_timeout: Duration,
+}
+
+impl Default for NetConfig {
+    fn default() -> Self {
+        Self {
+            input_delay: 2,
+            max_rollback: 12,
+            checksum_interval: 30,
+            disconnect_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum NetError {
+    Io(io::Error),
+    // The peers' confirmed states differ at this tick
+    Desync { tick: u32 },
+    // The peer started its session from a different seed
+    SeedMismatch { local: u64, remote: u64 },
+    Disconnected,
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetError::Io(err) => write!(f, "network error: {}", err),
+            NetError::Desync { tick } => write!(f, "simulation desync detected at tick {}", tick),
+            NetError::SeedMismatch { local, remote } => {
+                write!(
+                    f,
+                    "peer uses seed {} but this session uses {}",
+                    remote, local
+                )
+            }
+            NetError::Disconnected => write!(f, "peer stopped responding"),
+        }
+    }
+}
+
+impl std::error::Error for NetError {}
+
+impl From<io::Error> for NetError {
+    fn from(err: io::Error) -> Self {
+        NetError::Io(err)
+    }
+}
+
+// What a rollback restores: the gameplay state, plus the bookkeeping stepping adds to
+// Statistics are a fixed size and cheap to copy; shot analytics only ever grow, so only their
+// length is kept and re-simulated ticks replace what was recorded after it. Capturing a state
+// costs the same however long the match has run.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct RollbackState {
+    player_paddle: Paddle,
+    ai_paddle: Paddle,
+    ball: Ball,
+    player_score: i32,
+    ai_score: i32,
+    game_state: GameState,
+    rng: ChaCha8Rng,
+    rally_length: u32,
+    // Not part of the checksum, which only compares gameplay
+    #[serde(skip)]
+    stats: MatchStats,
+    #[serde(skip)]
+    shot_events: usize,
+    #[serde(skip)]
+    ticks: u64,
+}
+
+impl RollbackState {
+    fn capture(game: &Game) -> Self {
+        Self {
+            player_paddle: game.player_paddle.clone(),
+            ai_paddle: game.ai_paddle.clone(),
+            ball: game.ball.clone(),
+            player_score: game.player_score,
+            ai_score: game.ai_score,
+            game_state: game.game_state,
+            rng: game.rng.clone(),
+            rally_length: game.rally_length,
+            stats: game.stats.clone(),
+            shot_events: game.analytics.events.len(),
+            ticks: game.ticks,
+        }
+    }
+
+    fn restore(self, game: &mut Game) {
+        game.player_paddle = self.player_paddle;
+        game.ai_paddle = self.ai_paddle;
+        game.ball = self.ball;
+        game.player_score = self.player_score;
+        game.ai_score = self.ai_score;
+        game.game_state = self.game_state;
+        game.rng = self.rng;
+        game.rally_length = self.rally_length;
+        game.stats = self.stats;
+        game.analytics.events.truncate(self.shot_events);
+        game.ticks = self.ticks;
+    }
+
+    fn checksum(&self) -> u64 {
+        save::checksum(self)
+    }
+}
+
+// Wire format: every packet carries all unacknowledged commands so lost packets are harmless
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InputPacket {
+    pub seed: u64,
+    pub start_tick: u32,
+    pub commands: Vec<PaddleCommand>,
+    // The sender has received every command before this tick
+    pub ack: u32,
+    pub checksum: Option<(u32, u64)>,
+    // The sender's profile rating, sent like the seed so each peer learns the other's; None
+    // when the sender plays without a profile
+    #[serde(default)]
+    pub rating: Option<f32>,
+    // The sender's player name, sent like the rating so spectators can be shown both names
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+// Datagram transport between the two peers
+pub trait Transport {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()>;
+    // Returns the next pending packet without blocking
+    fn recv(&mut self) -> io::Result<Option<Vec<u8>>>;
+}
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+    peer: SocketAddr,
+}
+
+impl UdpTransport {
+    pub fn bind(local: SocketAddr, peer: SocketAddr) -> io::Result<Self> {
+        Self::new(UdpSocket::bind(local)?, peer)
+    }
+
+    pub fn new(socket: UdpSocket, peer: SocketAddr) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket, peer })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        match self.socket.send_to(packet, self.peer) {
+            Ok(_) => Ok(()),
+            // A full socket buffer behaves like a lost packet
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn recv(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut buf = [0u8; MAX_PACKET_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, from)) if from == self.peer => return Ok(Some(buf[..len].to_vec())),
+                Ok(_) => continue, // Ignore strangers
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => return Ok(None),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+// In-process transport, handy for tests and local experiments
+pub struct ChannelTransport {
+    tx: Sender<Vec<u8>>,
+    rx: Receiver<Vec<u8>>,
+}
+
+pub fn channel_pair() -> (ChannelTransport, ChannelTransport) {
+    let (a_tx, b_rx) = channel();
+    let (b_tx, a_rx) = channel();
+    (
+        ChannelTransport { tx: a_tx, rx: a_rx },
+        ChannelTransport { tx: b_tx, rx: b_rx },
+    )
+}
+
+impl Transport for ChannelTransport {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        // A dropped receiver is just a peer that went away
+        let _ = self.tx.send(packet.to_vec());
+        Ok(())
+    }
+
+    fn recv(&mut self) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.rx.try_recv().ok())
+    }
+}
+
+// Packet loss and latency applied to outgoing packets
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkConditions {
+    pub loss: f32,
+    pub latency: Duration,
+    pub jitter: Duration,
+}
+
+pub struct SimulatedLink<T: Transport> {
+    inner: T,
+    conditions: LinkConditions,
+    rng: ChaCha8Rng,
+    in_flight: Vec<(Instant, Vec<u8>)>,
+}
+
+impl<T: Transport> SimulatedLink<T> {
+    pub fn new(inner: T, conditions: LinkConditions, seed: u64) -> Self {
+        Self {
+            inner,
+            conditions,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            in_flight: Vec::new(),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        let mut i = 0;
+        while i < self.in_flight.len() {
+            if self.in_flight[i].0 <= now {
+                let (_, packet) = self.in_flight.remove(i);
+                self.inner.send(&packet)?;
+            } else {
+                i += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Transport> Transport for SimulatedLink<T> {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        if self.rng.gen::<f32>() >= self.conditions.loss {
+            let jitter = self.conditions.jitter.mul_f32(self.rng.gen::<f32>());
+            let due = Instant::now() + self.conditions.latency + jitter;
+            self.in_flight.push((due, packet.to_vec()));
+        }
+        self.flush()
+    }
+
+    fn recv(&mut self) -> io::Result<Option<Vec<u8>>> {
+        self.flush()?;
+        self.inner.recv()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdvanceResult {
+    Advanced,
+    // Too far ahead of the peer; the tick was not simulated and the command was dropped
+    Stalled,
+}
+
+pub struct RollbackSession<T: Transport> {
+    pub game: Game,
+    transport: T,
+    config: NetConfig,
+    seed: u64,
+    local_side: Side,
+    // Next tick to simulate
+    tick: u32,
+    local_inputs: BTreeMap<u32, PaddleCommand>,
+    remote_inputs: BTreeMap<u32, PaddleCommand>,
+    // Every remote command before this tick has been received
+    remote_confirmed: u32,
+    // The peer has received every local command before this tick
+    remote_ack: u32,
+    // Remote commands that were guessed when simulating unconfirmed ticks
+    predictions: BTreeMap<u32, PaddleCommand>,
+    // State at the start of each tick that may still be rolled back
+    snapshots: VecDeque<(u32, RollbackState)>,
+    // Events of simulated ticks and their match time, held back from the game's listeners
+    // until the tick is confirmed so a rollback never reaches them
+    unconfirmed_events: BTreeMap<u32, (Vec<GameEvent>, f32)>,
+    local_checksum: Option<(u32, u64)>,
+    pending_remote_checksums: BTreeMap<u32, u64>,
+    pending_local_checksums: BTreeMap<u32, u64>,
+    last_received: Instant,
+    rollbacks: u32,
+    local_rating: Option<f32>,
+    remote_rating: Option<f32>,
+    local_name: Option<String>,
+    remote_name: Option<String>,
+}
+
+impl<T: Transport> RollbackSession<T> {
+    pub fn new(seed: u64, local_side: Side, config: NetConfig, transport: T) -> Self {
+        let mut game = Game::with_seed(seed);
+        game.ai_side = None;
+
+        // Ticks before the input delay has elapsed have no commands from either side
+        let local_inputs = (0..config.input_delay)
+            .map(|tick| (tick, PaddleCommand::Stay))
+            .collect();
+
+        Self {
+            game,
+            transport,
+            config,
+            seed,
+            local_side,
+            tick: 0,
+            local_inputs,
+            remote_inputs: BTreeMap::new(),
+            remote_confirmed: 0,
+            remote_ack: 0,
+            predictions: BTreeMap::new(),
+            snapshots: VecDeque::new(),
+            unconfirmed_events: BTreeMap::new(),
+            local_checksum: None,
+            pending_remote_checksums: BTreeMap::new(),
+            pending_local_checksums: BTreeMap::new(),
+            last_received: Instant::now(),
+            rollbacks: 0,
+            local_rating: None,
+            remote_rating: None,
+            local_name: None,
+            remote_name: None,
+        }
+    }
+
+    // A session played under `rules` instead of the defaults; both peers must agree on them
+    pub fn with_rules(
+        seed: u64,
+        local_side: Side,
+        config: NetConfig,
+        rules: RuleSet,
+        transport: T,
+    ) -> Self {
+        let mut session = Self::new(seed, local_side, config, transport);
+        session.game.rules = rules;
+        // Serve again so the first rally already uses the agreed ball speed
+        session.game.reset();
+        session
+    }
+
+    // The local player's profile rating, told to the peer for rating the match
+    pub fn set_rating(&mut self, rating: Option<f32>) {
+        self.local_rating = rating;
+    }
+
+    // The peer's profile rating, once a packet has arrived; None while unknown or unrated
+    pub fn remote_rating(&self) -> Option<f32> {
+        self.remote_rating
+    }
+
+    // The local player's name, told to the peer along with the rating
+    pub fn set_name(&mut self, name: Option<String>) {
+        self.local_name = name;
+    }
+
+    // The peer's name, once a packet carrying one has arrived
+    pub fn remote_name(&self) -> Option<&str> {
+        self.remote_name.as_deref()
+    }
+
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    pub fn confirmed_tick(&self) -> u32 {
+        self.remote_confirmed.min(self.tick)
+    }
+
+    pub fn local_side(&self) -> Side {
+        self.local_side
+    }
+
+    pub fn rollback_count(&self) -> u32 {
+        self.rollbacks
+    }
+
+    // Simulate one tick with the local player's command, rolling back first if needed
+    pub fn advance(&mut self, command: PaddleCommand) -> Result<AdvanceResult, NetError> {
+        self.receive()?;
+
+        if self.tick >= self.remote_confirmed + self.config.max_rollback {
+            self.send_inputs()?;
+            return Ok(AdvanceResult::Stalled);
+        }
+
+        self.local_inputs
+            .insert(self.tick + self.config.input_delay, command);
+        self.send_inputs()?;
+        self.simulate_tick();
+        self.dispatch_confirmed_events();
+        self.exchange_checksums()?;
+        Ok(AdvanceResult::Advanced)
+    }
+
+    // Process incoming packets and resend unacknowledged commands without advancing
+    // Call this every frame while not advancing so the peer never waits on a lost packet
+    pub fn poll(&mut self) -> Result<(), NetError> {
+        self.receive()?;
+        self.send_inputs()
+    }
+
+    // Process incoming packets and correct any mispredictions
+    fn receive(&mut self) -> Result<(), NetError> {
+        let mut rollback_to: Option<u32> = None;
+
+        while let Some(bytes) = self.transport.recv()? {
+            let packet: InputPacket = match serde_json::from_slice(&bytes) {
+                Ok(packet) => packet,
+                Err(_) => continue,
+            };
+            if packet.seed != self.seed {
+                return Err(NetError::SeedMismatch {
+                    local: self.seed,
+                    remote: packet.seed,
+                });
+            }
+            self.last_received = Instant::now();
+            self.remote_ack = self.remote_ack.max(packet.ack);
+            self.remote_rating = packet.rating;
+            if packet.name.is_some() {
+                self.remote_name = packet.name;
+            }
+
+            if let Some((tick, checksum)) = packet.checksum {
+                self.pending_remote_checksums.insert(tick, checksum);
+            }
+
+            for (i, &command) in packet.commands.iter().enumerate() {
+                let tick = packet.start_tick + i as u32;
+                if tick >= self.remote_confirmed {
+                    self.remote_inputs.entry(tick).or_insert(command);
+                }
+            }
+
+            while let Some(&command) = self.remote_inputs.get(&self.remote_confirmed) {
+                if let Some(predicted) = self.predictions.remove(&self.remote_confirmed) {
+                    if predicted != command && rollback_to.is_none() {
+                        rollback_to = Some(self.remote_confirmed);
+                    }
+                }
+                self.remote_confirmed += 1;
+            }
+        }
+
+        if let Some(tick) = rollback_to {
+            self.rollback(tick);
+        }
+        self.dispatch_confirmed_events();
+
+        if self.last_received.elapsed() > self.config.disconnect_timeout {
+            return Err(NetError::Disconnected);
+        }
+
+        self.exchange_checksums()?;
+        self.prune();
+        Ok(())
+    }
+
+    fn remote_command(&mut self, tick: u32) -> PaddleCommand {
+        if let Some(&command) = self.remote_inputs.get(&tick) {
+            return command;
+        }
+
+        // Predict that the peer keeps doing whatever it did last
+        let predicted = self
+            .remote_inputs
+            .range(..tick)
+            .next_back()
+            .map(|(_, &command)| command)
+            .unwrap_or_default();
+        self.predictions.insert(tick, predicted);
+        predicted
+    }
+
+    fn simulate_tick(&mut self) {
+        let tick = self.tick;
+        self.snapshots
+            .push_back((tick, RollbackState::capture(&self.game)));
+
+        let local = self.local_inputs.get(&tick).copied().unwrap_or_default();
+        let remote = self.remote_command(tick);
+        let (left, right) = match self.local_side {
+            Side::Left => (local, remote),
+            Side::Right => (remote, local),
+        };
+
+        // Listeners hear the tick once it is confirmed, see dispatch_confirmed_events
+        let listeners = std::mem::take(&mut self.game.listeners);
+        let time = self.game.stats.time_played;
+        self.game.step(TICK_DT, left, right);
+        self.game.listeners = listeners;
+        self.unconfirmed_events
+            .insert(tick, (self.game.events.as_slice().to_vec(), time));
+        self.tick += 1;
+    }
+
+    // Hand the events of newly confirmed ticks to the game's listeners, in tick order
+    fn dispatch_confirmed_events(&mut self) {
+        let confirmed = self.confirmed_tick();
+        while let Some(entry) = self.unconfirmed_events.first_entry() {
+            if *entry.key() >= confirmed {
+                break;
+            }
+            let (events, time) = entry.remove();
+            for event in &events {
+                for listener in &mut self.game.listeners {
+                    listener.on_event(event, time);
+                }
+            }
+        }
+    }
+
+    fn rollback(&mut self, to_tick: u32) {
+        let index = match self.snapshots.iter().position(|(tick, _)| *tick == to_tick) {
+            Some(index) => index,
+            None => return,
+        };
+        let state = self.snapshots[index].1.clone();
+        self.snapshots.truncate(index);
+
+        // Visuals are left alone; silence sounds while re-simulating already-presented ticks.
+        // Listeners have only heard confirmed ticks, so they get the corrected events later
+        let sounds = (
+            self.game.paddle_hit_sound.take(),
+            self.game.wall_hit_sound.take(),
+            self.game.score_sound.take(),
+        );
+
+        state.restore(&mut self.game);
+
+        let target = self.tick;
+        self.tick = to_tick;
+        self.predictions.retain(|&tick, _| tick < to_tick);
+        self.unconfirmed_events.retain(|&tick, _| tick < to_tick);
+        while self.tick < target {
+            self.simulate_tick();
+        }
+
+        self.game.paddle_hit_sound = sounds.0;
+        self.game.wall_hit_sound = sounds.1;
+        self.game.score_sound = sounds.2;
+        self.rollbacks += 1;
+    }
+
+    fn confirmed_state(&self, tick: u32) -> Option<RollbackState> {
+        if tick == self.tick {
+            return Some(RollbackState::capture(&self.game));
+        }
+        self.snapshots
+            .iter()
+            .find(|(snapshot_tick, _)| *snapshot_tick == tick)
+            .map(|(_, state)| state.clone())
+    }
+
+    fn exchange_checksums(&mut self) -> Result<(), NetError> {
+        let interval = self.config.checksum_interval.max(1);
+        let next = self.local_checksum.map_or(0, |(tick, _)| tick + interval);
+
+        if next <= self.confirmed_tick() {
+            if let Some(state) = self.confirmed_state(next) {
+                let checksum = state.checksum();
+                self.local_checksum = Some((next, checksum));
+                self.pending_local_checksums.insert(next, checksum);
+            }
+        }
+
+        let local_ticks: Vec<u32> = self.pending_local_checksums.keys().copied().collect();
+        for tick in local_ticks {
+            if let Some(remote) = self.pending_remote_checksums.remove(&tick) {
+                let local = self.pending_local_checksums.remove(&tick);
+                if local != Some(remote) {
+                    return Err(NetError::Desync { tick });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn send_inputs(&mut self) -> Result<(), NetError> {
+        let start_tick = self.remote_ack;
+        let commands: Vec<PaddleCommand> = self
+            .local_inputs
+            .range(start_tick..)
+            .map(|(_, &command)| command)
+            .collect();
+
+        let packet = InputPacket {
+            seed: self.seed,
+            start_tick,
+            commands,
+            ack: self.remote_confirmed,
+            checksum: self.local_checksum,
+            rating: self.local_rating,
+            name: self.local_name.clone(),
+        };
+        let bytes = serde_json::to_vec(&packet).map_err(io::Error::from)?;
+        self.transport.send(&bytes)?;
+        Ok(())
+    }
+
+    fn prune(&mut self) {
+        // Nothing before the first unconfirmed tick can be rolled back any more
+        let floor = self.confirmed_tick();
+        let next_checksum = self
+            .local_checksum
+            .map_or(0, |(tick, _)| tick + self.config.checksum_interval.max(1));
+        let snapshot_floor = floor.min(next_checksum);
+
+        while matches!(self.snapshots.front(), Some((tick, _)) if *tick < snapshot_floor) {
+            self.snapshots.pop_front();
+        }
+
+        // Keep the latest confirmed remote command around for prediction
+        let remote_floor = floor.saturating_sub(1);
+        self.remote_inputs.retain(|&tick, _| tick >= remote_floor);
+
+        let local_floor = floor.min(self.remote_ack);
+        self.local_inputs.retain(|&tick, _| tick >= local_floor);
+
+        // Checksums the peer skipped past can never be compared
+        let horizon = 10 * self.config.checksum_interval.max(1);
+        self.pending_remote_checksums
+            .retain(|&tick, _| tick + horizon > floor);
+        self.pending_local_checksums
+            .retain(|&tick, _| tick + horizon > floor);
+    }
+}
@@ -0,0 +1,422 @@
+// Particles: presets, emitters and a fixed-size pool
+// Every particle in a game lives in one ParticleSystem whose storage is allocated up front and
+// never grows past its budget; emitters asking for more are simply given fewer particles. The
+// system has its own RNG, so effects never change the gameplay RNG or the match.
+
+use crate::Vec2D;
+use ::rand::{Rng, SeedableRng};
+use macroquad::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use std::f32::consts::PI;
+
+// Most particles alive at once in a game
+pub const PARTICLE_BUDGET: usize = 512;
+
+// Where particles start and which way they fly, relative to the emitter
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpawnShape {
+    // From the emitter, in every direction
+    Point,
+    // From anywhere in a disc, flying outwards
+    Circle { radius: f32 },
+    // From the emitter, within `spread` radians either side of its direction
+    Cone { spread: f32 },
+    // From a line of `length` across the emitter's direction, flying within `spread` of it
+    Line { length: f32, spread: f32 },
+}
+
+// How a value moves from its start to its end over a particle's life
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Curve {
+    Linear,
+    // Changes slowly at first
+    EaseIn,
+    // Changes quickly at first
+    EaseOut,
+}
+
+impl Curve {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => t,
+            Curve::EaseIn => t * t,
+            Curve::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParticlePreset {
+    // Particles in one burst
+    pub count: u32,
+    pub shape: SpawnShape,
+    pub speed: (f32, f32),
+    pub lifetime: (f32, f32),
+    pub start_color: Color,
+    pub end_color: Color,
+    // When not empty, each particle starts as one of these instead of `start_color` and fades
+    // to it with the alpha of `end_color`
+    pub palette: &'static [Color],
+    // Themed particles are tinted with the theme's particle colour when drawn
+    pub themed: bool,
+    pub start_size: f32,
+    pub end_size: f32,
+    pub size_curve: Curve,
+    // Downward acceleration in logical pixels per second squared; negative floats upwards
+    pub gravity: f32,
+    // Fraction of the velocity lost per second
+    pub drag: f32,
+}
+
+const CONFETTI_COLORS: [Color; 6] = [
+    Color::from_hex(0xff4d4d),
+    Color::from_hex(0xffd93d),
+    Color::from_hex(0x6bcb77),
+    Color::from_hex(0x4d96ff),
+    Color::from_hex(0xc77dff),
+    Color::from_hex(0xff9f1c),
+];
+
+impl ParticlePreset {
+    // Quick bright flecks thrown off a paddle hit
+    pub const SPARK: ParticlePreset = ParticlePreset {
+        count: 10,
+        shape: SpawnShape::Cone { spread: 0.7 },
+        speed: (150.0, 320.0),
+        lifetime: (0.2, 0.45),
+        start_color: WHITE,
+        end_color: Color::new(1.0, 0.6, 0.2, 0.0),
+        palette: &[],
+        themed: true,
+        start_size: 2.5,
+        end_size: 0.5,
+        size_curve: Curve::Linear,
+        gravity: 0.0,
+        drag: 3.0,
+    };
+
+    // Coloured pieces that fall after a goal
+    pub const CONFETTI: ParticlePreset = ParticlePreset {
+        count: 30,
+        shape: SpawnShape::Circle { radius: 10.0 },
+        speed: (80.0, 260.0),
+        lifetime: (1.2, 2.2),
+        start_color: WHITE,
+        end_color: Color::new(1.0, 1.0, 1.0, 0.0),
+        palette: &CONFETTI_COLORS,
+        themed: false,
+        start_size: 3.0,
+        end_size: 2.0,
+        size_curve: Curve::EaseIn,
+        gravity: 220.0,
+        drag: 1.5,
+    };
+
+    // Slow puffs that grow and drift up where the ball hits a wall
+    pub const SMOKE: ParticlePreset = ParticlePreset {
+        count: 5,
+        shape: SpawnShape::Circle { radius: 6.0 },
+        speed: (5.0, 25.0),
+        lifetime: (0.6, 1.2),
+        start_color: Color::new(0.6, 0.6, 0.6, 0.5),
+        end_color: Color::new(0.6, 0.6, 0.6, 0.0),
+        palette: &[],
+        themed: true,
+        start_size: 3.0,
+        end_size: 9.0,
+        size_curve: Curve::EaseOut,
+        gravity: -40.0,
+        drag: 2.0,
+    };
+
+    // A wall of particles thrown back into the court from the goal line
+    pub const GOAL_BURST: ParticlePreset = ParticlePreset {
+        count: 24,
+        shape: SpawnShape::Line {
+            length: 120.0,
+            spread: PI / 3.0,
+        },
+        speed: (100.0, 350.0),
+        lifetime: (0.5, 1.2),
+        start_color: WHITE,
+        end_color: Color::new(1.0, 1.0, 1.0, 0.0),
+        palette: &[],
+        themed: true,
+        start_size: 4.0,
+        end_size: 0.0,
+        size_curve: Curve::EaseIn,
+        gravity: 0.0,
+        drag: 2.0,
+    };
+
+    pub const NAMES: [&'static str; 4] = ["spark", "confetti", "smoke", "goal_burst"];
+
+    pub fn named(name: &str) -> Option<ParticlePreset> {
+        match name {
+            "spark" => Some(Self::SPARK),
+            "confetti" => Some(Self::CONFETTI),
+            "smoke" => Some(Self::SMOKE),
+            "goal_burst" => Some(Self::GOAL_BURST),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Particle {
+    pub position: Vec2D,
+    pub velocity: Vec2D,
+    pub lifetime: f32,
+    pub max_lifetime: f32,
+    pub color: Color,
+    pub preset: ParticlePreset,
+}
+
+fn range(rng: &mut impl Rng, (low, high): (f32, f32)) -> f32 {
+    if high > low {
+        rng.gen_range(low..high)
+    } else {
+        low
+    }
+}
+
+impl Particle {
+    // A single spark flying in any direction
+    pub fn new(x: f32, y: f32) -> Self {
+        let preset = ParticlePreset {
+            shape: SpawnShape::Point,
+            ..ParticlePreset::SPARK
+        };
+        Self::spawn(
+            &preset,
+            Vec2D::new(x, y),
+            Vec2D::new(1.0, 0.0),
+            &mut ::rand::thread_rng(),
+        )
+    }
+
+    // One particle of `preset` from an emitter at `position` facing `direction`
+    pub fn spawn(
+        preset: &ParticlePreset,
+        position: Vec2D,
+        direction: Vec2D,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let facing = direction.y.atan2(direction.x);
+        let (offset, angle) = match preset.shape {
+            SpawnShape::Point => (Vec2D::zero(), rng.gen_range(0.0..2.0 * PI)),
+            SpawnShape::Circle { radius } => {
+                let angle = rng.gen_range(0.0..2.0 * PI);
+                // Square root keeps the disc evenly filled
+                let distance = radius * rng.gen::<f32>().sqrt();
+                let offset = Vec2D::new(angle.cos() * distance, angle.sin() * distance);
+                (offset, angle)
+            }
+            SpawnShape::Cone { spread } => (Vec2D::zero(), facing + range(rng, (-spread, spread))),
+            SpawnShape::Line { length, spread } => {
+                let along = range(rng, (-length / 2.0, length / 2.0));
+                let offset = Vec2D::new(-facing.sin() * along, facing.cos() * along);
+                (offset, facing + range(rng, (-spread, spread)))
+            }
+        };
+        let speed = range(rng, preset.speed);
+        let lifetime = range(rng, preset.lifetime).max(f32::EPSILON);
+        let color = if preset.palette.is_empty() {
+            preset.start_color
+        } else {
+            preset.palette[rng.gen_range(0..preset.palette.len())]
+        };
+
+        Self {
+            position: Vec2D::new(position.x + offset.x, position.y + offset.y),
+            velocity: Vec2D::new(angle.cos() * speed, angle.sin() * speed),
+            lifetime,
+            max_lifetime: lifetime,
+            color,
+            preset: *preset,
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) -> bool {
+        let keep = (1.0 - self.preset.drag * dt).max(0.0);
+        self.velocity.x *= keep;
+        self.velocity.y = self.velocity.y * keep + self.preset.gravity * dt;
+        self.position.x += self.velocity.x * dt;
+        self.position.y += self.velocity.y * dt;
+        self.lifetime -= dt;
+
+        self.lifetime > 0.0
+    }
+
+    // How far through its life the particle is, from 0 to 1
+    pub fn age(&self) -> f32 {
+        (1.0 - self.lifetime / self.max_lifetime).clamp(0.0, 1.0)
+    }
+
+    pub fn size(&self) -> f32 {
+        let t = self.preset.size_curve.apply(self.age());
+        self.preset.start_size + (self.preset.end_size - self.preset.start_size) * t
+    }
+
+    // The colour at the particle's age, before any theme tint
+    pub fn current_color(&self) -> Color {
+        let end = if self.preset.palette.is_empty() {
+            self.preset.end_color
+        } else {
+            self.color.with_alpha(self.preset.end_color.a)
+        };
+        let t = self.age();
+        let mix = |from: f32, to: f32| from + (to - from) * t;
+        Color::new(
+            mix(self.color.r, end.r),
+            mix(self.color.g, end.g),
+            mix(self.color.b, end.b),
+            mix(self.color.a, end.a),
+        )
+    }
+
+    // The colour to draw; themed presets are multiplied by `tint`
+    pub fn tinted_color(&self, tint: Color) -> Color {
+        let color = self.current_color();
+        if !self.preset.themed {
+            return color;
+        }
+        Color::new(
+            color.r * tint.r,
+            color.g * tint.g,
+            color.b * tint.b,
+            color.a * tint.a,
+        )
+    }
+
+    pub fn draw(&self, tint: Color) {
+        let color = self.tinted_color(tint);
+        let size = self.size();
+        if size > 0.0 && color.a > 0.0 {
+            draw_circle(self.position.x, self.position.y, size, color);
+        }
+    }
+}
+
+// A preset placed in the world; bursts spawn a whole preset, a rate spawns particles over time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Emitter {
+    pub preset: ParticlePreset,
+    pub position: Vec2D,
+    pub direction: Vec2D,
+    // Particles per second for `update`
+    pub rate: f32,
+    pending: f32,
+}
+
+impl Emitter {
+    pub fn new(preset: ParticlePreset, position: Vec2D) -> Self {
+        Self {
+            preset,
+            position,
+            direction: Vec2D::new(1.0, 0.0),
+            rate: 0.0,
+            pending: 0.0,
+        }
+    }
+
+    pub fn aimed(mut self, direction: Vec2D) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    // Returns how many particles the budget allowed
+    pub fn burst(&self, particles: &mut ParticleSystem) -> u32 {
+        particles.emit(
+            &self.preset,
+            self.preset.count,
+            self.position,
+            self.direction,
+        )
+    }
+
+    pub fn update(&mut self, dt: f32, particles: &mut ParticleSystem) -> u32 {
+        self.pending += self.rate * dt;
+        let count = self.pending.floor().max(0.0);
+        self.pending -= count;
+        particles.emit(&self.preset, count as u32, self.position, self.direction)
+    }
+}
+
+pub struct ParticleSystem {
+    particles: Vec<Particle>,
+    budget: usize,
+    rng: ChaCha8Rng,
+}
+
+impl Default for ParticleSystem {
+    fn default() -> Self {
+        Self::new(PARTICLE_BUDGET, 0)
+    }
+}
+
+impl ParticleSystem {
+    pub fn new(budget: usize, seed: u64) -> Self {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        // A different stream from a gameplay RNG with the same seed
+        rng.set_stream(1);
+        Self {
+            particles: Vec::with_capacity(budget),
+            budget,
+            rng,
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Particle> {
+        self.particles.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    // Spawn up to `count` particles, as many as the budget has room for
+    pub fn emit(
+        &mut self,
+        preset: &ParticlePreset,
+        count: u32,
+        position: Vec2D,
+        direction: Vec2D,
+    ) -> u32 {
+        let room = self.budget - self.particles.len();
+        let count = (count as usize).min(room);
+        for _ in 0..count {
+            let particle = Particle::spawn(preset, position, direction, &mut self.rng);
+            self.particles.push(particle);
+        }
+        count as u32
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        self.particles.retain_mut(|particle| particle.update(dt));
+    }
+
+    pub fn draw(&self, tint: Color) {
+        for particle in &self.particles {
+            particle.draw(tint);
+        }
+    }
+}
@@ -0,0 +1,319 @@
+// Post-processing: the frame is drawn into a texture, then to the window through a CRT shader
+// The passes (bloom, scanlines, curvature, chromatic aberration and vignette) run in one GLSL 100
+// fragment shader with fixed loop counts, so GLES 2 and software GL drivers can compile it. When
+// post-processing is off or the shader can't be built, paddles and the ball draw their own glow.
+
+use crate::view::Viewport;
+use crate::{WINDOW_HEIGHT, WINDOW_WIDTH};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::sync::RwLock;
+
+// Uniforms the fragment shader reads besides the frame texture
+pub const UNIFORMS: [(&str, UniformType); 6] = [
+    ("LogicalSize", UniformType::Float2),
+    ("Bloom", UniformType::Float1),
+    ("Scanlines", UniformType::Float1),
+    ("Curvature", UniformType::Float1),
+    ("Aberration", UniformType::Float1),
+    ("Vignette", UniformType::Float1),
+];
+
+// Each pass has a strength from 0 (off) to 1
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostFxSettings {
+    pub enabled: bool,
+    pub bloom: f32,
+    pub scanlines: f32,
+    pub curvature: f32,
+    pub chromatic_aberration: f32,
+    pub vignette: f32,
+}
+
+impl PostFxSettings {
+    pub const DEFAULT: PostFxSettings = PostFxSettings {
+        enabled: false,
+        bloom: 0.6,
+        scanlines: 0.35,
+        curvature: 0.3,
+        chromatic_aberration: 0.4,
+        vignette: 0.5,
+    };
+
+    // Whether any pass would change the picture
+    pub fn is_active(&self) -> bool {
+        self.enabled
+            && [
+                self.bloom,
+                self.scanlines,
+                self.curvature,
+                self.chromatic_aberration,
+                self.vignette,
+            ]
+            .iter()
+            .any(|&strength| strength > 0.0)
+    }
+
+    pub fn clamped(&self) -> Self {
+        let clamp = |strength: f32| {
+            if strength.is_finite() {
+                strength.clamp(0.0, 1.0)
+            } else {
+                0.0
+            }
+        };
+        Self {
+            enabled: self.enabled,
+            bloom: clamp(self.bloom),
+            scanlines: clamp(self.scanlines),
+            curvature: clamp(self.curvature),
+            chromatic_aberration: clamp(self.chromatic_aberration),
+            vignette: clamp(self.vignette),
+        }
+    }
+}
+
+impl Default for PostFxSettings {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+static SETTINGS: RwLock<PostFxSettings> = RwLock::new(PostFxSettings::DEFAULT);
+
+pub fn settings() -> PostFxSettings {
+    *SETTINGS.read().unwrap_or_else(|err| err.into_inner())
+}
+
+pub fn set_settings(settings: PostFxSettings) {
+    *SETTINGS.write().unwrap_or_else(|err| err.into_inner()) = settings.clamped();
+}
+
+enum Pipeline {
+    Unbuilt,
+    // The shader didn't compile; the manual glow is used from then on
+    Failed,
+    Ready {
+        material: Material,
+        target: Option<RenderTarget>,
+        capturing: bool,
+    },
+}
+
+// GPU resources live on the render thread only
+thread_local! {
+    static PIPELINE: RefCell<Pipeline> = const { RefCell::new(Pipeline::Unbuilt) };
+}
+
+fn build() -> Pipeline {
+    let params = MaterialParams {
+        uniforms: UNIFORMS
+            .iter()
+            .map(|&(name, kind)| UniformDesc::new(name, kind))
+            .collect(),
+        ..Default::default()
+    };
+    let source = ShaderSource::Glsl {
+        vertex: VERTEX_SHADER,
+        fragment: FRAGMENT_SHADER,
+    };
+    match load_material(source, params) {
+        Ok(material) => Pipeline::Ready {
+            material,
+            target: None,
+            capturing: false,
+        },
+        Err(err) => {
+            eprintln!("Post-processing disabled, shader failed: {:?}", err);
+            Pipeline::Failed
+        }
+    }
+}
+
+// Whether the shader is running this frame; otherwise entities fake their own glow
+pub fn shaders_active() -> bool {
+    settings().is_active()
+        && PIPELINE.with(|pipeline| matches!(*pipeline.borrow(), Pipeline::Ready { .. }))
+}
+
+// Point drawing at the frame texture; returns false when post-processing is off
+pub fn begin_capture(viewport: &Viewport, dpi_scale: f32) -> bool {
+    if !settings().is_active() {
+        return false;
+    }
+    PIPELINE.with(|pipeline| {
+        let mut pipeline = pipeline.borrow_mut();
+        if matches!(*pipeline, Pipeline::Unbuilt) {
+            *pipeline = build();
+        }
+        let Pipeline::Ready {
+            target, capturing, ..
+        } = &mut *pipeline
+        else {
+            return false;
+        };
+
+        // The texture matches the viewport's pixels so HiDPI frames stay sharp
+        let width = (viewport.width() * dpi_scale).round().max(1.0) as u32;
+        let height = (viewport.height() * dpi_scale).round().max(1.0) as u32;
+        let resize = target.as_ref().is_none_or(|target| {
+            target.texture.width() as u32 != width || target.texture.height() as u32 != height
+        });
+        if resize {
+            let new_target = render_target(width, height);
+            new_target.texture.set_filter(FilterMode::Linear);
+            *target = Some(new_target);
+        }
+
+        set_camera(&capture_camera_for(target.clone()));
+        clear_background(BLACK);
+        *capturing = true;
+        true
+    })
+}
+
+fn capture_camera_for(target: Option<RenderTarget>) -> Camera2D {
+    let mut camera = Camera2D::from_display_rect(Rect::new(0.0, 0.0, WINDOW_WIDTH, WINDOW_HEIGHT));
+    camera.render_target = target;
+    camera
+}
+
+// The camera drawing into the frame texture, while a frame is being captured
+pub fn capture_camera() -> Option<Camera2D> {
+    PIPELINE.with(|pipeline| match &*pipeline.borrow() {
+        Pipeline::Ready {
+            target: Some(target),
+            capturing: true,
+            ..
+        } => Some(capture_camera_for(Some(target.clone()))),
+        _ => None,
+    })
+}
+
+// Draw the captured frame into the viewport through the shader
+pub fn present(viewport: &Viewport, dpi_scale: f32) {
+    PIPELINE.with(|pipeline| {
+        let mut pipeline = pipeline.borrow_mut();
+        let Pipeline::Ready {
+            material,
+            target: Some(target),
+            capturing,
+        } = &mut *pipeline
+        else {
+            return;
+        };
+        if !std::mem::take(capturing) {
+            return;
+        }
+
+        let fx = settings();
+        set_camera(&viewport.camera(dpi_scale));
+        material.set_uniform("LogicalSize", vec2(WINDOW_WIDTH, WINDOW_HEIGHT));
+        material.set_uniform("Bloom", fx.bloom);
+        material.set_uniform("Scanlines", fx.scanlines);
+        material.set_uniform("Curvature", fx.curvature);
+        material.set_uniform("Aberration", fx.chromatic_aberration);
+        material.set_uniform("Vignette", fx.vignette);
+        gl_use_material(material);
+        draw_texture_ex(
+            &target.texture,
+            0.0,
+            0.0,
+            WHITE,
+            DrawTextureParams {
+                dest_size: Some(vec2(WINDOW_WIDTH, WINDOW_HEIGHT)),
+                // Render targets are stored bottom row first
+                flip_y: true,
+                ..Default::default()
+            },
+        );
+        gl_use_default_material();
+    });
+}
+
+pub const VERTEX_SHADER: &str = "#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+
+varying mediump vec2 uv;
+varying lowp vec4 color;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    color = color0 / 255.0;
+    uv = texcoord;
+}
+";
+
+pub const FRAGMENT_SHADER: &str = "#version 100
+precision mediump float;
+
+varying mediump vec2 uv;
+varying lowp vec4 color;
+
+uniform sampler2D Texture;
+uniform vec2 LogicalSize;
+uniform float Bloom;
+uniform float Scanlines;
+uniform float Curvature;
+uniform float Aberration;
+uniform float Vignette;
+
+// Bend the picture like the glass of a CRT
+vec2 curve(vec2 p) {
+    p = p * 2.0 - 1.0;
+    vec2 offset = abs(p.yx) * Curvature * 0.35;
+    p = p + p * offset * offset;
+    return p * 0.5 + 0.5;
+}
+
+// Red and blue sampled slightly apart, in logical pixels
+vec3 split(vec2 p) {
+    vec2 shift = vec2(Aberration * 1.5 / LogicalSize.x, 0.0);
+    return vec3(
+        texture2D(Texture, p + shift).r,
+        texture2D(Texture, p).g,
+        texture2D(Texture, p - shift).b
+    );
+}
+
+// Average of two rings of taps around the pixel
+vec3 bloom(vec2 p) {
+    vec3 sum = vec3(0.0);
+    for (int i = 0; i < 12; i++) {
+        float angle = float(i) * 0.5236;
+        float radius = 3.0 + 3.0 * mod(float(i), 2.0);
+        vec2 offset = vec2(cos(angle), sin(angle)) * radius / LogicalSize;
+        sum += texture2D(Texture, p + offset).rgb;
+    }
+    return sum / 12.0;
+}
+
+void main() {
+    vec2 p = Curvature > 0.0 ? curve(uv) : uv;
+    if (p.x < 0.0 || p.x > 1.0 || p.y < 0.0 || p.y > 1.0) {
+        gl_FragColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec3 rgb = Aberration > 0.0 ? split(p) : texture2D(Texture, p).rgb;
+    if (Bloom > 0.0) {
+        rgb += bloom(p) * Bloom * 1.5;
+    }
+
+    // One dark line every other logical pixel row
+    float line = 0.5 + 0.5 * cos(p.y * LogicalSize.y * 3.14159);
+    rgb *= 1.0 - Scanlines * 0.6 * line;
+
+    float edge = 16.0 * p.x * p.y * (1.0 - p.x) * (1.0 - p.y);
+    rgb *= mix(1.0, clamp(pow(edge, 0.3), 0.0, 1.0), Vignette);
+
+    gl_FragColor = vec4(rgb, 1.0) * color;
+}
+";
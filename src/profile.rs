@@ -0,0 +1,464 @@
+// Local player profiles with career statistics and an Elo-style rating
+// All profiles live in one versioned file; older layouts are upgraded on load, one version at a
+// time, so a newer game never loses an older player's history.
+
+use crate::hud::{Layout, COLUMN_GAP, HEADING_SIZE, LIST_INDENT, MENU_INDENT};
+use crate::save::data_dir;
+use crate::stats::{format_duration, MatchStats};
+use crate::text::Align;
+use crate::theme;
+use crate::{Difficulty, Game, Side};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const PROFILES_VERSION: u32 = 2;
+pub const PROFILES_FILE_NAME: &str = "profiles.json";
+pub const MAX_NAME_LENGTH: usize = 16;
+pub const INITIAL_RATING: f32 = 1000.0;
+// How far a single match can move a rating
+pub const RATING_K: f32 = 32.0;
+
+pub fn default_profiles_path() -> PathBuf {
+    data_dir().join(PROFILES_FILE_NAME)
+}
+
+// Fixed ratings for the computer at each difficulty, so beating it is worth a sensible amount
+pub fn ai_rating(difficulty: Difficulty) -> f32 {
+    match difficulty {
+        Difficulty::Easy => 800.0,
+        Difficulty::Normal => 1000.0,
+        Difficulty::Hard => 1200.0,
+        Difficulty::Learned => 1100.0,
+    }
+}
+
+// Chance of winning for a player rated `rating` against `opponent`
+pub fn expected_score(rating: f32, opponent: f32) -> f32 {
+    1.0 / (1.0 + 10f32.powf((opponent - rating) / 400.0))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchMode {
+    VsAi,
+    Lan,
+    Netplay,
+    Online,
+}
+
+impl MatchMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            MatchMode::VsAi => "vs AI",
+            MatchMode::Lan => "LAN",
+            MatchMode::Netplay => "Netplay",
+            MatchMode::Online => "Online",
+        }
+    }
+}
+
+// A finished match from the point of view of the profile's paddle
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MatchOutcome {
+    pub mode: MatchMode,
+    pub difficulty: Option<Difficulty>,
+    pub won: bool,
+    pub points_for: u32,
+    pub points_against: u32,
+    pub longest_rally: u32,
+    pub paddle_hits: u32,
+    pub time_played: f32,
+    // None for opponents without a rating, which leave the profile's rating alone
+    pub opponent_rating: Option<f32>,
+}
+
+impl MatchOutcome {
+    pub fn from_game(
+        game: &Game,
+        side: Side,
+        mode: MatchMode,
+        difficulty: Option<Difficulty>,
+    ) -> Self {
+        let (own, other) = match side {
+            Side::Left => (game.player_score, game.ai_score),
+            Side::Right => (game.ai_score, game.player_score),
+        };
+        Self {
+            mode,
+            difficulty,
+            won: own > other,
+            points_for: own.max(0) as u32,
+            points_against: other.max(0) as u32,
+            longest_rally: game.stats.longest_rally,
+            paddle_hits: game.stats.side(side).paddle_hits,
+            time_played: game.stats.time_played,
+            opponent_rating: difficulty.map(ai_rating),
+        }
+    }
+
+    // A match played somewhere else, e.g. on a server, known only from its statistics;
+    // `won` is passed in because a forfeit can be won from behind
+    pub fn from_stats(stats: &MatchStats, side: Side, won: bool, mode: MatchMode) -> Self {
+        Self {
+            mode,
+            difficulty: None,
+            won,
+            points_for: stats.side(side).points,
+            points_against: stats.side(side.opponent()).points,
+            longest_rally: stats.longest_rally,
+            paddle_hits: stats.side(side).paddle_hits,
+            time_played: stats.time_played,
+            opponent_rating: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DifficultyRecord {
+    pub played: u32,
+    pub won: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    pub rating: f32,
+    pub peak_rating: f32,
+    pub matches: u32,
+    pub wins: u32,
+    pub current_streak: u32,
+    pub best_streak: u32,
+    pub longest_rally: u32,
+    pub points_for: u32,
+    pub points_against: u32,
+    pub paddle_hits: u32,
+    pub time_played: f32,
+    pub by_difficulty: BTreeMap<Difficulty, DifficultyRecord>,
+    pub by_mode: BTreeMap<MatchMode, u32>,
+    // Unlocked achievement ids with their unlock time in seconds since the Unix epoch
+    pub achievements: BTreeMap<String, u64>,
+}
+
+impl Profile {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            rating: INITIAL_RATING,
+            peak_rating: INITIAL_RATING,
+            matches: 0,
+            wins: 0,
+            current_streak: 0,
+            best_streak: 0,
+            longest_rally: 0,
+            points_for: 0,
+            points_against: 0,
+            paddle_hits: 0,
+            time_played: 0.0,
+            by_difficulty: BTreeMap::new(),
+            by_mode: BTreeMap::new(),
+            achievements: BTreeMap::new(),
+        }
+    }
+
+    pub fn losses(&self) -> u32 {
+        self.matches - self.wins
+    }
+
+    // The mode with the most matches; the earliest mode wins ties
+    pub fn favourite_mode(&self) -> Option<MatchMode> {
+        self.by_mode
+            .iter()
+            .fold(
+                None,
+                |best: Option<(MatchMode, u32)>, (&mode, &count)| match best {
+                    Some((_, best_count)) if best_count >= count => best,
+                    _ => Some((mode, count)),
+                },
+            )
+            .map(|(mode, _)| mode)
+    }
+
+    // Add a finished match to the career totals; returns the rating change, which is zero
+    // against unrated opponents
+    pub fn record(&mut self, outcome: &MatchOutcome) -> f32 {
+        self.matches += 1;
+        if outcome.won {
+            self.wins += 1;
+            self.current_streak += 1;
+            self.best_streak = self.best_streak.max(self.current_streak);
+        } else {
+            self.current_streak = 0;
+        }
+        self.longest_rally = self.longest_rally.max(outcome.longest_rally);
+        self.points_for += outcome.points_for;
+        self.points_against += outcome.points_against;
+        self.paddle_hits += outcome.paddle_hits;
+        self.time_played += outcome.time_played;
+        *self.by_mode.entry(outcome.mode).or_default() += 1;
+        if let Some(difficulty) = outcome.difficulty {
+            let record = self.by_difficulty.entry(difficulty).or_default();
+            record.played += 1;
+            record.won += u32::from(outcome.won);
+        }
+
+        let Some(opponent_rating) = outcome.opponent_rating else {
+            return 0.0;
+        };
+        let score = if outcome.won { 1.0 } else { 0.0 };
+        let change = RATING_K * (score - expected_score(self.rating, opponent_rating));
+        self.rating += change;
+        self.peak_rating = self.peak_rating.max(self.rating);
+        change
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Turn the layout of `version` into the next one
+fn upgrade(mut value: Value, version: u64) -> io::Result<Value> {
+    match version {
+        // Version 2 added achievements
+        1 => {
+            if let Some(profiles) = value.get_mut("profiles").and_then(Value::as_array_mut) {
+                for profile in profiles.iter_mut().filter_map(Value::as_object_mut) {
+                    profile.insert("achievements".to_owned(), Value::Object(Default::default()));
+                }
+            }
+        }
+        _ => {
+            return Err(invalid_data(format!(
+                "unsupported profiles version {}",
+                version
+            )))
+        }
+    }
+    value["version"] = Value::from(version + 1);
+    Ok(value)
+}
+
+// Bring a profiles file of any known version up to PROFILES_VERSION
+pub fn migrate(mut value: Value) -> io::Result<Value> {
+    loop {
+        let version = value
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| invalid_data("profiles file has no version".to_owned()))?;
+        if version == u64::from(PROFILES_VERSION) {
+            return Ok(value);
+        }
+        if version > u64::from(PROFILES_VERSION) {
+            return Err(invalid_data(format!(
+                "profiles version {} is newer than this game supports",
+                version
+            )));
+        }
+        value = upgrade(value, version)?;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProfileStore {
+    pub version: u32,
+    // Name of the profile that plays the next match
+    pub active: Option<String>,
+    pub profiles: Vec<Profile>,
+}
+
+impl Default for ProfileStore {
+    fn default() -> Self {
+        Self {
+            version: PROFILES_VERSION,
+            active: None,
+            profiles: Vec::new(),
+        }
+    }
+}
+
+impl ProfileStore {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let value = migrate(serde_json::from_str(json)?)?;
+        Ok(serde_json::from_value(value)?)
+    }
+
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, self.to_json()?)?;
+        fs::rename(&tmp_path, path)
+    }
+
+    pub fn read_from(path: &Path) -> io::Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    // A missing file is an empty store; anything unreadable is an error so it isn't overwritten
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match Self::read_from(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            result => result,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    pub fn active(&self) -> Option<&Profile> {
+        self.get(self.active.as_deref()?)
+    }
+
+    pub fn active_mut(&mut self) -> Option<&mut Profile> {
+        let name = self.active.as_deref()?;
+        self.profiles
+            .iter_mut()
+            .find(|profile| profile.name == name)
+    }
+
+    // Add a profile and make it active
+    pub fn create(&mut self, name: &str) -> Result<&Profile, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err("name cannot be empty".to_owned());
+        }
+        if name.chars().count() > MAX_NAME_LENGTH {
+            return Err(format!(
+                "name must be at most {} characters",
+                MAX_NAME_LENGTH
+            ));
+        }
+        if self
+            .profiles
+            .iter()
+            .any(|profile| profile.name.eq_ignore_ascii_case(name))
+        {
+            return Err(format!("{} already exists", name));
+        }
+        self.profiles.push(Profile::new(name));
+        self.active = Some(name.to_owned());
+        Ok(&self.profiles[self.profiles.len() - 1])
+    }
+
+    pub fn select(&mut self, name: &str) -> bool {
+        let exists = self.get(name).is_some();
+        if exists {
+            self.active = Some(name.to_owned());
+        }
+        exists
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Profile> {
+        let index = self
+            .profiles
+            .iter()
+            .position(|profile| profile.name == name)?;
+        if self.active.as_deref() == Some(name) {
+            self.active = None;
+        }
+        Some(self.profiles.remove(index))
+    }
+
+    // Record a match for the active profile; returns the rating change if one is active
+    pub fn record(&mut self, outcome: &MatchOutcome) -> Option<f32> {
+        Some(self.active_mut()?.record(outcome))
+    }
+}
+
+fn percentage(part: u32, whole: u32) -> String {
+    if whole == 0 {
+        "-".to_owned()
+    } else {
+        format!("{:.0}%", part as f32 * 100.0 / whole as f32)
+    }
+}
+
+// Label and value pairs for the career stats page
+pub fn career_rows(profile: &Profile) -> Vec<(String, String)> {
+    let mut rows = vec![
+        (
+            "Rating".to_owned(),
+            format!("{:.0} (peak {:.0})", profile.rating, profile.peak_rating),
+        ),
+        (
+            "Matches".to_owned(),
+            format!(
+                "{} ({} won, {} lost, {})",
+                profile.matches,
+                profile.wins,
+                profile.losses(),
+                percentage(profile.wins, profile.matches)
+            ),
+        ),
+        (
+            "Streak".to_owned(),
+            format!("{} (best {})", profile.current_streak, profile.best_streak),
+        ),
+        (
+            "Longest rally".to_owned(),
+            profile.longest_rally.to_string(),
+        ),
+        (
+            "Points".to_owned(),
+            format!(
+                "{} for, {} against",
+                profile.points_for, profile.points_against
+            ),
+        ),
+        ("Paddle hits".to_owned(), profile.paddle_hits.to_string()),
+        (
+            "Time played".to_owned(),
+            format_duration(profile.time_played),
+        ),
+        (
+            "Favourite mode".to_owned(),
+            profile
+                .favourite_mode()
+                .map_or("-".to_owned(), |mode| mode.name().to_owned()),
+        ),
+    ];
+    for difficulty in Difficulty::ALL {
+        let record = profile
+            .by_difficulty
+            .get(&difficulty)
+            .copied()
+            .unwrap_or_default();
+        rows.push((
+            format!("vs {} AI", difficulty.name()),
+            format!(
+                "{} / {} won ({})",
+                record.won,
+                record.played,
+                percentage(record.won, record.played)
+            ),
+        ));
+    }
+    rows
+}
+
+pub fn draw_career_stats(profile: &Profile) {
+    let theme = theme::active();
+    let layout = Layout::current();
+    let mut above = layout.heading(&profile.name.to_uppercase(), HEADING_SIZE, theme.text);
+    let rows = career_rows(profile);
+    let value_x = MENU_INDENT
+        + LIST_INDENT
+        + layout.column_width(rows.iter().map(|row| row.0.as_str()), 22.0)
+        + COLUMN_GAP;
+    for (label, value) in &rows {
+        above = layout.row(above, label, 22.0, theme.text_dim);
+        layout.line(value, value_x, above.y, 22.0, theme.text, Align::Left);
+    }
+    layout.footer("ESC to go back", theme.text_dim);
+}
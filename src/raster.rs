@@ -0,0 +1,208 @@
+// Software rasteriser: draws the court into an RGB image without a window or GPU
+// It draws the same scene as Game::draw during a match, with the glow the window draws when CRT
+// effects are off: court, trail, paddles, ball, particles, goal flash and scores. Menus, banners
+// and mod overlays are left out. Pixels are filled when their centre is covered, so the output
+// is crisp at any scale.
+
+use crate::hud::{Layout, MARGIN};
+use crate::theme::Theme;
+use crate::{text, Game, Side, WINDOW_HEIGHT, WINDOW_WIDTH};
+use macroquad::prelude::*;
+
+// An 8-bit RGB image, row by row
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(width: u32, height: u32, fill: Color) -> Self {
+        let [r, g, b, _] = fill.into();
+        Self {
+            width,
+            height,
+            pixels: [r, g, b].repeat((width * height) as usize),
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let i = ((y * self.width + x) * 3) as usize;
+        [self.pixels[i], self.pixels[i + 1], self.pixels[i + 2]]
+    }
+
+    // Blend `color` over the pixels whose centres lie inside `rect`, in image pixels
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) {
+        let columns = span(rect.x, rect.w, self.width);
+        for y in span(rect.y, rect.h, self.height) {
+            for x in columns.clone() {
+                self.blend(x, y, color);
+            }
+        }
+    }
+
+    pub fn fill_circle(&mut self, center: Vec2, radius: f32, color: Color) {
+        let bounds = Rect::new(
+            center.x - radius,
+            center.y - radius,
+            radius * 2.0,
+            radius * 2.0,
+        );
+        let columns = span(bounds.x, bounds.w, self.width);
+        for y in span(bounds.y, bounds.h, self.height) {
+            for x in columns.clone() {
+                let offset = vec2(x as f32 + 0.5, y as f32 + 0.5) - center;
+                if offset.length_squared() <= radius * radius {
+                    self.blend(x, y, color);
+                }
+            }
+        }
+    }
+
+    // A border `thickness` wide just inside `rect`, like draw_rectangle_lines
+    pub fn outline_rect(&mut self, rect: Rect, thickness: f32, color: Color) {
+        let Rect { x, y, w, h } = rect;
+        self.fill_rect(Rect::new(x, y, w, thickness), color);
+        self.fill_rect(Rect::new(x, y + h - thickness, w, thickness), color);
+        let side = h - thickness * 2.0;
+        self.fill_rect(Rect::new(x, y + thickness, thickness, side), color);
+        self.fill_rect(
+            Rect::new(x + w - thickness, y + thickness, thickness, side),
+            color,
+        );
+    }
+
+    fn blend(&mut self, x: u32, y: u32, color: Color) {
+        let i = ((y * self.width + x) * 3) as usize;
+        let a = color.a.clamp(0.0, 1.0);
+        for (pixel, channel) in self.pixels[i..i + 3]
+            .iter_mut()
+            .zip([color.r, color.g, color.b])
+        {
+            let under = *pixel as f32 / 255.0;
+            let mixed = under + (channel.clamp(0.0, 1.0) - under) * a;
+            *pixel = (mixed * 255.0).round() as u8;
+        }
+    }
+}
+
+// Pixels along one axis whose centres fall in `start..start + length`
+fn span(start: f32, length: f32, limit: u32) -> std::ops::Range<u32> {
+    let clamp = |value: f32| value.clamp(0.0, limit as f32) as u32;
+    clamp((start - 0.5).ceil())..clamp((start + length - 0.5).ceil())
+}
+
+// Draws logical court coordinates onto a frame `scale` times the court's size
+struct Painter {
+    frame: Frame,
+    scale: f32,
+    offset: Vec2,
+}
+
+impl Painter {
+    fn to_frame(&self, rect: Rect) -> Rect {
+        Rect::new(
+            (rect.x + self.offset.x) * self.scale,
+            (rect.y + self.offset.y) * self.scale,
+            rect.w * self.scale,
+            rect.h * self.scale,
+        )
+    }
+
+    fn rect(&mut self, rect: Rect, color: Color) {
+        self.frame.fill_rect(self.to_frame(rect), color);
+    }
+
+    fn circle(&mut self, center: Vec2, radius: f32, color: Color) {
+        let center = (center + self.offset) * self.scale;
+        self.frame.fill_circle(center, radius * self.scale, color);
+    }
+
+    // The stacked outlines the window draws around paddles and the ball
+    fn glow(&mut self, rect: Rect, spacing: f32, thickness: f32, intensity: f32, color: Color) {
+        for i in 1..=3 {
+            let offset = i as f32 * spacing;
+            let outline = Rect::new(
+                rect.x - offset,
+                rect.y - offset,
+                rect.w + offset * 2.0,
+                rect.h + offset * 2.0,
+            );
+            let alpha = intensity / i as f32;
+            let outline = self.to_frame(outline);
+            self.frame
+                .outline_rect(outline, thickness * self.scale, color.with_alpha(alpha));
+        }
+    }
+}
+
+// Image size for a scale, at least one pixel each way
+pub fn frame_size(scale: f32) -> (u32, u32) {
+    let size = |length: f32| ((length * scale).round() as u32).max(1);
+    (size(WINDOW_WIDTH), size(WINDOW_HEIGHT))
+}
+
+// The court as Game::draw shows it, `scale` times the court's logical size
+pub fn render(game: &Game, theme: &Theme, scale: f32) -> Frame {
+    let (width, height) = frame_size(scale);
+    let mut painter = Painter {
+        frame: Frame::new(width, height, theme.background),
+        scale,
+        offset: game.feel.shake_offset(),
+    };
+
+    let dash = WINDOW_HEIGHT / 20.0;
+    for i in (0..20).step_by(2) {
+        let rect = Rect::new(WINDOW_WIDTH / 2.0 - 2.0, i as f32 * dash, 4.0, dash);
+        painter.rect(rect, theme.center_line);
+    }
+
+    for (i, position) in game.ball_trail.iter().enumerate() {
+        let alpha = (i as f32 / game.ball_trail.len() as f32) * 0.3;
+        let color = theme.trail.with_alpha(theme.trail.a * alpha);
+        painter.circle(vec2(position.x, position.y), 3.0, color);
+    }
+
+    for side in [Side::Left, Side::Right] {
+        let rect = game.paddle(side).squashed_rect(game.feel.squash(side));
+        let color = theme.paddle(side);
+        painter.rect(rect, color);
+        painter.glow(rect, 2.0, 2.0, 0.3, color);
+    }
+    let ball = game.ball.get_rect();
+    painter.rect(ball, theme.ball);
+    painter.glow(ball, 1.5, 1.0, 0.4, theme.ball);
+
+    for particle in game.particles.iter() {
+        let size = particle.size();
+        let color = particle.tinted_color(theme.particles);
+        if size > 0.0 && color.a > 0.0 {
+            let position = vec2(particle.position.x, particle.position.y);
+            painter.circle(position, size, color);
+        }
+    }
+
+    if let Some(scorer) = game.feel.flash_side {
+        let color = theme.paddle(scorer);
+        let alpha = 0.35 * game.feel.flash_strength();
+        let court = Rect::new(0.0, 0.0, WINDOW_WIDTH, WINDOW_HEIGHT);
+        painter.rect(court, color.with_alpha(color.a * alpha));
+    }
+
+    let layout = Layout {
+        width: WINDOW_WIDTH,
+        height: WINDOW_HEIGHT,
+        margin: MARGIN,
+        scale,
+    };
+    let scores = [game.player_score, game.ai_score];
+    let pops = [Side::Left, Side::Right].map(|side| game.feel.score_scale(side));
+    let placements = layout.score_placements(scores, pops);
+    for ((score, baseline, size), side) in placements.into_iter().zip([Side::Left, Side::Right]) {
+        for rect in text::pixel_rects(&score, baseline.x, baseline.y, size) {
+            painter.rect(rect, theme.paddle(side));
+        }
+    }
+    painter.frame
+}
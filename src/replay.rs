@@ -0,0 +1,92 @@
+// Match replays: the state a match started from and every step played after it
+// Game::step is deterministic, so stepping a game restored to the start state with the recorded
+// commands and time steps plays the match out exactly as it happened. Local matches are recorded
+// and the last one is kept in the data directory for `pong-render`.
+
+use crate::mods::ModConfig;
+use crate::save::{data_dir, SaveState};
+use crate::{Game, PaddleCommand};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const REPLAY_VERSION: u32 = 1;
+pub const REPLAY_FILE_NAME: &str = "last_match.replay.json";
+
+pub fn default_replay_path() -> PathBuf {
+    data_dir().join(REPLAY_FILE_NAME)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayStep {
+    pub dt: f32,
+    pub left: PaddleCommand,
+    pub right: PaddleCommand,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub start: SaveState,
+    // Mods change how steps play out, so they are rebuilt for playback
+    #[serde(default)]
+    pub mods: Vec<ModConfig>,
+    pub steps: Vec<ReplayStep>,
+}
+
+impl Replay {
+    // Start recording from the game's current state
+    pub fn start(game: &Game, mods: &[ModConfig]) -> Self {
+        Self {
+            version: REPLAY_VERSION,
+            start: game.snapshot(),
+            mods: mods.to_vec(),
+            steps: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, dt: f32, left: PaddleCommand, right: PaddleCommand) {
+        self.steps.push(ReplayStep { dt, left, right });
+    }
+
+    // Match time covered, in seconds
+    pub fn duration(&self) -> f32 {
+        self.steps.iter().map(|step| step.dt).sum()
+    }
+
+    // A silent game at the start of the replay, ready to be stepped through it
+    pub fn game(&self) -> Game {
+        let mut game = Game::with_seed(0);
+        // Mods adjust the rules when added; the start state already has the adjusted rules
+        game.apply_mods(&self.mods);
+        game.restore(self.start.clone());
+        game
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let replay: Self = serde_json::from_str(json)?;
+        if replay.version != REPLAY_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported replay version {}", replay.version),
+            ));
+        }
+        Ok(replay)
+    }
+
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_json()?)
+    }
+
+    pub fn read_from(path: &Path) -> io::Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+}
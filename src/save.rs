@@ -0,0 +1,138 @@
+// Save files for resuming an in-progress match
+// Only gameplay state is stored; sounds, particles and the ball trail are rebuilt on load
+
+use crate::analytics::MatchAnalytics;
+use crate::stats::MatchStats;
+use crate::{AiSettings, Ball, Game, GameState, Paddle, RuleSet, Side};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const SAVE_VERSION: u32 = 1;
+pub const SAVE_FILE_NAME: &str = "match.json";
+
+// Directory for everything the game persists between runs
+// PONG_DATA_DIR overrides the platform default
+pub fn data_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os("PONG_DATA_DIR") {
+        return PathBuf::from(dir);
+    }
+
+    dirs::data_local_dir()
+        .map(|dir| dir.join("pong"))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+pub fn default_save_path() -> PathBuf {
+    data_dir().join(SAVE_FILE_NAME)
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SaveState {
+    pub version: u32,
+    pub player_paddle: Paddle,
+    pub ai_paddle: Paddle,
+    pub ball: Ball,
+    pub player_score: i32,
+    pub ai_score: i32,
+    pub game_state: GameState,
+    pub rules: RuleSet,
+    pub ai: AiSettings,
+    pub rng: ChaCha8Rng,
+    pub ai_side: Option<Side>,
+    #[serde(default)]
+    pub rally_length: u32,
+    #[serde(default)]
+    pub stats: MatchStats,
+    #[serde(default)]
+    pub analytics: MatchAnalytics,
+}
+
+impl SaveState {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let state: Self = serde_json::from_str(json)?;
+        if state.version != SAVE_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported save version {}", state.version),
+            ));
+        }
+        Ok(state)
+    }
+
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // Write to a temporary file first so a crash never leaves a truncated save
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, self.to_json()?)?;
+        fs::rename(&tmp_path, path)
+    }
+
+    pub fn read_from(path: &Path) -> io::Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    // FNV-1a hash of the serialised state, used to detect diverging simulations
+    pub fn checksum(&self) -> u64 {
+        checksum(self)
+    }
+}
+
+// FNV-1a hash of any value's JSON form
+pub fn checksum<T: Serialize>(value: &T) -> u64 {
+    let bytes = serde_json::to_vec(value).unwrap_or_default();
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+impl Game {
+    pub fn snapshot(&self) -> SaveState {
+        SaveState {
+            version: SAVE_VERSION,
+            player_paddle: self.player_paddle.clone(),
+            ai_paddle: self.ai_paddle.clone(),
+            ball: self.ball.clone(),
+            player_score: self.player_score,
+            ai_score: self.ai_score,
+            game_state: self.game_state,
+            rules: self.rules,
+            ai: self.ai,
+            rng: self.rng.clone(),
+            ai_side: self.ai_side,
+            rally_length: self.rally_length,
+            stats: self.stats.clone(),
+            analytics: self.analytics.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, state: SaveState) {
+        self.player_paddle = state.player_paddle;
+        self.ai_paddle = state.ai_paddle;
+        self.ball = state.ball;
+        self.player_score = state.player_score;
+        self.ai_score = state.ai_score;
+        self.game_state = state.game_state;
+        self.rules = state.rules;
+        self.ai = state.ai;
+        self.rng = state.rng;
+        self.ai_side = state.ai_side;
+        self.rally_length = state.rally_length;
+        self.ticks = 0;
+        self.stats = state.stats;
+        self.analytics = state.analytics;
+
+        // Visual-only state is regenerated rather than saved
+        self.particles.clear();
+        self.ball_trail.clear();
+    }
+}
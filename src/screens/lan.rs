@@ -0,0 +1,129 @@
+// LAN screens: hosting a game and browsing for one to join
+
+use macroquad::prelude::*;
+use pong::discovery::{self, BrowserEvent, LanBrowser, LanHost, LanMatch};
+use pong::hud::{Layout, HEADING_SIZE, HINT_SIZE, STATUS_SIZE};
+use pong::*;
+use std::net::{SocketAddr, UdpSocket};
+
+// Announce a game on the LAN until someone joins it
+pub async fn host_lan(
+    discovery: SocketAddr,
+    name: String,
+) -> Option<(LanMatch, UdpSocket, Option<LanHost>)> {
+    let setup = UdpSocket::bind("0.0.0.0:0").and_then(|socket| {
+        let port = socket.local_addr()?.port();
+        let host = LanHost::bind(
+            discovery::announce_addr(discovery),
+            &name,
+            port,
+            RuleSet::default(),
+            ::rand::random(),
+        )?;
+        Ok((socket, host))
+    });
+    let (socket, mut host) = match setup {
+        Ok(setup) => setup,
+        Err(err) => {
+            eprintln!("Failed to host a LAN game: {}", err);
+            return None;
+        }
+    };
+
+    let mut status = format!("Hosting {}'s game: waiting for a player...", name);
+    loop {
+        view::begin_frame();
+        let theme = theme::active();
+        match host.poll() {
+            Ok(Some(lan_match)) => return Some((lan_match, socket, Some(host))),
+            Ok(None) => {}
+            Err(err) => status = format!("LAN error: {}", err),
+        }
+
+        let layout = Layout::current();
+        let heading = layout.heading(&status, STATUS_SIZE, theme.text);
+        layout.below(heading, "ESC to cancel", HINT_SIZE, theme.text_dim);
+        if is_key_pressed(KeyCode::Escape) {
+            return None;
+        }
+        view::next_frame().await;
+    }
+}
+
+// List games announced on the LAN and join the selected one
+pub async fn browse_lan(discovery: SocketAddr, name: String) -> Option<(LanMatch, UdpSocket)> {
+    let setup = LanBrowser::bind(discovery).and_then(|browser| {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        Ok((browser, socket))
+    });
+    let (mut browser, socket) = match setup {
+        Ok(setup) => setup,
+        Err(err) => {
+            eprintln!("Failed to listen for LAN games on {}: {}", discovery, err);
+            return None;
+        }
+    };
+    let game_port = socket.local_addr().map(|addr| addr.port()).unwrap_or(0);
+
+    let mut selected = 0;
+    let mut status = "Searching for LAN games...".to_owned();
+    loop {
+        view::begin_frame();
+        let theme = theme::active();
+        match browser.poll() {
+            Ok(Some(BrowserEvent::Accepted(lan_match))) => return Some((lan_match, socket)),
+            Ok(Some(BrowserEvent::Rejected(reason))) => {
+                status = format!("Join refused: {}", reason)
+            }
+            Ok(None) => {}
+            Err(err) => status = format!("LAN error: {}", err),
+        }
+
+        let games = browser.games();
+        selected = selected.min(games.len().saturating_sub(1));
+        if is_key_pressed(KeyCode::Up) {
+            selected = selected.saturating_sub(1);
+        }
+        if is_key_pressed(KeyCode::Down) && selected + 1 < games.len() {
+            selected += 1;
+        }
+
+        let layout = Layout::current();
+        let heading = layout.heading("LAN GAMES", HEADING_SIZE, theme.text);
+        let mut above = layout.below(heading, &status, 20.0, theme.text_dim);
+        for (i, game) in games.iter().enumerate() {
+            let ping = match game.ping {
+                Some(ping) => format!("{} ms", ping.as_millis()),
+                None => "? ms".to_owned(),
+            };
+            let line = format!(
+                "{}  first to {}  {}",
+                game.host_name, game.rules.winning_score, ping
+            );
+            let color = if i == selected {
+                theme.text
+            } else {
+                theme.text_dim
+            };
+            above = layout.row(above, &line, 22.0, color);
+        }
+        layout.footer(
+            "UP/DOWN to choose, ENTER to join, ESC to go back",
+            theme.text_dim,
+        );
+
+        if view::enter_pressed() {
+            if let Some(game) = games.get(selected) {
+                let host = game.addr;
+                status = format!("Joining {}...", game.host_name);
+                if let Err(err) = browser.join(host, &name, game_port) {
+                    status = format!("LAN error: {}", err);
+                }
+            }
+        }
+        if is_key_pressed(KeyCode::Escape) {
+            return None;
+        }
+        view::next_frame().await;
+    }
+}
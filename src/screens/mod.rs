@@ -0,0 +1,4 @@
+// Menu screens shown between matches
+
+pub mod lan;
+pub mod profiles;
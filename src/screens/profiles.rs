@@ -0,0 +1,240 @@
+// Profile screens and the profile store for this run, with achievement toasts
+
+use macroquad::prelude::*;
+use pong::achievements::{self, builtin_achievements, draw_achievement_list, Achievement, Toasts};
+use pong::hud::{Layout, HEADING_SIZE};
+use pong::profile::{
+    default_profiles_path, draw_career_stats, MatchOutcome, ProfileStore, MAX_NAME_LENGTH,
+};
+use pong::*;
+use std::path::PathBuf;
+
+fn player_name() -> String {
+    std::env::var("USER").unwrap_or_else(|_| "Player".to_owned())
+}
+
+// The profile store for this run; an unreadable file is never saved over
+pub struct Profiles {
+    pub store: ProfileStore,
+    path: Option<PathBuf>,
+    achievements: Vec<Achievement>,
+    toasts: Toasts,
+}
+
+impl Profiles {
+    pub fn load() -> Self {
+        let path = default_profiles_path();
+        match ProfileStore::load(&path) {
+            Ok(store) => Self {
+                store,
+                path: Some(path),
+                achievements: builtin_achievements(),
+                toasts: Toasts::default(),
+            },
+            Err(err) => {
+                eprintln!("Failed to load profiles from {}: {}", path.display(), err);
+                Self {
+                    store: ProfileStore::default(),
+                    path: None,
+                    achievements: builtin_achievements(),
+                    toasts: Toasts::default(),
+                }
+            }
+        }
+    }
+
+    fn save(&self) {
+        if let Some(path) = &self.path {
+            if let Err(err) = self.store.write_to(path) {
+                eprintln!("Failed to save profiles: {}", err);
+            }
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.store
+            .active()
+            .map_or_else(player_name, |profile| profile.name.clone())
+    }
+
+    // Record a finished match for the active profile; returns the rating change
+    pub fn record(&mut self, game: &Game, outcome: &MatchOutcome, side: Side) -> Option<f32> {
+        let change = self.store.record(outcome);
+        if change.is_some() {
+            self.check_achievements(game, side, Some(outcome));
+            self.save();
+        }
+        change
+    }
+
+    // Unlock achievements met by the match so far, or by its result once there is an outcome
+    pub fn check_achievements(&mut self, game: &Game, side: Side, outcome: Option<&MatchOutcome>) {
+        let unlocked = achievements::unlock(
+            &mut self.store,
+            &self.achievements,
+            &game.stats,
+            side,
+            game.rally_length,
+            game.rules.top_speed(),
+            outcome,
+        );
+        for achievement in &unlocked {
+            self.toasts.push_unlocked(achievement);
+        }
+        // Mid-match unlocks are saved straight away; results are saved by `record`
+        if !unlocked.is_empty() && outcome.is_none() {
+            self.save();
+        }
+    }
+
+    pub fn draw_toasts(&mut self) {
+        self.toasts.update(get_frame_time());
+        self.toasts.draw();
+    }
+}
+
+// Pick, create or delete profiles and look at their career stats
+pub async fn choose_profile(profiles: &mut Profiles) {
+    let mut selected = profiles
+        .store
+        .active
+        .as_deref()
+        .and_then(|active| {
+            profiles
+                .store
+                .profiles
+                .iter()
+                .position(|p| p.name == active)
+        })
+        .unwrap_or(0);
+    let mut new_name: Option<String> = None;
+    let mut viewing = false;
+    let mut listing = false;
+    let mut confirm_delete = false;
+    let mut status = String::new();
+
+    loop {
+        view::begin_frame();
+        let theme = theme::active();
+        let count = profiles.store.profiles.len();
+        selected = selected.min(count.saturating_sub(1));
+
+        if viewing {
+            if let Some(profile) = profiles.store.profiles.get(selected) {
+                draw_career_stats(profile);
+            }
+            if is_key_pressed(KeyCode::Escape) {
+                viewing = false;
+            }
+        } else if listing {
+            if let Some(profile) = profiles.store.profiles.get(selected) {
+                draw_achievement_list(profile, &profiles.achievements);
+            }
+            if is_key_pressed(KeyCode::Escape) {
+                listing = false;
+            }
+        } else if let Some(name) = new_name.as_mut() {
+            while let Some(character) = get_char_pressed() {
+                if !character.is_control() && name.chars().count() < MAX_NAME_LENGTH {
+                    name.push(character);
+                }
+            }
+            if is_key_pressed(KeyCode::Backspace) {
+                name.pop();
+            }
+
+            let layout = Layout::current();
+            let heading = layout.heading("NEW PROFILE", HEADING_SIZE, theme.text);
+            let above = layout.below(heading, &status, 20.0, theme.text_dim);
+            layout.row(above, &format!("Name: {}_", name), 28.0, theme.text);
+            layout.footer("ENTER to create, ESC to cancel", theme.text_dim);
+
+            if view::enter_pressed() {
+                match profiles.store.create(name) {
+                    Ok(_) => {
+                        profiles.save();
+                        selected = count;
+                        new_name = None;
+                        status.clear();
+                    }
+                    Err(err) => status = err,
+                }
+            } else if is_key_pressed(KeyCode::Escape) {
+                new_name = None;
+                status.clear();
+            }
+        } else {
+            if is_key_pressed(KeyCode::Up) {
+                selected = selected.saturating_sub(1);
+                confirm_delete = false;
+            }
+            if is_key_pressed(KeyCode::Down) && selected + 1 < count {
+                selected += 1;
+                confirm_delete = false;
+            }
+
+            let layout = Layout::current();
+            let heading = layout.heading("PROFILES", HEADING_SIZE, theme.text);
+            let mut above = layout.below(heading, &status, 20.0, theme.text_dim);
+            if count == 0 {
+                layout.row(above, "No profiles yet", 22.0, theme.text_dim);
+            }
+            for (i, profile) in profiles.store.profiles.iter().enumerate() {
+                let active = profiles.store.active.as_deref() == Some(profile.name.as_str());
+                let line = format!(
+                    "{} {}  rating {:.0}  {} matches",
+                    if active { "*" } else { " " },
+                    profile.name,
+                    profile.rating,
+                    profile.matches
+                );
+                let color = if i == selected {
+                    theme.text
+                } else {
+                    theme.text_dim
+                };
+                above = layout.row(above, &line, 22.0, color);
+            }
+            layout.footer(
+                "ENTER to play as, N new, S stats, A achievements, DEL delete, ESC back",
+                theme.text_dim,
+            );
+
+            let name = profiles
+                .store
+                .profiles
+                .get(selected)
+                .map(|p| p.name.clone());
+            if is_key_pressed(KeyCode::N) {
+                // Drop the N itself so it doesn't start the name
+                while get_char_pressed().is_some() {}
+                new_name = Some(String::new());
+                status.clear();
+            } else if let Some(name) = name {
+                if view::enter_pressed() {
+                    profiles.store.select(&name);
+                    profiles.save();
+                    return;
+                } else if is_key_pressed(KeyCode::S) {
+                    viewing = true;
+                } else if is_key_pressed(KeyCode::A) {
+                    listing = true;
+                } else if is_key_pressed(KeyCode::Delete) {
+                    if confirm_delete {
+                        profiles.store.remove(&name);
+                        profiles.save();
+                        status = format!("Deleted {}", name);
+                        confirm_delete = false;
+                    } else {
+                        status = format!("Press DEL again to delete {}", name);
+                        confirm_delete = true;
+                    }
+                }
+            }
+            if is_key_pressed(KeyCode::Escape) {
+                return;
+            }
+        }
+        view::next_frame().await;
+    }
+}
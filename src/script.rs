@@ -0,0 +1,445 @@
+// Embedded Rhai scripts for paddle controllers and game modes
+// Scripts are sandboxed: they cannot load files or modules, run for a bounded number of
+// operations and only see a read-only copy of the game state. Game-mode scripts change the match
+// through a few whitelisted actions. Script files are reloaded when they change, and errors are
+// shown in the game instead of stopping it.
+
+use crate::bot::Controller;
+use crate::hud::{Anchor, Layout};
+use crate::mods::GameMod;
+use crate::text;
+use crate::theme;
+use crate::{Game, PaddleCommand, RuleSet, Side, BALL_SIZE, WINDOW_HEIGHT, WINDOW_WIDTH};
+use macroquad::prelude::*;
+use rhai::module_resolvers::DummyModuleResolver;
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// How often script files are checked for changes
+pub const RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+// Operations one call may run before it is stopped, which also ends infinite loops
+pub const MAX_OPERATIONS: u64 = 100_000;
+// Paddle heights a script may ask for
+pub const MIN_SCRIPT_PADDLE_HEIGHT: f32 = 10.0;
+
+// A change to the match requested by a game-mode script
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScriptAction {
+    SetBallVelocity(f32, f32),
+    SetPaddleHeight(Side, f32),
+    ShowMessage(String),
+}
+
+fn number(value: &Dynamic) -> Option<f32> {
+    value
+        .as_float()
+        .ok()
+        .or_else(|| value.as_int().ok().map(|int| int as f64))
+        .map(|value| value as f32)
+}
+
+fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Left => "left",
+        Side::Right => "right",
+    }
+}
+
+fn parse_side(value: &str) -> Option<Side> {
+    match value {
+        "left" => Some(Side::Left),
+        "right" => Some(Side::Right),
+        _ => None,
+    }
+}
+
+fn sandboxed_engine(actions: Arc<Mutex<Vec<ScriptAction>>>) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(1024);
+    engine.set_max_array_size(1024);
+    engine.set_max_map_size(256);
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+
+    let queue = actions.clone();
+    engine.register_fn("set_ball_velocity", move |vx: Dynamic, vy: Dynamic| {
+        if let (Some(vx), Some(vy)) = (number(&vx), number(&vy)) {
+            queue
+                .lock()
+                .unwrap()
+                .push(ScriptAction::SetBallVelocity(vx, vy));
+        }
+    });
+    let queue = actions.clone();
+    engine.register_fn("set_paddle_height", move |side: &str, height: Dynamic| {
+        if let (Some(side), Some(height)) = (parse_side(side), number(&height)) {
+            queue
+                .lock()
+                .unwrap()
+                .push(ScriptAction::SetPaddleHeight(side, height));
+        }
+    });
+    engine.register_fn("show_message", move |text: &str| {
+        actions
+            .lock()
+            .unwrap()
+            .push(ScriptAction::ShowMessage(text.to_owned()));
+    });
+    engine
+}
+
+fn float(value: f32) -> Dynamic {
+    Dynamic::from_float(value as f64)
+}
+
+fn map(entries: &[(&str, Dynamic)]) -> Dynamic {
+    let map: Map = entries
+        .iter()
+        .map(|(key, value)| ((*key).into(), value.clone()))
+        .collect();
+    Dynamic::from_map(map)
+}
+
+// The read-only view of the game handed to scripts
+pub fn script_state(game: &Game) -> Dynamic {
+    let paddle = |side: Side| {
+        let paddle = game.paddle(side);
+        let score = match side {
+            Side::Left => game.player_score,
+            Side::Right => game.ai_score,
+        };
+        map(&[
+            ("x", float(paddle.position.x)),
+            ("y", float(paddle.position.y)),
+            ("center_y", float(paddle.get_center_y())),
+            ("height", float(paddle.height)),
+            ("score", Dynamic::from_int(score as i64)),
+        ])
+    };
+    let ball = &game.ball;
+    map(&[
+        (
+            "ball",
+            map(&[
+                ("x", float(ball.position.x + BALL_SIZE / 2.0)),
+                ("y", float(ball.position.y + BALL_SIZE / 2.0)),
+                ("vx", float(ball.velocity.x)),
+                ("vy", float(ball.velocity.y)),
+                ("speed", float(ball.speed)),
+            ]),
+        ),
+        ("left", paddle(Side::Left)),
+        ("right", paddle(Side::Right)),
+        ("width", float(WINDOW_WIDTH)),
+        ("height", float(WINDOW_HEIGHT)),
+        ("time", float(game.stats.time_played)),
+        ("rally", Dynamic::from_int(game.rally_length as i64)),
+    ])
+}
+
+// A script file with its compiled form and the last problem it had
+pub struct Script {
+    path: PathBuf,
+    engine: Engine,
+    source: Option<String>,
+    ast: Option<AST>,
+    error: Option<String>,
+    actions: Arc<Mutex<Vec<ScriptAction>>>,
+    last_check: Instant,
+}
+
+impl Script {
+    // Never fails: a missing or broken script is reported through `error`
+    pub fn load(path: &Path) -> Self {
+        let actions = Arc::new(Mutex::new(Vec::new()));
+        let mut script = Self {
+            path: path.to_owned(),
+            engine: sandboxed_engine(actions.clone()),
+            source: None,
+            ast: None,
+            error: None,
+            actions,
+            last_check: Instant::now(),
+        };
+        script.reload_if_changed();
+        script
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        self.ast.is_some()
+    }
+
+    // Recompile if the file's contents changed; returns whether it did
+    pub fn reload_if_changed(&mut self) -> bool {
+        self.last_check = Instant::now();
+        let source = match fs::read_to_string(&self.path) {
+            Ok(source) => source,
+            Err(err) => {
+                self.error = Some(format!("cannot read {}: {}", self.path.display(), err));
+                self.source = None;
+                self.ast = None;
+                return false;
+            }
+        };
+        if self.source.as_ref() == Some(&source) {
+            return false;
+        }
+
+        match self.engine.compile(&source) {
+            Ok(ast) => {
+                self.ast = Some(ast);
+                self.error = None;
+            }
+            Err(err) => {
+                self.ast = None;
+                self.error = Some(format!("{}: {}", self.path.display(), err));
+            }
+        }
+        self.source = Some(source);
+        true
+    }
+
+    // Check for changes at most every RELOAD_INTERVAL
+    pub fn poll(&mut self) -> bool {
+        self.last_check.elapsed() >= RELOAD_INTERVAL && self.reload_if_changed()
+    }
+
+    pub fn has_function(&self, name: &str) -> bool {
+        self.ast
+            .as_ref()
+            .is_some_and(|ast| ast.iter_functions().any(|function| function.name == name))
+    }
+
+    // Call a script function if the script defines it; runtime errors are kept in `error`
+    pub fn call(&mut self, name: &str, args: impl rhai::FuncArgs) -> Option<Dynamic> {
+        if !self.has_function(name) {
+            return None;
+        }
+        let ast = self.ast.as_ref()?;
+        let options = CallFnOptions::new().eval_ast(false);
+        match self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut Scope::new(),
+            ast,
+            name,
+            args,
+        ) {
+            Ok(value) => {
+                self.error = None;
+                Some(value)
+            }
+            Err(err) => {
+                self.error = Some(format!("{}: {}", name, err));
+                None
+            }
+        }
+    }
+
+    // Actions queued by the calls since the last time this was asked
+    pub fn take_actions(&mut self) -> Vec<ScriptAction> {
+        std::mem::take(&mut *self.actions.lock().unwrap())
+    }
+
+    // Error text on a strip along the line above the instructions
+    pub fn draw_error(&self) {
+        if let Some(error) = &self.error {
+            let line: String = error.chars().take(90).collect();
+            let line = format!("Script error: {}", line);
+            let layout = Layout::current();
+            let offset = layout.lines_up(1);
+            let size = layout.text_size(16.0);
+            let dimensions = text::measure(&line, size);
+            let baseline = layout.place(Anchor::BottomLeft, dimensions, offset);
+            let padding = dimensions.height / 2.0;
+            draw_rectangle(
+                0.0,
+                baseline.y - dimensions.offset_y - padding,
+                layout.width,
+                dimensions.height + padding * 2.0,
+                Color::new(0.3, 0.0, 0.0, 0.8),
+            );
+            layout.text(&line, Anchor::BottomLeft, offset, 16.0, RED);
+        }
+    }
+}
+
+// A paddle played by a script's `decide(state)` function
+// `state.side` says which paddle it plays; `decide` answers "up", "down" or "stay", or a number
+// whose sign gives the direction. Anything else, including errors, counts as stay.
+pub struct ScriptController {
+    pub script: Script,
+}
+
+impl ScriptController {
+    pub fn load(path: &Path) -> Self {
+        Self {
+            script: Script::load(path),
+        }
+    }
+}
+
+impl Controller for ScriptController {
+    fn command(&mut self, game: &Game, side: Side) -> PaddleCommand {
+        self.script.poll();
+        let mut state = script_state(game);
+        if let Some(mut map) = state.write_lock::<Map>() {
+            map.insert("side".into(), side_name(side).to_owned().into());
+        }
+        let answer = self.script.call("decide", (state,));
+        // Controllers only look at the game
+        self.script.take_actions();
+
+        let Some(answer) = answer else {
+            return PaddleCommand::Stay;
+        };
+        if let Some(direction) = number(&answer) {
+            return match direction {
+                d if d < 0.0 => PaddleCommand::Up,
+                d if d > 0.0 => PaddleCommand::Down,
+                _ => PaddleCommand::Stay,
+            };
+        }
+        match answer.into_string().as_deref() {
+            Ok("up") => PaddleCommand::Up,
+            Ok("down") => PaddleCommand::Down,
+            _ => PaddleCommand::Stay,
+        }
+    }
+
+    fn status(&self) -> Option<String> {
+        self.script.error().map(str::to_owned)
+    }
+}
+
+// A game mode written as a script
+// Optional functions: `rules(rules)` returns changed rules when the mode is enabled, and
+// `on_tick(state, dt)`, `on_paddle_hit(state, side)` and `on_goal(state, scorer)` react to the
+// match by calling `set_ball_velocity`, `set_paddle_height` or `show_message`.
+// Edits to the script are picked up while playing, except to `rules`: the rules are settled when
+// the mode is added, so changing them needs a restart.
+pub struct ScriptMod {
+    pub script: Script,
+    rules: Option<Map>,
+    message: Option<String>,
+}
+
+impl ScriptMod {
+    pub fn load(path: &Path) -> Self {
+        let mut script = Script::load(path);
+        let defaults = RuleSet::default();
+        let current = map(&[
+            (
+                "winning_score",
+                Dynamic::from_int(defaults.winning_score as i64),
+            ),
+            ("ball_speed", float(defaults.ball_speed)),
+            ("paddle_speed", float(defaults.paddle_speed)),
+            ("hit_speedup", float(defaults.hit_speedup)),
+            ("max_speedup", float(defaults.max_speedup)),
+        ]);
+        let rules = script
+            .call("rules", (current,))
+            .and_then(|rules| rules.try_cast::<Map>());
+        Self {
+            script,
+            rules,
+            message: None,
+        }
+    }
+
+    fn apply(&mut self, game: &mut Game) {
+        for action in self.script.take_actions() {
+            match action {
+                ScriptAction::SetBallVelocity(vx, vy) => {
+                    if vx.is_finite() && vy.is_finite() {
+                        game.ball.velocity.x = vx;
+                        game.ball.velocity.y = vy;
+                    }
+                }
+                ScriptAction::SetPaddleHeight(side, height) => {
+                    if height.is_finite() {
+                        game.paddle_mut(side)
+                            .resize(height.clamp(MIN_SCRIPT_PADDLE_HEIGHT, WINDOW_HEIGHT));
+                    }
+                }
+                // An empty message clears the last one
+                ScriptAction::ShowMessage(text) => {
+                    self.message = (!text.is_empty()).then_some(text)
+                }
+            }
+        }
+    }
+}
+
+impl GameMod for ScriptMod {
+    fn name(&self) -> &'static str {
+        "script"
+    }
+
+    fn modify_config(&self, rules: &mut RuleSet) {
+        let Some(changes) = &self.rules else {
+            return;
+        };
+        let get = |key: &str| changes.get(key).and_then(number);
+        if let Some(score) = get("winning_score") {
+            rules.winning_score = (score as i32).max(1);
+        }
+        if let Some(speed) = get("ball_speed") {
+            rules.ball_speed = speed.max(1.0);
+        }
+        if let Some(speed) = get("paddle_speed") {
+            rules.paddle_speed = speed.max(0.0);
+        }
+        if let Some(factor) = get("hit_speedup") {
+            rules.hit_speedup = factor.max(1.0);
+        }
+        if let Some(factor) = get("max_speedup") {
+            rules.max_speedup = factor.max(1.0);
+        }
+    }
+
+    fn on_tick(&mut self, game: &mut Game, dt: f32) {
+        self.script.poll();
+        self.script.call("on_tick", (script_state(game), dt as f64));
+        self.apply(game);
+    }
+
+    fn on_paddle_hit(&mut self, game: &mut Game, side: Side) {
+        self.script.call(
+            "on_paddle_hit",
+            (script_state(game), side_name(side).to_owned()),
+        );
+        self.apply(game);
+    }
+
+    fn on_goal(&mut self, game: &mut Game, scorer: Side) {
+        self.script.call(
+            "on_goal",
+            (script_state(game), side_name(scorer).to_owned()),
+        );
+        self.apply(game);
+    }
+
+    fn on_draw(&self, _game: &Game) {
+        let theme = theme::active();
+        if let Some(message) = &self.message {
+            Layout::current().centered(message, -180.0, 24.0, theme.accent);
+        }
+        self.script.draw_error();
+    }
+}
@@ -0,0 +1,976 @@
+// Dedicated authoritative match server and its client side
+// The server owns the only simulation. Clients send paddle commands and render the snapshots
+// they receive, predicting their own paddle and interpolating everything else.
+// Messages are JSON objects, one per line, over TCP.
+
+use crate::netplay::TICK_DT;
+use crate::spectator::{MatchInfo, SpectatorFeed, SpectatorMessage, WRITE_TIMEOUT};
+use crate::stats::MatchStats;
+use crate::{Game, GameState, PaddleCommand, RuleSet, Side, Vec2D};
+use crate::{PADDLE_HEIGHT, WINDOW_HEIGHT, WINDOW_WIDTH};
+use ::rand::Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{
+    channel, sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError,
+};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_SERVER_PORT: u16 = 7100;
+// Server ticks between snapshots (20 Hz at 60 ticks per second)
+pub const SNAPSHOT_INTERVAL: u32 = 3;
+// Clients render remote objects this far in the past so there are snapshots on both sides
+pub const INTERPOLATION_DELAY: f64 = 0.1;
+pub const MAX_ROOM_NAME_LEN: usize = 32;
+// Messages waiting for a client before it is judged too slow and disconnected
+pub const CLIENT_QUEUE: usize = 256;
+// Inputs a seat can have waiting; a client this far ahead loses its oldest ones
+pub const MAX_QUEUED_INPUTS: usize = 60;
+
+pub type ClientId = u32;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    // `rating` is the player's profile rating, if they have one
+    Hello {
+        name: String,
+        #[serde(default)]
+        rating: Option<f32>,
+    },
+    ListRooms,
+    CreateRoom {
+        room: String,
+    },
+    JoinRoom {
+        room: String,
+    },
+    // Watch a room read-only
+    Spectate {
+        room: String,
+    },
+    Ready,
+    Input {
+        tick: u32,
+        command: PaddleCommand,
+    },
+    Leave,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Welcome {
+        client_id: ClientId,
+    },
+    RoomList {
+        rooms: Vec<RoomInfo>,
+    },
+    Joined {
+        room: String,
+        side: Side,
+    },
+    Spectating {
+        room: String,
+    },
+    Spectator {
+        message: SpectatorMessage,
+    },
+    RoomUpdate {
+        room: String,
+        players: Vec<PlayerInfo>,
+    },
+    MatchStart {
+        seed: u64,
+        rules: RuleSet,
+    },
+    // `last_input` is the newest input tick from the receiving client that the server has applied
+    Snapshot {
+        state: StateSnapshot,
+        last_input: u32,
+    },
+    // `stats` lets players record the match in their profile
+    MatchEnd {
+        winner: Side,
+        #[serde(default)]
+        stats: MatchStats,
+    },
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RoomInfo {
+    pub name: String,
+    pub players: usize,
+    pub in_match: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInfo {
+    pub name: String,
+    pub side: Side,
+    pub ready: bool,
+    // None for players without a profile, whose matches leave ratings alone
+    #[serde(default)]
+    pub rating: Option<f32>,
+}
+
+// Everything a client needs to draw a frame
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub tick: u32,
+    pub ball_position: Vec2D,
+    pub ball_velocity: Vec2D,
+    pub left_paddle_y: f32,
+    pub right_paddle_y: f32,
+    pub left_score: i32,
+    pub right_score: i32,
+    pub game_state: GameState,
+    pub rally_length: u32,
+}
+
+impl StateSnapshot {
+    pub fn capture(game: &Game, tick: u32) -> Self {
+        Self {
+            tick,
+            ball_position: game.ball.position,
+            ball_velocity: game.ball.velocity,
+            left_paddle_y: game.player_paddle.position.y,
+            right_paddle_y: game.ai_paddle.position.y,
+            left_score: game.player_score,
+            right_score: game.ai_score,
+            game_state: game.game_state,
+            rally_length: game.rally_length,
+        }
+    }
+
+    pub fn paddle_y(&self, side: Side) -> f32 {
+        match side {
+            Side::Left => self.left_paddle_y,
+            Side::Right => self.right_paddle_y,
+        }
+    }
+
+    pub fn set_paddle_y(&mut self, side: Side, y: f32) {
+        match side {
+            Side::Left => self.left_paddle_y = y,
+            Side::Right => self.right_paddle_y = y,
+        }
+    }
+
+    // Copy the snapshot into a game used only for drawing
+    pub fn apply_to(&self, game: &mut Game) {
+        game.ball.position = self.ball_position;
+        game.ball.velocity = self.ball_velocity;
+        game.player_paddle.position.y = self.left_paddle_y;
+        game.ai_paddle.position.y = self.right_paddle_y;
+        game.player_score = self.left_score;
+        game.ai_score = self.right_score;
+        game.game_state = self.game_state;
+        game.rally_length = self.rally_length;
+    }
+}
+
+fn winner(game: &Game) -> Side {
+    if game.player_score >= game.ai_score {
+        Side::Left
+    } else {
+        Side::Right
+    }
+}
+
+struct ClientEntry {
+    name: String,
+    rating: Option<f32>,
+    tx: SyncSender<ServerMessage>,
+    // Shut down to disconnect a client that can't keep up
+    stream: TcpStream,
+    room: Option<String>,
+}
+
+impl ClientEntry {
+    fn send(&self, message: ServerMessage) {
+        // Closing the connection ends its reader, which removes the client from the lobby;
+        // a disconnected client is cleaned up the same way
+        if let Err(TrySendError::Full(_)) = self.tx.try_send(message) {
+            let _ = self.stream.shutdown(std::net::Shutdown::Both);
+        }
+    }
+}
+
+struct Seat {
+    client: ClientId,
+    side: Side,
+    ready: bool,
+    // Inputs not yet stepped, oldest first; each tick steps one of them
+    inputs: VecDeque<(u32, PaddleCommand)>,
+    // Tick of the newest input stepped so far
+    last_input: u32,
+}
+
+impl Seat {
+    fn queue_input(&mut self, tick: u32, command: PaddleCommand) {
+        let newest = self
+            .inputs
+            .back()
+            .map_or(self.last_input, |&(queued, _)| queued);
+        if tick <= newest {
+            return;
+        }
+        if self.inputs.len() == MAX_QUEUED_INPUTS {
+            self.inputs.pop_front();
+        }
+        self.inputs.push_back((tick, command));
+    }
+
+    // The command for the next tick; a seat whose input hasn't arrived yet stays put
+    fn next_command(&mut self) -> PaddleCommand {
+        match self.inputs.pop_front() {
+            Some((tick, command)) => {
+                self.last_input = tick;
+                command
+            }
+            None => PaddleCommand::Stay,
+        }
+    }
+
+    fn clear_inputs(&mut self) {
+        self.inputs.clear();
+        self.last_input = 0;
+    }
+}
+
+struct Room {
+    seats: Vec<Seat>,
+    spectators: Vec<ClientId>,
+    feed: SpectatorFeed,
+    game: Option<Game>,
+    tick: u32,
+}
+
+impl Room {
+    fn new() -> Self {
+        Self {
+            seats: Vec::new(),
+            spectators: Vec::new(),
+            feed: SpectatorFeed::default(),
+            game: None,
+            tick: 0,
+        }
+    }
+
+    fn members(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.seats
+            .iter()
+            .map(|seat| seat.client)
+            .chain(self.spectators.iter().copied())
+    }
+}
+
+#[derive(Default)]
+struct Lobby {
+    next_id: ClientId,
+    clients: HashMap<ClientId, ClientEntry>,
+    rooms: HashMap<String, Room>,
+}
+
+impl Lobby {
+    fn send(&self, id: ClientId, message: ServerMessage) {
+        if let Some(client) = self.clients.get(&id) {
+            client.send(message);
+        }
+    }
+
+    fn error(&self, id: ClientId, message: &str) {
+        self.send(
+            id,
+            ServerMessage::Error {
+                message: message.to_owned(),
+            },
+        );
+    }
+
+    fn register(&mut self, tx: SyncSender<ServerMessage>, stream: TcpStream) -> ClientId {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.clients.insert(
+            id,
+            ClientEntry {
+                name: format!("player-{}", id),
+                rating: None,
+                tx,
+                stream,
+                room: None,
+            },
+        );
+        self.send(id, ServerMessage::Welcome { client_id: id });
+        id
+    }
+
+    fn remove_client(&mut self, id: ClientId) {
+        self.leave_room(id);
+        self.clients.remove(&id);
+    }
+
+    fn broadcast_room_update(&self, room_name: &str) {
+        let room = match self.rooms.get(room_name) {
+            Some(room) => room,
+            None => return,
+        };
+        let players: Vec<PlayerInfo> = room
+            .seats
+            .iter()
+            .map(|seat| {
+                let client = self.clients.get(&seat.client);
+                PlayerInfo {
+                    name: client.map(|client| client.name.clone()).unwrap_or_default(),
+                    side: seat.side,
+                    ready: seat.ready,
+                    rating: client.and_then(|client| client.rating),
+                }
+            })
+            .collect();
+
+        for member in room.members() {
+            self.send(
+                member,
+                ServerMessage::RoomUpdate {
+                    room: room_name.to_owned(),
+                    players: players.clone(),
+                },
+            );
+        }
+    }
+
+    fn handle(&mut self, id: ClientId, message: ClientMessage) {
+        match message {
+            ClientMessage::Hello { name, rating } => {
+                if let Some(client) = self.clients.get_mut(&id) {
+                    client.name = name;
+                    client.rating = rating;
+                }
+            }
+            ClientMessage::ListRooms => {
+                let rooms = self
+                    .rooms
+                    .iter()
+                    .map(|(name, room)| RoomInfo {
+                        name: name.clone(),
+                        players: room.seats.len(),
+                        in_match: room.game.is_some(),
+                    })
+                    .collect();
+                self.send(id, ServerMessage::RoomList { rooms });
+            }
+            ClientMessage::CreateRoom { room } => self.create_room(id, room),
+            ClientMessage::JoinRoom { room } => self.join_room(id, room),
+            ClientMessage::Spectate { room } => self.spectate(id, room),
+            ClientMessage::Ready => self.ready(id),
+            ClientMessage::Input { tick, command } => {
+                let room_name = self.clients.get(&id).and_then(|client| client.room.clone());
+                let room = room_name.and_then(|name| self.rooms.get_mut(&name));
+                if let Some(room) = room {
+                    if let Some(seat) = room.seats.iter_mut().find(|seat| seat.client == id) {
+                        seat.queue_input(tick, command);
+                    }
+                }
+            }
+            ClientMessage::Leave => self.leave_room(id),
+        }
+    }
+
+    fn create_room(&mut self, id: ClientId, room_name: String) {
+        if room_name.is_empty() || room_name.len() > MAX_ROOM_NAME_LEN {
+            self.error(id, "invalid room name");
+            return;
+        }
+        if self.rooms.contains_key(&room_name) {
+            self.error(id, "room already exists");
+            return;
+        }
+
+        self.leave_room(id);
+        self.rooms.insert(room_name.clone(), Room::new());
+        self.seat_client(id, room_name);
+    }
+
+    fn join_room(&mut self, id: ClientId, room_name: String) {
+        let room = match self.rooms.get(&room_name) {
+            Some(room) => room,
+            None => {
+                self.error(id, "no such room");
+                return;
+            }
+        };
+        if room.seats.iter().any(|seat| seat.client == id) {
+            return;
+        }
+        if room.seats.len() >= 2 {
+            self.error(id, "room is full");
+            return;
+        }
+
+        self.leave_room(id);
+        self.seat_client(id, room_name);
+    }
+
+    fn spectate(&mut self, id: ClientId, room_name: String) {
+        if !self.rooms.contains_key(&room_name) {
+            self.error(id, "no such room");
+            return;
+        }
+
+        self.leave_room(id);
+        let keyframe = match self.rooms.get_mut(&room_name) {
+            Some(room) => {
+                room.spectators.push(id);
+                room.feed.keyframe()
+            }
+            None => return,
+        };
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.room = Some(room_name.clone());
+        }
+
+        self.send(
+            id,
+            ServerMessage::Spectating {
+                room: room_name.clone(),
+            },
+        );
+        // Late joiners catch up from the current state
+        if let Some(message) = keyframe {
+            self.send(id, ServerMessage::Spectator { message });
+        }
+        self.broadcast_room_update(&room_name);
+    }
+
+    fn match_info(&self, room: &Room) -> MatchInfo {
+        let name = |side: Side| {
+            room.seats
+                .iter()
+                .find(|seat| seat.side == side)
+                .and_then(|seat| self.clients.get(&seat.client))
+                .map(|client| client.name.clone())
+                .unwrap_or_default()
+        };
+        MatchInfo {
+            left_name: name(Side::Left),
+            right_name: name(Side::Right),
+        }
+    }
+
+    fn seat_client(&mut self, id: ClientId, room_name: String) {
+        let room = match self.rooms.get_mut(&room_name) {
+            Some(room) => room,
+            None => return,
+        };
+        let side = match room.seats.first() {
+            Some(seat) => seat.side.opponent(),
+            None => Side::Left,
+        };
+        room.seats.push(Seat {
+            client: id,
+            side,
+            ready: false,
+            inputs: VecDeque::new(),
+            last_input: 0,
+        });
+
+        if let Some(client) = self.clients.get_mut(&id) {
+            client.room = Some(room_name.clone());
+        }
+        self.send(
+            id,
+            ServerMessage::Joined {
+                room: room_name.clone(),
+                side,
+            },
+        );
+        self.broadcast_room_update(&room_name);
+    }
+
+    fn ready(&mut self, id: ClientId) {
+        let room_name = match self.clients.get(&id).and_then(|client| client.room.clone()) {
+            Some(name) => name,
+            None => {
+                self.error(id, "not in a room");
+                return;
+            }
+        };
+        let info = match self.rooms.get(&room_name) {
+            Some(room) => self.match_info(room),
+            None => return,
+        };
+        let room = match self.rooms.get_mut(&room_name) {
+            Some(room) => room,
+            None => return,
+        };
+        if room.game.is_some() {
+            return;
+        }
+        if let Some(seat) = room.seats.iter_mut().find(|seat| seat.client == id) {
+            seat.ready = true;
+        }
+
+        let start = room.seats.len() == 2 && room.seats.iter().all(|seat| seat.ready);
+        let mut start_message = None;
+        if start {
+            let seed = ::rand::thread_rng().gen();
+            let mut game = Game::with_seed(seed);
+            game.ai_side = None;
+            start_message = Some(ServerMessage::MatchStart {
+                seed,
+                rules: game.rules,
+            });
+            room.game = Some(game);
+            room.tick = 0;
+            room.feed.set_info(info);
+            room.feed.reset();
+            for seat in &mut room.seats {
+                seat.clear_inputs();
+            }
+        }
+
+        self.broadcast_room_update(&room_name);
+        if let (Some(message), Some(room)) = (start_message, self.rooms.get(&room_name)) {
+            for seat in &room.seats {
+                self.send(seat.client, message.clone());
+            }
+        }
+    }
+
+    fn leave_room(&mut self, id: ClientId) {
+        let room_name = match self
+            .clients
+            .get_mut(&id)
+            .and_then(|client| client.room.take())
+        {
+            Some(name) => name,
+            None => return,
+        };
+        let room = match self.rooms.get_mut(&room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if room.spectators.contains(&id) {
+            room.spectators.retain(|&spectator| spectator != id);
+            return;
+        }
+
+        let leaver_side = room
+            .seats
+            .iter()
+            .find(|seat| seat.client == id)
+            .map(|seat| seat.side);
+        room.seats.retain(|seat| seat.client != id);
+
+        if room.seats.is_empty() {
+            let spectators = std::mem::take(&mut room.spectators);
+            self.rooms.remove(&room_name);
+            for spectator in spectators {
+                if let Some(client) = self.clients.get_mut(&spectator) {
+                    client.room = None;
+                }
+                self.error(spectator, "room closed");
+            }
+            return;
+        }
+
+        // Abandoning a match forfeits it
+        let mut forfeit = None;
+        if let Some(game) = room.game.take() {
+            if let Some(side) = leaver_side {
+                forfeit = Some(ServerMessage::MatchEnd {
+                    winner: side.opponent(),
+                    stats: game.stats,
+                });
+            }
+        }
+        for seat in &mut room.seats {
+            seat.ready = false;
+        }
+        let remaining: Vec<ClientId> = room.members().collect();
+
+        if let Some(message) = forfeit {
+            for client in remaining {
+                self.send(client, message.clone());
+            }
+        }
+        self.broadcast_room_update(&room_name);
+    }
+
+    // Advance every running match by one tick
+    fn tick(&mut self) {
+        let mut finished = Vec::new();
+
+        for (name, room) in self.rooms.iter_mut() {
+            let game = match room.game.as_mut() {
+                Some(game) => game,
+                None => continue,
+            };
+
+            let mut command = |side: Side| {
+                room.seats
+                    .iter_mut()
+                    .find(|seat| seat.side == side)
+                    .map_or(PaddleCommand::Stay, |seat| seat.next_command())
+            };
+            let left = command(Side::Left);
+            let right = command(Side::Right);
+            game.step(TICK_DT, left, right);
+            room.tick += 1;
+
+            let over = game.game_state == GameState::GameOver;
+            if over || room.tick % SNAPSHOT_INTERVAL == 0 {
+                let state = StateSnapshot::capture(game, room.tick);
+                for seat in &room.seats {
+                    if let Some(client) = self.clients.get(&seat.client) {
+                        client.send(ServerMessage::Snapshot {
+                            state: state.clone(),
+                            last_input: seat.last_input,
+                        });
+                    }
+                }
+
+                let message = room.feed.encode(&state);
+                for spectator in &room.spectators {
+                    if let Some(client) = self.clients.get(spectator) {
+                        client.send(ServerMessage::Spectator {
+                            message: message.clone(),
+                        });
+                    }
+                }
+            }
+
+            if over {
+                let message = ServerMessage::MatchEnd {
+                    winner: winner(game),
+                    stats: game.stats.clone(),
+                };
+                room.game = None;
+                for seat in &mut room.seats {
+                    seat.ready = false;
+                }
+                for member in room.members() {
+                    if let Some(client) = self.clients.get(&member) {
+                        client.send(message.clone());
+                    }
+                }
+                finished.push(name.clone());
+            }
+        }
+
+        for name in finished {
+            self.broadcast_room_update(&name);
+        }
+    }
+}
+
+pub struct Server {
+    listener: TcpListener,
+    lobby: Arc<Mutex<Lobby>>,
+    running: Arc<AtomicBool>,
+}
+
+pub struct ServerHandle {
+    addr: SocketAddr,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl ServerHandle {
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            lobby: Arc::new(Mutex::new(Lobby::default())),
+            running: Arc::new(AtomicBool::new(true)),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Run on a background thread until the handle is stopped or dropped
+    pub fn spawn(self) -> io::Result<ServerHandle> {
+        let addr = self.local_addr()?;
+        let running = self.running.clone();
+        let thread = thread::spawn(move || self.run());
+        Ok(ServerHandle {
+            addr,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn run(self) -> io::Result<()> {
+        let tick_lobby = self.lobby.clone();
+        let tick_running = self.running.clone();
+        let ticker = thread::spawn(move || {
+            let tick = Duration::from_secs_f32(TICK_DT);
+            let mut next = Instant::now();
+            while tick_running.load(Ordering::SeqCst) {
+                tick_lobby.lock().unwrap().tick();
+                next += tick;
+                if let Some(wait) = next.checked_duration_since(Instant::now()) {
+                    thread::sleep(wait);
+                }
+            }
+        });
+
+        while self.running.load(Ordering::SeqCst) {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    let lobby = self.lobby.clone();
+                    thread::spawn(move || handle_connection(lobby, stream));
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                Err(err) => {
+                    self.running.store(false, Ordering::SeqCst);
+                    let _ = ticker.join();
+                    return Err(err);
+                }
+            }
+        }
+
+        let _ = ticker.join();
+        Ok(())
+    }
+}
+
+fn handle_connection(lobby: Arc<Mutex<Lobby>>, stream: TcpStream) {
+    if stream.set_nonblocking(false).is_err() || stream.set_nodelay(true).is_err() {
+        return;
+    }
+    let (mut writer, control) = match (stream.try_clone(), stream.try_clone()) {
+        (Ok(writer), Ok(control)) => (writer, control),
+        _ => return,
+    };
+    let _ = writer.set_write_timeout(Some(WRITE_TIMEOUT));
+
+    let (tx, rx) = sync_channel::<ServerMessage>(CLIENT_QUEUE);
+    let id = lobby.lock().unwrap().register(tx, control);
+
+    // The writer exits once the lobby drops this client's sender
+    thread::spawn(move || {
+        for message in rx {
+            if write_message(&mut writer, &message).is_err() {
+                break;
+            }
+        }
+        let _ = writer.shutdown(std::net::Shutdown::Both);
+    });
+
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        match serde_json::from_str::<ClientMessage>(&line) {
+            Ok(message) => lobby.lock().unwrap().handle(id, message),
+            Err(_) => lobby.lock().unwrap().error(id, "malformed message"),
+        }
+    }
+
+    lobby.lock().unwrap().remove_client(id);
+}
+
+pub(crate) fn write_message<W: Write, M: Serialize>(writer: &mut W, message: &M) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line)
+}
+
+// Parse JSON lines from `stream` on a background thread until it closes
+pub(crate) fn spawn_json_reader<T: DeserializeOwned + Send + 'static>(
+    stream: TcpStream,
+) -> Receiver<T> {
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if let Ok(message) = serde_json::from_str::<T>(&line) {
+                if tx.send(message).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    rx
+}
+
+// Client connection to a match server
+pub struct ServerConnection {
+    stream: TcpStream,
+    rx: Receiver<ServerMessage>,
+}
+
+impl ServerConnection {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        let rx = spawn_json_reader(stream.try_clone()?);
+        Ok(Self { stream, rx })
+    }
+
+    pub fn send(&mut self, message: &ClientMessage) -> io::Result<()> {
+        write_message(&mut self.stream, message)
+    }
+
+    pub fn try_recv(&self) -> Option<ServerMessage> {
+        self.rx.try_recv().ok()
+    }
+
+    // Blocks for up to `timeout`; errors if the server closed the connection
+    pub fn recv_timeout(&self, timeout: Duration) -> io::Result<Option<ServerMessage>> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(message) => Ok(Some(message)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(io::ErrorKind::ConnectionAborted.into()),
+        }
+    }
+}
+
+// Client-side prediction for the local paddle and interpolation for everything else
+pub struct ClientPredictor {
+    side: Side,
+    rules: RuleSet,
+    next_input_tick: u32,
+    pending: VecDeque<(u32, PaddleCommand)>,
+    predicted_y: f32,
+    snapshots: VecDeque<(f64, StateSnapshot)>,
+}
+
+impl ClientPredictor {
+    pub fn new(side: Side, rules: RuleSet) -> Self {
+        Self {
+            side,
+            rules,
+            next_input_tick: 1,
+            pending: VecDeque::new(),
+            predicted_y: WINDOW_HEIGHT / 2.0 - PADDLE_HEIGHT / 2.0,
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    pub fn predicted_paddle_y(&self) -> f32 {
+        self.predicted_y
+    }
+
+    fn predict(&self, y: f32, command: PaddleCommand) -> f32 {
+        let y = y + command.direction() * self.rules.paddle_speed * TICK_DT;
+        y.clamp(0.0, WINDOW_HEIGHT - PADDLE_HEIGHT)
+    }
+
+    // Apply a local command immediately; returns the tick to send to the server with it
+    pub fn record_input(&mut self, command: PaddleCommand) -> u32 {
+        let tick = self.next_input_tick;
+        self.next_input_tick += 1;
+        self.pending.push_back((tick, command));
+        self.predicted_y = self.predict(self.predicted_y, command);
+        tick
+    }
+
+    // Reconcile with the server: start from its paddle position and replay unacknowledged inputs
+    pub fn apply_snapshot(&mut self, state: StateSnapshot, last_input: u32, received_at: f64) {
+        while matches!(self.pending.front(), Some((tick, _)) if *tick <= last_input) {
+            self.pending.pop_front();
+        }
+
+        let mut y = state.paddle_y(self.side);
+        for &(_, command) in &self.pending {
+            y = self.predict(y, command);
+        }
+        self.predicted_y = y;
+
+        self.snapshots.push_back((received_at, state));
+        while self.snapshots.len() > 2
+            && self.snapshots[1].0 < received_at - INTERPOLATION_DELAY * 2.0
+        {
+            self.snapshots.pop_front();
+        }
+    }
+
+    pub fn render_state(&self, now: f64) -> Option<StateSnapshot> {
+        let render_time = now - INTERPOLATION_DELAY;
+        let (_, latest) = self.snapshots.back()?;
+
+        let mut state = latest.clone();
+        let pair = self
+            .snapshots
+            .iter()
+            .zip(self.snapshots.iter().skip(1))
+            .find(|((from, _), (to, _))| *from <= render_time && render_time <= *to);
+
+        if let Some(((from_time, from), (to_time, to))) = pair {
+            let t = if to_time > from_time {
+                ((render_time - from_time) / (to_time - from_time)) as f32
+            } else {
+                1.0
+            };
+            let lerp = |a: f32, b: f32| a + (b - a) * t;
+
+            state = to.clone();
+            // Do not smear the ball across the court when it is re-served
+            let jump = (to.ball_position.x - from.ball_position.x).abs();
+            if jump < WINDOW_WIDTH / 2.0 {
+                state.ball_position = Vec2D::new(
+                    lerp(from.ball_position.x, to.ball_position.x),
+                    lerp(from.ball_position.y, to.ball_position.y),
+                );
+            }
+            let opponent = self.side.opponent();
+            state.set_paddle_y(
+                opponent,
+                lerp(from.paddle_y(opponent), to.paddle_y(opponent)),
+            );
+        } else if let Some((_, earliest)) = self.snapshots.front() {
+            if render_time < self.snapshots.front().map_or(0.0, |(time, _)| *time) {
+                state = earliest.clone();
+            }
+        }
+
+        state.set_paddle_y(self.side, self.predicted_y);
+        Some(state)
+    }
+}
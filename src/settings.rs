@@ -0,0 +1,87 @@
+// Player settings kept between runs: the visual theme, font, post-processing and game-feel effects
+
+use crate::feel::FeelSettings;
+use crate::postfx::PostFxSettings;
+use crate::save::data_dir;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const SETTINGS_VERSION: u32 = 1;
+pub const SETTINGS_FILE_NAME: &str = "settings.json";
+
+pub fn default_settings_path() -> PathBuf {
+    data_dir().join(SETTINGS_FILE_NAME)
+}
+
+fn default_theme() -> String {
+    "classic".to_owned()
+}
+
+fn default_font() -> String {
+    "pixel".to_owned()
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Settings {
+    pub version: u32,
+    // A built-in theme name, `custom` or the path of a theme file
+    #[serde(default = "default_theme")]
+    pub theme: String,
+    // `pixel`, `default` or the path of a TrueType font
+    #[serde(default = "default_font")]
+    pub font: String,
+    #[serde(default)]
+    pub postfx: PostFxSettings,
+    #[serde(default)]
+    pub feel: FeelSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            theme: default_theme(),
+            font: default_font(),
+            postfx: PostFxSettings::default(),
+            feel: FeelSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let settings: Self = serde_json::from_str(json)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if settings.version != SETTINGS_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported settings version {}", settings.version),
+            ));
+        }
+        Ok(settings)
+    }
+
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, self.to_json()?)?;
+        fs::rename(&tmp_path, path)
+    }
+
+    // A missing file gives the default settings
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(json) => Self::from_json(&json),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err),
+        }
+    }
+}
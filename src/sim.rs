@@ -0,0 +1,604 @@
+// Batch bot-vs-bot simulation with statistics
+// Matches run headless at the fixed tick rate through `Game::step`, spread over worker
+// threads. The two players swap sides every other match so neither gains from the court.
+
+use crate::bot::{BotController, BuiltinAi, Controller, HandicappedAi, DEFAULT_TIME_BUDGET};
+use crate::netplay::{TICK_DT, TICK_RATE};
+use crate::{AiSettings, Game, GameState, PaddleCommand, RuleSet, Side};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+// Upper limit for --hit-speedup and --max-speedup; anything larger is almost certainly a typo
+pub const SPEEDUP_LIMIT: f32 = 10.0;
+
+pub const SIM_USAGE: &str = "Usage: pong-sim [OPTIONS]
+
+Options:
+  -a, --player-a <SPEC>  First player (default: ai:0.5)
+  -b, --player-b <SPEC>  Second player (default: ai:0.4)
+  -n, --matches <N>      Number of matches (default: 1000)
+  --seed <N>             Seed of the first match; match i uses seed + i (default: 0)
+  --threads <N>          Worker threads (default: all cores)
+  --max-ticks <N>        Give up on a match after this many ticks (default: 36000)
+  --winning-score <N>    Points needed to win a match (default: 5)
+  --hit-speedup <X>      Ball speed factor on each paddle hit (1-10, default: 1, a constant speed)
+  --max-speedup <X>      Fastest ball as a multiple of the serve speed (1-10, default: 2)
+  --bot-budget <MS>      Time a bot has to answer each tick (default: 10)
+  --format <FORMAT>      text, csv or json (default: text)
+  -o, --output <FILE>    Write the report to FILE instead of stdout
+  -h, --help             Print this help
+
+Players:
+  ai                     The built-in AI at full speed
+  ai:<SPEED>[:<DEAD>]    The built-in AI moving on SPEED (0-1) of ticks, with dead zone DEAD
+  idle                   Never moves
+  bot:<CMD>              An external bot program (see --left-bot in pong --help)";
+
+// Ten minutes of play
+pub const DEFAULT_MAX_TICKS: u32 = 10 * 60 * TICK_RATE;
+// 95% confidence
+const Z: f64 = 1.96;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlayerSpec {
+    Ai,
+    Handicapped(AiSettings),
+    Idle,
+    Bot(String),
+}
+
+impl FromStr for PlayerSpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, String> {
+        if let Some(command) = spec.strip_prefix("bot:") {
+            return Ok(PlayerSpec::Bot(command.to_owned()));
+        }
+        let mut parts = spec.split(':');
+        match parts.next() {
+            Some("idle") if parts.next().is_none() => Ok(PlayerSpec::Idle),
+            Some("ai") => {
+                let speed = parts.next();
+                let dead_zone = parts.next();
+                if parts.next().is_some() {
+                    return Err(format!("invalid player: {}", spec));
+                }
+                let Some(speed) = speed else {
+                    return Ok(PlayerSpec::Ai);
+                };
+                let invalid = || format!("invalid AI settings: {}", spec);
+                let speed_factor: f32 = speed.parse().map_err(|_| invalid())?;
+                if !(0.0..=1.0).contains(&speed_factor) {
+                    return Err(invalid());
+                }
+                let dead_zone = match dead_zone {
+                    Some(dead_zone) => dead_zone.parse().map_err(|_| invalid())?,
+                    None => AiSettings::default().dead_zone,
+                };
+                Ok(PlayerSpec::Handicapped(AiSettings {
+                    speed_factor,
+                    dead_zone,
+                }))
+            }
+            _ => Err(format!(
+                "invalid player: {} (expected ai, ai:<speed>, idle or bot:<cmd>)",
+                spec
+            )),
+        }
+    }
+}
+
+impl fmt::Display for PlayerSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerSpec::Ai => write!(f, "ai"),
+            PlayerSpec::Handicapped(settings) => {
+                write!(f, "ai:{}:{}", settings.speed_factor, settings.dead_zone)
+            }
+            PlayerSpec::Idle => write!(f, "idle"),
+            PlayerSpec::Bot(command) => write!(f, "bot:{}", command),
+        }
+    }
+}
+
+struct Idle;
+
+impl Controller for Idle {
+    fn command(&mut self, _game: &Game, _side: Side) -> PaddleCommand {
+        PaddleCommand::Stay
+    }
+}
+
+impl PlayerSpec {
+    pub fn controller(&self, seed: u64, budget: Duration) -> io::Result<Box<dyn Controller>> {
+        Ok(match self {
+            PlayerSpec::Ai => Box::new(BuiltinAi),
+            PlayerSpec::Handicapped(settings) => Box::new(HandicappedAi::new(*settings, seed)),
+            PlayerSpec::Idle => Box::new(Idle),
+            PlayerSpec::Bot(command) => Box::new(BotController::spawn(command, budget)?),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimConfig {
+    pub player_a: PlayerSpec,
+    pub player_b: PlayerSpec,
+    pub matches: u32,
+    pub seed: u64,
+    pub threads: usize,
+    pub max_ticks: u32,
+    pub rules: RuleSet,
+    pub bot_budget: Duration,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            player_a: PlayerSpec::Handicapped(AiSettings {
+                speed_factor: 0.5,
+                ..AiSettings::default()
+            }),
+            player_b: PlayerSpec::Handicapped(AiSettings {
+                speed_factor: 0.4,
+                ..AiSettings::default()
+            }),
+            matches: 1000,
+            seed: 0,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            max_ticks: DEFAULT_MAX_TICKS,
+            rules: RuleSet::default(),
+            bot_budget: DEFAULT_TIME_BUDGET,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Player {
+    A,
+    B,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MatchRecord {
+    pub index: u32,
+    pub seed: u64,
+    pub a_side: Side,
+    // None when the match hit the tick limit
+    pub winner: Option<Player>,
+    pub a_points: i32,
+    pub b_points: i32,
+    pub ticks: u32,
+    // Paddle hits in each point, in order
+    pub rallies: Vec<u32>,
+}
+
+// Play match `index` of the batch
+pub fn run_match(config: &SimConfig, index: u32) -> io::Result<MatchRecord> {
+    let seed = config.seed.wrapping_add(u64::from(index));
+    let a_side = if index.is_multiple_of(2) {
+        Side::Left
+    } else {
+        Side::Right
+    };
+    let mut a = config.player_a.controller(seed, config.bot_budget)?;
+    let mut b = config
+        .player_b
+        .controller(seed ^ 0x5eed, config.bot_budget)?;
+
+    let mut game = Game::with_seed(seed);
+    game.rules = config.rules;
+    game.ai_side = None;
+    game.reset();
+
+    let mut ticks = 0;
+    let mut rallies = Vec::new();
+    while ticks < config.max_ticks && game.game_state == GameState::Playing {
+        let (a_command, b_command) = (
+            a.command(&game, a_side),
+            b.command(&game, a_side.opponent()),
+        );
+        let (left, right) = match a_side {
+            Side::Left => (a_command, b_command),
+            Side::Right => (b_command, a_command),
+        };
+
+        let points = game.player_score + game.ai_score;
+        let rally = game.rally_length;
+        game.step(TICK_DT, left, right);
+        game.particles.clear();
+        ticks += 1;
+
+        if game.player_score + game.ai_score != points {
+            rallies.push(rally);
+        }
+    }
+
+    let (a_points, b_points) = match a_side {
+        Side::Left => (game.player_score, game.ai_score),
+        Side::Right => (game.ai_score, game.player_score),
+    };
+    let winner = match game.game_state {
+        GameState::GameOver if a_points > b_points => Some(Player::A),
+        GameState::GameOver => Some(Player::B),
+        _ => None,
+    };
+    if let Some(winner) = winner {
+        let winning_side = if winner == Player::A {
+            a_side
+        } else {
+            a_side.opponent()
+        };
+        a.finish(winning_side);
+        b.finish(winning_side);
+    }
+
+    Ok(MatchRecord {
+        index,
+        seed,
+        a_side,
+        winner,
+        a_points,
+        b_points,
+        ticks,
+        rallies,
+    })
+}
+
+// Play every match of the batch on `config.threads` workers; records come back in order
+pub fn run_batch(config: &SimConfig) -> io::Result<Vec<MatchRecord>> {
+    let next = AtomicU32::new(0);
+    let records = Mutex::new(Vec::with_capacity(config.matches as usize));
+    let error: Mutex<Option<io::Error>> = Mutex::new(None);
+
+    thread::scope(|scope| {
+        for _ in 0..config.threads.max(1) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= config.matches {
+                    break;
+                }
+                match run_match(config, index) {
+                    Ok(record) => records.lock().unwrap().push(record),
+                    Err(err) => {
+                        // Stop handing out matches once one has failed
+                        next.store(config.matches, Ordering::Relaxed);
+                        error.lock().unwrap().get_or_insert(err);
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    if let Some(err) = error.into_inner().unwrap() {
+        return Err(err);
+    }
+    let mut records = records.into_inner().unwrap();
+    records.sort_by_key(|record| record.index);
+    Ok(records)
+}
+
+// A sample mean or proportion with its 95% confidence interval
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Estimate {
+    pub value: f64,
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Estimate {
+    // Wilson score interval, which stays sensible for rates near 0 or 1
+    pub fn proportion(successes: u32, trials: u32) -> Self {
+        if trials == 0 {
+            return Self::default();
+        }
+        let n = f64::from(trials);
+        let p = f64::from(successes) / n;
+        let denominator = 1.0 + Z * Z / n;
+        let centre = (p + Z * Z / (2.0 * n)) / denominator;
+        let margin = Z * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt() / denominator;
+        Self {
+            value: p,
+            low: (centre - margin).max(0.0),
+            high: (centre + margin).min(1.0),
+        }
+    }
+
+    // Normal approximation around the sample mean
+    pub fn mean(samples: &[f64]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        if samples.len() < 2 {
+            return Self {
+                value: mean,
+                low: mean,
+                high: mean,
+            };
+        }
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        let margin = Z * (variance / n).sqrt();
+        Self {
+            value: mean,
+            low: mean - margin,
+            high: mean + margin,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub matches: u32,
+    pub a_wins: u32,
+    pub b_wins: u32,
+    pub unfinished: u32,
+    pub a_win_rate: Estimate,
+    pub b_win_rate: Estimate,
+    pub rally_length: Estimate,
+    pub points_per_match: Estimate,
+    pub ticks_per_match: Estimate,
+}
+
+impl Summary {
+    pub fn from_records(records: &[MatchRecord]) -> Self {
+        let count = |player| {
+            records
+                .iter()
+                .filter(|record| record.winner == Some(player))
+                .count() as u32
+        };
+        let (a_wins, b_wins) = (count(Player::A), count(Player::B));
+        let decided = a_wins + b_wins;
+
+        let rallies: Vec<f64> = records
+            .iter()
+            .flat_map(|record| record.rallies.iter().map(|&rally| f64::from(rally)))
+            .collect();
+        let points: Vec<f64> = records
+            .iter()
+            .map(|record| f64::from(record.a_points + record.b_points))
+            .collect();
+        let ticks: Vec<f64> = records
+            .iter()
+            .map(|record| f64::from(record.ticks))
+            .collect();
+
+        Self {
+            matches: records.len() as u32,
+            a_wins,
+            b_wins,
+            unfinished: records.len() as u32 - decided,
+            a_win_rate: Estimate::proportion(a_wins, decided),
+            b_win_rate: Estimate::proportion(b_wins, decided),
+            rally_length: Estimate::mean(&rallies),
+            points_per_match: Estimate::mean(&points),
+            ticks_per_match: Estimate::mean(&ticks),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Csv,
+    Json,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimOptions {
+    pub config: SimConfig,
+    pub format: OutputFormat,
+    pub output: Option<PathBuf>,
+    pub help: bool,
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} expects a value", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+pub fn parse_sim_args<I: IntoIterator<Item = String>>(args: I) -> Result<SimOptions, String> {
+    let mut config = SimConfig::default();
+    let mut format = OutputFormat::Text;
+    let mut output = None;
+    let mut help = false;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-a" | "--player-a" => {
+                config.player_a = args.next().ok_or("--player-a expects a value")?.parse()?;
+            }
+            "-b" | "--player-b" => {
+                config.player_b = args.next().ok_or("--player-b expects a value")?.parse()?;
+            }
+            "-n" | "--matches" => config.matches = parse_value("--matches", args.next())?,
+            "--seed" => config.seed = parse_value("--seed", args.next())?,
+            "--threads" => config.threads = parse_value("--threads", args.next())?,
+            "--max-ticks" => config.max_ticks = parse_value("--max-ticks", args.next())?,
+            "--winning-score" => {
+                config.rules.winning_score = parse_value("--winning-score", args.next())?;
+            }
+            "--hit-speedup" => {
+                config.rules.hit_speedup = parse_value("--hit-speedup", args.next())?;
+            }
+            "--max-speedup" => {
+                config.rules.max_speedup = parse_value("--max-speedup", args.next())?;
+            }
+            "--bot-budget" => {
+                config.bot_budget =
+                    Duration::from_millis(parse_value("--bot-budget", args.next())?);
+            }
+            "--format" => {
+                format = match parse_value::<String>("--format", args.next())?.as_str() {
+                    "text" => OutputFormat::Text,
+                    "csv" => OutputFormat::Csv,
+                    "json" => OutputFormat::Json,
+                    other => {
+                        return Err(format!(
+                            "invalid format: {} (expected text, csv or json)",
+                            other
+                        ))
+                    }
+                }
+            }
+            "-o" | "--output" => output = Some(parse_value("--output", args.next())?),
+            "-h" | "--help" => help = true,
+            _ => return Err(format!("unknown argument: {}", arg)),
+        }
+    }
+
+    if config.threads == 0 {
+        return Err("--threads must be at least 1".to_owned());
+    }
+    if config.rules.winning_score < 1 {
+        return Err("--winning-score must be at least 1".to_owned());
+    }
+    // NaN and infinity fail the range check too
+    let speedup = 1.0..=SPEEDUP_LIMIT;
+    if !(speedup.contains(&config.rules.hit_speedup) && speedup.contains(&config.rules.max_speedup))
+    {
+        return Err(format!(
+            "--hit-speedup and --max-speedup must be between 1 and {}",
+            SPEEDUP_LIMIT
+        ));
+    }
+
+    Ok(SimOptions {
+        config,
+        format,
+        output,
+        help,
+    })
+}
+
+fn format_estimate(estimate: &Estimate, scale: f64, unit: &str) -> String {
+    format!(
+        "{:.2}{} (95% CI {:.2}{} - {:.2}{})",
+        estimate.value * scale,
+        unit,
+        estimate.low * scale,
+        unit,
+        estimate.high * scale,
+        unit
+    )
+}
+
+pub fn write_text<W: Write>(
+    mut writer: W,
+    config: &SimConfig,
+    summary: &Summary,
+) -> io::Result<()> {
+    writeln!(
+        writer,
+        "{} matches: A = {}, B = {}",
+        summary.matches, config.player_a, config.player_b
+    )?;
+    writeln!(
+        writer,
+        "A wins:           {}  {}",
+        summary.a_wins,
+        format_estimate(&summary.a_win_rate, 100.0, "%")
+    )?;
+    writeln!(
+        writer,
+        "B wins:           {}  {}",
+        summary.b_wins,
+        format_estimate(&summary.b_win_rate, 100.0, "%")
+    )?;
+    writeln!(writer, "Unfinished:       {}", summary.unfinished)?;
+    writeln!(
+        writer,
+        "Rally length:     {}",
+        format_estimate(&summary.rally_length, 1.0, "")
+    )?;
+    writeln!(
+        writer,
+        "Points per match: {}",
+        format_estimate(&summary.points_per_match, 1.0, "")
+    )?;
+    writeln!(
+        writer,
+        "Match length:     {}",
+        format_estimate(&summary.ticks_per_match, f64::from(TICK_DT), "s")
+    )
+}
+
+pub fn write_csv<W: Write>(mut writer: W, records: &[MatchRecord]) -> io::Result<()> {
+    writeln!(
+        writer,
+        "index,seed,a_side,winner,a_points,b_points,ticks,points,mean_rally,longest_rally"
+    )?;
+    for record in records {
+        let winner = match record.winner {
+            Some(Player::A) => "A",
+            Some(Player::B) => "B",
+            None => "",
+        };
+        let side = match record.a_side {
+            Side::Left => "left",
+            Side::Right => "right",
+        };
+        let mean_rally = Estimate::mean(
+            &record
+                .rallies
+                .iter()
+                .map(|&rally| f64::from(rally))
+                .collect::<Vec<_>>(),
+        );
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{:.2},{}",
+            record.index,
+            record.seed,
+            side,
+            winner,
+            record.a_points,
+            record.b_points,
+            record.ticks,
+            record.rallies.len(),
+            mean_rally.value,
+            record.rallies.iter().max().copied().unwrap_or(0)
+        )?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    player_a: String,
+    player_b: String,
+    seed: u64,
+    summary: &'a Summary,
+    matches: &'a [MatchRecord],
+}
+
+pub fn write_json<W: Write>(
+    mut writer: W,
+    config: &SimConfig,
+    summary: &Summary,
+    records: &[MatchRecord],
+) -> io::Result<()> {
+    let report = JsonReport {
+        player_a: config.player_a.to_string(),
+        player_b: config.player_b.to_string(),
+        seed: config.seed,
+        summary,
+        matches: records,
+    };
+    serde_json::to_writer_pretty(&mut writer, &report)?;
+    writeln!(writer)
+}
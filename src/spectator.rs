@@ -0,0 +1,309 @@
+// Spectator streams for watching a match live
+// A feed turns successive snapshots into a keyframe followed by small deltas; any score or
+// phase change, and every KEYFRAME_INTERVAL frames, produces a new keyframe. Spectators that
+// join late are sent the current state as a keyframe and ignore deltas until they have one.
+
+use crate::hud::{Anchor, Layout};
+use crate::server::{spawn_json_reader, write_message, StateSnapshot};
+use crate::theme;
+use crate::{Vec2D, BALL_SIZE, WINDOW_HEIGHT};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread;
+use std::time::Duration;
+
+pub const KEYFRAME_INTERVAL: u32 = 60;
+// Frames a spectator may fall behind by before it is dropped, about two seconds of play
+pub const SPECTATOR_QUEUE: usize = 120;
+// Connections that take longer than this to accept a write are closed
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+// Never extrapolate the ball further than this past the newest frame
+pub const MAX_EXTRAPOLATION: f64 = 0.1;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatchInfo {
+    pub left_name: String,
+    pub right_name: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SpectatorMessage {
+    Keyframe {
+        info: MatchInfo,
+        state: StateSnapshot,
+    },
+    Delta {
+        tick: u32,
+        ball_position: Vec2D,
+        ball_velocity: Vec2D,
+        left_paddle_y: f32,
+        right_paddle_y: f32,
+        rally_length: u32,
+    },
+}
+
+#[derive(Default)]
+pub struct SpectatorFeed {
+    info: MatchInfo,
+    latest: Option<StateSnapshot>,
+    frames_since_keyframe: u32,
+}
+
+impl SpectatorFeed {
+    pub fn new(info: MatchInfo) -> Self {
+        Self {
+            info,
+            latest: None,
+            frames_since_keyframe: 0,
+        }
+    }
+
+    pub fn info(&self) -> &MatchInfo {
+        &self.info
+    }
+
+    // Changing the names forces the next frame to be a keyframe
+    pub fn set_info(&mut self, info: MatchInfo) {
+        if info != self.info {
+            self.info = info;
+            self.latest = None;
+        }
+    }
+
+    // Start over, e.g. when a new match begins
+    pub fn reset(&mut self) {
+        self.latest = None;
+    }
+
+    // The current state as a keyframe, for spectators that have just joined
+    pub fn keyframe(&self) -> Option<SpectatorMessage> {
+        self.latest
+            .as_ref()
+            .map(|state| SpectatorMessage::Keyframe {
+                info: self.info.clone(),
+                state: state.clone(),
+            })
+    }
+
+    pub fn encode(&mut self, state: &StateSnapshot) -> SpectatorMessage {
+        let needs_keyframe = match &self.latest {
+            Some(latest) => {
+                latest.left_score != state.left_score
+                    || latest.right_score != state.right_score
+                    || latest.game_state != state.game_state
+                    || state.tick < latest.tick
+                    || self.frames_since_keyframe + 1 >= KEYFRAME_INTERVAL
+            }
+            None => true,
+        };
+        self.latest = Some(state.clone());
+
+        if needs_keyframe {
+            self.frames_since_keyframe = 0;
+            SpectatorMessage::Keyframe {
+                info: self.info.clone(),
+                state: state.clone(),
+            }
+        } else {
+            self.frames_since_keyframe += 1;
+            SpectatorMessage::Delta {
+                tick: state.tick,
+                ball_position: state.ball_position,
+                ball_velocity: state.ball_velocity,
+                left_paddle_y: state.left_paddle_y,
+                right_paddle_y: state.right_paddle_y,
+                rally_length: state.rally_length,
+            }
+        }
+    }
+}
+
+// What a spectator knows about the match it is watching
+#[derive(Default)]
+pub struct SpectatorView {
+    pub info: Option<MatchInfo>,
+    state: Option<StateSnapshot>,
+    received_at: f64,
+}
+
+impl SpectatorView {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> Option<&StateSnapshot> {
+        self.state.as_ref()
+    }
+
+    pub fn apply(&mut self, message: SpectatorMessage, now: f64) {
+        match message {
+            SpectatorMessage::Keyframe { info, state } => {
+                self.info = Some(info);
+                self.state = Some(state);
+                self.received_at = now;
+            }
+            SpectatorMessage::Delta {
+                tick,
+                ball_position,
+                ball_velocity,
+                left_paddle_y,
+                right_paddle_y,
+                rally_length,
+            } => {
+                // Deltas are meaningless until a keyframe has arrived
+                if let Some(state) = self.state.as_mut() {
+                    if tick >= state.tick {
+                        state.tick = tick;
+                        state.ball_position = ball_position;
+                        state.ball_velocity = ball_velocity;
+                        state.left_paddle_y = left_paddle_y;
+                        state.right_paddle_y = right_paddle_y;
+                        state.rally_length = rally_length;
+                        self.received_at = now;
+                    }
+                }
+            }
+        }
+    }
+
+    // Latest state with the ball carried forward along its velocity since it arrived
+    pub fn render_state(&self, now: f64) -> Option<StateSnapshot> {
+        let mut state = self.state.clone()?;
+        let elapsed = (now - self.received_at).clamp(0.0, MAX_EXTRAPOLATION) as f32;
+        state.ball_position.x += state.ball_velocity.x * elapsed;
+        state.ball_position.y = (state.ball_position.y + state.ball_velocity.y * elapsed)
+            .clamp(0.0, WINDOW_HEIGHT - BALL_SIZE);
+        Some(state)
+    }
+}
+
+pub fn draw_spectator_hud(info: &MatchInfo, state: &StateSnapshot) {
+    let theme = theme::active();
+    let layout = Layout::current();
+    layout.text(
+        &info.left_name,
+        Anchor::TopLeft,
+        Vec2::ZERO,
+        24.0,
+        theme.left_paddle,
+    );
+    layout.text(
+        &info.right_name,
+        Anchor::TopRight,
+        Vec2::ZERO,
+        24.0,
+        theme.right_paddle,
+    );
+
+    // Under the scoreboard
+    let speed = state.ball_velocity.length();
+    let stats = format!(
+        "SPECTATING   Rally: {}   Ball speed: {:.0} px/s",
+        state.rally_length, speed
+    );
+    layout.text(&stats, Anchor::Top, vec2(0.0, 80.0), 18.0, theme.text_dim);
+}
+
+// Serves a local or peer-to-peer match to any number of spectators over TCP
+pub struct SpectatorBroadcaster {
+    listener: TcpListener,
+    spectators: Vec<SyncSender<SpectatorMessage>>,
+    feed: SpectatorFeed,
+}
+
+impl SpectatorBroadcaster {
+    pub fn bind<A: ToSocketAddrs>(addr: A, info: MatchInfo) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            spectators: Vec::new(),
+            feed: SpectatorFeed::new(info),
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn spectator_count(&self) -> usize {
+        self.spectators.len()
+    }
+
+    // New names reach spectators with the next frame, which becomes a keyframe
+    pub fn set_info(&mut self, info: MatchInfo) {
+        self.feed.set_info(info);
+    }
+
+    fn accept(&mut self) -> Vec<SyncSender<SpectatorMessage>> {
+        let mut joined = Vec::new();
+        while let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(false).is_err() {
+                continue;
+            }
+            let _ = stream.set_nodelay(true);
+            joined.push(spawn_writer(stream));
+        }
+        joined
+    }
+
+    // Send the current frame; call once per simulation tick
+    pub fn publish(&mut self, state: &StateSnapshot) {
+        let joined = self.accept();
+        let message = self.feed.encode(state);
+
+        // Slow or departed spectators are dropped rather than allowed to stall the game
+        // or queue frames without limit
+        self.spectators
+            .retain(|tx| tx.try_send(message.clone()).is_ok());
+
+        if let Some(keyframe) = self.feed.keyframe() {
+            for tx in joined {
+                if tx.try_send(keyframe.clone()).is_ok() {
+                    self.spectators.push(tx);
+                }
+            }
+        }
+    }
+}
+
+// The writer stops, closing the connection, once the sender is dropped or a write stalls
+fn spawn_writer(mut stream: TcpStream) -> SyncSender<SpectatorMessage> {
+    let (tx, rx) = sync_channel::<SpectatorMessage>(SPECTATOR_QUEUE);
+    let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+    thread::spawn(move || {
+        for message in rx {
+            if write_message(&mut stream, &message).is_err() {
+                break;
+            }
+        }
+        let _ = stream.shutdown(std::net::Shutdown::Both);
+    });
+    tx
+}
+
+// Read-only connection to a SpectatorBroadcaster
+pub struct SpectatorClient {
+    rx: Receiver<SpectatorMessage>,
+}
+
+impl SpectatorClient {
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        Ok(Self {
+            rx: spawn_json_reader(stream),
+        })
+    }
+
+    pub fn try_recv(&self) -> Option<SpectatorMessage> {
+        self.rx.try_recv().ok()
+    }
+
+    pub fn recv_timeout(&self, timeout: std::time::Duration) -> Option<SpectatorMessage> {
+        self.rx.recv_timeout(timeout).ok()
+    }
+}
@@ -0,0 +1,239 @@
+// Per-match statistics and the post-game summary screen
+// Statistics subscribe to the game's events: serves, paddle hits, wall hits and goals
+
+use crate::events::{EventListener, GameEvent};
+use crate::hud::{Layout, COLUMN_GAP, LINE_GAP};
+use crate::save::data_dir;
+use crate::text::Align;
+use crate::theme;
+use crate::{Ball, Side};
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Hits further than this from the paddle centre (as a fraction of half its height) are edge hits
+pub const EDGE_CONTACT: f32 = 0.5;
+
+pub fn default_stats_dir() -> PathBuf {
+    data_dir().join("stats")
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SideStats {
+    pub paddle_hits: u32,
+    pub edge_hits: u32,
+    pub centre_hits: u32,
+    pub points: u32,
+    // Points won when this side served
+    pub points_on_serve: u32,
+    #[serde(default)]
+    pub fastest_return: f32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MatchStats {
+    // Points played; each one is a rally
+    pub rallies: u32,
+    pub longest_rally: u32,
+    pub wall_hits: u32,
+    pub max_ball_speed: f32,
+    pub time_played: f32,
+    pub left: SideStats,
+    pub right: SideStats,
+    // The side the ball was served away from for the current point
+    pub server: Option<Side>,
+}
+
+impl MatchStats {
+    pub fn side(&self, side: Side) -> &SideStats {
+        match side {
+            Side::Left => &self.left,
+            Side::Right => &self.right,
+        }
+    }
+
+    fn side_mut(&mut self, side: Side) -> &mut SideStats {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+
+    // `contact` is where the ball met the paddle, from -1 (top) to 1 (bottom)
+    pub fn record_paddle_hit(&mut self, side: Side, contact: f32, speed: f32) {
+        let stats = self.side_mut(side);
+        stats.paddle_hits += 1;
+        stats.fastest_return = stats.fastest_return.max(speed);
+        if contact.abs() > EDGE_CONTACT {
+            stats.edge_hits += 1;
+        } else {
+            stats.centre_hits += 1;
+        }
+    }
+
+    pub fn record_wall_hit(&mut self) {
+        self.wall_hits += 1;
+    }
+
+    // Called before the next serve, with the paddle hits of the finished rally
+    pub fn record_point(&mut self, winner: Side, rally_length: u32) {
+        self.rallies += 1;
+        self.longest_rally = self.longest_rally.max(rally_length);
+        let on_serve = self.server == Some(winner);
+        let stats = self.side_mut(winner);
+        stats.points += 1;
+        if on_serve {
+            stats.points_on_serve += 1;
+        }
+    }
+
+    pub fn record_tick(&mut self, dt: f32, ball: &Ball) {
+        self.time_played += dt;
+        let speed = (ball.velocity.x * ball.velocity.x + ball.velocity.y * ball.velocity.y).sqrt();
+        self.max_ball_speed = self.max_ball_speed.max(speed);
+    }
+
+    pub fn total_hits(&self) -> u32 {
+        self.left.paddle_hits + self.right.paddle_hits
+    }
+
+    pub fn average_rally(&self) -> f32 {
+        if self.rallies == 0 {
+            0.0
+        } else {
+            self.total_hits() as f32 / self.rallies as f32
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+
+    pub fn write_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_json()?)
+    }
+
+    // Write to a new timestamped file in `dir` and return its path
+    // Exports within the same second get a numbered suffix instead of replacing each other.
+    pub fn export(&self, dir: &Path) -> io::Result<PathBuf> {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let json = self.to_json()?;
+        fs::create_dir_all(dir)?;
+        for attempt in 0u32.. {
+            let name = match attempt {
+                0 => format!("match-{}.json", seconds),
+                _ => format!("match-{}-{}.json", seconds, attempt),
+            };
+            let path = dir.join(name);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(json.as_bytes())?;
+                    return Ok(path);
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        unreachable!("ran out of export file names")
+    }
+}
+
+impl EventListener for MatchStats {
+    fn on_event(&mut self, event: &GameEvent, _time: f32) {
+        match *event {
+            GameEvent::Serve { server, .. } => self.server = Some(server),
+            GameEvent::PaddleHit {
+                side,
+                contact,
+                speed,
+                ..
+            } => self.record_paddle_hit(side, contact, speed),
+            GameEvent::WallHit { .. } => self.record_wall_hit(),
+            GameEvent::Goal {
+                scorer,
+                rally_length,
+                ..
+            } => self.record_point(scorer, rally_length),
+            GameEvent::MatchEnd { .. } => {}
+        }
+    }
+}
+
+pub fn format_duration(seconds: f32) -> String {
+    let total = seconds.max(0.0) as u32;
+    format!("{}:{:02}", total / 60, total % 60)
+}
+
+// Rows of the summary table: label, left value, right value (empty for match-wide numbers)
+pub fn summary_rows(stats: &MatchStats) -> Vec<(&'static str, String, String)> {
+    let per_side = |label, value: fn(&SideStats) -> u32| {
+        (
+            label,
+            value(&stats.left).to_string(),
+            value(&stats.right).to_string(),
+        )
+    };
+    let mut rows = vec![
+        per_side("Paddle hits", |side| side.paddle_hits),
+        per_side("Centre hits", |side| side.centre_hits),
+        per_side("Edge hits", |side| side.edge_hits),
+        per_side("Points on serve", |side| side.points_on_serve),
+    ];
+    rows.push(("Rallies", stats.rallies.to_string(), String::new()));
+    rows.push((
+        "Longest rally",
+        stats.longest_rally.to_string(),
+        String::new(),
+    ));
+    rows.push((
+        "Average rally",
+        format!("{:.1}", stats.average_rally()),
+        String::new(),
+    ));
+    rows.push((
+        "Max ball speed",
+        format!("{:.0}", stats.max_ball_speed),
+        String::new(),
+    ));
+    rows.push((
+        "Time played",
+        format_duration(stats.time_played),
+        String::new(),
+    ));
+    rows
+}
+
+// Statistics table for the game over screen, starting at `top`
+pub fn draw_match_summary(stats: &MatchStats, top: f32) {
+    let theme = theme::active();
+    let layout = Layout::current();
+    let rows = summary_rows(stats);
+
+    // A table centred on the court: labels, then each side's values right-aligned under its name
+    let label_width = layout.column_width(rows.iter().map(|row| row.0), 20.0);
+    let values = rows.iter().flat_map(|row| [row.1.as_str(), row.2.as_str()]);
+    let value_width = layout
+        .column_width(values, 20.0)
+        .max(layout.column_width(["LEFT", "RIGHT"], 18.0));
+    let width = label_width + 2.0 * (COLUMN_GAP + value_width);
+    let label_x = (layout.width - width) / 2.0;
+    let left_x = label_x + label_width + COLUMN_GAP + value_width;
+    let right_x = left_x + COLUMN_GAP + value_width;
+
+    let mut above = layout.line("LEFT", left_x, top, 18.0, theme.text_dim, Align::Right);
+    layout.line("RIGHT", right_x, top, 18.0, theme.text_dim, Align::Right);
+    for (label, left, right) in &rows {
+        let top = above.bottom() + LINE_GAP;
+        above = layout.line(label, label_x, top, 20.0, theme.text_dim, Align::Left);
+        layout.line(left, left_x, top, 20.0, theme.text, Align::Right);
+        layout.line(right, right_x, top, 20.0, theme.text, Align::Right);
+    }
+}
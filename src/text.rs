@@ -0,0 +1,277 @@
+// Text drawing and measuring with the active font
+// The default font is a retro 5x7 pixel font embedded below and drawn as rectangles, so it needs
+// no texture and measures exactly. macroquad's built-in font and TrueType files can be chosen
+// instead. All text goes through here so alignment is the same whichever font is active.
+
+use macroquad::prelude::*;
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+
+// Names accepted in settings and on the command line besides .ttf and .otf files
+// `default` is macroquad's built-in font
+pub const FONT_NAMES: [&str; 2] = ["pixel", "default"];
+
+// Pixel font cell: glyphs are 5 pixels wide with a gap after them, and 7 pixels above the
+// baseline with one row below it for descenders
+pub const GLYPH_WIDTH: f32 = 5.0;
+pub const GLYPH_ADVANCE: f32 = 6.0;
+pub const GLYPH_ASCENT: f32 = 7.0;
+// A font size of this many units draws glyph pixels one unit across
+pub const PIXELS_PER_EM: f32 = 12.0;
+
+pub enum TextFont {
+    Pixel,
+    Builtin,
+    Ttf(Font),
+}
+
+// Fonts hold textures, so the active one lives on the render thread
+thread_local! {
+    static ACTIVE: RefCell<TextFont> = const { RefCell::new(TextFont::Pixel) };
+}
+
+pub fn is_font_name(name: &str) -> bool {
+    FONT_NAMES.contains(&name) || name.ends_with(".ttf") || name.ends_with(".otf")
+}
+
+// A font name or the path of a TrueType font
+pub fn load(name: &str) -> io::Result<TextFont> {
+    match name {
+        "pixel" => Ok(TextFont::Pixel),
+        "default" => Ok(TextFont::Builtin),
+        _ if is_font_name(name) => {
+            let bytes = fs::read(name)?;
+            load_ttf_font_from_bytes(&bytes)
+                .map(TextFont::Ttf)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", err)))
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown font: {}", name),
+        )),
+    }
+}
+
+pub fn set_font(font: TextFont) {
+    ACTIVE.with(|active| *active.borrow_mut() = font);
+}
+
+// The rows of a pixel font glyph, top first, with bit 4 the leftmost pixel
+// Characters outside printable ASCII are drawn as `?`
+pub fn pixel_glyph(c: char) -> [u8; 8] {
+    let index = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    PIXEL_GLYPHS[index]
+}
+
+fn pixel_scale(size: f32) -> f32 {
+    size / PIXELS_PER_EM
+}
+
+fn measure_pixel(text: &str, size: f32) -> TextDimensions {
+    let scale = pixel_scale(size);
+    let count = text.chars().count() as f32;
+    TextDimensions {
+        width: (count * GLYPH_ADVANCE - (GLYPH_ADVANCE - GLYPH_WIDTH)).max(0.0) * scale,
+        height: GLYPH_ASCENT * scale,
+        offset_y: GLYPH_ASCENT * scale,
+    }
+}
+
+fn draw_pixel(text: &str, x: f32, y: f32, size: f32, color: Color) {
+    for rect in pixel_rects(text, x, y, size) {
+        draw_rectangle(rect.x, rect.y, rect.w, rect.h, color);
+    }
+}
+
+// The rectangles that draw `text` in the pixel font with its baseline at `y`, one per run of lit
+// pixels in each row
+pub fn pixel_rects(text: &str, x: f32, y: f32, size: f32) -> Vec<Rect> {
+    let scale = pixel_scale(size);
+    let top = y - GLYPH_ASCENT * scale;
+    let mut rects = Vec::new();
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i as f32 * GLYPH_ADVANCE * scale;
+        for (row, bits) in pixel_glyph(c).iter().enumerate() {
+            let mut column = 0;
+            while column < 5 {
+                if bits & (0x10 >> column) == 0 {
+                    column += 1;
+                    continue;
+                }
+                let start = column;
+                while column < 5 && bits & (0x10 >> column) != 0 {
+                    column += 1;
+                }
+                rects.push(Rect::new(
+                    left + start as f32 * scale,
+                    top + row as f32 * scale,
+                    (column - start) as f32 * scale,
+                    scale,
+                ));
+            }
+        }
+    }
+    rects
+}
+
+// Size of `text` in the active font; `offset_y` is the height above the baseline
+pub fn measure(text: &str, size: f32) -> TextDimensions {
+    ACTIVE.with(|active| match &*active.borrow() {
+        TextFont::Pixel => measure_pixel(text, size),
+        TextFont::Builtin => measure_text(text, None, size as u16, 1.0),
+        TextFont::Ttf(font) => measure_text(text, Some(font), size as u16, 1.0),
+    })
+}
+
+// Like macroquad's draw_text, with the active font; `y` is the baseline
+pub fn draw_text(text: &str, x: f32, y: f32, size: f32, color: Color) -> TextDimensions {
+    ACTIVE.with(|active| match &*active.borrow() {
+        TextFont::Pixel => {
+            draw_pixel(text, x, y, size, color);
+            measure_pixel(text, size)
+        }
+        TextFont::Builtin => macroquad::text::draw_text(text, x, y, size, color),
+        TextFont::Ttf(font) => draw_text_ex(
+            text,
+            x,
+            y,
+            TextParams {
+                font: Some(font),
+                font_size: size as u16,
+                color,
+                ..Default::default()
+            },
+        ),
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+// The left edge of `text` when it is aligned on `x`
+pub fn aligned_x(text: &str, x: f32, size: f32, align: Align) -> f32 {
+    let width = measure(text, size).width;
+    match align {
+        Align::Left => x,
+        Align::Center => x - width / 2.0,
+        Align::Right => x - width,
+    }
+}
+
+pub fn draw_aligned(
+    text: &str,
+    x: f32,
+    y: f32,
+    size: f32,
+    color: Color,
+    align: Align,
+) -> TextDimensions {
+    draw_text(text, aligned_x(text, x, size, align), y, size, color)
+}
+
+// Printable ASCII from space to tilde
+const PIXEL_GLYPHS: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04, 0x00], // '!'
+    [0x0a, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x0a, 0x0a, 0x1f, 0x0a, 0x1f, 0x0a, 0x0a, 0x00], // '#'
+    [0x04, 0x0f, 0x14, 0x0e, 0x05, 0x1e, 0x04, 0x00], // '$'
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03, 0x00], // '%'
+    [0x0c, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0d, 0x00], // '&'
+    [0x04, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '\''
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02, 0x00], // '('
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08, 0x00], // ')'
+    [0x00, 0x04, 0x15, 0x0e, 0x15, 0x04, 0x00, 0x00], // '*'
+    [0x00, 0x04, 0x04, 0x1f, 0x04, 0x04, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x04, 0x08], // ','
+    [0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0c, 0x0c, 0x00], // '.'
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00, 0x00], // '/'
+    [0x0e, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0e, 0x00], // '0'
+    [0x04, 0x0c, 0x04, 0x04, 0x04, 0x04, 0x0e, 0x00], // '1'
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1f, 0x00], // '2'
+    [0x1f, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0e, 0x00], // '3'
+    [0x02, 0x06, 0x0a, 0x12, 0x1f, 0x02, 0x02, 0x00], // '4'
+    [0x1f, 0x10, 0x1e, 0x01, 0x01, 0x11, 0x0e, 0x00], // '5'
+    [0x06, 0x08, 0x10, 0x1e, 0x11, 0x11, 0x0e, 0x00], // '6'
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08, 0x00], // '7'
+    [0x0e, 0x11, 0x11, 0x0e, 0x11, 0x11, 0x0e, 0x00], // '8'
+    [0x0e, 0x11, 0x11, 0x0f, 0x01, 0x02, 0x0c, 0x00], // '9'
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x0c, 0x00, 0x00], // ':'
+    [0x00, 0x0c, 0x0c, 0x00, 0x0c, 0x04, 0x08, 0x00], // ';'
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02, 0x00], // '<'
+    [0x00, 0x00, 0x1f, 0x00, 0x1f, 0x00, 0x00, 0x00], // '='
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08, 0x00], // '>'
+    [0x0e, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04, 0x00], // '?'
+    [0x0e, 0x11, 0x01, 0x0d, 0x15, 0x15, 0x0e, 0x00], // '@'
+    [0x0e, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11, 0x00], // 'A'
+    [0x1e, 0x11, 0x11, 0x1e, 0x11, 0x11, 0x1e, 0x00], // 'B'
+    [0x0e, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0e, 0x00], // 'C'
+    [0x1c, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1c, 0x00], // 'D'
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x1f, 0x00], // 'E'
+    [0x1f, 0x10, 0x10, 0x1e, 0x10, 0x10, 0x10, 0x00], // 'F'
+    [0x0e, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0f, 0x00], // 'G'
+    [0x11, 0x11, 0x11, 0x1f, 0x11, 0x11, 0x11, 0x00], // 'H'
+    [0x0e, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e, 0x00], // 'I'
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0c, 0x00], // 'J'
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11, 0x00], // 'K'
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1f, 0x00], // 'L'
+    [0x11, 0x1b, 0x15, 0x15, 0x11, 0x11, 0x11, 0x00], // 'M'
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11, 0x00], // 'N'
+    [0x0e, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e, 0x00], // 'O'
+    [0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x10, 0x00], // 'P'
+    [0x0e, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0d, 0x00], // 'Q'
+    [0x1e, 0x11, 0x11, 0x1e, 0x14, 0x12, 0x11, 0x00], // 'R'
+    [0x0f, 0x10, 0x10, 0x0e, 0x01, 0x01, 0x1e, 0x00], // 'S'
+    [0x1f, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x00], // 'T'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0e, 0x00], // 'U'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0a, 0x04, 0x00], // 'V'
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0a, 0x00], // 'W'
+    [0x11, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x11, 0x00], // 'X'
+    [0x11, 0x11, 0x11, 0x0a, 0x04, 0x04, 0x04, 0x00], // 'Y'
+    [0x1f, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1f, 0x00], // 'Z'
+    [0x0e, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0e, 0x00], // '['
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00, 0x00], // '\\'
+    [0x0e, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0e, 0x00], // ']'
+    [0x04, 0x0a, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f, 0x00], // '_'
+    [0x08, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x0e, 0x01, 0x0f, 0x11, 0x0f, 0x00], // 'a'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1e, 0x00], // 'b'
+    [0x00, 0x00, 0x0e, 0x10, 0x10, 0x11, 0x0e, 0x00], // 'c'
+    [0x01, 0x01, 0x0d, 0x13, 0x11, 0x11, 0x0f, 0x00], // 'd'
+    [0x00, 0x00, 0x0e, 0x11, 0x1f, 0x10, 0x0e, 0x00], // 'e'
+    [0x06, 0x09, 0x08, 0x1c, 0x08, 0x08, 0x08, 0x00], // 'f'
+    [0x00, 0x0f, 0x11, 0x11, 0x0f, 0x01, 0x0e, 0x00], // 'g'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11, 0x00], // 'h'
+    [0x04, 0x00, 0x0c, 0x04, 0x04, 0x04, 0x0e, 0x00], // 'i'
+    [0x02, 0x00, 0x06, 0x02, 0x02, 0x12, 0x0c, 0x00], // 'j'
+    [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12, 0x00], // 'k'
+    [0x0c, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0e, 0x00], // 'l'
+    [0x00, 0x00, 0x1a, 0x15, 0x15, 0x11, 0x11, 0x00], // 'm'
+    [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11, 0x00], // 'n'
+    [0x00, 0x00, 0x0e, 0x11, 0x11, 0x11, 0x0e, 0x00], // 'o'
+    [0x00, 0x1e, 0x11, 0x11, 0x1e, 0x10, 0x10, 0x00], // 'p'
+    [0x00, 0x0f, 0x11, 0x11, 0x0f, 0x01, 0x01, 0x00], // 'q'
+    [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10, 0x00], // 'r'
+    [0x00, 0x00, 0x0f, 0x10, 0x0e, 0x01, 0x1e, 0x00], // 's'
+    [0x08, 0x08, 0x1c, 0x08, 0x08, 0x09, 0x06, 0x00], // 't'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0d, 0x00], // 'u'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0a, 0x04, 0x00], // 'v'
+    [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0a, 0x00], // 'w'
+    [0x00, 0x00, 0x11, 0x0a, 0x04, 0x0a, 0x11, 0x00], // 'x'
+    [0x00, 0x11, 0x11, 0x11, 0x0f, 0x01, 0x0e, 0x00], // 'y'
+    [0x00, 0x00, 0x1f, 0x02, 0x04, 0x08, 0x1f, 0x00], // 'z'
+    [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02, 0x00], // '{'
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x00], // '|'
+    [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08, 0x00], // '}'
+    [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00, 0x00], // '~'
+];
@@ -0,0 +1,232 @@
+// Visual themes: the colours used by every screen
+// One theme is active at a time and all drawing code asks for it, like macroquad's own drawing
+// state. Themes are built in or read from a JSON file of hex colours; colours a file leaves out
+// come from the classic theme.
+
+use crate::save::data_dir;
+use crate::Side;
+use macroquad::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+pub const THEME_VERSION: u32 = 1;
+pub const THEME_FILE_NAME: &str = "theme.json";
+// Names accepted in settings and on the command line, in the order T cycles through them
+// `custom` is the theme file in the data directory
+pub const THEME_NAMES: [&str; 5] = ["classic", "neon", "amber", "high_contrast", "custom"];
+
+static ACTIVE: RwLock<Theme> = RwLock::new(Theme::CLASSIC);
+
+pub fn default_theme_path() -> PathBuf {
+    data_dir().join(THEME_FILE_NAME)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    #[serde(with = "hex")]
+    pub background: Color,
+    #[serde(with = "hex")]
+    pub left_paddle: Color,
+    #[serde(with = "hex")]
+    pub right_paddle: Color,
+    #[serde(with = "hex")]
+    pub ball: Color,
+    #[serde(with = "hex")]
+    pub trail: Color,
+    #[serde(with = "hex")]
+    pub particles: Color,
+    #[serde(with = "hex")]
+    pub center_line: Color,
+    #[serde(with = "hex")]
+    pub text: Color,
+    // Hints, labels and unselected menu entries
+    #[serde(with = "hex")]
+    pub text_dim: Color,
+    // Highlights such as achievement toasts and script messages
+    #[serde(with = "hex")]
+    pub accent: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::CLASSIC
+    }
+}
+
+impl Theme {
+    pub const CLASSIC: Theme = Theme {
+        background: BLACK,
+        left_paddle: WHITE,
+        right_paddle: WHITE,
+        ball: WHITE,
+        trail: WHITE,
+        particles: WHITE,
+        center_line: WHITE,
+        text: WHITE,
+        text_dim: Color::from_hex(0x828282),
+        accent: GOLD,
+    };
+
+    pub const NEON: Theme = Theme {
+        background: Color::from_hex(0x0a0014),
+        left_paddle: Color::from_hex(0x00f0ff),
+        right_paddle: Color::from_hex(0xff2bd6),
+        ball: Color::from_hex(0xf8ff3a),
+        trail: Color::from_hex(0xf8ff3a),
+        particles: Color::from_hex(0xff9d00),
+        center_line: Color::from_hex(0x4b2080),
+        text: Color::from_hex(0xe8e8ff),
+        text_dim: Color::from_hex(0x8a7fb0),
+        accent: Color::from_hex(0x39ff14),
+    };
+
+    pub const AMBER: Theme = Theme {
+        background: Color::from_hex(0x140a00),
+        left_paddle: Color::from_hex(0xffb000),
+        right_paddle: Color::from_hex(0xffb000),
+        ball: Color::from_hex(0xffc940),
+        trail: Color::from_hex(0xffb000),
+        particles: Color::from_hex(0xffb000),
+        center_line: Color::from_hex(0x7a4f00),
+        text: Color::from_hex(0xffb000),
+        text_dim: Color::from_hex(0xa06a00),
+        accent: Color::from_hex(0xffe0a0),
+    };
+
+    // Pure colours on black, with the two paddles easy to tell apart
+    pub const HIGH_CONTRAST: Theme = Theme {
+        background: BLACK,
+        left_paddle: Color::from_hex(0xffff00),
+        right_paddle: Color::from_hex(0x00ffff),
+        ball: WHITE,
+        trail: WHITE,
+        particles: WHITE,
+        center_line: WHITE,
+        text: WHITE,
+        text_dim: Color::from_hex(0xd0d0d0),
+        accent: Color::from_hex(0xffff00),
+    };
+
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match name {
+            "classic" => Some(Self::CLASSIC),
+            "neon" => Some(Self::NEON),
+            "amber" => Some(Self::AMBER),
+            "high_contrast" => Some(Self::HIGH_CONTRAST),
+            _ => None,
+        }
+    }
+
+    pub fn paddle(&self, side: Side) -> Color {
+        match side {
+            Side::Left => self.left_paddle,
+            Side::Right => self.right_paddle,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&ThemeFile {
+            version: THEME_VERSION,
+            theme: *self,
+        })
+    }
+
+    pub fn from_json(json: &str) -> io::Result<Self> {
+        let file: ThemeFile = serde_json::from_str(json)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if file.version != THEME_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported theme version {}", file.version),
+            ));
+        }
+        Ok(file.theme)
+    }
+
+    pub fn read_from(path: &Path) -> io::Result<Self> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ThemeFile {
+    version: u32,
+    #[serde(flatten)]
+    theme: Theme,
+}
+
+// A built-in theme name, `custom`, or the path of a theme file
+pub fn resolve(name: &str) -> io::Result<Theme> {
+    if let Some(theme) = Theme::builtin(name) {
+        return Ok(theme);
+    }
+    if name == "custom" {
+        return Theme::read_from(&default_theme_path());
+    }
+    if name.ends_with(".json") {
+        return Theme::read_from(Path::new(name));
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("unknown theme: {}", name),
+    ))
+}
+
+// The theme after `name` in THEME_NAMES, skipping `custom` when there is no theme file
+pub fn next_theme_name(name: &str) -> &'static str {
+    let has_custom = default_theme_path().exists();
+    let start = THEME_NAMES.iter().position(|&n| n == name).unwrap_or(0);
+    (1..=THEME_NAMES.len())
+        .map(|i| THEME_NAMES[(start + i) % THEME_NAMES.len()])
+        .find(|&n| n != "custom" || has_custom)
+        .unwrap_or("classic")
+}
+
+pub fn active() -> Theme {
+    *ACTIVE.read().unwrap_or_else(|err| err.into_inner())
+}
+
+pub fn set_active(theme: Theme) {
+    *ACTIVE.write().unwrap_or_else(|err| err.into_inner()) = theme;
+}
+
+// Colours as "#rrggbb" or "#rrggbbaa"
+mod hex {
+    use macroquad::color::Color;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        let [r, g, b, a] = [color.r, color.g, color.b, color.a]
+            .map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+        let text = if a == 255 {
+            format!("#{:02x}{:02x}{:02x}", r, g, b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+        };
+        serializer.serialize_str(&text)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        parse(&text).ok_or_else(|| de::Error::custom(format!("invalid colour: {}", text)))
+    }
+
+    pub fn parse(text: &str) -> Option<Color> {
+        let digits = text.strip_prefix('#')?;
+        if !matches!(digits.len(), 6 | 8) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let value = u32::from_str_radix(digits, 16).ok()?;
+        let rgba = if digits.len() == 6 {
+            value << 8 | 0xff
+        } else {
+            value
+        };
+        let [r, g, b, a] = rgba.to_be_bytes();
+        Some(Color::from_rgba(r, g, b, a))
+    }
+}
@@ -0,0 +1,562 @@
+// Terminal front-end: play in a terminal, including over SSH with no GPU or display server
+// The court is drawn into a small pixel canvas, two pixels to each character cell using the upper
+// half block with separate foreground and background colours, and only cells that changed since
+// the last frame are written. The match runs on the same Game::step as the window, at the netplay
+// tick rate.
+
+use crate::hud::{MARGIN, SCORE_SIZE};
+use crate::netplay::TICK_DT;
+use crate::text::{self, GLYPH_ADVANCE, PIXELS_PER_EM};
+use crate::theme::Theme;
+use crate::{Game, GameState, PaddleCommand, BALL_SIZE, PADDLE_WIDTH, WINDOW_HEIGHT, WINDOW_WIDTH};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{
+    Color as TermColor, Print, ResetColor, SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use macroquad::prelude::{Color, Rect};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+pub type Rgb = [u8; 3];
+
+// Smallest terminal the court is drawn in; below it only the status line is shown
+pub const MIN_COLUMNS: u16 = 40;
+pub const MIN_ROWS: u16 = 12;
+// Rows under the court for the score and controls
+pub const STATUS_ROWS: u16 = 1;
+const FRAME_TIME: Duration = Duration::from_millis(33);
+// Without key release events a press counts as held this long; terminal key repeat extends it
+pub const KEY_HOLD: Duration = Duration::from_millis(200);
+// Longest real time simulated in one frame, so a stalled connection doesn't fast-forward the match
+const MAX_FRAME_DT: f32 = 0.25;
+const BLACK: Rgb = [0, 0, 0];
+
+pub fn rgb(color: Color) -> Rgb {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [channel(color.r), channel(color.g), channel(color.b)]
+}
+
+// `color` drawn over `under` with its alpha
+pub fn blend(under: Rgb, color: Color) -> Rgb {
+    let a = color.a.clamp(0.0, 1.0);
+    let over = rgb(color);
+    let mix = |u: u8, o: u8| (u as f32 + (o as f32 - u as f32) * a).round() as u8;
+    [
+        mix(under[0], over[0]),
+        mix(under[1], over[1]),
+        mix(under[2], over[2]),
+    ]
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<Rgb>,
+}
+
+impl Canvas {
+    pub fn new(width: usize, height: usize, fill: Rgb) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![fill; width * height],
+        }
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Rgb {
+        self.pixels[y * self.width + x]
+    }
+
+    // Fill the pixels whose centres lie inside the rectangle, in canvas pixels
+    // Rectangles thinner than a pixel still cover the pixel under their centre, so the ball and
+    // centre line never vanish on a small terminal
+    pub fn fill_rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: Color) {
+        let span = |start: f32, length: f32, limit: usize| {
+            let mut first = (start - 0.5).ceil();
+            let mut last = (start + length - 0.5).ceil();
+            if last <= first {
+                first = (start + length / 2.0).floor();
+                last = first + 1.0;
+            }
+            let clamp = |value: f32| value.clamp(0.0, limit as f32) as usize;
+            clamp(first)..clamp(last)
+        };
+        for row in span(y, h, self.height) {
+            for column in span(x, w, self.width) {
+                let pixel = &mut self.pixels[row * self.width + column];
+                *pixel = blend(*pixel, color);
+            }
+        }
+    }
+
+    // Character cells, row by row; each covers two pixel rows
+    pub fn cells(&self) -> Vec<Cell> {
+        let mut cells = Vec::with_capacity(self.width * self.height.div_ceil(2));
+        for row in (0..self.height).step_by(2) {
+            for column in 0..self.width {
+                let bottom = if row + 1 < self.height {
+                    self.get(column, row + 1)
+                } else {
+                    BLACK
+                };
+                cells.push(Cell {
+                    top: self.get(column, row),
+                    bottom,
+                });
+            }
+        }
+        cells
+    }
+}
+
+// Where the logical court lands on a canvas, letterboxed with square pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CourtMap {
+    // Canvas pixels per logical unit
+    pub scale: f32,
+    pub x: f32,
+    pub y: f32,
+}
+
+impl CourtMap {
+    pub fn fit(width: usize, height: usize) -> Self {
+        let scale = (width as f32 / WINDOW_WIDTH).min(height as f32 / WINDOW_HEIGHT);
+        Self {
+            scale,
+            x: (width as f32 - WINDOW_WIDTH * scale) / 2.0,
+            y: (height as f32 - WINDOW_HEIGHT * scale) / 2.0,
+        }
+    }
+
+    pub fn fill(&self, canvas: &mut Canvas, rect: Rect, color: Color) {
+        canvas.fill_rect(
+            self.x + rect.x * self.scale,
+            self.y + rect.y * self.scale,
+            rect.w * self.scale,
+            rect.h * self.scale,
+            color,
+        );
+    }
+}
+
+// The court as the window would show it, without shake and other effects
+pub fn render(game: &Game, theme: &Theme, width: usize, height: usize) -> Canvas {
+    let mut canvas = Canvas::new(width, height, BLACK);
+    let map = CourtMap::fit(width, height);
+    let court = Rect::new(0.0, 0.0, WINDOW_WIDTH, WINDOW_HEIGHT);
+    map.fill(&mut canvas, court, theme.background);
+
+    let dash = WINDOW_HEIGHT / 20.0;
+    for i in (0..20).step_by(2) {
+        let rect = Rect::new(WINDOW_WIDTH / 2.0 - 2.0, i as f32 * dash, 4.0, dash);
+        map.fill(&mut canvas, rect, theme.center_line);
+    }
+
+    let scores = [game.player_score, game.ai_score];
+    for (score, center_x) in scores.into_iter().zip([0.25, 0.75]) {
+        draw_score(
+            &mut canvas,
+            &map,
+            score,
+            center_x * WINDOW_WIDTH,
+            theme.text,
+        );
+    }
+
+    for (i, position) in game.ball_trail.iter().enumerate() {
+        let alpha = (i as f32 / game.ball_trail.len() as f32) * 0.3;
+        let color = theme.trail.with_alpha(theme.trail.a * alpha);
+        let rect = Rect::new(position.x - 3.0, position.y - 3.0, 6.0, 6.0);
+        map.fill(&mut canvas, rect, color);
+    }
+
+    for (paddle, color) in [
+        (&game.player_paddle, theme.left_paddle),
+        (&game.ai_paddle, theme.right_paddle),
+    ] {
+        let position = paddle.position;
+        let rect = Rect::new(position.x, position.y, PADDLE_WIDTH, paddle.height);
+        map.fill(&mut canvas, rect, color);
+    }
+    let ball = game.ball.position;
+    let rect = Rect::new(ball.x, ball.y, BALL_SIZE, BALL_SIZE);
+    map.fill(&mut canvas, rect, theme.ball);
+
+    for particle in game.particles.iter() {
+        let size = particle.size();
+        let color = particle.tinted_color(theme.particles);
+        if size > 0.0 && color.a > 0.0 {
+            let position = particle.position;
+            let rect = Rect::new(position.x - size, position.y - size, size * 2.0, size * 2.0);
+            map.fill(&mut canvas, rect, color);
+        }
+    }
+    canvas
+}
+
+// A score in the pixel font, centred on `center_x`, with each font pixel whole canvas pixels
+fn draw_score(canvas: &mut Canvas, map: &CourtMap, score: i32, center_x: f32, color: Color) {
+    let digits = score.to_string();
+    let pixel = (SCORE_SIZE / PIXELS_PER_EM * map.scale).round().max(1.0) / map.scale;
+    let width = (digits.len() as f32 * GLYPH_ADVANCE - 1.0) * pixel;
+    let left = center_x - width / 2.0;
+    for (i, c) in digits.chars().enumerate() {
+        let glyph_x = left + i as f32 * GLYPH_ADVANCE * pixel;
+        for (row, bits) in text::pixel_glyph(c).into_iter().enumerate() {
+            for column in 0..5 {
+                if bits & (0b10000 >> column) != 0 {
+                    let x = glyph_x + column as f32 * pixel;
+                    let y = MARGIN + row as f32 * pixel;
+                    map.fill(canvas, Rect::new(x, y, pixel, pixel), color);
+                }
+            }
+        }
+    }
+}
+
+// One character cell: the upper half block in `top`, on a background of `bottom`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub top: Rgb,
+    pub bottom: Rgb,
+}
+
+// Nearest colour in the xterm 256-colour palette: the 6x6x6 cube, or the grey ramp for greys
+pub fn ansi256(color: Rgb) -> u8 {
+    let [r, g, b] = color;
+    if r == g && g == b {
+        return match r {
+            0..=7 => 16,
+            249..=255 => 231,
+            _ => 232 + ((r as u16 - 8) * 24 / 241) as u8,
+        };
+    }
+    let level = |value: u8| {
+        if value < 48 {
+            0
+        } else if value < 115 {
+            1
+        } else {
+            (value - 35) / 40
+        }
+    };
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    TrueColor,
+    Ansi256,
+}
+
+impl ColorMode {
+    // Terminals announce 24-bit colour in COLORTERM; everything else gets the 256-colour palette
+    pub fn detect() -> Self {
+        Self::from_colorterm(std::env::var("COLORTERM").ok().as_deref())
+    }
+
+    pub fn from_colorterm(value: Option<&str>) -> Self {
+        match value {
+            Some("truecolor" | "24bit") => ColorMode::TrueColor,
+            _ => ColorMode::Ansi256,
+        }
+    }
+
+    pub fn color(self, color: Rgb) -> TermColor {
+        match self {
+            ColorMode::TrueColor => TermColor::Rgb {
+                r: color[0],
+                g: color[1],
+                b: color[2],
+            },
+            ColorMode::Ansi256 => TermColor::AnsiValue(ansi256(color)),
+        }
+    }
+}
+
+// What is on the terminal, so each frame only sends what changed
+pub struct Screen {
+    pub mode: ColorMode,
+    columns: u16,
+    rows: u16,
+    cells: Vec<Cell>,
+    status: String,
+}
+
+impl Screen {
+    pub fn new(mode: ColorMode) -> Self {
+        Self {
+            mode,
+            columns: 0,
+            rows: 0,
+            cells: Vec::new(),
+            status: String::new(),
+        }
+    }
+
+    // Forget the terminal's contents so the next frame is drawn in full, as after a resize
+    pub fn invalidate(&mut self) {
+        self.columns = 0;
+        self.rows = 0;
+        self.cells.clear();
+    }
+
+    // Write the cells that changed since the last frame, with the status line below them
+    pub fn draw(
+        &mut self,
+        out: &mut impl Write,
+        columns: u16,
+        cells: &[Cell],
+        status: &str,
+    ) -> io::Result<()> {
+        let rows = if columns == 0 {
+            0
+        } else {
+            (cells.len() / columns as usize) as u16
+        };
+        let full = columns != self.columns || rows != self.rows;
+        if full {
+            queue!(out, ResetColor, Clear(ClearType::All))?;
+        }
+
+        let mut cursor = None;
+        let mut colors = None;
+        for (i, cell) in cells.iter().enumerate() {
+            if !full && self.cells[i] == *cell {
+                continue;
+            }
+            let position = ((i % columns as usize) as u16, (i / columns as usize) as u16);
+            if cursor != Some(position) {
+                queue!(out, MoveTo(position.0, position.1))?;
+            }
+            if colors != Some((cell.top, cell.bottom)) {
+                queue!(
+                    out,
+                    SetForegroundColor(self.mode.color(cell.top)),
+                    SetBackgroundColor(self.mode.color(cell.bottom))
+                )?;
+                colors = Some((cell.top, cell.bottom));
+            }
+            queue!(out, Print('▀'))?;
+            cursor = Some((position.0 + 1, position.1));
+        }
+
+        if full || status != self.status {
+            let status: String = status.chars().take(columns as usize).collect();
+            queue!(
+                out,
+                ResetColor,
+                MoveTo(0, rows),
+                Clear(ClearType::CurrentLine),
+                Print(&status)
+            )?;
+            self.status = status;
+        } else if colors.is_some() {
+            queue!(out, ResetColor)?;
+        }
+        out.flush()?;
+
+        self.columns = columns;
+        self.rows = rows;
+        self.cells.clear();
+        self.cells.extend_from_slice(cells);
+        Ok(())
+    }
+}
+
+// Paddle keys held down; each stores when it was last pressed or repeated
+// Terminals that report key releases hold a key until it is let go, others for KEY_HOLD after the
+// last press, which the terminal's key repeat keeps renewing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeldKeys {
+    pub reports_release: bool,
+    up: Option<Instant>,
+    down: Option<Instant>,
+}
+
+impl HeldKeys {
+    pub fn new(reports_release: bool) -> Self {
+        Self {
+            reports_release,
+            up: None,
+            down: None,
+        }
+    }
+
+    // A press or repeat; the opposite direction is let go
+    pub fn press(&mut self, command: PaddleCommand, now: Instant) {
+        match command {
+            PaddleCommand::Up => (self.up, self.down) = (Some(now), None),
+            PaddleCommand::Down => (self.up, self.down) = (None, Some(now)),
+            PaddleCommand::Stay => {}
+        }
+    }
+
+    pub fn release(&mut self, command: PaddleCommand) {
+        match command {
+            PaddleCommand::Up => self.up = None,
+            PaddleCommand::Down => self.down = None,
+            PaddleCommand::Stay => {}
+        }
+    }
+
+    fn held(&self, pressed: Option<Instant>, now: Instant) -> bool {
+        pressed.is_some_and(|at| self.reports_release || now.duration_since(at) < KEY_HOLD)
+    }
+
+    pub fn command(&self, now: Instant) -> PaddleCommand {
+        if self.held(self.up, now) {
+            PaddleCommand::Up
+        } else if self.held(self.down, now) {
+            PaddleCommand::Down
+        } else {
+            PaddleCommand::Stay
+        }
+    }
+}
+
+fn paddle_key(code: KeyCode) -> PaddleCommand {
+    match code {
+        KeyCode::Char('w' | 'W') | KeyCode::Up => PaddleCommand::Up,
+        KeyCode::Char('s' | 'S') | KeyCode::Down => PaddleCommand::Down,
+        _ => PaddleCommand::Stay,
+    }
+}
+
+// Raw mode and the alternate screen, restored when dropped, including on a panic
+struct TerminalGuard {
+    keyboard_enhanced: bool,
+}
+
+impl TerminalGuard {
+    fn enter(out: &mut impl Write) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let guard = Self {
+            keyboard_enhanced: terminal::supports_keyboard_enhancement().unwrap_or(false),
+        };
+        execute!(out, EnterAlternateScreen, Hide, Clear(ClearType::All))?;
+        if guard.keyboard_enhanced {
+            let flags = KeyboardEnhancementFlags::REPORT_EVENT_TYPES;
+            execute!(out, PushKeyboardEnhancementFlags(flags))?;
+        }
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        if self.keyboard_enhanced {
+            let _ = execute!(out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(out, ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn status_line(game: &Game, paused: bool) -> String {
+    let score = format!("{} - {}", game.player_score, game.ai_score);
+    match game.game_state {
+        GameState::GameOver => {
+            let winner = if game.player_score > game.ai_score {
+                "PLAYER"
+            } else {
+                "AI"
+            };
+            format!(" {} WINS {}   R to play again  Q to quit", winner, score)
+        }
+        _ if paused => format!(" {}   Paused: P to resume  Q to quit", score),
+        _ => format!(" {}   W/S or arrows to move  P to pause  Q to quit", score),
+    }
+}
+
+// Play `game` against the computer in the terminal until the player quits
+pub fn run(mut game: Game, theme: Theme) -> io::Result<()> {
+    let mut out = io::stdout();
+    let guard = TerminalGuard::enter(&mut out)?;
+    let mut keys = HeldKeys::new(guard.keyboard_enhanced);
+    let mut screen = Screen::new(ColorMode::detect());
+    let mut paused = false;
+    let mut accumulator = 0.0;
+    let mut last = Instant::now();
+
+    loop {
+        let deadline = last + FRAME_TIME;
+        while event::poll(deadline.saturating_duration_since(Instant::now()))? {
+            let key = match event::read()? {
+                Event::Key(key) => key,
+                Event::Resize(..) => {
+                    screen.invalidate();
+                    continue;
+                }
+                _ => continue,
+            };
+            let KeyEvent {
+                code,
+                modifiers,
+                kind,
+                ..
+            } = key;
+            if kind == KeyEventKind::Release {
+                keys.release(paddle_key(code));
+                continue;
+            }
+            match code {
+                KeyCode::Char('q' | 'Q') | KeyCode::Esc => return Ok(()),
+                // Raw mode turns Ctrl+C into a key press instead of a signal
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                KeyCode::Char('p' | 'P') if game.game_state == GameState::Playing => {
+                    paused = !paused;
+                }
+                KeyCode::Char('r' | 'R') | KeyCode::Enter
+                    if game.game_state == GameState::GameOver =>
+                {
+                    game.reset();
+                }
+                code => keys.press(paddle_key(code), Instant::now()),
+            }
+        }
+
+        let now = Instant::now();
+        let dt = now.duration_since(last).as_secs_f32().min(MAX_FRAME_DT);
+        last = now;
+        if !paused {
+            accumulator += dt;
+            while accumulator >= TICK_DT {
+                accumulator -= TICK_DT;
+                if game.game_state != GameState::Playing {
+                    game.feel.settle(TICK_DT);
+                    continue;
+                }
+                // Hard returns freeze the match for a moment, as in the window
+                if game.feel.hold(TICK_DT) {
+                    continue;
+                }
+                let right = game.opponent_command();
+                game.step(TICK_DT, keys.command(now), right);
+            }
+        }
+
+        let (columns, rows) = terminal::size()?;
+        if columns < MIN_COLUMNS || rows < MIN_ROWS {
+            let status = format!("Make the terminal at least {}x{}", MIN_COLUMNS, MIN_ROWS);
+            screen.draw(&mut out, columns, &[], &status)?;
+            continue;
+        }
+        let height = (rows - STATUS_ROWS) as usize * 2;
+        let canvas = render(&game, &theme, columns as usize, height);
+        screen.draw(
+            &mut out,
+            columns,
+            &canvas.cells(),
+            &status_line(&game, paused),
+        )?;
+    }
+}
@@ -0,0 +1,145 @@
+// Window scaling: everything is drawn in logical WINDOW_WIDTH x WINDOW_HEIGHT units
+// The logical court is scaled to fit the window, keeping its aspect ratio, and centred with black
+// bars filling the rest, so physics and drawing code never see the real window size. On HiDPI
+// displays the camera renders at the full framebuffer resolution.
+
+use crate::postfx;
+use crate::theme;
+use crate::{WINDOW_HEIGHT, WINDOW_WIDTH};
+use macroquad::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Smallest scale used, so a minimised window never divides by zero
+const MIN_SCALE: f32 = 0.01;
+
+static FULLSCREEN: AtomicBool = AtomicBool::new(false);
+
+// Where the logical court sits in a window measured in points
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    // Window points per logical unit
+    pub scale: f32,
+    pub window_width: f32,
+    pub window_height: f32,
+}
+
+impl Viewport {
+    pub fn fit(window_width: f32, window_height: f32) -> Self {
+        let scale = (window_width / WINDOW_WIDTH)
+            .min(window_height / WINDOW_HEIGHT)
+            .max(MIN_SCALE);
+        Self {
+            x: (window_width - WINDOW_WIDTH * scale) / 2.0,
+            y: (window_height - WINDOW_HEIGHT * scale) / 2.0,
+            scale,
+            window_width,
+            window_height,
+        }
+    }
+
+    // The viewport for the window as it is this frame
+    pub fn current() -> Self {
+        Self::fit(screen_width(), screen_height())
+    }
+
+    pub fn width(&self) -> f32 {
+        WINDOW_WIDTH * self.scale
+    }
+
+    pub fn height(&self) -> f32 {
+        WINDOW_HEIGHT * self.scale
+    }
+
+    // Convert a window position (such as the mouse) to logical units
+    pub fn to_logical(&self, point: Vec2) -> Vec2 {
+        vec2(
+            (point.x - self.x) / self.scale,
+            (point.y - self.y) / self.scale,
+        )
+    }
+
+    pub fn to_window(&self, point: Vec2) -> Vec2 {
+        vec2(point.x * self.scale + self.x, point.y * self.scale + self.y)
+    }
+
+    // Whether a window position falls on the court rather than the bars around it
+    pub fn contains(&self, point: Vec2) -> bool {
+        let logical = self.to_logical(point);
+        (0.0..=WINDOW_WIDTH).contains(&logical.x) && (0.0..=WINDOW_HEIGHT).contains(&logical.y)
+    }
+
+    // A camera drawing logical units into this viewport
+    // `dpi_scale` is framebuffer pixels per window point; GL viewports start at the bottom left
+    pub fn camera(&self, dpi_scale: f32) -> Camera2D {
+        let bottom = self.window_height - self.y - self.height();
+        Camera2D {
+            target: vec2(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0),
+            zoom: vec2(2.0 / WINDOW_WIDTH, 2.0 / WINDOW_HEIGHT),
+            viewport: Some((
+                (self.x * dpi_scale).round() as i32,
+                (bottom * dpi_scale).round() as i32,
+                (self.width() * dpi_scale).round() as i32,
+                (self.height() * dpi_scale).round() as i32,
+            )),
+            ..Default::default()
+        }
+    }
+}
+
+pub fn is_fullscreen() -> bool {
+    FULLSCREEN.load(Ordering::Relaxed)
+}
+
+pub fn set_fullscreen(fullscreen: bool) {
+    FULLSCREEN.store(fullscreen, Ordering::Relaxed);
+    macroquad::window::set_fullscreen(fullscreen);
+}
+
+fn fullscreen_toggle_pressed() -> bool {
+    let alt = is_key_down(KeyCode::LeftAlt) || is_key_down(KeyCode::RightAlt);
+    is_key_pressed(KeyCode::F11) || (alt && is_key_pressed(KeyCode::Enter))
+}
+
+// Start a frame: handle the fullscreen keys, clear the window and draw in logical units
+// The bars stay black; the court is filled with the theme's background
+// Every frame started here must end with next_frame below
+pub fn begin_frame() {
+    if fullscreen_toggle_pressed() {
+        set_fullscreen(!is_fullscreen());
+    }
+    clear_background(BLACK);
+    let viewport = Viewport::current();
+    if !postfx::begin_capture(&viewport, screen_dpi_scale()) {
+        set_camera(&viewport.camera(screen_dpi_scale()));
+    }
+    draw_rectangle(
+        0.0,
+        0.0,
+        WINDOW_WIDTH,
+        WINDOW_HEIGHT,
+        theme::active().background,
+    );
+}
+
+// Move everything drawn from now on by `offset` logical units, or back with Vec2::ZERO
+pub fn offset_camera(offset: Vec2) {
+    let mut camera =
+        postfx::capture_camera().unwrap_or_else(|| Viewport::current().camera(screen_dpi_scale()));
+    camera.target -= offset;
+    set_camera(&camera);
+}
+
+// Finish the frame, passing it through the post-processing shader when that is on
+pub async fn next_frame() {
+    postfx::present(&Viewport::current(), screen_dpi_scale());
+    macroquad::window::next_frame().await;
+}
+
+// Enter on its own; Alt+Enter belongs to the fullscreen toggle
+pub fn enter_pressed() -> bool {
+    is_key_pressed(KeyCode::Enter)
+        && !is_key_down(KeyCode::LeftAlt)
+        && !is_key_down(KeyCode::RightAlt)
+}
@@ -7,6 +7,24 @@ use pong::*;
 mod tests {
     use super::*;
 
+    // Command-line arguments as the parsers take them
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    // One tick with neither paddle moving
+    fn stay(game: &mut Game) {
+        game.step(netplay::TICK_DT, PaddleCommand::Stay, PaddleCommand::Stay);
+    }
+
+    // Send the ball into the left paddle so it meets it `offset` pixels below the centre
+    fn hit_left_paddle(game: &mut Game, offset: f32) {
+        let centre = game.paddle(Side::Left).get_center_y();
+        game.ball.position = Vec2D::new(48.0, centre + offset - BALL_SIZE / 2.0);
+        game.ball.velocity = Vec2D::new(-game.ball.speed, 0.0);
+        stay(game);
+    }
+
     mod math_utilities {
         use super::*;
 
@@ -118,7 +136,8 @@ mod tests {
             particle.update(dt);
 
             // Position should have changed based on velocity
-            let position_changed = particle.position.x != initial_pos.x || particle.position.y != initial_pos.y;
+            let position_changed =
+                particle.position.x != initial_pos.x || particle.position.y != initial_pos.y;
             assert!(position_changed);
         }
     }
@@ -258,6 +277,23 @@ mod tests {
             // Should have new random velocity
             assert!(ball.velocity.length() > 0.0);
         }
+
+        #[test]
+        fn returns_speed_up_as_the_rule_set_says() {
+            let mut game = Game::with_seed(2);
+            game.rules.hit_speedup = 1.5;
+            game.rules.max_speedup = 1.2;
+            hit_left_paddle(&mut game, 0.0);
+            assert!((game.ball.speed - BALL_SPEED * 1.2).abs() < 1e-3);
+            assert!((game.ball.velocity.length() - game.rules.top_speed()).abs() < 1e-3);
+
+            // By default every return keeps the serve speed
+            let mut game = Game::with_seed(2);
+            assert_eq!(game.rules.top_speed(), BALL_SPEED);
+            hit_left_paddle(&mut game, 0.0);
+            hit_left_paddle(&mut game, 0.0);
+            assert_eq!(game.ball.speed, BALL_SPEED);
+        }
     }
 
     mod collision_detection {
@@ -359,8 +395,4171 @@ mod tests {
             let channels = u16::from_le_bytes([sound_data[22], sound_data[23]]);
             assert_eq!(channels, 1); // Mono
 
-            let sample_rate = u32::from_le_bytes([sound_data[24], sound_data[25], sound_data[26], sound_data[27]]);
+            let sample_rate = u32::from_le_bytes([
+                sound_data[24],
+                sound_data[25],
+                sound_data[26],
+                sound_data[27],
+            ]);
             assert_eq!(sample_rate, 44100); // 44.1 kHz
         }
     }
-}
\ No newline at end of file
+
+    mod save_and_resume {
+        use super::*;
+
+        fn game_mid_match() -> Game {
+            let mut game = Game::with_seed(42);
+            game.player_score = 3;
+            game.ai_score = 2;
+            game.player_paddle.position.y = 123.456;
+            game.player_paddle.velocity = -PADDLE_SPEED;
+            game.ai_paddle.position.y = 401.25;
+            game.ball.position = Vec2D::new(317.3, 211.9);
+            game.ball.velocity = Vec2D::new(-301.7, 178.3);
+            game.rules.winning_score = 7;
+            game.ai.speed_factor = 0.65;
+            game
+        }
+
+        #[test]
+        fn with_seed_is_deterministic() {
+            let a = Game::with_seed(7);
+            let b = Game::with_seed(7);
+            assert_eq!(a.ball, b.ball);
+            assert_eq!(a.snapshot(), b.snapshot());
+        }
+
+        #[test]
+        fn snapshot_round_trips_through_json() {
+            let state = game_mid_match().snapshot();
+            let json = state.to_json().unwrap();
+            let loaded = SaveState::from_json(&json).unwrap();
+            assert_eq!(loaded, state);
+        }
+
+        #[test]
+        fn restore_reproduces_gameplay_state_and_rng() {
+            let mut original = game_mid_match();
+            let json = original.snapshot().to_json().unwrap();
+
+            let mut restored = Game::with_seed(0);
+            restored.restore(SaveState::from_json(&json).unwrap());
+            assert_eq!(restored.snapshot(), original.snapshot());
+
+            // The RNG stream continues exactly where it left off
+            original.ball.serve(&mut original.rng);
+            restored.ball.serve(&mut restored.rng);
+            assert_eq!(restored.ball, original.ball);
+        }
+
+        #[test]
+        fn save_file_round_trips_on_disk() {
+            let path =
+                std::env::temp_dir().join(format!("pong_save_test_{}.json", std::process::id()));
+            let state = game_mid_match().snapshot();
+
+            state.write_to(&path).unwrap();
+            let loaded = SaveState::read_from(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(loaded, state);
+        }
+
+        #[test]
+        fn unsupported_save_version_is_rejected() {
+            let mut state = game_mid_match().snapshot();
+            state.version += 1;
+            let json = state.to_json().unwrap();
+            assert!(SaveState::from_json(&json).is_err());
+        }
+    }
+
+    // Achievement tests: definitions, conditions, unlocking and toasts
+    mod achievements {
+        use pong::achievements::*;
+        use pong::profile::*;
+        use pong::stats::MatchStats;
+        use pong::*;
+
+        // The default rule set's top speed: the ball never speeds up
+        const TOP_SPEED: f32 = BALL_SPEED;
+
+        fn won_match(difficulty: Difficulty, points_against: u32) -> MatchOutcome {
+            MatchOutcome {
+                mode: MatchMode::VsAi,
+                difficulty: Some(difficulty),
+                won: true,
+                points_for: 5,
+                points_against,
+                longest_rally: 4,
+                paddle_hits: 12,
+                time_played: 50.0,
+                opponent_rating: Some(ai_rating(difficulty)),
+            }
+        }
+
+        // Progress for the left paddle
+        fn progress<'a>(
+            profile: &'a Profile,
+            stats: &'a MatchStats,
+            rally_length: u32,
+            outcome: Option<&'a MatchOutcome>,
+        ) -> Progress<'a> {
+            Progress {
+                profile,
+                stats,
+                side: Side::Left,
+                rally_length,
+                top_speed: RuleSet::default().top_speed(),
+                outcome,
+            }
+        }
+
+        fn ids(unlocked: &[Achievement]) -> Vec<&str> {
+            unlocked
+                .iter()
+                .map(|achievement| achievement.id.as_str())
+                .collect()
+        }
+
+        #[test]
+        fn builtin_definitions_parse() {
+            let achievements = builtin_achievements();
+            assert!(achievements.len() >= 4);
+            let rally = achievements
+                .iter()
+                .find(|achievement| achievement.id == "rally_30")
+                .unwrap();
+            assert_eq!(rally.condition, Condition::Rally { hits: 30 });
+
+            let duplicate = r#"[
+        {"id": "a", "name": "A", "description": "", "condition": {"kind": "win"}},
+        {"id": "a", "name": "B", "description": "", "condition": {"kind": "shutout"}}
+    ]"#;
+            assert!(parse_achievements(duplicate).is_err());
+            let unknown =
+                r#"[{"id": "a", "name": "A", "description": "", "condition": {"kind": "fly"}}]"#;
+            assert!(parse_achievements(unknown).is_err());
+        }
+
+        #[test]
+        fn conditions_check_the_match_and_the_career() {
+            let mut profile = Profile::new("ada");
+            let mut stats = MatchStats::default();
+            stats.left.edge_hits = 10;
+            stats.left.fastest_return = 460.0;
+            stats.right.edge_hits = 2;
+            let shutout = won_match(Difficulty::Hard, 0);
+            let live = progress(&profile, &stats, 30, None);
+            assert!(Condition::Rally { hits: 30 }.is_met(&live));
+            assert!(!Condition::Win.is_met(&live));
+            assert!(Condition::EdgeReturns { count: 10 }.is_met(&live));
+            assert!(Condition::BallSpeed { speed: 450.0 }.is_met(&live));
+            // Without a speed-up any return is at the top speed
+            assert!(Condition::TopSpeed.is_met(&live));
+            let sped_up = Progress {
+                top_speed: BALL_SPEED * MAX_SPEEDUP,
+                ..live
+            };
+            assert!(!Condition::TopSpeed.is_met(&sped_up));
+            let slow_rules = Progress {
+                top_speed: 460.0,
+                ..live
+            };
+            assert!(Condition::TopSpeed.is_met(&slow_rules));
+            let right = Progress {
+                side: Side::Right,
+                ..live
+            };
+            assert!(!Condition::EdgeReturns { count: 10 }.is_met(&right));
+
+            let ended = progress(&profile, &stats, 0, Some(&shutout));
+            assert!(Condition::Shutout.is_met(&ended));
+            assert!(Condition::BeatDifficulty {
+                difficulty: Difficulty::Hard
+            }
+            .is_met(&ended));
+            assert!(!Condition::BeatDifficulty {
+                difficulty: Difficulty::Learned
+            }
+            .is_met(&ended));
+            let close = won_match(Difficulty::Easy, 4);
+            assert!(!Condition::Shutout.is_met(&progress(&profile, &stats, 0, Some(&close))));
+
+            for _ in 0..3 {
+                profile.record(&shutout);
+            }
+            let career = progress(&profile, &stats, 0, None);
+            assert!(Condition::WinStreak { count: 3 }.is_met(&career));
+            assert!(Condition::Matches { count: 3 }.is_met(&career));
+            assert!(!Condition::Matches { count: 25 }.is_met(&career));
+            assert!(Condition::PlayMode {
+                mode: MatchMode::VsAi
+            }
+            .is_met(&career));
+            assert!(!Condition::PlayMode {
+                mode: MatchMode::Lan
+            }
+            .is_met(&career));
+        }
+
+        #[test]
+        fn unlocking_is_persisted_once_per_profile() {
+            let achievements = builtin_achievements();
+            let mut store = ProfileStore::default();
+            let stats = MatchStats::default();
+            let outcome = won_match(Difficulty::Hard, 0);
+            assert!(unlock(
+                &mut store,
+                &achievements,
+                &stats,
+                Side::Left,
+                0,
+                TOP_SPEED,
+                Some(&outcome)
+            )
+            .is_empty());
+
+            store.create("Ada").unwrap();
+            store.record(&outcome);
+            let unlocked = unlock(
+                &mut store,
+                &achievements,
+                &stats,
+                Side::Left,
+                0,
+                TOP_SPEED,
+                Some(&outcome),
+            );
+            assert_eq!(ids(&unlocked), vec!["first_win", "shutout", "beat_hard"]);
+            assert!(store.active().unwrap().achievements.contains_key("shutout"));
+
+            // Already unlocked achievements stay quiet
+            assert!(unlock(
+                &mut store,
+                &achievements,
+                &stats,
+                Side::Left,
+                0,
+                TOP_SPEED,
+                Some(&outcome)
+            )
+            .is_empty());
+            let unlocked = unlock(
+                &mut store,
+                &achievements,
+                &stats,
+                Side::Left,
+                31,
+                TOP_SPEED,
+                None,
+            );
+            assert_eq!(ids(&unlocked), vec!["rally_30"]);
+
+            // Other profiles start from scratch
+            store.create("Grace").unwrap();
+            assert!(store.active().unwrap().achievements.is_empty());
+
+            let json = store.to_json().unwrap();
+            let loaded = ProfileStore::from_json(&json).unwrap();
+            assert_eq!(loaded.get("Ada").unwrap().achievements.len(), 4);
+        }
+
+        #[test]
+        fn fast_returns_are_earned_in_real_rallies() {
+            // Two perfect trackers keep the rally going while each return speeds the ball up to
+            // the rule set's top speed
+            let mut game = Game::with_seed(4);
+            game.ai_side = None;
+            game.rules.hit_speedup = 1.05;
+            game.rules.max_speedup = 1.3;
+            let top_speed = game.rules.top_speed();
+            let mut fastest = 0.0;
+            for _ in 0..3600 {
+                let left = game.tracking_command(Side::Left, 0.0);
+                let right = game.tracking_command(Side::Right, 0.0);
+                game.step(1.0 / 60.0, left, right);
+                fastest = game.stats.left.fastest_return;
+                if fastest >= top_speed * 0.999 {
+                    break;
+                }
+            }
+            assert!(fastest >= 450.0, "fastest return was {}", fastest);
+            assert!(fastest <= top_speed + 1e-3);
+
+            let mut store = ProfileStore::default();
+            store.create("Ada").unwrap();
+            let unlocked = unlock(
+                &mut store,
+                &builtin_achievements(),
+                &game.stats,
+                Side::Left,
+                game.rally_length,
+                top_speed,
+                None,
+            );
+            assert!(ids(&unlocked).contains(&"speed_demon"));
+            assert!(game.stats.max_ball_speed >= fastest);
+
+            // The next point is served at the normal speed again
+            let goals = game.player_score + game.ai_score;
+            while game.player_score + game.ai_score == goals {
+                game.step(1.0 / 60.0, PaddleCommand::Stay, PaddleCommand::Stay);
+            }
+            assert_eq!(game.ball.speed, game.rules.ball_speed);
+            assert!((game.ball.velocity.length() - game.rules.ball_speed).abs() < 1e-3);
+        }
+
+        #[test]
+        fn toasts_are_shown_one_at_a_time() {
+            let mut toasts = Toasts::default();
+            let achievements = builtin_achievements();
+            toasts.push_unlocked(&achievements[0]);
+            toasts.push_unlocked(&achievements[1]);
+            assert_eq!(toasts.len(), 2);
+
+            toasts.update(TOAST_DURATION / 2.0);
+            assert_eq!(toasts.len(), 2);
+            toasts.update(TOAST_DURATION);
+            assert_eq!(toasts.len(), 1);
+            toasts.update(TOAST_DURATION + 0.1);
+            assert!(toasts.is_empty());
+        }
+    }
+
+    // Shot analytics tests: event recording, heatmap binning and CSV export
+    mod shot_analytics {
+        use super::hit_left_paddle;
+        use pong::analytics::*;
+        use pong::bot::{play_headless, BuiltinAi, HandicappedAi, MAX_MATCH_TICKS};
+        use pong::netplay::TICK_DT;
+        use pong::*;
+
+        fn events(game: &Game, kind: ShotKind) -> Vec<ShotEvent> {
+            game.analytics
+                .events
+                .iter()
+                .filter(|event| event.kind == kind)
+                .copied()
+                .collect()
+        }
+
+        #[test]
+        fn contacts_walls_and_goals_are_recorded() {
+            let mut game = Game::with_seed(1);
+            hit_left_paddle(&mut game, 30.0);
+            let contacts = events(&game, ShotKind::PaddleContact);
+            assert_eq!(contacts.len(), 1);
+            assert_eq!(contacts[0].side, Some(Side::Left));
+            assert!(contacts[0].offset.unwrap() > 0.5);
+
+            // Bounce off the top wall in the middle of the court
+            game.ball.position = Vec2D::new(WINDOW_WIDTH / 2.0, 1.0);
+            game.ball.velocity = Vec2D::new(200.0, -300.0);
+            game.step(TICK_DT, PaddleCommand::Stay, PaddleCommand::Stay);
+            let walls = events(&game, ShotKind::WallBounce);
+            assert_eq!(walls.len(), 1);
+            assert!(walls[0].y < WINDOW_HEIGHT / 2.0);
+            assert!((walls[0].x - WINDOW_WIDTH / 2.0).abs() < 20.0);
+
+            // Out past the right paddle, low down: the right side concedes
+            game.ball.position = Vec2D::new(WINDOW_WIDTH - 1.0, WINDOW_HEIGHT - 40.0);
+            game.ball.velocity = Vec2D::new(game.ball.speed, 0.0);
+            game.step(TICK_DT, PaddleCommand::Stay, PaddleCommand::Stay);
+            let goals = events(&game, ShotKind::Goal);
+            assert_eq!(goals.len(), 1);
+            assert_eq!(goals[0].side, Some(Side::Right));
+            assert!(goals[0].y > WINDOW_HEIGHT - 50.0);
+
+            game.reset();
+            assert!(game.analytics.events.is_empty());
+        }
+
+        #[test]
+        fn heatmaps_bin_events() {
+            let heatmap = Heatmap::from_values([-1.0, -0.99, 0.0, 0.01, 1.0, 5.0], -1.0, 1.0, 4);
+            assert_eq!(heatmap.bins, vec![2, 0, 2, 2]);
+            assert_eq!(heatmap.total(), 6);
+            assert_eq!(heatmap.peak(), 2);
+            assert_eq!(heatmap.intensity(1), 0.0);
+            assert_eq!(heatmap.intensity(3), 1.0);
+
+            let mut game = Game::with_seed(2);
+            hit_left_paddle(&mut game, -35.0);
+            hit_left_paddle(&mut game, -35.0);
+            hit_left_paddle(&mut game, 0.0);
+            let paddle = game.analytics.paddle_heatmap(Side::Left);
+            assert_eq!(paddle.bins.len(), PADDLE_BINS);
+            assert_eq!(paddle.total(), 3);
+            assert_eq!(paddle.bins[..PADDLE_BINS / 4].iter().sum::<u32>(), 2);
+            assert_eq!(game.analytics.paddle_heatmap(Side::Right).total(), 0);
+        }
+
+        #[test]
+        fn whole_matches_record_every_shot() {
+            let mut game = Game::with_seed(11);
+            game.ai_side = None;
+            let mut left = BuiltinAi;
+            let mut right = HandicappedAi::new(
+                AiSettings {
+                    speed_factor: 0.4,
+                    ..AiSettings::default()
+                },
+                11,
+            );
+            play_headless(&mut game, &mut left, &mut right, MAX_MATCH_TICKS)
+                .expect("match finishes");
+
+            let analytics = &game.analytics;
+            assert_eq!(
+                events(&game, ShotKind::PaddleContact).len() as u32,
+                game.stats.total_hits()
+            );
+            assert_eq!(
+                analytics.goal_heatmap(Side::Right).total(),
+                game.player_score as u32
+            );
+            assert_eq!(
+                analytics.goal_heatmap(Side::Left).total(),
+                game.ai_score as u32
+            );
+            assert!(
+                analytics.wall_heatmap(true).total() + analytics.wall_heatmap(false).total() > 0
+            );
+            assert!(analytics
+                .events
+                .windows(2)
+                .all(|pair| pair[0].time <= pair[1].time));
+
+            // Analytics are part of the saved state
+            let mut restored = Game::with_seed(99);
+            restored.restore(game.snapshot());
+            assert_eq!(&restored.analytics, analytics);
+        }
+
+        #[test]
+        fn events_export_to_csv() {
+            let mut game = Game::with_seed(4);
+            hit_left_paddle(&mut game, 0.0);
+            game.analytics.record_wall_bounce(1.5, 120.0, 0.0);
+
+            let mut csv = Vec::new();
+            game.analytics.write_csv(&mut csv).unwrap();
+            let csv = String::from_utf8(csv).unwrap();
+            let lines: Vec<&str> = csv.lines().collect();
+            assert_eq!(lines[0], "time,kind,side,x,y,offset");
+            assert_eq!(lines.len(), 3);
+            assert!(lines[1].contains(",paddle_contact,left,"));
+            assert!(lines[1].ends_with(",0.0000"));
+            assert_eq!(lines[2], "1.5000,wall_bounce,,120.00,0.00,");
+
+            let dir =
+                std::env::temp_dir().join(format!("pong_analytics_test_{}", std::process::id()));
+            let path = events_path(&dir.join("match-42.json"));
+            assert_eq!(path, dir.join("match-42-events.csv"));
+            game.analytics.export_csv(&path).unwrap();
+            assert_eq!(std::fs::read_to_string(&path).unwrap(), csv);
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    // External bot tests: subprocess protocol, time budget and headless matches
+    mod external_bots {
+        use pong::bot::*;
+        use pong::*;
+        use std::process::Command;
+        use std::time::{Duration, Instant};
+
+        // A shell loop that runs `answer` for every state message, with the message's tick in `$tick`
+        #[cfg(unix)]
+        fn on_state(answer: &str) -> String {
+            format!(
+                r#"while read line; do case "$line" in *'"type":"state"'*) tick=${{line#*'"tick":'}}; tick=${{tick%%,*}}; {};; esac; done"#,
+                answer
+            )
+        }
+
+        // A bot written as a shell script that answers every state message with `reply`
+        #[cfg(unix)]
+        fn shell_bot(script: &str, budget: Duration) -> BotController {
+            let mut command = Command::new("sh");
+            command.args(["-c", script]);
+            BotController::from_command(command, budget).unwrap()
+        }
+
+        #[cfg(unix)]
+        fn replying(reply: &str) -> String {
+            on_state(&format!("echo $tick {}", reply))
+        }
+
+        #[test]
+        fn replies_are_parsed_case_insensitively() {
+            assert_eq!(parse_reply("1 up\n"), Some((1, PaddleCommand::Up)));
+            assert_eq!(parse_reply(" 20  DOWN "), Some((20, PaddleCommand::Down)));
+            assert_eq!(parse_reply("3 stay"), Some((3, PaddleCommand::Stay)));
+            assert_eq!(parse_reply("3 jump"), None);
+            // Replies must say which tick they answer
+            assert_eq!(parse_reply("up"), None);
+            assert_eq!(parse_reply("-1 up"), None);
+            assert_eq!(parse_reply("4 up down"), None);
+        }
+
+        #[test]
+        fn state_is_reported_from_the_bots_side() {
+            let mut game = Game::with_seed(1);
+            game.player_score = 2;
+            game.ai_paddle.position.y = 100.0;
+
+            let state = BotState::capture(&game, Side::Right, 9);
+            assert_eq!(state.tick, 9);
+            assert_eq!(state.paddle_y, 100.0);
+            assert_eq!(state.opponent_paddle_y, game.player_paddle.position.y);
+            assert_eq!((state.score, state.opponent_score), (0, 2));
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn bot_commands_move_its_paddle() {
+            let mut bot = shell_bot(&replying("up"), Duration::from_secs(2));
+            let mut game = Game::with_seed(1);
+            let start_y = game.player_paddle.position.y;
+
+            for _ in 0..10 {
+                let command = bot.command(&game, Side::Left);
+                assert_eq!(command, PaddleCommand::Up);
+                game.step(0.016, command, PaddleCommand::Stay);
+            }
+
+            assert!(game.player_paddle.position.y < start_y);
+            assert_eq!(bot.stats().replies, 10);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn slow_bots_stay_within_the_budget() {
+            let mut bot = shell_bot(
+                &on_state("sleep 1; echo $tick up"),
+                Duration::from_millis(20),
+            );
+            let game = Game::with_seed(1);
+
+            let started = Instant::now();
+            assert_eq!(bot.command(&game, Side::Left), PaddleCommand::Stay);
+            assert_eq!(bot.command(&game, Side::Left), PaddleCommand::Stay);
+            assert!(started.elapsed() < Duration::from_millis(500));
+            assert_eq!(bot.stats().timeouts, 2);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn late_replies_are_not_applied_to_the_next_tick() {
+            // Each answer comes 300 ms after its state, during the following tick's 200 ms budget
+            let mut bot = shell_bot(
+                &on_state("sleep 0.3; echo $tick up"),
+                Duration::from_millis(200),
+            );
+            let game = Game::with_seed(1);
+
+            assert_eq!(bot.command(&game, Side::Left), PaddleCommand::Stay);
+            assert_eq!(bot.command(&game, Side::Left), PaddleCommand::Stay);
+            let stats = bot.stats();
+            assert_eq!((stats.replies, stats.timeouts), (0, 2));
+            assert!(stats.late >= 1);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn misbehaving_bots_default_to_stay() {
+            let game = Game::with_seed(1);
+
+            let mut garbage = shell_bot(&replying("jump"), Duration::from_secs(2));
+            assert_eq!(garbage.command(&game, Side::Left), PaddleCommand::Stay);
+            assert_eq!(garbage.stats().invalid, 1);
+
+            let mut crashed = shell_bot("exit 3", Duration::from_secs(2));
+            for _ in 0..5 {
+                assert_eq!(crashed.command(&game, Side::Right), PaddleCommand::Stay);
+            }
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn dropped_bots_still_hear_who_won() {
+            let path =
+                std::env::temp_dir().join(format!("pong_bot_end_{}.json", std::process::id()));
+            let script = format!(
+                r#"while read line; do case "$line" in *'"type":"end"'*) echo "$line" > '{}';; esac; done"#,
+                path.display()
+            );
+            let mut bot = shell_bot(&script, Duration::from_millis(20));
+
+            bot.finish(Side::Right);
+            drop(bot);
+
+            let written = std::fs::read_to_string(&path).unwrap();
+            let _ = std::fs::remove_file(&path);
+            let message: BotMessage = serde_json::from_str(written.trim()).unwrap();
+            assert_eq!(
+                message,
+                BotMessage::End {
+                    winner: Side::Right
+                }
+            );
+        }
+
+        #[test]
+        fn missing_bot_programs_fail_to_spawn() {
+            assert!(BotController::spawn("", DEFAULT_TIME_BUDGET).is_err());
+            assert!(
+                BotController::spawn("/nonexistent/pong-bot --fast", DEFAULT_TIME_BUDGET).is_err()
+            );
+        }
+
+        // Moves up and down in a fixed pattern, missing plenty of balls
+        struct Sweeper {
+            ticks: u32,
+        }
+
+        impl Controller for Sweeper {
+            fn command(&mut self, _game: &Game, _side: Side) -> PaddleCommand {
+                self.ticks += 1;
+                if (self.ticks / 45).is_multiple_of(2) {
+                    PaddleCommand::Up
+                } else {
+                    PaddleCommand::Down
+                }
+            }
+        }
+
+        #[test]
+        fn headless_matches_are_deterministic() {
+            let play = |seed| {
+                let mut game = Game::with_seed(seed);
+                let winner = play_headless(
+                    &mut game,
+                    &mut BuiltinAi,
+                    &mut Sweeper { ticks: 0 },
+                    MAX_MATCH_TICKS,
+                );
+                (winner, game.snapshot())
+            };
+
+            let first = play(5);
+            assert_eq!(first.0, Some(Side::Left));
+            assert_eq!(first, play(5));
+        }
+
+        #[test]
+        fn only_the_builtin_ai_is_handicapped_in_bot_matches() {
+            let game = |flags: &[&str]| {
+                let args = flags.iter().map(|flag| flag.to_string());
+                bot_game(&pong::cli::parse_args(args).unwrap().bots.unwrap())
+            };
+
+            // In a window the left side without a bot is the player at the keyboard
+            assert_eq!(game(&["--right-bot", "bot"]).ai_side, None);
+            assert_eq!(
+                game(&["--right-bot", "bot", "--headless"]).ai_side,
+                Some(Side::Left)
+            );
+            assert_eq!(game(&["--left-bot", "bot"]).ai_side, Some(Side::Right));
+            assert_eq!(game(&["--left-bot", "a", "--right-bot", "b"]).ai_side, None);
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn builtin_ai_beats_a_bot_that_never_moves() {
+            let mut bot = shell_bot(&replying("stay"), Duration::from_secs(2));
+            let mut game = Game::with_seed(2);
+
+            let winner = play_headless(&mut game, &mut bot, &mut BuiltinAi, MAX_MATCH_TICKS);
+            assert_eq!(winner, Some(Side::Right));
+            assert_eq!(game.ai_score, game.rules.winning_score);
+            assert_eq!(bot.stats().timeouts, 0);
+        }
+    }
+
+    // Debug overlay tests: tick counting, the AI target and, with the feature, tunables and timings
+    mod debug_overlay {
+        use pong::*;
+
+        #[test]
+        fn games_count_ticks_and_keep_their_seed() {
+            let mut game = Game::with_seed(77);
+            assert_eq!((game.seed, game.ticks), (77, 0));
+            for _ in 0..30 {
+                game.step(1.0 / 60.0, PaddleCommand::Stay, PaddleCommand::Stay);
+            }
+            assert_eq!(game.ticks, 30);
+
+            // Steps outside a match don't count, and a new match starts again from zero
+            game.game_state = GameState::GameOver;
+            game.step(1.0 / 60.0, PaddleCommand::Stay, PaddleCommand::Stay);
+            assert_eq!(game.ticks, 30);
+            game.reset();
+            assert_eq!(game.ticks, 0);
+
+            // The simple AI heads for the target the overlay draws
+            game.ball.position.y = 100.0;
+            assert_eq!(game.ai_target_y(), 100.0 + BALL_SIZE / 2.0);
+            assert_eq!(game.ai_command(Side::Right), PaddleCommand::Up);
+        }
+
+        #[cfg(feature = "debug-overlay")]
+        mod overlay {
+            use pong::debug::{DebugOverlay, FrameTimes, Tunable, FRAME_HISTORY};
+            use pong::*;
+
+            #[test]
+            fn tunables_change_the_game_within_their_range() {
+                let mut game = Game::with_seed(3);
+                let heading = game.ball.velocity.normalize();
+                Tunable::BallSpeed.set(&mut game, 500.0);
+                assert_eq!((game.ball.speed, game.rules.ball_speed), (500.0, 500.0));
+                assert!((game.ball.velocity.length() - 500.0).abs() < 1e-3);
+                let new_heading = game.ball.velocity.normalize();
+                assert!((new_heading.x - heading.x).abs() < 1e-5);
+                assert!((new_heading.y - heading.y).abs() < 1e-5);
+
+                // Paddles grow around their centre, and values are kept in range
+                let center = game.ai_paddle.get_center_y();
+                Tunable::RightPaddleHeight.set(&mut game, 120.0);
+                assert_eq!(game.ai_paddle.height, 120.0);
+                assert_eq!(game.ai_paddle.get_center_y(), center);
+                Tunable::AiDeadZone.set(&mut game, -5.0);
+                assert_eq!(game.ai.dead_zone, 0.0);
+                Tunable::PaddleSpeed.set(&mut game, 1e9);
+                assert_eq!(game.rules.paddle_speed, Tunable::PaddleSpeed.range().1);
+            }
+
+            #[test]
+            fn the_panel_selects_adjusts_and_resets() {
+                let mut game = Game::with_seed(3);
+                let mut overlay = DebugOverlay::new(&game);
+                assert!(!overlay.visible);
+                overlay.select(-1);
+                assert_eq!(overlay.tunable(), Tunable::RightPaddleHeight);
+                overlay.select(1);
+                assert_eq!(overlay.tunable(), Tunable::BallSpeed);
+
+                overlay.select(1);
+                overlay.adjust(&mut game, 2.0);
+                assert_eq!(game.rules.paddle_speed, PADDLE_SPEED + 50.0);
+                overlay.reset(&mut game);
+                assert_eq!(game.rules.paddle_speed, PADDLE_SPEED);
+
+                let lines = overlay.lines(&game);
+                assert!(lines.iter().any(|line| line.starts_with("Seed 0x3")));
+                assert!(lines.iter().any(|line| line == "> Paddle speed 300"));
+            }
+
+            #[test]
+            fn frame_times_cover_recent_frames() {
+                let mut times = FrameTimes::default();
+                assert_eq!((times.average(), times.worst()), (0.0, 0.0));
+                times.push(0.1);
+                for _ in 0..FRAME_HISTORY {
+                    times.push(0.02);
+                }
+                // The slow frame has dropped out of the history
+                assert!((times.average() - 0.02).abs() < 1e-6);
+                assert_eq!(times.worst(), 0.02);
+                times.push(0.05);
+                assert_eq!(times.worst(), 0.05);
+            }
+        }
+    }
+
+    // LAN discovery tests: hosts and browsers talking over loopback
+    mod lan_discovery {
+        use pong::discovery::*;
+        use pong::*;
+        use std::net::{SocketAddr, UdpSocket};
+        use std::thread;
+        use std::time::{Duration, Instant};
+
+        const TIMEOUT: Duration = Duration::from_secs(5);
+
+        fn loopback_browser() -> LanBrowser {
+            LanBrowser::bind("127.0.0.1:0".parse().unwrap()).unwrap()
+        }
+
+        fn host_for(browser: &LanBrowser, name: &str, game_port: u16) -> LanHost {
+            let discovery = browser.local_addr().unwrap();
+            LanHost::bind(
+                announce_addr(discovery),
+                name,
+                game_port,
+                RuleSet::default(),
+                77,
+            )
+            .unwrap()
+        }
+
+        // Poll both ends until `done` holds for the browser
+        fn poll_until<F: Fn(&LanBrowser) -> bool>(
+            host: &mut LanHost,
+            browser: &mut LanBrowser,
+            done: F,
+        ) {
+            let deadline = Instant::now() + TIMEOUT;
+            while !done(browser) {
+                assert!(Instant::now() < deadline, "timed out polling discovery");
+                host.poll().unwrap();
+                browser.poll().unwrap();
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+
+        #[test]
+        fn unspecified_listen_address_announces_to_broadcast() {
+            let addr = announce_addr("0.0.0.0:7300".parse().unwrap());
+            assert_eq!(addr, "255.255.255.255:7300".parse::<SocketAddr>().unwrap());
+            assert_eq!(default_listen_addr().port(), DISCOVERY_PORT);
+        }
+
+        #[test]
+        fn browser_lists_announced_games_with_ping() {
+            let mut browser = loopback_browser();
+            let mut host = host_for(&browser, "alice", 9000);
+
+            poll_until(&mut host, &mut browser, |browser| {
+                browser
+                    .games()
+                    .first()
+                    .is_some_and(|game| game.ping.is_some())
+            });
+
+            let game = &browser.games()[0];
+            assert_eq!(game.host_name, "alice");
+            assert_eq!(game.game_port, 9000);
+            assert_eq!(game.rules, RuleSet::default());
+            assert_eq!(game.addr.port(), host.local_addr().unwrap().port());
+            assert!(game.ping.unwrap() < Duration::from_secs(1));
+        }
+
+        #[test]
+        fn joining_agrees_on_peers_seed_and_sides() {
+            let mut browser = loopback_browser();
+            let host_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let guest_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let mut host = host_for(&browser, "alice", host_socket.local_addr().unwrap().port());
+            poll_until(&mut host, &mut browser, |browser| {
+                !browser.games().is_empty()
+            });
+
+            let game_addr = browser.games()[0].addr;
+            browser
+                .join(game_addr, "bob", guest_socket.local_addr().unwrap().port())
+                .unwrap();
+
+            let deadline = Instant::now() + TIMEOUT;
+            let (mut hosted, mut joined) = (None, None);
+            while hosted.is_none() || joined.is_none() {
+                assert!(Instant::now() < deadline, "join never completed");
+                if let Some(lan_match) = host.poll().unwrap() {
+                    hosted = Some(lan_match);
+                }
+                if let Some(event) = browser.poll().unwrap() {
+                    joined = Some(event);
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+
+            let hosted = hosted.unwrap();
+            let joined = match joined.unwrap() {
+                BrowserEvent::Accepted(lan_match) => lan_match,
+                other => panic!("expected to be accepted, got {:?}", other),
+            };
+            assert_eq!(
+                hosted.peer.port(),
+                guest_socket.local_addr().unwrap().port()
+            );
+            assert_eq!(joined.peer.port(), host_socket.local_addr().unwrap().port());
+            assert_eq!(hosted.seed, joined.seed);
+            assert_eq!(hosted.peer_name, "bob");
+            assert_eq!(joined.peer_name, "alice");
+            assert_eq!(hosted.side, Side::Left);
+            assert_eq!(joined.side, Side::Right);
+        }
+
+        #[test]
+        fn lan_sessions_start_under_the_hosts_rules() {
+            let rules = RuleSet {
+                ball_speed: 420.0,
+                ..RuleSet::default()
+            };
+            let lan_match = LanMatch {
+                peer: "127.0.0.1:9000".parse().unwrap(),
+                peer_name: "alice".to_owned(),
+                seed: 5,
+                side: Side::Right,
+                rules,
+            };
+
+            let session = lan_match.session(pong::netplay::channel_pair().0);
+            assert_eq!(session.tick(), 0);
+            assert_eq!(session.game.rules, rules);
+            assert_eq!(session.game.ball.speed, rules.ball_speed);
+            assert!((session.game.ball.velocity.length() - rules.ball_speed).abs() < 1e-3);
+        }
+
+        #[test]
+        fn full_games_reject_other_players() {
+            let mut first = loopback_browser();
+            let mut second = loopback_browser();
+            let mut host = host_for(&first, "alice", 9000);
+            poll_until(&mut host, &mut first, |browser| !browser.games().is_empty());
+            let game_addr = first.games()[0].addr;
+
+            first.join(game_addr, "bob", 9001).unwrap();
+            let deadline = Instant::now() + TIMEOUT;
+            while host.poll().unwrap().is_none() {
+                assert!(
+                    Instant::now() < deadline,
+                    "host never accepted the first player"
+                );
+                first.poll().unwrap();
+                thread::sleep(Duration::from_millis(5));
+            }
+
+            second.join(game_addr, "carol", 9002).unwrap();
+            let deadline = Instant::now() + TIMEOUT;
+            let event = loop {
+                assert!(
+                    Instant::now() < deadline,
+                    "second player never got an answer"
+                );
+                host.poll().unwrap();
+                if let Some(event) = second.poll().unwrap() {
+                    break event;
+                }
+                thread::sleep(Duration::from_millis(5));
+            };
+            assert_eq!(event, BrowserEvent::Rejected("game is full".to_owned()));
+        }
+
+        #[test]
+        fn foreign_traffic_is_ignored() {
+            let mut browser = loopback_browser();
+            let stranger = UdpSocket::bind("127.0.0.1:0").unwrap();
+            stranger
+                .send_to(b"not a pong packet", browser.local_addr().unwrap())
+                .unwrap();
+            stranger
+        .send_to(
+            br#"{"type":"announce","version":999,"host_name":"future","game_port":1,"rules":{"winning_score":5,"ball_speed":1.0,"paddle_speed":1.0}}"#,
+            browser.local_addr().unwrap(),
+        )
+        .unwrap();
+            thread::sleep(Duration::from_millis(50));
+
+            assert_eq!(browser.poll().unwrap(), None);
+            assert!(browser.games().is_empty());
+        }
+    }
+
+    // Reinforcement-learning environment tests: determinism, rewards and the JSON protocol
+    mod learning_environment {
+        use pong::env::*;
+        use pong::*;
+        use std::io::Cursor;
+
+        fn run_episode(env: &mut PongEnv, seed: u64, steps: usize) -> Vec<Step> {
+            env.reset(seed);
+            (0..steps)
+                .map(|i| {
+                    let action =
+                        [PaddleCommand::Up, PaddleCommand::Stay, PaddleCommand::Down][i / 20 % 3];
+                    env.step(action)
+                })
+                .collect()
+        }
+
+        #[test]
+        fn same_seed_gives_identical_episodes() {
+            let mut env = PongEnv::new(EnvConfig::default());
+            let first = run_episode(&mut env, 42, 500);
+            let second = run_episode(&mut env, 42, 500);
+            assert_eq!(first, second);
+
+            let other = run_episode(&mut env, 43, 500);
+            assert_ne!(first, other);
+        }
+
+        #[test]
+        fn observation_matches_configured_names() {
+            let config = EnvConfig {
+                observation: ObservationConfig {
+                    ball_velocity: false,
+                    scores: true,
+                    ..ObservationConfig::default()
+                },
+                ..EnvConfig::default()
+            };
+            let mut env = PongEnv::new(config);
+            let observation = env.reset(1);
+
+            assert_eq!(
+                env.observation_names(),
+                vec![
+                    "ball_x",
+                    "ball_y",
+                    "agent_paddle_y",
+                    "opponent_paddle_y",
+                    "agent_score",
+                    "opponent_score"
+                ]
+            );
+            assert_eq!(observation.len(), 6);
+            assert!(observation[..4]
+                .iter()
+                .all(|value| (-1.0..=1.0).contains(value)));
+        }
+
+        #[test]
+        fn mirrored_observation_puts_agent_on_the_left() {
+            let left = PongEnv::new(EnvConfig::default());
+            let right = PongEnv::new(EnvConfig {
+                agent_side: Side::Right,
+                ..EnvConfig::default()
+            });
+
+            // Both start from the same serve, seen from opposite ends of the court
+            let left_view = left.observe();
+            let right_view = right.observe();
+            assert!((left_view[0] + right_view[0]).abs() < 1e-5);
+            assert!((left_view[2] + right_view[2]).abs() < 1e-5);
+            assert_eq!(left_view[1], right_view[1]);
+        }
+
+        #[test]
+        fn conceding_a_point_is_penalised_and_winning_ends_the_episode() {
+            let config = EnvConfig {
+                rules: RuleSet {
+                    winning_score: 2,
+                    ..RuleSet::default()
+                },
+                ..EnvConfig::default()
+            };
+            let mut env = PongEnv::new(config);
+            env.reset(7);
+
+            // Parked at the top, the agent loses every point to the built-in AI eventually
+            let mut total = 0.0;
+            let mut last = None;
+            for _ in 0..20_000 {
+                let step = env.step(PaddleCommand::Up);
+                total += step.reward;
+                let done = step.done;
+                last = Some(step);
+                if done {
+                    break;
+                }
+            }
+
+            let last = last.unwrap();
+            assert!(last.done);
+            assert!(!last.info.truncated);
+            assert_eq!(last.info.winner, Some(Side::Right));
+            assert_eq!(last.info.opponent_score, 2);
+            assert_eq!(total, -2.0 + last.info.agent_score as f32);
+
+            // Stepping past the end changes nothing and keeps reporting the result
+            let after = env.step(PaddleCommand::Down);
+            assert!(after.done && !after.info.truncated);
+            assert_eq!(after.reward, 0.0);
+            assert_eq!(after.info.winner, Some(Side::Right));
+            assert_eq!(after.info.steps, last.info.steps);
+            assert_eq!(after.observation, last.observation);
+        }
+
+        #[test]
+        fn episodes_are_truncated_after_max_steps() {
+            let mut env = PongEnv::new(EnvConfig {
+                max_steps: 30,
+                frame_skip: 2,
+                ..EnvConfig::default()
+            });
+            env.reset(3);
+
+            let steps = (0..30)
+                .map(|_| env.step(PaddleCommand::Stay))
+                .collect::<Vec<_>>();
+            assert!(steps[..29].iter().all(|step| !step.done));
+            assert!(steps[29].done && steps[29].info.truncated);
+            assert_eq!(steps[29].info.steps, 30);
+        }
+
+        #[test]
+        fn runs_many_steps_without_rendering() {
+            let mut env = PongEnv::new(EnvConfig::default());
+            env.reset(11);
+            for i in 0..20_000u32 {
+                let action = if i % 7 < 3 {
+                    PaddleCommand::Up
+                } else {
+                    PaddleCommand::Down
+                };
+                if env.step(action).done {
+                    env.reset(u64::from(i));
+                }
+            }
+            assert!(env.game().particles.is_empty());
+        }
+
+        #[test]
+        fn json_protocol_drives_the_environment() {
+            let input = [
+        r#"{"cmd":"configure","config":{"observation":{"ball_velocity":false},"max_steps":2}}"#,
+        r#"{"cmd":"reset","seed":5}"#,
+        r#"{"cmd":"step","action":"Up"}"#,
+        r#"{"cmd":"step","action":"Jump"}"#,
+        r#"{"cmd":"step","action":"Down"}"#,
+        r#"{"cmd":"close"}"#,
+        r#"{"cmd":"reset","seed":6}"#,
+    ]
+    .join("\n");
+            let mut output = Vec::new();
+            serve_env(Cursor::new(input), &mut output).unwrap();
+
+            let responses: Vec<EnvResponse> = String::from_utf8(output)
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            assert_eq!(responses.len(), 5);
+            assert_eq!(
+                responses[0],
+                EnvResponse::Configured {
+                    observation_names: ["ball_x", "ball_y", "agent_paddle_y", "opponent_paddle_y"]
+                        .map(str::to_owned)
+                        .to_vec()
+                }
+            );
+            assert!(
+                matches!(&responses[1], EnvResponse::Reset { observation } if observation.len() == 4)
+            );
+            assert!(matches!(&responses[2], EnvResponse::Step(step) if !step.done));
+            assert!(matches!(&responses[3], EnvResponse::Error { .. }));
+            assert!(
+                matches!(&responses[4], EnvResponse::Step(step) if step.done && step.info.truncated)
+            );
+        }
+    }
+
+    // Game event tests: what the simulation emits and who hears about it
+    mod game_events {
+        use super::stay;
+        use pong::netplay::TICK_DT;
+        use pong::*;
+        use std::sync::{Arc, Mutex};
+
+        // Records every event it hears, shared with the test
+        #[derive(Clone, Default)]
+        struct Recorder {
+            events: Arc<Mutex<Vec<(GameEvent, f32)>>>,
+        }
+
+        impl EventListener for Recorder {
+            fn on_event(&mut self, event: &GameEvent, time: f32) {
+                self.events.lock().unwrap().push((*event, time));
+            }
+        }
+
+        #[test]
+        fn wall_hits_are_emitted_once_per_bounce() {
+            let mut game = Game::with_seed(1);
+            // Travel up into the top wall from well below it, then away again
+            game.ball.position = Vec2D::new(WINDOW_WIDTH / 2.0, 20.0);
+            game.ball.velocity = Vec2D::new(100.0, -game.ball.speed);
+
+            let mut wall_hits = 0;
+            for _ in 0..30 {
+                stay(&mut game);
+                wall_hits += game
+                    .events
+                    .iter()
+                    .filter(|event| matches!(event, GameEvent::WallHit { .. }))
+                    .count();
+            }
+            assert_eq!(wall_hits, 1);
+            assert_eq!(game.stats.wall_hits, 1);
+            assert!(game.ball.velocity.y > 0.0);
+        }
+
+        #[test]
+        fn goals_are_followed_by_a_serve_and_the_match_end() {
+            let mut game = Game::with_seed(2);
+            assert!(matches!(game.events.as_slice(), [GameEvent::Serve { .. }]));
+            game.player_score = game.rules.winning_score - 1;
+            game.ball.position = Vec2D::new(WINDOW_WIDTH - 1.0, WINDOW_HEIGHT / 2.0);
+            game.ball.velocity = Vec2D::new(game.ball.speed, 0.0);
+            stay(&mut game);
+
+            match game.events.as_slice() {
+                [GameEvent::Goal {
+                    scorer: Side::Left,
+                    rally_length: 0,
+                    ..
+                }, GameEvent::Serve { server, velocity }, GameEvent::MatchEnd { winner: Side::Left }] =>
+                {
+                    assert_eq!(*velocity, game.ball.velocity);
+                    assert_eq!(*server == Side::Left, velocity.x > 0.0);
+                }
+                events => panic!("unexpected events: {:?}", events),
+            }
+            assert_eq!(game.game_state, GameState::GameOver);
+
+            // Nothing happens once the match is over
+            stay(&mut game);
+            assert_eq!(game.events.len(), 3);
+        }
+
+        #[test]
+        fn paddle_hits_carry_the_contact_point() {
+            let mut game = Game::with_seed(3);
+            let centre = game.paddle(Side::Left).get_center_y();
+            game.ball.position = Vec2D::new(48.0, centre + 30.0 - BALL_SIZE / 2.0);
+            game.ball.velocity = Vec2D::new(-game.ball.speed, 0.0);
+            stay(&mut game);
+
+            match game.events.as_slice() {
+                [GameEvent::PaddleHit {
+                    side: Side::Left,
+                    contact,
+                    speed,
+                    ..
+                }] => {
+                    assert!((contact - 30.0 / (PADDLE_HEIGHT / 2.0)).abs() < 0.05);
+                    assert_eq!(*speed, game.ball.speed);
+                }
+                events => panic!("unexpected events: {:?}", events),
+            }
+            assert_eq!(game.rally_length, 1);
+            assert_eq!(game.stats.left.paddle_hits, 1);
+        }
+
+        #[test]
+        fn listeners_hear_every_event_in_order() {
+            let mut game = Game::with_seed(4);
+            let recorder = Recorder::default();
+            game.subscribe(Box::new(recorder.clone()));
+
+            let mut emitted = Vec::new();
+            for _ in 0..3000 {
+                let left = game.ai_command(Side::Left);
+                game.step(TICK_DT, left, PaddleCommand::Stay);
+                emitted.extend(game.events.iter().copied());
+                if game.game_state == GameState::GameOver {
+                    break;
+                }
+            }
+
+            let heard = recorder.events.lock().unwrap();
+            let events: Vec<GameEvent> = heard.iter().map(|(event, _)| *event).collect();
+            assert_eq!(events, emitted);
+            assert!(heard.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+            let goals = events
+                .iter()
+                .filter(|event| matches!(event, GameEvent::Goal { .. }))
+                .count();
+            assert!(goals > 0);
+            assert_eq!(goals as u32, game.stats.rallies);
+        }
+    }
+
+    // Frame export tests: the software rasteriser, frame timing, PNG and GIF output and options
+    mod frame_export {
+        use super::args;
+        use macroquad::prelude::*;
+        use pong::export::*;
+        use pong::raster::{self, Frame};
+        use pong::replay::Replay;
+        use pong::sim::PlayerSpec;
+        use pong::theme::Theme;
+        use pong::*;
+        use std::path::Path;
+
+        fn rgb(color: Color) -> [u8; 3] {
+            let [r, g, b, _] = color.into();
+            [r, g, b]
+        }
+
+        #[test]
+        fn rectangles_and_circles_fill_covered_pixel_centres() {
+            let mut frame = Frame::new(10, 10, BLACK);
+            frame.fill_rect(Rect::new(1.0, 1.0, 3.0, 2.0), WHITE);
+            let lit = |frame: &Frame| {
+                (0..10)
+                    .flat_map(|y| (0..10).map(move |x| (x, y)))
+                    .filter(|&(x, y)| frame.pixel(x, y) != [0; 3])
+                    .count()
+            };
+            assert_eq!(lit(&frame), 6);
+            assert_eq!(frame.pixel(3, 2), [255; 3]);
+            assert_eq!(frame.pixel(4, 2), [0; 3]);
+
+            let mut frame = Frame::new(10, 10, BLACK);
+            frame.fill_circle(vec2(5.0, 5.0), 2.0, WHITE.with_alpha(0.5));
+            assert_eq!(lit(&frame), 12);
+            assert_eq!(frame.pixel(5, 5), [128; 3]);
+            // Drawing off the edge is clipped
+            frame.fill_rect(Rect::new(-5.0, -5.0, 30.0, 6.0), WHITE);
+            assert_eq!(frame.pixel(9, 0), [255; 3]);
+        }
+
+        #[test]
+        fn rendered_frames_show_the_court_like_the_window() {
+            let mut game = Game::with_seed(5);
+            game.player_score = 3;
+            let theme = Theme::NEON;
+            let frame = raster::render(&game, &theme, 0.5);
+            assert_eq!((frame.width, frame.height), (400, 300));
+            assert_eq!(frame.pixel(100, 290), rgb(theme.background));
+
+            // Paddles are drawn where the game has them
+            let left = game.player_paddle.get_rect();
+            let center = (left.center() * 0.5).as_uvec2();
+            assert_eq!(frame.pixel(center.x, center.y), rgb(theme.left_paddle));
+            let right = game.ai_paddle.get_rect();
+            let center = (right.center() * 0.5).as_uvec2();
+            assert_eq!(frame.pixel(center.x, center.y), rgb(theme.right_paddle));
+
+            // Scores sit over each half in their player's colour
+            let lit_in = |x_range: std::ops::Range<u32>, color: Color| {
+                x_range
+                    .flat_map(|x| (0..60).map(move |y| (x, y)))
+                    .any(|(x, y)| frame.pixel(x, y) == rgb(color))
+            };
+            assert!(lit_in(80..120, theme.left_paddle));
+            assert!(lit_in(280..320, theme.right_paddle));
+
+            // A goal flash tints the whole court
+            game.feel.flash = 0.3;
+            game.feel.flash_side = Some(Side::Left);
+            let flashed = raster::render(&game, &theme, 0.5);
+            assert_ne!(flashed.pixel(100, 290), rgb(theme.background));
+        }
+
+        #[test]
+        fn frames_are_taken_at_a_fixed_rate_of_match_time() {
+            let options = ExportOptions {
+                fps: 10,
+                duration: Some(2.0),
+                from: 1.0,
+                ..ExportOptions::default()
+            };
+            let mut playback = Playback::from_options(&options).unwrap();
+            let mut count = 0;
+            let rendered = render_frames(&mut playback, &Theme::CLASSIC, &options, |frame| {
+                assert_eq!((frame.width, frame.height), (800, 600));
+                count += 1;
+                Ok(())
+            })
+            .unwrap();
+            assert_eq!((rendered, count), (21, 21));
+            assert!((playback.time - 3.0).abs() < 0.02);
+
+            // A replay of 0.73s gets frames from 0 to 0.7s and one more for its last step
+            let mut game = Game::with_seed(8);
+            game.replay = Some(Replay::start(&game, &[]));
+            for _ in 0..44 {
+                game.step(1.0 / 60.0, PaddleCommand::Up, PaddleCommand::Down);
+            }
+            let replay = game.replay.take().unwrap();
+            let options = ExportOptions {
+                fps: 20,
+                ..ExportOptions::default()
+            };
+            let mut playback = Playback::replay(&replay);
+            let rendered =
+                render_frames(&mut playback, &Theme::CLASSIC, &options, |_| Ok(())).unwrap();
+            assert_eq!(rendered, 16);
+            assert_eq!(playback.game.snapshot(), game.snapshot());
+        }
+
+        #[test]
+        fn png_and_gif_output_are_valid_files() {
+            let frame = raster::render(&Game::with_seed(1), &Theme::CLASSIC, 0.25);
+            let mut png = Vec::new();
+            write_png(&mut png, &frame).unwrap();
+            assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+
+            let mut gif = GifWriter::new(Vec::new(), frame.width, frame.height, 25).unwrap();
+            gif.write(&frame).unwrap();
+            gif.write(&frame).unwrap();
+            let gif = gif.finish().unwrap();
+            assert_eq!(&gif[..6], b"GIF89a");
+            assert_eq!(*gif.last().unwrap(), 0x3b);
+        }
+
+        #[test]
+        fn export_options_are_parsed_and_checked() {
+            let options =
+                parse_export_args(args(&["-a", "idle", "--seed", "9", "-o", "clip.GIF"])).unwrap();
+            assert_eq!(options.format(), ExportFormat::Gif);
+            assert_eq!(options.scale(), 0.5);
+            match options.source {
+                ExportSource::Simulated { left, seed, .. } => {
+                    assert_eq!((left, seed), (PlayerSpec::Idle, 9));
+                }
+                other => panic!("expected a simulated match, got {:?}", other),
+            }
+
+            let options =
+                parse_export_args(args(&["--replay", "m.json", "-o", "shot.png"])).unwrap();
+            assert_eq!(options.source, ExportSource::Replay("m.json".into()));
+            assert_eq!(options.format(), ExportFormat::Png);
+            assert_eq!(
+                ExportFormat::for_path(Path::new("frames")),
+                ExportFormat::PngSequence
+            );
+
+            for bad in [
+                &["--replay", "m.json", "--seed", "1"][..],
+                &["--fps", "0"],
+                &["--fps", "120"],
+                &["--scale", "0"],
+                &["--from", "-1"],
+                &["--duration", "0"],
+                &["--frames"],
+            ] {
+                assert!(
+                    parse_export_args(args(bad)).is_err(),
+                    "{:?} was accepted",
+                    bad
+                );
+            }
+        }
+    }
+
+    // Game feel tests: which events start which effects, and turning effects off
+    mod game_feel {
+        use pong::cli::parse_args;
+        use pong::feel::{Feel, FeelSettings, HARD_CONTACT, SHAKE_DISTANCE};
+        use pong::netplay::TICK_DT;
+        use pong::settings::Settings;
+        use pong::*;
+
+        fn hit(side: Side, contact: f32) -> GameEvent {
+            GameEvent::PaddleHit {
+                side,
+                contact,
+                position: Vec2D::new(0.0, 0.0),
+                speed: BALL_SPEED,
+            }
+        }
+
+        fn goal(scorer: Side) -> GameEvent {
+            GameEvent::Goal {
+                scorer,
+                position: Vec2D::new(0.0, 0.0),
+                speed: BALL_SPEED,
+                rally_length: 3,
+            }
+        }
+
+        #[test]
+        fn hard_returns_pause_briefly_and_squash_the_paddle() {
+            let mut feel = Feel::default();
+            feel.on_event(&hit(Side::Left, 0.1), BALL_SPEED, &FeelSettings::ALL);
+            assert_eq!(feel.squash(Side::Left), 1.0);
+            assert_eq!(feel.squash(Side::Right), 0.0);
+            assert!(!feel.hold(TICK_DT), "a soft return doesn't pause");
+
+            feel.on_event(
+                &hit(Side::Right, -HARD_CONTACT),
+                BALL_SPEED,
+                &FeelSettings::ALL,
+            );
+            let mut held = 0;
+            while feel.hold(TICK_DT) {
+                held += 1;
+                assert!(held < 60, "hit-stop never ends");
+            }
+            assert!(held >= 2, "held for {} frames", held);
+
+            feel.update(1.0);
+            assert_eq!(feel.squash(Side::Left), 0.0);
+            assert_eq!(feel.squash(Side::Right), 0.0);
+        }
+
+        #[test]
+        fn goals_shake_flash_and_pop_the_score() {
+            let mut slow = Feel::default();
+            let mut fast = Feel::default();
+            slow.on_event(&goal(Side::Right), BALL_SPEED, &FeelSettings::ALL);
+            fast.on_event(&goal(Side::Right), BALL_SPEED * 1.5, &FeelSettings::ALL);
+            assert!(fast.trauma > slow.trauma, "faster balls shake harder");
+            assert_eq!(slow.flash_side, Some(Side::Right));
+            assert_eq!(slow.flash_strength(), 1.0);
+            assert!(slow.score_scale(Side::Right) > 1.0);
+            assert_eq!(slow.score_scale(Side::Left), 1.0);
+
+            // Shake stays within its distance and everything settles down
+            for _ in 0..120 {
+                assert!(fast.shake_offset().length() <= SHAKE_DISTANCE);
+                fast.update(TICK_DT);
+            }
+            assert_eq!(fast.shake_offset(), macroquad::math::Vec2::ZERO);
+            assert_eq!(fast.flash_side, None);
+            assert_eq!(fast.score_scale(Side::Right), 1.0);
+        }
+
+        #[test]
+        fn effects_of_the_winning_goal_wind_down_after_the_match() {
+            let mut game = Game::with_seed(3);
+            game.rules.winning_score = 1;
+            while game.game_state == GameState::Playing {
+                game.step(TICK_DT, PaddleCommand::Stay, PaddleCommand::Stay);
+            }
+            assert!(game.feel.flash_side.is_some());
+            assert!(game.feel.trauma > 0.0);
+
+            for _ in 0..120 {
+                game.update(TICK_DT);
+            }
+            assert_eq!(game.feel.flash_side, None);
+            assert_eq!(game.feel.trauma, 0.0);
+            assert_eq!(game.feel.score_pop, [0.0, 0.0]);
+            assert_eq!(game.feel.shake_offset(), macroquad::math::Vec2::ZERO);
+        }
+
+        #[test]
+        fn effects_can_be_turned_off_one_by_one() {
+            let mut feel = Feel::default();
+            feel.on_event(&hit(Side::Left, 1.0), BALL_SPEED, &FeelSettings::OFF);
+            feel.on_event(&goal(Side::Left), BALL_SPEED, &FeelSettings::OFF);
+            assert_eq!(feel, Feel::default());
+
+            let no_shake = FeelSettings {
+                screen_shake: false,
+                ..FeelSettings::ALL
+            };
+            feel.on_event(&goal(Side::Left), BALL_SPEED, &no_shake);
+            assert_eq!(feel.trauma, 0.0);
+            assert_eq!(feel.flash_side, Some(Side::Left));
+
+            // Settings files only need the effects they change
+            let settings =
+                Settings::from_json(r#"{"version": 1, "feel": {"hit_stop": false}}"#).unwrap();
+            assert!(!settings.feel.hit_stop);
+            assert!(settings.feel.screen_shake && settings.feel.score_pop);
+            assert_eq!(
+                Settings::from_json(r#"{"version": 1}"#).unwrap().feel,
+                FeelSettings::ALL
+            );
+
+            let args = ["--reduced-motion".to_owned()];
+            assert!(parse_args(args).unwrap().reduced_motion);
+        }
+
+        #[test]
+        fn the_simulation_ignores_the_effects() {
+            let mut game = Game::with_seed(5);
+            let mut plain = Game::with_seed(5);
+            game.ball.position = Vec2D::new(WINDOW_WIDTH - 1.0, WINDOW_HEIGHT / 2.0);
+            game.ball.velocity = Vec2D::new(game.ball.speed, 0.0);
+            plain.ball = game.ball.clone();
+
+            game.step(TICK_DT, PaddleCommand::Stay, PaddleCommand::Stay);
+            assert!(game.feel.trauma > 0.0);
+            assert_eq!(game.feel.flash_side, Some(Side::Left));
+
+            // Stepping directly, as netplay and servers do, never pauses for hit-stop
+            game.feel.hit_stop = 1.0;
+            plain.step(TICK_DT, PaddleCommand::Stay, PaddleCommand::Stay);
+            for _ in 0..30 {
+                game.step(TICK_DT, PaddleCommand::Stay, PaddleCommand::Stay);
+                plain.step(TICK_DT, PaddleCommand::Stay, PaddleCommand::Stay);
+            }
+            assert_eq!(game.ball, plain.ball);
+            assert_eq!(game.player_score, plain.player_score);
+        }
+    }
+
+    // Learned AI tests: network evaluation, weight files, training and CLI options
+    mod learned_ai {
+        use super::args;
+        use pong::cli::parse_args;
+        use pong::learned::*;
+        use pong::*;
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha8Rng;
+
+        // A linear network that chases the ball: inputs are ball x/y, ball velocity x/y, own and
+        // opposing paddle y
+        fn tracker() -> Network {
+            #[rustfmt::skip]
+    let weights = vec![
+        0.0, -1.0, 0.0, 0.0, 1.0, 0.0,
+        0.0, 1.0, 0.0, 0.0, -1.0, 0.0,
+        0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+        0.0, 0.0, 0.02,
+    ];
+            Network {
+                layers: vec![6, 3],
+                weights,
+            }
+        }
+
+        fn quick_config(threads: usize) -> TrainConfig {
+            TrainConfig {
+                population: 6,
+                generations: 2,
+                matches: 1,
+                elite: 2,
+                seed: 5,
+                threads,
+                max_ticks: 600,
+                ..TrainConfig::default()
+            }
+        }
+
+        #[test]
+        fn networks_have_the_expected_shape() {
+            let layers = Network::default_layers(8);
+            assert_eq!(layers, vec![6, 8, 3]);
+            assert_eq!(Network::parameter_count(&layers), 7 * 8 + 9 * 3);
+
+            let mut rng = ChaCha8Rng::seed_from_u64(1);
+            let network = Network::random(&layers, &mut rng);
+            assert_eq!(network.weights.len(), Network::parameter_count(&layers));
+            let outputs = network.forward(&[0.0; 6]);
+            assert_eq!(outputs.len(), 3);
+            assert!(outputs.iter().all(|value| value.is_finite()));
+        }
+
+        #[test]
+        fn decide_picks_the_highest_output() {
+            let network = tracker();
+            let mut game = Game::with_seed(1);
+
+            game.ball.position.y = 50.0;
+            assert_eq!(network.decide(&game, Side::Left), PaddleCommand::Up);
+            game.ball.position.y = WINDOW_HEIGHT - 50.0;
+            assert_eq!(network.decide(&game, Side::Right), PaddleCommand::Down);
+            game.ball.position.y = game.paddle(Side::Left).get_center_y() - BALL_SIZE / 2.0;
+            assert_eq!(network.decide(&game, Side::Left), PaddleCommand::Stay);
+        }
+
+        #[test]
+        fn weights_round_trip_and_reject_bad_files() {
+            let path =
+                std::env::temp_dir().join(format!("pong_weights_test_{}.json", std::process::id()));
+            let network = tracker();
+            network.save(&path, 12, 3.5).unwrap();
+            assert_eq!(Network::load(&path).unwrap(), network);
+            std::fs::remove_file(&path).unwrap();
+
+            let json = network.to_json(1, 0.0).unwrap();
+            let future = json.replacen(
+                &format!("\"version\": {}", WEIGHTS_VERSION),
+                "\"version\": 99",
+                1,
+            );
+            let err = Network::from_json(&future).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+            let mut truncated = network.clone();
+            truncated.weights.pop();
+            let err = Network::from_json(&truncated.to_json(1, 0.0).unwrap()).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+            let wrong_inputs = Network {
+                layers: vec![4, 3],
+                weights: vec![0.0; 15],
+            };
+            let err = Network::from_json(&wrong_inputs.to_json(1, 0.0).unwrap()).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        }
+
+        #[test]
+        fn training_is_deterministic_across_thread_counts() {
+            let run = |threads| {
+                let mut trainer = Trainer::new(quick_config(threads));
+                let reports: Vec<_> = (0..2).map(|_| trainer.step_generation()).collect();
+                (reports, trainer.champion().clone())
+            };
+
+            let (reports, champion) = run(1);
+            assert_eq!(reports.len(), 2);
+            assert_eq!(reports[1].generation, 2);
+            assert!(reports
+                .iter()
+                .all(|report| report.best_fitness >= report.mean_fitness));
+            assert_eq!(run(3), (reports, champion));
+        }
+
+        #[test]
+        fn difficulty_maps_to_ai_settings() {
+            assert_eq!("easy".parse(), Ok(Difficulty::Easy));
+            assert_eq!("learned".parse(), Ok(Difficulty::Learned));
+            assert!("impossible".parse::<Difficulty>().is_err());
+
+            assert!(
+                Difficulty::Easy.ai_settings().speed_factor
+                    < Difficulty::Normal.ai_settings().speed_factor
+            );
+            assert_eq!(Difficulty::Normal.ai_settings(), AiSettings::default());
+            // The learned AI plays at the speed it was trained at
+            assert_eq!(Difficulty::Learned.ai_settings().speed_factor, 1.0);
+        }
+
+        #[test]
+        fn cli_parses_train_and_difficulty_options() {
+            let options = parse_args(args(&[
+                "train",
+                "--generations",
+                "7",
+                "--population",
+                "10",
+                "--out",
+                "w.json",
+            ]))
+            .unwrap();
+            let train = options.train.unwrap();
+            assert_eq!(train.config.generations, 7);
+            assert_eq!(train.config.population, 10);
+            assert_eq!(train.output, std::path::PathBuf::from("w.json"));
+            assert!(parse_args(args(&["train", "--population", "1"])).is_err());
+            assert!(parse_args(args(&["train", "--generations", "0"])).is_err());
+            assert!(parse_args(args(&["train", "--headless"])).is_err());
+
+            let options = parse_args(args(&["--difficulty", "hard"])).unwrap();
+            assert_eq!(options.difficulty, Some(Difficulty::Hard));
+            assert!(options.train.is_none());
+
+            // Weights imply the learned difficulty
+            let options = parse_args(args(&["--ai-weights", "w.json"])).unwrap();
+            assert_eq!(options.difficulty, Some(Difficulty::Learned));
+            assert!(parse_args(args(&["--ai-weights", "w.json", "--difficulty", "easy"])).is_err());
+            assert!(parse_args(args(&[
+                "--difficulty",
+                "hard",
+                "--spectate",
+                "127.0.0.1:7000"
+            ]))
+            .is_err());
+        }
+    }
+
+    // Game mod tests: hooks, the example mods, composition and configuration
+    mod game_mods {
+        use super::{args, hit_left_paddle, stay};
+        use pong::cli::parse_args;
+        use pong::mods::*;
+        use pong::*;
+        use std::sync::{Arc, Mutex};
+
+        // Push the ball out past the right paddle
+        fn score_left(game: &mut Game) {
+            game.ball.position = Vec2D::new(WINDOW_WIDTH - 1.0, WINDOW_HEIGHT / 2.0);
+            game.ball.velocity = Vec2D::new(game.ball.speed, 0.0);
+            stay(game);
+        }
+
+        #[derive(Debug, Default, PartialEq)]
+        struct Calls {
+            hits: Vec<Side>,
+            goals: Vec<Side>,
+            ticks: u32,
+        }
+
+        // Records the hooks it is given, shared with the test
+        struct Recorder {
+            calls: Arc<Mutex<Calls>>,
+        }
+
+        impl GameMod for Recorder {
+            fn name(&self) -> &'static str {
+                "recorder"
+            }
+
+            fn modify_config(&self, rules: &mut RuleSet) {
+                rules.winning_score = 3;
+            }
+
+            fn on_tick(&mut self, _game: &mut Game, _dt: f32) {
+                self.calls.lock().unwrap().ticks += 1;
+            }
+
+            fn on_paddle_hit(&mut self, _game: &mut Game, side: Side) {
+                self.calls.lock().unwrap().hits.push(side);
+            }
+
+            fn on_goal(&mut self, _game: &mut Game, scorer: Side) {
+                self.calls.lock().unwrap().goals.push(scorer);
+            }
+        }
+
+        #[test]
+        fn hooks_follow_the_simulation() {
+            let calls = Arc::new(Mutex::new(Calls::default()));
+            let mut game = Game::with_seed(1);
+            game.add_mod(Box::new(Recorder {
+                calls: calls.clone(),
+            }));
+            assert_eq!(game.rules.winning_score, 3);
+
+            hit_left_paddle(&mut game, 0.0);
+            stay(&mut game);
+            score_left(&mut game);
+            assert_eq!(
+                *calls.lock().unwrap(),
+                Calls {
+                    hits: vec![Side::Left],
+                    goals: vec![Side::Left],
+                    ticks: 3,
+                }
+            );
+
+            // The modified rules decide the match
+            score_left(&mut game);
+            score_left(&mut game);
+            assert_eq!(game.game_state, GameState::GameOver);
+            stay(&mut game);
+            assert_eq!(calls.lock().unwrap().ticks, 5);
+        }
+
+        #[test]
+        fn gravity_pulls_the_ball_down() {
+            let mut game = Game::with_seed(2);
+            game.apply_mods(&[ModConfig::GravityBall { strength: 600.0 }]);
+            game.ball.position = Vec2D::new(WINDOW_WIDTH / 2.0, 100.0);
+            game.ball.velocity = Vec2D::new(200.0, 0.0);
+            for _ in 0..30 {
+                stay(&mut game);
+            }
+            assert!((game.ball.velocity.y - 300.0).abs() < 1.0);
+            assert!(game.ball.position.y > 150.0);
+        }
+
+        #[test]
+        fn paddles_shrink_until_a_goal() {
+            let mut game = Game::with_seed(3);
+            game.apply_mods(&["shrinking_paddles".parse().unwrap()]);
+            let centre = game.paddle(Side::Left).get_center_y();
+            hit_left_paddle(&mut game, 0.0);
+            hit_left_paddle(&mut game, 0.0);
+            let paddle = game.paddle(Side::Left);
+            assert!((paddle.height - PADDLE_HEIGHT * 0.81).abs() < 0.01);
+            assert!((paddle.get_center_y() - centre).abs() < 0.01);
+            assert_eq!(game.paddle(Side::Right).height, PADDLE_HEIGHT);
+
+            for _ in 0..30 {
+                hit_left_paddle(&mut game, 0.0);
+            }
+            assert_eq!(game.paddle(Side::Left).height, 30.0);
+
+            // Shrunk paddles are saved with the match
+            let mut restored = Game::with_seed(99);
+            restored.restore(game.snapshot());
+            assert_eq!(restored.paddle(Side::Left).height, 30.0);
+
+            score_left(&mut game);
+            assert_eq!(game.paddle(Side::Left).height, PADDLE_HEIGHT);
+            hit_left_paddle(&mut game, 0.0);
+            game.reset();
+            assert_eq!(game.paddle(Side::Left).height, PADDLE_HEIGHT);
+        }
+
+        #[test]
+        fn mods_compose() {
+            let mut game = Game::with_seed(4);
+            game.apply_mods(
+                &parse_mod_list("invisible_ball, gravity_ball,shrinking_paddles").unwrap(),
+            );
+            assert_eq!(game.mods.len(), 3);
+            assert_eq!(game.mods[0].name(), "invisible ball");
+            assert!((game.rules.ball_speed - BALL_SPEED * 0.85).abs() < 0.01);
+            game.reset();
+            assert!((game.ball.speed - BALL_SPEED * 0.85).abs() < 0.01);
+
+            hit_left_paddle(&mut game, 0.0);
+            assert!(game.paddle(Side::Left).height < PADDLE_HEIGHT);
+            assert!(game.ball.velocity.y > 0.0);
+        }
+
+        #[test]
+        fn mods_are_configured_by_file_and_command_line() {
+            let json = r#"{
+        "version": 1,
+        "mods": [
+            {"name": "gravity_ball", "strength": 120.0},
+            {"name": "invisible_ball"}
+        ]
+    }"#;
+            let file = ModsFile::from_json(json).unwrap();
+            assert_eq!(file.mods[0], ModConfig::GravityBall { strength: 120.0 });
+            assert_eq!(
+                file.mods[1],
+                ModConfig::InvisibleBall {
+                    width: 240.0,
+                    ball_speed_factor: 0.85
+                }
+            );
+            assert_eq!(ModsFile::from_json(&file.to_json().unwrap()).unwrap(), file);
+
+            let unknown = r#"{"version": 1, "mods": [{"name": "anti_gravity"}]}"#;
+            assert!(ModsFile::from_json(unknown).is_err());
+            let newer = r#"{"version": 2, "mods": []}"#;
+            assert_eq!(
+                ModsFile::from_json(newer).unwrap_err().kind(),
+                std::io::ErrorKind::InvalidData
+            );
+            let missing = std::env::temp_dir().join("pong_mods_test_missing.json");
+            assert_eq!(ModsFile::load(&missing).unwrap(), ModsFile::default());
+
+            let options = parse_args(args(&["--mods", "gravity_ball,shrinking_paddles"])).unwrap();
+            assert_eq!(options.mods.unwrap().len(), 2);
+            assert!(parse_args(args(&["--mods", "gravity_ball,moon"])).is_err());
+            assert!(parse_args(args(&[
+                "--mods",
+                "gravity_ball",
+                "--spectate",
+                "127.0.0.1:7000"
+            ]))
+            .is_err());
+        }
+    }
+
+    // Rollback netplay tests: two peers must converge on identical gameplay state
+    mod rollback_netplay {
+        use super::args;
+        use pong::cli::parse_args;
+        use pong::netplay::*;
+        use pong::*;
+        use std::net::UdpSocket;
+        use std::sync::{Arc, Mutex};
+        use std::thread;
+        use std::time::Duration;
+
+        const SEED: u64 = 1234;
+
+        // Records every event a peer's listeners hear
+        #[derive(Clone, Default)]
+        struct Recorder {
+            events: Arc<Mutex<Vec<(GameEvent, f32)>>>,
+        }
+
+        impl EventListener for Recorder {
+            fn on_event(&mut self, event: &GameEvent, time: f32) {
+                self.events.lock().unwrap().push((*event, time));
+            }
+        }
+
+        // Each peer plays the built-in AI logic for its own paddle, based on its own (possibly predicted) view
+        fn bot_command<T: Transport>(session: &RollbackSession<T>) -> PaddleCommand {
+            session.game.ai_command(session.local_side())
+        }
+
+        fn run_until_tick<T: Transport>(session: &mut RollbackSession<T>, target: u32) {
+            while session.tick() < target {
+                let command = bot_command(session);
+                session.advance(command).expect("netplay session failed");
+                thread::sleep(Duration::from_micros(200));
+            }
+
+            // Keep answering until the peer has acknowledged everything
+            for _ in 0..100 {
+                session.poll().expect("netplay session failed");
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        // Let both peers receive everything that is still in flight
+        fn settle<A: Transport, B: Transport>(
+            a: &mut RollbackSession<A>,
+            b: &mut RollbackSession<B>,
+        ) {
+            for _ in 0..200 {
+                a.poll().unwrap();
+                b.poll().unwrap();
+                if a.confirmed_tick() == a.tick() && b.confirmed_tick() == b.tick() {
+                    return;
+                }
+                thread::sleep(Duration::from_millis(2));
+            }
+            panic!("peers never confirmed all inputs");
+        }
+
+        #[test]
+        fn peers_tell_each_other_their_ratings_and_names() {
+            let (left_link, right_link) = channel_pair();
+            let mut left = RollbackSession::new(SEED, Side::Left, NetConfig::default(), left_link);
+            let mut right =
+                RollbackSession::new(SEED, Side::Right, NetConfig::default(), right_link);
+            left.set_rating(Some(1234.0));
+            left.set_name(Some("Ada".to_owned()));
+            right.set_name(Some("Grace".to_owned()));
+            assert_eq!(right.remote_rating(), None);
+            assert_eq!(right.remote_name(), None);
+
+            for _ in 0..10 {
+                left.advance(PaddleCommand::Stay).unwrap();
+                right.advance(PaddleCommand::Stay).unwrap();
+            }
+            settle(&mut left, &mut right);
+
+            assert_eq!(right.remote_rating(), Some(1234.0));
+            // A peer without a profile stays unrated
+            assert_eq!(left.remote_rating(), None);
+            assert_eq!(right.remote_name(), Some("Ada"));
+            assert_eq!(left.remote_name(), Some("Grace"));
+        }
+
+        #[test]
+        fn peers_converge_over_perfect_link() {
+            let (left_link, right_link) = channel_pair();
+            let mut left = RollbackSession::new(SEED, Side::Left, NetConfig::default(), left_link);
+            let mut right =
+                RollbackSession::new(SEED, Side::Right, NetConfig::default(), right_link);
+
+            for _ in 0..600 {
+                let command = bot_command(&left);
+                left.advance(command).unwrap();
+                let command = bot_command(&right);
+                right.advance(command).unwrap();
+            }
+            settle(&mut left, &mut right);
+
+            assert_eq!(left.tick(), right.tick());
+            assert_eq!(left.game.snapshot(), right.game.snapshot());
+        }
+
+        #[test]
+        fn peers_converge_with_loss_and_latency() {
+            let conditions = LinkConditions {
+                loss: 0.2,
+                latency: Duration::from_millis(20),
+                jitter: Duration::from_millis(10),
+            };
+            let (left_link, right_link) = channel_pair();
+            let left_link = SimulatedLink::new(left_link, conditions, 1);
+            let right_link = SimulatedLink::new(right_link, conditions, 2);
+            let config = NetConfig {
+                input_delay: 1,
+                ..NetConfig::default()
+            };
+            let mut left = RollbackSession::new(SEED, Side::Left, config, left_link);
+            let mut right = RollbackSession::new(SEED, Side::Right, config, right_link);
+            let recorders = [Recorder::default(), Recorder::default()];
+            left.game.subscribe(Box::new(recorders[0].clone()));
+            right.game.subscribe(Box::new(recorders[1].clone()));
+
+            while left.tick() < 400 || right.tick() < 400 {
+                if left.tick() < 400 {
+                    let command = bot_command(&left);
+                    left.advance(command).unwrap();
+                } else {
+                    left.poll().unwrap();
+                }
+                if right.tick() < 400 {
+                    let command = bot_command(&right);
+                    right.advance(command).unwrap();
+                } else {
+                    right.poll().unwrap();
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            settle(&mut left, &mut right);
+
+            assert!(left.rollback_count() + right.rollback_count() > 0);
+            assert_eq!(left.game.snapshot(), right.game.snapshot());
+
+            // Re-simulated ticks replace what they recorded the first time instead of adding to it
+            for game in [&left.game, &right.game] {
+                assert_eq!(game.ticks, 400);
+                assert!((game.stats.time_played - 400.0 * TICK_DT).abs() < 1e-3);
+                let stats = &game.stats;
+                let recorded = stats.total_hits() + stats.wall_hits + stats.rallies;
+                assert_eq!(game.analytics.events.len(), recorded as usize);
+            }
+
+            // Listeners only hear confirmed ticks, so both peers' listeners heard the same match
+            // with no mispredicted events
+            let heard = recorders.map(|recorder| recorder.events.lock().unwrap().clone());
+            assert_eq!(heard[0], heard[1]);
+            let hits = heard[0]
+                .iter()
+                .filter(|(event, _)| matches!(event, GameEvent::PaddleHit { .. }))
+                .count();
+            assert_eq!(hits, left.game.stats.total_hits() as usize);
+        }
+
+        #[test]
+        fn peers_converge_over_udp_loopback() {
+            let left_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let right_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+            let left_addr = left_socket.local_addr().unwrap();
+            let right_addr = right_socket.local_addr().unwrap();
+
+            let left_thread = thread::spawn(move || {
+                let transport = UdpTransport::new(left_socket, right_addr).unwrap();
+                let mut session =
+                    RollbackSession::new(SEED, Side::Left, NetConfig::default(), transport);
+                run_until_tick(&mut session, 300);
+                session
+            });
+            let right_thread = thread::spawn(move || {
+                let transport = UdpTransport::new(right_socket, left_addr).unwrap();
+                let mut session =
+                    RollbackSession::new(SEED, Side::Right, NetConfig::default(), transport);
+                run_until_tick(&mut session, 300);
+                session
+            });
+
+            let mut left = left_thread.join().unwrap();
+            let mut right = right_thread.join().unwrap();
+            settle(&mut left, &mut right);
+
+            assert_eq!(left.game.snapshot(), right.game.snapshot());
+        }
+
+        #[test]
+        fn diverging_state_is_reported_as_desync() {
+            let (left_link, right_link) = channel_pair();
+            let config = NetConfig {
+                checksum_interval: 10,
+                ..NetConfig::default()
+            };
+            let mut left = RollbackSession::new(SEED, Side::Left, config, left_link);
+            let mut right = RollbackSession::new(SEED, Side::Right, config, right_link);
+
+            // Corrupt one peer's simulation behind the netcode's back
+            right.game.player_score = 3;
+
+            let mut desync = None;
+            for _ in 0..120 {
+                if let Err(err) = left.advance(PaddleCommand::Stay) {
+                    desync = Some(err);
+                    break;
+                }
+                if let Err(err) = right.advance(PaddleCommand::Stay) {
+                    desync = Some(err);
+                    break;
+                }
+            }
+
+            assert!(matches!(desync, Some(NetError::Desync { .. })));
+        }
+
+        #[test]
+        fn mismatched_seeds_are_rejected() {
+            let (left_link, right_link) = channel_pair();
+            let mut left = RollbackSession::new(1, Side::Left, NetConfig::default(), left_link);
+            let mut right = RollbackSession::new(2, Side::Right, NetConfig::default(), right_link);
+
+            left.advance(PaddleCommand::Stay).unwrap();
+            let result = right.advance(PaddleCommand::Stay);
+
+            assert!(matches!(result, Err(NetError::SeedMismatch { .. })));
+        }
+
+        #[test]
+        fn simulated_loss_must_be_a_probability() {
+            let loss = |value: &str| {
+                parse_args(args(&[
+                    "--netplay",
+                    "127.0.0.1:7000",
+                    "127.0.0.1:7001",
+                    "--sim-loss",
+                    value,
+                ]))
+            };
+
+            assert_eq!(loss("0.25").unwrap().netplay.unwrap().link.loss, 0.25);
+            assert!(loss("1").is_ok());
+            for value in ["NaN", "inf", "-0.1", "1.5"] {
+                assert!(loss(value).is_err(), "{} was accepted", value);
+            }
+        }
+    }
+
+    // Particle tests: presets, spawn shapes, emitters, the budget and the separate RNG
+    mod particle_presets {
+        use pong::netplay::TICK_DT;
+        use pong::particles::{Curve, Emitter, ParticlePreset, SpawnShape, PARTICLE_BUDGET};
+        use pong::*;
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha8Rng;
+
+        fn origin() -> Vec2D {
+            Vec2D::new(400.0, 300.0)
+        }
+
+        #[test]
+        fn presets_spawn_within_their_shapes() {
+            let mut rng = ChaCha8Rng::seed_from_u64(1);
+            for name in ParticlePreset::NAMES {
+                let preset = ParticlePreset::named(name).unwrap();
+                for _ in 0..200 {
+                    let particle =
+                        Particle::spawn(&preset, origin(), Vec2D::new(1.0, 0.0), &mut rng);
+                    let speed = particle.velocity.length();
+                    assert!(speed >= preset.speed.0 - 0.01 && speed <= preset.speed.1 + 0.01);
+                    assert!(
+                        particle.lifetime >= preset.lifetime.0
+                            && particle.lifetime <= preset.lifetime.1
+                    );
+
+                    let offset = Vec2D::new(
+                        particle.position.x - origin().x,
+                        particle.position.y - origin().y,
+                    );
+                    match preset.shape {
+                        SpawnShape::Point => assert_eq!(offset.length(), 0.0),
+                        SpawnShape::Circle { radius } => assert!(offset.length() <= radius + 0.01),
+                        SpawnShape::Cone { spread } => {
+                            assert_eq!(offset.length(), 0.0);
+                            assert!(particle.velocity.y.atan2(particle.velocity.x).abs() <= spread);
+                        }
+                        SpawnShape::Line { length, spread } => {
+                            // A line across a rightward emitter runs up and down
+                            assert!(offset.x.abs() < 0.01 && offset.y.abs() <= length / 2.0 + 0.01);
+                            assert!(particle.velocity.y.atan2(particle.velocity.x).abs() <= spread);
+                        }
+                    }
+                }
+            }
+            assert!(ParticlePreset::named("fireworks").is_none());
+        }
+
+        #[test]
+        fn particles_follow_their_preset_over_time() {
+            let mut rng = ChaCha8Rng::seed_from_u64(2);
+            let mut confetti = Particle::spawn(
+                &ParticlePreset::CONFETTI,
+                origin(),
+                Vec2D::new(1.0, 0.0),
+                &mut rng,
+            );
+            let mut smoke = Particle::spawn(
+                &ParticlePreset::SMOKE,
+                origin(),
+                Vec2D::new(1.0, 0.0),
+                &mut rng,
+            );
+            assert!(ParticlePreset::CONFETTI.palette.contains(&confetti.color));
+            assert_eq!(smoke.current_color(), ParticlePreset::SMOKE.start_color);
+            assert_eq!(smoke.size(), ParticlePreset::SMOKE.start_size);
+
+            // Gravity pulls confetti down and lets smoke rise once drag has slowed them
+            for _ in 0..30 {
+                confetti.update(TICK_DT);
+                smoke.update(TICK_DT);
+            }
+            assert!(confetti.velocity.y > 0.0);
+            assert!(smoke.velocity.y < 0.0);
+            assert!(smoke.size() > ParticlePreset::SMOKE.start_size);
+            assert!(smoke.current_color().a < ParticlePreset::SMOKE.start_color.a);
+
+            assert_eq!(Curve::EaseIn.apply(0.5), 0.25);
+            assert_eq!(Curve::EaseOut.apply(0.5), 0.75);
+            assert_eq!(Curve::Linear.apply(2.0), 1.0);
+        }
+
+        #[test]
+        fn the_budget_caps_every_emitter() {
+            let mut particles = ParticleSystem::new(20, 3);
+            let burst = Emitter::new(ParticlePreset::CONFETTI, origin());
+            assert_eq!(burst.burst(&mut particles), 20);
+            assert_eq!(burst.burst(&mut particles), 0);
+            assert_eq!(particles.len(), 20);
+
+            // Everything dies out, freeing the pool for new particles
+            for _ in 0..300 {
+                particles.update(TICK_DT);
+            }
+            assert!(particles.is_empty());
+
+            // A steady emitter spawns its rate over time
+            let mut smoke = Emitter::new(ParticlePreset::SMOKE, origin()).with_rate(10.0);
+            let spawned: u32 = (0..20).map(|_| smoke.update(0.05, &mut particles)).sum();
+            assert_eq!(spawned, 10);
+
+            // Long rallies never go over the game's budget
+            let mut game = Game::with_seed(4);
+            assert_eq!(game.particles.budget(), PARTICLE_BUDGET);
+            for _ in 0..5_000 {
+                let left = game.tracking_command(Side::Left, 5.0);
+                let right = game.tracking_command(Side::Right, 5.0);
+                game.step(TICK_DT, left, right);
+                assert!(game.particles.len() <= PARTICLE_BUDGET);
+            }
+        }
+
+        #[test]
+        fn particles_never_change_the_match() {
+            let mut game = Game::with_seed(6);
+            let mut no_effects = Game::with_seed(6);
+            no_effects.particles = ParticleSystem::new(0, 99);
+            for _ in 0..3_000 {
+                let left = game.tracking_command(Side::Left, 30.0);
+                game.step(TICK_DT, left, game.ai_command(Side::Right));
+                let left = no_effects.tracking_command(Side::Left, 30.0);
+                no_effects.step(TICK_DT, left, no_effects.ai_command(Side::Right));
+            }
+            assert!(game.stats.total_hits() > 0);
+            assert_eq!(game.ball, no_effects.ball);
+            assert_eq!(game.rng, no_effects.rng);
+            assert_eq!(
+                (game.player_score, game.ai_score),
+                (no_effects.player_score, no_effects.ai_score)
+            );
+
+            // The same seed gives the same effects
+            let mut a = ParticleSystem::new(10, 8);
+            let mut b = ParticleSystem::new(10, 8);
+            Emitter::new(ParticlePreset::SPARK, origin()).burst(&mut a);
+            Emitter::new(ParticlePreset::SPARK, origin()).burst(&mut b);
+            for (a, b) in a.iter().zip(b.iter()) {
+                assert_eq!(a.velocity, b.velocity);
+            }
+        }
+    }
+
+    // Post-processing tests: effect settings, settings files and the shader source
+    mod post_processing {
+        use pong::postfx::{self, PostFxSettings, FRAGMENT_SHADER, UNIFORMS, VERTEX_SHADER};
+        use pong::settings::Settings;
+
+        #[test]
+        fn effects_are_off_until_enabled() {
+            let defaults = PostFxSettings::default();
+            assert!(!defaults.enabled);
+            assert!(!defaults.is_active());
+
+            let enabled = PostFxSettings {
+                enabled: true,
+                ..defaults
+            };
+            assert!(enabled.is_active());
+
+            // Enabled with every strength at zero changes nothing, so the shader is skipped
+            let blank = PostFxSettings {
+                enabled: true,
+                bloom: 0.0,
+                scanlines: 0.0,
+                curvature: 0.0,
+                chromatic_aberration: 0.0,
+                vignette: 0.0,
+            };
+            assert!(!blank.is_active());
+        }
+
+        #[test]
+        fn strengths_are_clamped() {
+            let wild = PostFxSettings {
+                enabled: true,
+                bloom: 4.0,
+                scanlines: -1.0,
+                curvature: f32::NAN,
+                chromatic_aberration: f32::INFINITY,
+                vignette: 0.25,
+            };
+            let clamped = wild.clamped();
+            assert_eq!(clamped.bloom, 1.0);
+            assert_eq!(clamped.scanlines, 0.0);
+            assert_eq!(clamped.curvature, 0.0);
+            assert_eq!(clamped.chromatic_aberration, 0.0);
+            assert_eq!(clamped.vignette, 0.25);
+
+            postfx::set_settings(wild);
+            assert_eq!(postfx::settings(), clamped);
+        }
+
+        #[test]
+        fn effects_are_kept_in_the_settings_file() {
+            let path =
+                std::env::temp_dir().join(format!("pong_postfx_test_{}.json", std::process::id()));
+            let settings = Settings {
+                postfx: PostFxSettings {
+                    enabled: true,
+                    scanlines: 0.8,
+                    ..PostFxSettings::default()
+                },
+                ..Settings::default()
+            };
+            settings.write_to(&path).unwrap();
+            assert_eq!(Settings::load(&path).unwrap(), settings);
+
+            // Files from before post-processing, or with only some strengths, get the defaults
+            let old = Settings::from_json(r#"{"version": 1, "theme": "amber"}"#).unwrap();
+            assert_eq!(old.postfx, PostFxSettings::default());
+            let partial =
+                Settings::from_json(r#"{"version": 1, "postfx": {"enabled": true, "bloom": 0.1}}"#)
+                    .unwrap();
+            assert!(partial.postfx.enabled);
+            assert_eq!(partial.postfx.bloom, 0.1);
+            assert_eq!(partial.postfx.vignette, PostFxSettings::DEFAULT.vignette);
+        }
+
+        #[test]
+        fn shaders_declare_every_uniform() {
+            assert!(VERTEX_SHADER.starts_with("#version 100"));
+            assert!(FRAGMENT_SHADER.starts_with("#version 100"));
+            for (name, _) in UNIFORMS {
+                assert!(FRAGMENT_SHADER.contains(&format!(" {};", name)), "{}", name);
+            }
+        }
+    }
+
+    // Player profile tests: ratings, career stats, storage and migration
+    mod player_profiles {
+        use pong::profile::*;
+        use pong::*;
+
+        fn outcome(won: bool, difficulty: Difficulty) -> MatchOutcome {
+            MatchOutcome {
+                mode: MatchMode::VsAi,
+                difficulty: Some(difficulty),
+                won,
+                points_for: if won { 5 } else { 2 },
+                points_against: if won { 2 } else { 5 },
+                longest_rally: 7,
+                paddle_hits: 20,
+                time_played: 60.0,
+                opponent_rating: Some(ai_rating(difficulty)),
+            }
+        }
+
+        #[test]
+        fn ratings_follow_the_elo_formula() {
+            assert!((expected_score(1000.0, 1000.0) - 0.5).abs() < 1e-6);
+            assert!(
+                (expected_score(1200.0, 1000.0) + expected_score(1000.0, 1200.0) - 1.0).abs()
+                    < 1e-6
+            );
+
+            let mut profile = Profile::new("ada");
+            let change = profile.record(&outcome(true, Difficulty::Normal));
+            assert!((change - RATING_K / 2.0).abs() < 1e-4);
+            assert!((profile.rating - (INITIAL_RATING + RATING_K / 2.0)).abs() < 1e-4);
+
+            // Losing to a weaker opponent costs more than losing to a stronger one
+            let mut easy = Profile::new("a");
+            let mut hard = Profile::new("b");
+            let easy_loss = easy.record(&outcome(false, Difficulty::Easy));
+            let hard_loss = hard.record(&outcome(false, Difficulty::Hard));
+            assert!(easy_loss < hard_loss && hard_loss < 0.0);
+            assert_eq!(easy.peak_rating, INITIAL_RATING);
+
+            // Unrated opponents count towards the career but not the rating
+            let mut unrated = outcome(true, Difficulty::Hard);
+            unrated.opponent_rating = None;
+            let rating = profile.rating;
+            assert_eq!(profile.record(&unrated), 0.0);
+            assert_eq!((profile.rating, profile.matches), (rating, 2));
+        }
+
+        #[test]
+        fn career_stats_accumulate() {
+            let mut profile = Profile::new("ada");
+            for won in [true, true, false, true] {
+                profile.record(&outcome(won, Difficulty::Hard));
+            }
+            let mut lan = outcome(true, Difficulty::Easy);
+            lan.mode = MatchMode::Lan;
+            lan.difficulty = None;
+            lan.longest_rally = 12;
+            profile.record(&lan);
+
+            assert_eq!(profile.matches, 5);
+            assert_eq!(profile.wins, 4);
+            assert_eq!(profile.losses(), 1);
+            assert_eq!(profile.current_streak, 2);
+            assert_eq!(profile.best_streak, 2);
+            assert_eq!(profile.longest_rally, 12);
+            assert_eq!(profile.points_for, 5 + 5 + 2 + 5 + 5);
+            assert_eq!(profile.time_played, 300.0);
+            assert_eq!(
+                profile.by_difficulty[&Difficulty::Hard],
+                DifficultyRecord { played: 4, won: 3 }
+            );
+            assert!(!profile.by_difficulty.contains_key(&Difficulty::Easy));
+            assert_eq!(profile.favourite_mode(), Some(MatchMode::VsAi));
+
+            let rows = career_rows(&profile);
+            let row = |label: &str| rows.iter().find(|row| row.0 == label).unwrap().1.clone();
+            assert_eq!(row("Matches"), "5 (4 won, 1 lost, 80%)");
+            assert_eq!(row("vs hard AI"), "3 / 4 won (75%)");
+            assert_eq!(row("vs easy AI"), "0 / 0 won (-)");
+            assert_eq!(row("Favourite mode"), "vs AI");
+        }
+
+        #[test]
+        fn outcomes_are_taken_from_a_finished_game() {
+            let mut game = Game::with_seed(3);
+            game.player_score = 2;
+            game.ai_score = 5;
+            game.stats.longest_rally = 9;
+            game.stats.right.paddle_hits = 14;
+            game.stats.time_played = 42.0;
+
+            let outcome = MatchOutcome::from_game(&game, Side::Right, MatchMode::Netplay, None);
+            assert!(outcome.won);
+            assert_eq!((outcome.points_for, outcome.points_against), (5, 2));
+            assert_eq!(outcome.paddle_hits, 14);
+            assert_eq!(outcome.longest_rally, 9);
+            assert_eq!(outcome.opponent_rating, None);
+
+            let outcome =
+                MatchOutcome::from_game(&game, Side::Left, MatchMode::VsAi, Some(Difficulty::Hard));
+            assert!(!outcome.won);
+            assert_eq!(outcome.opponent_rating, Some(ai_rating(Difficulty::Hard)));
+
+            // Server matches arrive as statistics; a forfeit can be won while behind
+            game.stats.left.points = 2;
+            game.stats.right.points = 5;
+            let outcome =
+                MatchOutcome::from_stats(&game.stats, Side::Left, true, MatchMode::Online);
+            assert!(outcome.won);
+            assert_eq!((outcome.points_for, outcome.points_against), (2, 5));
+            assert_eq!(outcome.time_played, 42.0);
+            assert_eq!(outcome.opponent_rating, None);
+        }
+
+        #[test]
+        fn store_manages_profiles() {
+            let mut store = ProfileStore::default();
+            assert!(store.record(&outcome(true, Difficulty::Easy)).is_none());
+
+            store.create("  Ada ").unwrap();
+            assert_eq!(store.active().unwrap().name, "Ada");
+            assert!(store.create("ada").is_err());
+            assert!(store.create("   ").is_err());
+            assert!(store.create(&"x".repeat(MAX_NAME_LENGTH + 1)).is_err());
+
+            store.create("Grace").unwrap();
+            assert!(store.select("Ada"));
+            assert!(!store.select("Nobody"));
+            assert!(store.record(&outcome(true, Difficulty::Easy)).unwrap() > 0.0);
+            assert_eq!(store.get("Ada").unwrap().matches, 1);
+            assert_eq!(store.get("Grace").unwrap().matches, 0);
+
+            assert!(store.remove("Ada").is_some());
+            assert!(store.active.is_none());
+            assert_eq!(store.profiles.len(), 1);
+        }
+
+        #[test]
+        fn store_round_trips_and_rejects_unknown_versions() {
+            let mut store = ProfileStore::default();
+            store.create("Ada").unwrap();
+            store.record(&outcome(true, Difficulty::Learned));
+
+            let path = std::env::temp_dir()
+                .join(format!("pong_profiles_test_{}.json", std::process::id()));
+            store.write_to(&path).unwrap();
+            assert_eq!(ProfileStore::load(&path).unwrap(), store);
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(ProfileStore::load(&path).unwrap(), ProfileStore::default());
+
+            let mut value: serde_json::Value =
+                serde_json::from_str(&store.to_json().unwrap()).unwrap();
+            value["version"] = (PROFILES_VERSION + 1).into();
+            let err = ProfileStore::from_json(&value.to_string()).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+            value.as_object_mut().unwrap().remove("version");
+            assert!(migrate(value).is_err());
+        }
+
+        #[test]
+        fn version_1_profiles_gain_achievements() {
+            let json = r#"{
+        "version": 1,
+        "active": "Ada",
+        "profiles": [{
+            "name": "Ada", "rating": 1016.0, "peak_rating": 1016.0, "matches": 1, "wins": 1,
+            "current_streak": 1, "best_streak": 1, "longest_rally": 6, "points_for": 5,
+            "points_against": 1, "paddle_hits": 20, "time_played": 90.0,
+            "by_difficulty": {"normal": {"played": 1, "won": 1}}, "by_mode": {"vs_ai": 1}
+        }]
+    }"#;
+
+            let store = ProfileStore::from_json(json).unwrap();
+            assert_eq!(store.version, PROFILES_VERSION);
+            let profile = store.active().unwrap();
+            assert_eq!(profile.rating, 1016.0);
+            assert_eq!(profile.by_difficulty[&Difficulty::Normal].won, 1);
+            assert!(profile.achievements.is_empty());
+        }
+    }
+
+    // Replay tests: recording, exact playback with mods and the replay file
+    mod replays {
+        use pong::mods::ModConfig;
+        use pong::replay::{Replay, REPLAY_VERSION};
+        use pong::*;
+
+        // Play `steps` uneven frames against the built-in AI on both sides, recording them
+        fn recorded_match(mods: &[ModConfig], steps: usize) -> (Game, Replay) {
+            let mut game = Game::with_seed(42);
+            game.apply_mods(mods);
+            game.reset();
+            game.replay = Some(Replay::start(&game, mods));
+            for i in 0..steps {
+                let dt = [1.0 / 60.0, 1.0 / 144.0, 1.0 / 30.0][i % 3];
+                let left = game.ai_command(Side::Left);
+                let right = game.ai_command(Side::Right);
+                game.step(dt, left, right);
+            }
+            let replay = game.replay.take().unwrap();
+            (game, replay)
+        }
+
+        fn play_back(replay: &Replay) -> Game {
+            let mut game = replay.game();
+            for step in &replay.steps {
+                game.step(step.dt, step.left, step.right);
+            }
+            game
+        }
+
+        #[test]
+        fn steps_are_recorded_only_while_playing() {
+            let (mut game, replay) = recorded_match(&[], 300);
+            assert_eq!(replay.steps.len(), 300);
+            assert!(
+                (replay.duration() - 100.0 * (1.0 / 60.0 + 1.0 / 144.0 + 1.0 / 30.0)).abs() < 1e-3
+            );
+
+            game.replay = Some(Replay::start(&game, &[]));
+            game.game_state = GameState::GameOver;
+            game.step(0.1, PaddleCommand::Up, PaddleCommand::Up);
+            assert!(game.replay.unwrap().steps.is_empty());
+        }
+
+        #[test]
+        fn playback_reproduces_the_match() {
+            let (game, replay) = recorded_match(&[], 2000);
+            assert_eq!(play_back(&replay).snapshot(), game.snapshot());
+
+            // Mods are rebuilt for playback without applying their rule changes twice
+            let mods = [
+                ModConfig::GravityBall { strength: 200.0 },
+                ModConfig::ShrinkingPaddles {
+                    factor: 0.8,
+                    min_height: 30.0,
+                },
+            ];
+            let (game, replay) = recorded_match(&mods, 2000);
+            let played = play_back(&replay);
+            assert_eq!(played.snapshot(), game.snapshot());
+            assert_eq!(played.rules, game.rules);
+        }
+
+        #[test]
+        fn replays_round_trip_through_files() {
+            let (_, replay) = recorded_match(&[ModConfig::GravityBall { strength: 100.0 }], 120);
+            let path =
+                std::env::temp_dir().join(format!("pong_replay_test_{}.json", std::process::id()));
+            replay.write_to(&path).unwrap();
+            assert_eq!(Replay::read_from(&path).unwrap(), replay);
+            std::fs::remove_file(&path).unwrap();
+
+            let newer = replay.to_json().unwrap().replacen(
+                &format!("\"version\":{}", REPLAY_VERSION),
+                "\"version\":99",
+                1,
+            );
+            assert!(Replay::from_json(&newer).is_err());
+        }
+    }
+
+    // Scripting tests: controllers, game modes, the sandbox, errors and hot reload
+    mod scripting {
+        use pong::bot::Controller;
+        use pong::mods::{GameMod, ModConfig};
+        use pong::netplay::TICK_DT;
+        use pong::script::*;
+        use pong::*;
+        use std::path::{Path, PathBuf};
+
+        // A script file in a per-test temporary directory
+        fn write_script(test: &str, source: &str) -> PathBuf {
+            let dir = std::env::temp_dir().join(format!("pong_script_test_{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(format!("{}.rhai", test));
+            std::fs::write(&path, source).unwrap();
+            path
+        }
+
+        fn asset(name: &str) -> PathBuf {
+            Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("assets/scripts")
+                .join(name)
+        }
+
+        fn ball_at(game: &mut Game, y: f32, vx: f32) {
+            game.ball.position = Vec2D::new(WINDOW_WIDTH / 2.0, y - BALL_SIZE / 2.0);
+            game.ball.velocity = Vec2D::new(vx, 0.0);
+        }
+
+        #[test]
+        fn controllers_answer_with_words_or_numbers() {
+            let mut game = Game::with_seed(1);
+            let mut tracker = ScriptController::load(&asset("tracker.rhai"));
+            assert!(tracker.script.is_loaded(), "{:?}", tracker.script.error());
+
+            ball_at(&mut game, 50.0, 300.0);
+            assert_eq!(tracker.command(&game, Side::Right), PaddleCommand::Up);
+            ball_at(&mut game, WINDOW_HEIGHT - 50.0, 300.0);
+            assert_eq!(tracker.command(&game, Side::Right), PaddleCommand::Down);
+            // Moving away: head back to the middle, where the paddle already is
+            assert_eq!(tracker.command(&game, Side::Left), PaddleCommand::Stay);
+
+            let path = write_script(
+                "numbers",
+                "fn decide(state) { if state.side == \"left\" { -1 } else { 0.5 } }",
+            );
+            let mut numbers = ScriptController::load(&path);
+            assert_eq!(numbers.command(&game, Side::Left), PaddleCommand::Up);
+            assert_eq!(numbers.command(&game, Side::Right), PaddleCommand::Down);
+            assert!(numbers.status().is_none());
+        }
+
+        #[test]
+        fn scripts_are_sandboxed() {
+            let game = Game::with_seed(2);
+            let ball = game.ball.clone();
+
+            // The state is a copy, modules cannot be loaded and runaway loops are stopped
+            let path = write_script(
+                "meddling",
+                "fn decide(state) { state.ball.x = 0.0; \"up\" }",
+            );
+            let mut meddling = ScriptController::load(&path);
+            assert_eq!(meddling.command(&game, Side::Left), PaddleCommand::Up);
+            assert_eq!(game.ball, ball);
+
+            let path = write_script(
+                "import",
+                "fn decide(state) { import \"secrets\" as s; \"up\" }",
+            );
+            let mut import = ScriptController::load(&path);
+            assert_eq!(import.command(&game, Side::Left), PaddleCommand::Stay);
+            assert!(import.status().is_some());
+
+            let path = write_script("forever", "fn decide(state) { loop { } }");
+            let mut forever = ScriptController::load(&path);
+            assert_eq!(forever.command(&game, Side::Left), PaddleCommand::Stay);
+            assert!(forever.status().unwrap().contains("decide"));
+
+            // Controllers cannot act on the match
+            let path = write_script(
+                "actions",
+                "fn decide(state) { set_ball_velocity(0, 0); \"stay\" }",
+            );
+            let mut actions = ScriptController::load(&path);
+            actions.command(&game, Side::Left);
+            assert!(actions.script.take_actions().is_empty());
+        }
+
+        #[test]
+        fn errors_are_reported_instead_of_panicking() {
+            let game = Game::with_seed(3);
+            let path = write_script("broken", "fn decide(state) { \"up\" ");
+            let mut broken = ScriptController::load(&path);
+            assert!(!broken.script.is_loaded());
+            assert!(broken.status().is_some());
+            assert_eq!(broken.command(&game, Side::Left), PaddleCommand::Stay);
+
+            let path = write_script("runtime", "fn decide(state) { state.nothing.deeper }");
+            let mut runtime = ScriptController::load(&path);
+            assert_eq!(runtime.command(&game, Side::Left), PaddleCommand::Stay);
+            assert!(runtime.status().is_some());
+
+            let missing = ScriptController::load(Path::new("/nonexistent/pong.rhai"));
+            assert!(missing.status().unwrap().contains("cannot read"));
+        }
+
+        #[test]
+        fn scripts_reload_when_the_file_changes() {
+            let game = Game::with_seed(4);
+            let path = write_script("reload", "fn decide(state) { \"up\" }");
+            let mut controller = ScriptController::load(&path);
+            assert_eq!(controller.command(&game, Side::Left), PaddleCommand::Up);
+            assert!(!controller.script.reload_if_changed());
+
+            std::fs::write(&path, "fn decide(state) { \"down\" ").unwrap();
+            assert!(controller.script.reload_if_changed());
+            assert_eq!(controller.command(&game, Side::Left), PaddleCommand::Stay);
+            assert!(controller.status().is_some());
+
+            std::fs::write(&path, "fn decide(state) { \"down\" }").unwrap();
+            assert!(controller.script.reload_if_changed());
+            assert_eq!(controller.command(&game, Side::Left), PaddleCommand::Down);
+            assert!(controller.status().is_none());
+        }
+
+        #[test]
+        fn game_mode_scripts_change_the_match() {
+            let mut game = Game::with_seed(5);
+            game.apply_mods(&[ModConfig::Script {
+                path: asset("sudden_death.rhai"),
+            }]);
+            assert_eq!(game.rules.winning_score, 1);
+
+            // Returns leave the paddle faster
+            let centre = game.paddle(Side::Left).get_center_y();
+            game.ball.position = Vec2D::new(48.0, centre - BALL_SIZE / 2.0);
+            game.ball.velocity = Vec2D::new(-game.ball.speed, 0.0);
+            game.step(TICK_DT, PaddleCommand::Stay, PaddleCommand::Stay);
+            assert!((game.ball.velocity.x - game.ball.speed * 1.2).abs() < 0.01);
+
+            let path = write_script(
+        "paddles",
+        "fn on_goal(state, scorer) { set_paddle_height(scorer, 200); set_paddle_height(\"left\", -5) }",
+    );
+            let mut paddles = ScriptMod::load(&path);
+            paddles.on_goal(&mut game, Side::Right);
+            assert_eq!(game.paddle(Side::Right).height, 200.0);
+            assert_eq!(game.paddle(Side::Left).height, MIN_SCRIPT_PADDLE_HEIGHT);
+
+            // A failing hook leaves the match alone
+            let path = write_script("failing", "fn on_tick(state, dt) { throw \"oops\" }");
+            let mut failing = ScriptMod::load(&path);
+            let ball = game.ball.clone();
+            failing.on_tick(&mut game, TICK_DT);
+            assert_eq!(game.ball, ball);
+            assert!(failing.script.error().unwrap().contains("oops"));
+        }
+    }
+
+    // Dedicated server tests: lobby protocol, authoritative snapshots and client prediction
+    mod dedicated_server {
+        use pong::server::*;
+        use pong::*;
+        use std::time::{Duration, Instant};
+
+        const TIMEOUT: Duration = Duration::from_secs(5);
+
+        fn start_server() -> ServerHandle {
+            Server::bind("127.0.0.1:0").unwrap().spawn().unwrap()
+        }
+
+        fn connect(server: &ServerHandle, name: &str) -> ServerConnection {
+            let mut connection = ServerConnection::connect(server.local_addr()).unwrap();
+            connection
+                .send(&ClientMessage::Hello {
+                    name: name.to_owned(),
+                    rating: None,
+                })
+                .unwrap();
+            connection
+        }
+
+        // Wait for the first message matching `predicate`, skipping everything else
+        fn expect<F: Fn(&ServerMessage) -> bool>(
+            connection: &ServerConnection,
+            predicate: F,
+        ) -> ServerMessage {
+            let deadline = Instant::now() + TIMEOUT;
+            while Instant::now() < deadline {
+                if let Some(message) = connection.recv_timeout(Duration::from_millis(50)).unwrap() {
+                    if predicate(&message) {
+                        return message;
+                    }
+                }
+            }
+            panic!("timed out waiting for server message");
+        }
+
+        fn start_match(server: &ServerHandle, room: &str) -> (ServerConnection, ServerConnection) {
+            let mut host = connect(server, "host");
+            let mut guest = connect(server, "guest");
+
+            host.send(&ClientMessage::CreateRoom {
+                room: room.to_owned(),
+            })
+            .unwrap();
+            expect(&host, |m| {
+                matches!(
+                    m,
+                    ServerMessage::Joined {
+                        side: Side::Left,
+                        ..
+                    }
+                )
+            });
+            guest
+                .send(&ClientMessage::JoinRoom {
+                    room: room.to_owned(),
+                })
+                .unwrap();
+            expect(&guest, |m| {
+                matches!(
+                    m,
+                    ServerMessage::Joined {
+                        side: Side::Right,
+                        ..
+                    }
+                )
+            });
+
+            host.send(&ClientMessage::Ready).unwrap();
+            guest.send(&ClientMessage::Ready).unwrap();
+            expect(&host, |m| matches!(m, ServerMessage::MatchStart { .. }));
+            expect(&guest, |m| matches!(m, ServerMessage::MatchStart { .. }));
+            (host, guest)
+        }
+
+        #[test]
+        fn lobby_lists_rooms_and_rejects_bad_joins() {
+            let server = start_server();
+            let mut host = connect(&server, "host");
+            let mut other = connect(&server, "other");
+            let mut third = connect(&server, "third");
+
+            other
+                .send(&ClientMessage::JoinRoom {
+                    room: "missing".to_owned(),
+                })
+                .unwrap();
+            expect(&other, |m| matches!(m, ServerMessage::Error { .. }));
+
+            host.send(&ClientMessage::CreateRoom {
+                room: "ladder".to_owned(),
+            })
+            .unwrap();
+            expect(&host, |m| matches!(m, ServerMessage::Joined { .. }));
+            other
+                .send(&ClientMessage::JoinRoom {
+                    room: "ladder".to_owned(),
+                })
+                .unwrap();
+            let update = expect(
+                &host,
+                |m| matches!(m, ServerMessage::RoomUpdate { players, .. } if players.len() == 2),
+            );
+            if let ServerMessage::RoomUpdate { players, .. } = update {
+                assert_eq!(players[0].name, "host");
+                assert_eq!(players[1].name, "other");
+            }
+
+            third
+                .send(&ClientMessage::JoinRoom {
+                    room: "ladder".to_owned(),
+                })
+                .unwrap();
+            expect(&third, |m| matches!(m, ServerMessage::Error { .. }));
+
+            third.send(&ClientMessage::ListRooms).unwrap();
+            let list = expect(&third, |m| matches!(m, ServerMessage::RoomList { .. }));
+            assert_eq!(
+                list,
+                ServerMessage::RoomList {
+                    rooms: vec![RoomInfo {
+                        name: "ladder".to_owned(),
+                        players: 2,
+                        in_match: false,
+                    }]
+                }
+            );
+        }
+
+        #[test]
+        fn server_applies_client_inputs_to_authoritative_state() {
+            let server = start_server();
+            let (mut host, _guest) = start_match(&server, "inputs");
+
+            let first = expect(&host, |m| matches!(m, ServerMessage::Snapshot { .. }));
+            let start_y = match first {
+                ServerMessage::Snapshot { state, .. } => state.left_paddle_y,
+                _ => unreachable!(),
+            };
+
+            for tick in 1..=30 {
+                host.send(&ClientMessage::Input {
+                    tick,
+                    command: PaddleCommand::Up,
+                })
+                .unwrap();
+            }
+
+            let moved = expect(
+                &host,
+                |m| matches!(m, ServerMessage::Snapshot { state, last_input } if *last_input == 30 && state.left_paddle_y < start_y),
+            );
+            if let ServerMessage::Snapshot { state, .. } = moved {
+                assert!(state.tick > 0);
+                assert_eq!(
+                    state.right_paddle_y,
+                    WINDOW_HEIGHT / 2.0 - PADDLE_HEIGHT / 2.0
+                );
+            }
+        }
+
+        #[test]
+        fn inputs_arriving_together_are_each_stepped_before_being_acknowledged() {
+            let server = start_server();
+            let (mut host, _guest) = start_match(&server, "burst");
+            let start_y = WINDOW_HEIGHT / 2.0 - PADDLE_HEIGHT / 2.0;
+            let step = RuleSet::default().paddle_speed * pong::netplay::TICK_DT;
+
+            // Far more inputs than ticks pass while they are sent, plus a stale duplicate
+            for tick in 1..=20 {
+                host.send(&ClientMessage::Input {
+                    tick,
+                    command: PaddleCommand::Up,
+                })
+                .unwrap();
+            }
+            host.send(&ClientMessage::Input {
+                tick: 5,
+                command: PaddleCommand::Down,
+            })
+            .unwrap();
+
+            // Every acknowledged input has moved the paddle exactly once, and nothing else has
+            let mut acknowledged = 0;
+            while acknowledged < 20 {
+                if let ServerMessage::Snapshot { state, last_input } =
+                    expect(&host, |m| matches!(m, ServerMessage::Snapshot { .. }))
+                {
+                    assert!(last_input >= acknowledged);
+                    let expected = start_y - last_input as f32 * step;
+                    assert!(
+                        (state.left_paddle_y - expected).abs() < 0.01,
+                        "input {} left the paddle at {}",
+                        last_input,
+                        state.left_paddle_y
+                    );
+                    acknowledged = last_input;
+                }
+            }
+        }
+
+        #[test]
+        fn leaving_mid_match_forfeits_to_the_opponent() {
+            let server = start_server();
+            let (mut host, guest) = start_match(&server, "forfeit");
+
+            host.send(&ClientMessage::Leave).unwrap();
+            let end = expect(&guest, |m| matches!(m, ServerMessage::MatchEnd { .. }));
+            match end {
+                ServerMessage::MatchEnd { winner, stats } => {
+                    assert_eq!(winner, Side::Right);
+                    // The guest records the forfeit win from the stats of the abandoned match
+                    let outcome = profile::MatchOutcome::from_stats(
+                        &stats,
+                        Side::Right,
+                        true,
+                        profile::MatchMode::Online,
+                    );
+                    assert!(outcome.won);
+                    assert_eq!(outcome.mode.name(), "Online");
+                }
+                other => panic!("expected MatchEnd, got {:?}", other),
+            }
+        }
+
+        #[test]
+        fn room_updates_share_player_ratings() {
+            let server = start_server();
+            let mut rated = ServerConnection::connect(server.local_addr()).unwrap();
+            rated
+                .send(&ClientMessage::Hello {
+                    name: "rated".to_owned(),
+                    rating: Some(1234.0),
+                })
+                .unwrap();
+            rated
+                .send(&ClientMessage::CreateRoom {
+                    room: "ratings".to_owned(),
+                })
+                .unwrap();
+            let mut unrated = connect(&server, "unrated");
+            unrated
+                .send(&ClientMessage::JoinRoom {
+                    room: "ratings".to_owned(),
+                })
+                .unwrap();
+
+            let update = expect(
+                &rated,
+                |m| matches!(m, ServerMessage::RoomUpdate { players, .. } if players.len() == 2),
+            );
+            let ServerMessage::RoomUpdate { players, .. } = update else {
+                unreachable!()
+            };
+            let rating = |name: &str| {
+                players
+                    .iter()
+                    .find(|player| player.name == name)
+                    .map(|player| player.rating)
+            };
+            assert_eq!(rating("rated"), Some(Some(1234.0)));
+            // Players without a profile stay unrated, so matches against them leave ratings alone
+            assert_eq!(rating("unrated"), Some(None));
+        }
+
+        fn snapshot(tick: u32, ball_x: f32, left_y: f32, right_y: f32) -> StateSnapshot {
+            StateSnapshot {
+                tick,
+                ball_position: Vec2D::new(ball_x, 300.0),
+                ball_velocity: Vec2D::new(100.0, 0.0),
+                left_paddle_y: left_y,
+                right_paddle_y: right_y,
+                left_score: 0,
+                right_score: 0,
+                game_state: GameState::Playing,
+                rally_length: 0,
+            }
+        }
+
+        #[test]
+        fn prediction_replays_unacknowledged_inputs() {
+            let rules = RuleSet::default();
+            let mut predictor = ClientPredictor::new(Side::Left, rules);
+
+            for _ in 0..10 {
+                predictor.record_input(PaddleCommand::Down);
+            }
+            let step = rules.paddle_speed * pong::netplay::TICK_DT;
+
+            // The server has applied only the first four inputs
+            predictor.apply_snapshot(snapshot(4, 400.0, 200.0, 260.0), 4, 0.0);
+
+            assert!((predictor.predicted_paddle_y() - (200.0 + 6.0 * step)).abs() < 0.001);
+        }
+
+        #[test]
+        fn remote_objects_are_interpolated_between_snapshots() {
+            let mut predictor = ClientPredictor::new(Side::Left, RuleSet::default());
+            predictor.apply_snapshot(snapshot(3, 100.0, 250.0, 100.0), 0, 1.0);
+            predictor.apply_snapshot(snapshot(6, 200.0, 250.0, 200.0), 0, 1.05);
+
+            // Rendering runs INTERPOLATION_DELAY behind, halfway between the two snapshots
+            let state = predictor.render_state(1.025 + INTERPOLATION_DELAY).unwrap();
+
+            assert!((state.ball_position.x - 150.0).abs() < 0.01);
+            assert!((state.right_paddle_y - 150.0).abs() < 0.01);
+            assert_eq!(state.left_paddle_y, predictor.predicted_paddle_y());
+        }
+    }
+
+    // Batch simulation tests: player specs, determinism across threads and statistics
+    mod batch_simulation {
+        use pong::sim::*;
+        use pong::*;
+
+        fn quick_config(threads: usize) -> SimConfig {
+            SimConfig {
+                matches: 24,
+                seed: 100,
+                threads,
+                rules: RuleSet {
+                    winning_score: 2,
+                    ..RuleSet::default()
+                },
+                ..SimConfig::default()
+            }
+        }
+
+        #[test]
+        fn player_specs_parse_and_round_trip() {
+            assert_eq!("ai".parse(), Ok(PlayerSpec::Ai));
+            assert_eq!("idle".parse(), Ok(PlayerSpec::Idle));
+            assert_eq!(
+                "ai:0.5:4".parse(),
+                Ok(PlayerSpec::Handicapped(AiSettings {
+                    speed_factor: 0.5,
+                    dead_zone: 4.0
+                }))
+            );
+            assert_eq!(
+                "bot:python3 bot.py".parse(),
+                Ok(PlayerSpec::Bot("python3 bot.py".to_owned()))
+            );
+            assert!("ai:1.5".parse::<PlayerSpec>().is_err());
+            assert!("human".parse::<PlayerSpec>().is_err());
+
+            let spec: PlayerSpec = "ai:0.25".parse().unwrap();
+            assert_eq!(spec.to_string().parse(), Ok(spec));
+        }
+
+        #[test]
+        fn results_do_not_depend_on_thread_count() {
+            let serial = run_batch(&quick_config(1)).unwrap();
+            let parallel = run_batch(&quick_config(4)).unwrap();
+
+            assert_eq!(serial.len(), 24);
+            assert_eq!(serial, parallel);
+            assert!(serial
+                .iter()
+                .enumerate()
+                .all(|(i, record)| record.index == i as u32));
+        }
+
+        #[test]
+        fn players_alternate_sides() {
+            let records = run_batch(&quick_config(2)).unwrap();
+            assert_eq!(records[0].a_side, Side::Left);
+            assert_eq!(records[1].a_side, Side::Right);
+            assert_eq!(records[1].seed, 101);
+        }
+
+        #[test]
+        fn idle_player_loses_every_match() {
+            let config = SimConfig {
+                player_a: PlayerSpec::Ai,
+                player_b: PlayerSpec::Idle,
+                ..quick_config(2)
+            };
+            let records = run_batch(&config).unwrap();
+            let summary = Summary::from_records(&records);
+
+            assert_eq!(summary.a_wins, 24);
+            assert_eq!(summary.a_win_rate.value, 1.0);
+            assert!(summary.a_win_rate.low > 0.8);
+            assert_eq!(summary.points_per_match.value, 2.0);
+            assert!(records.iter().all(|record| record.rallies.len() == 2));
+        }
+
+        #[test]
+        fn matches_past_the_tick_limit_are_unfinished() {
+            let config = SimConfig {
+                max_ticks: 10,
+                ..quick_config(1)
+            };
+            let records = run_batch(&config).unwrap();
+            let summary = Summary::from_records(&records);
+
+            assert_eq!(summary.unfinished, 24);
+            assert!(records
+                .iter()
+                .all(|record| record.winner.is_none() && record.ticks == 10));
+        }
+
+        #[test]
+        fn confidence_intervals_bracket_the_estimate() {
+            let rate = Estimate::proportion(30, 100);
+            assert!((rate.value - 0.3).abs() < 1e-9);
+            assert!(rate.low < 0.3 && rate.high > 0.3);
+            assert!((rate.low - 0.219).abs() < 0.01 && (rate.high - 0.396).abs() < 0.01);
+
+            let mean = Estimate::mean(&[2.0, 4.0, 6.0, 8.0]);
+            assert_eq!(mean.value, 5.0);
+            assert!((mean.high - mean.value - 1.96 * (20.0f64 / 3.0 / 4.0).sqrt()).abs() < 1e-9);
+        }
+
+        #[test]
+        fn reports_are_written_as_csv_and_json() {
+            let config = quick_config(2);
+            let records = run_batch(&config).unwrap();
+            let summary = Summary::from_records(&records);
+
+            let mut csv = Vec::new();
+            write_csv(&mut csv, &records).unwrap();
+            let csv = String::from_utf8(csv).unwrap();
+            assert_eq!(csv.lines().count(), 25);
+            assert!(csv.starts_with("index,seed,a_side,winner"));
+
+            let mut json = Vec::new();
+            write_json(&mut json, &config, &summary, &records).unwrap();
+            let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+            assert_eq!(json["matches"].as_array().unwrap().len(), 24);
+            assert_eq!(json["summary"]["matches"], 24);
+            assert_eq!(json["player_a"], "ai:0.5:10");
+        }
+
+        #[test]
+        fn arguments_are_parsed() {
+            let args = [
+                "-a",
+                "idle",
+                "-n",
+                "50",
+                "--format",
+                "csv",
+                "--threads",
+                "3",
+                "--hit-speedup",
+                "1.1",
+            ];
+            let options = parse_sim_args(args.iter().map(|arg| arg.to_string())).unwrap();
+            assert_eq!(options.config.player_a, PlayerSpec::Idle);
+            assert_eq!(options.config.matches, 50);
+            assert_eq!(options.config.threads, 3);
+            assert_eq!(options.config.rules.hit_speedup, 1.1);
+            assert_eq!(options.config.rules.max_speedup, MAX_SPEEDUP);
+            assert_eq!(options.format, OutputFormat::Csv);
+
+            assert!(parse_sim_args(["--format".to_owned(), "xml".to_owned()]).is_err());
+            assert!(parse_sim_args(["--threads".to_owned(), "0".to_owned()]).is_err());
+            assert!(parse_sim_args(["--max-speedup".to_owned(), "0.5".to_owned()]).is_err());
+            assert!(parse_sim_args(["--max-speedup".to_owned(), "inf".to_owned()]).is_err());
+            assert!(parse_sim_args(["--hit-speedup".to_owned(), "NaN".to_owned()]).is_err());
+            assert!(parse_sim_args(["--hit-speedup".to_owned(), "11".to_owned()]).is_err());
+        }
+    }
+
+    // Spectator stream tests: keyframes, deltas, late joiners and server rooms
+    mod spectator_stream {
+        use pong::server::*;
+        use pong::spectator::*;
+        use pong::*;
+        use std::time::{Duration, Instant};
+
+        fn state(tick: u32, left_score: i32) -> StateSnapshot {
+            StateSnapshot {
+                tick,
+                ball_position: Vec2D::new(100.0 + tick as f32, 300.0),
+                ball_velocity: Vec2D::new(350.0, 0.0),
+                left_paddle_y: 260.0,
+                right_paddle_y: 260.0,
+                left_score,
+                right_score: 0,
+                game_state: GameState::Playing,
+                rally_length: tick / 10,
+            }
+        }
+
+        fn info() -> MatchInfo {
+            MatchInfo {
+                left_name: "alice".to_owned(),
+                right_name: "bob".to_owned(),
+            }
+        }
+
+        #[test]
+        fn rally_length_counts_paddle_hits_and_resets_on_goal() {
+            let mut game = Game::with_seed(3);
+            game.ball.position =
+                Vec2D::new(game.player_paddle.position.x + PADDLE_WIDTH + 1.0, 300.0);
+            game.ball.velocity = Vec2D::new(-BALL_SPEED, 0.0);
+            game.player_paddle.position.y = 300.0 - PADDLE_HEIGHT / 2.0;
+
+            game.step(0.016, PaddleCommand::Stay, PaddleCommand::Stay);
+            assert_eq!(game.rally_length, 1);
+
+            game.ball.position = Vec2D::new(WINDOW_WIDTH + 10.0, 300.0);
+            game.ball.velocity = Vec2D::new(BALL_SPEED, 0.0);
+            game.step(0.016, PaddleCommand::Stay, PaddleCommand::Stay);
+            assert_eq!(game.rally_length, 0);
+        }
+
+        #[test]
+        fn feed_sends_keyframe_then_deltas_until_score_changes() {
+            let mut feed = SpectatorFeed::new(info());
+
+            assert!(matches!(
+                feed.encode(&state(1, 0)),
+                SpectatorMessage::Keyframe { .. }
+            ));
+            assert!(matches!(
+                feed.encode(&state(2, 0)),
+                SpectatorMessage::Delta { .. }
+            ));
+            assert!(matches!(
+                feed.encode(&state(3, 1)),
+                SpectatorMessage::Keyframe { .. }
+            ));
+        }
+
+        #[test]
+        fn feed_repeats_keyframes_periodically() {
+            let mut feed = SpectatorFeed::new(info());
+            let keyframes = (0..KEYFRAME_INTERVAL * 3)
+                .filter(|&tick| {
+                    matches!(
+                        feed.encode(&state(tick, 0)),
+                        SpectatorMessage::Keyframe { .. }
+                    )
+                })
+                .count();
+            assert_eq!(keyframes, 3);
+        }
+
+        #[test]
+        fn view_ignores_deltas_until_first_keyframe() {
+            let mut feed = SpectatorFeed::new(info());
+            let keyframe = feed.encode(&state(1, 0));
+            let delta = feed.encode(&state(2, 0));
+
+            let mut view = SpectatorView::new();
+            view.apply(delta.clone(), 0.0);
+            assert!(view.state().is_none());
+
+            view.apply(keyframe, 0.0);
+            view.apply(delta, 0.0);
+            assert_eq!(view.state(), Some(&state(2, 0)));
+            assert_eq!(view.info, Some(info()));
+        }
+
+        #[test]
+        fn late_joiner_catches_up_from_a_keyframe() {
+            let mut broadcaster = SpectatorBroadcaster::bind("127.0.0.1:0", info()).unwrap();
+            for tick in 0..20 {
+                broadcaster.publish(&state(tick, 0));
+            }
+
+            let client = SpectatorClient::connect(broadcaster.local_addr().unwrap()).unwrap();
+            let mut view = SpectatorView::new();
+            let deadline = Instant::now() + Duration::from_secs(5);
+            let mut tick = 20;
+            while view.state().is_none_or(|state| state.tick < 25) && Instant::now() < deadline {
+                broadcaster.publish(&state(tick, 0));
+                tick += 1;
+                while let Some(message) = client.recv_timeout(Duration::from_millis(10)) {
+                    view.apply(message, 0.0);
+                }
+            }
+
+            assert_eq!(broadcaster.spectator_count(), 1);
+            let latest = view.state().expect("spectator never received a keyframe");
+            assert!(latest.tick >= 25);
+            assert_eq!(latest.ball_position.x, 100.0 + latest.tick as f32);
+            assert_eq!(view.info, Some(info()));
+        }
+
+        #[test]
+        fn spectators_that_never_read_are_dropped() {
+            let mut broadcaster = SpectatorBroadcaster::bind("127.0.0.1:0", info()).unwrap();
+            broadcaster.publish(&state(0, 0));
+            let _stalled = std::net::TcpStream::connect(broadcaster.local_addr().unwrap()).unwrap();
+
+            // Once the socket buffers fill, frames queue up until the spectator is cut off
+            let deadline = Instant::now() + Duration::from_secs(10);
+            let mut tick = 1;
+            let mut joined = false;
+            while Instant::now() < deadline {
+                broadcaster.publish(&state(tick, 0));
+                tick += 1;
+                joined |= broadcaster.spectator_count() == 1;
+                if joined && broadcaster.spectator_count() == 0 {
+                    break;
+                }
+            }
+            assert!(joined, "the spectator never joined");
+            assert_eq!(
+                broadcaster.spectator_count(),
+                0,
+                "gave up after {} frames",
+                tick
+            );
+        }
+
+        // Wait for the first message matching `predicate`, skipping everything else
+        fn expect<F: Fn(&ServerMessage) -> bool>(
+            connection: &ServerConnection,
+            predicate: F,
+        ) -> ServerMessage {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline {
+                if let Some(message) = connection.recv_timeout(Duration::from_millis(50)).unwrap() {
+                    if predicate(&message) {
+                        return message;
+                    }
+                }
+            }
+            panic!("timed out waiting for server message");
+        }
+
+        fn connect(server: &ServerHandle, name: &str) -> ServerConnection {
+            let mut connection = ServerConnection::connect(server.local_addr()).unwrap();
+            connection
+                .send(&ClientMessage::Hello {
+                    name: name.to_owned(),
+                    rating: None,
+                })
+                .unwrap();
+            connection
+        }
+
+        #[test]
+        fn server_streams_room_to_spectators() {
+            let server = Server::bind("127.0.0.1:0").unwrap().spawn().unwrap();
+            let mut host = connect(&server, "alice");
+            let mut guest = connect(&server, "bob");
+
+            host.send(&ClientMessage::CreateRoom {
+                room: "final".to_owned(),
+            })
+            .unwrap();
+            expect(&host, |m| matches!(m, ServerMessage::Joined { .. }));
+            guest
+                .send(&ClientMessage::JoinRoom {
+                    room: "final".to_owned(),
+                })
+                .unwrap();
+            expect(&guest, |m| matches!(m, ServerMessage::Joined { .. }));
+            host.send(&ClientMessage::Ready).unwrap();
+            guest.send(&ClientMessage::Ready).unwrap();
+            expect(&host, |m| matches!(m, ServerMessage::MatchStart { .. }));
+
+            // Join after the match has started
+            let mut watcher = connect(&server, "carol");
+            watcher
+                .send(&ClientMessage::Spectate {
+                    room: "final".to_owned(),
+                })
+                .unwrap();
+            expect(&watcher, |m| matches!(m, ServerMessage::Spectating { .. }));
+            let first = expect(&watcher, |m| matches!(m, ServerMessage::Spectator { .. }));
+            match first {
+                ServerMessage::Spectator {
+                    message:
+                        SpectatorMessage::Keyframe {
+                            info: match_info, ..
+                        },
+                } => assert_eq!(match_info, info()),
+                other => panic!("expected a keyframe first, got {:?}", other),
+            }
+
+            // A spectator leaving does not end the match; a player leaving does
+            watcher.send(&ClientMessage::Leave).unwrap();
+            host.send(&ClientMessage::Leave).unwrap();
+            let end = expect(&guest, |m| matches!(m, ServerMessage::MatchEnd { .. }));
+            assert!(matches!(
+                end,
+                ServerMessage::MatchEnd {
+                    winner: Side::Right,
+                    ..
+                }
+            ));
+        }
+    }
+
+    // Match statistics tests: hit classification, scoring, whole matches and export
+    mod match_statistics {
+        use super::hit_left_paddle;
+        use pong::bot::{play_headless, BuiltinAi, HandicappedAi, MAX_MATCH_TICKS};
+        use pong::netplay::TICK_DT;
+        use pong::stats::*;
+        use pong::*;
+
+        #[test]
+        fn paddle_hits_are_split_into_centre_and_edge() {
+            let mut game = Game::with_seed(1);
+            hit_left_paddle(&mut game, 5.0);
+            hit_left_paddle(&mut game, -35.0);
+            hit_left_paddle(&mut game, 30.0);
+
+            assert_eq!(game.stats.left.paddle_hits, 3);
+            assert_eq!(game.stats.left.centre_hits, 1);
+            assert_eq!(game.stats.left.edge_hits, 2);
+            assert_eq!(game.stats.right, SideStats::default());
+            assert!(game.stats.time_played > 0.0);
+            assert!(game.stats.max_ball_speed >= game.rules.ball_speed - 0.01);
+        }
+
+        #[test]
+        fn points_record_rallies_and_serves() {
+            let mut game = Game::with_seed(2);
+            hit_left_paddle(&mut game, 0.0);
+            hit_left_paddle(&mut game, 0.0);
+            let server = game.stats.server;
+
+            // Push the ball out on the right: a point for the left side after a two-hit rally
+            game.ball.position = Vec2D::new(WINDOW_WIDTH - 1.0, WINDOW_HEIGHT / 2.0);
+            game.ball.velocity = Vec2D::new(game.ball.speed, 0.0);
+            game.step(TICK_DT, PaddleCommand::Stay, PaddleCommand::Stay);
+
+            assert_eq!(game.stats.rallies, 1);
+            assert_eq!(game.stats.longest_rally, 2);
+            assert_eq!(game.stats.left.points, 1);
+            let on_serve = u32::from(server == Some(Side::Left));
+            assert_eq!(game.stats.left.points_on_serve, on_serve);
+
+            // The next point's server follows the new serve direction
+            let expected = if game.ball.velocity.x > 0.0 {
+                Side::Left
+            } else {
+                Side::Right
+            };
+            assert_eq!(game.stats.server, Some(expected));
+
+            game.reset();
+            assert_eq!(game.stats.rallies, 0);
+            assert_eq!(game.stats.left, SideStats::default());
+        }
+
+        #[test]
+        fn whole_match_statistics_add_up() {
+            let mut game = Game::with_seed(11);
+            game.ai_side = None;
+            let mut left = BuiltinAi;
+            let mut right = HandicappedAi::new(
+                AiSettings {
+                    speed_factor: 0.4,
+                    ..AiSettings::default()
+                },
+                11,
+            );
+            play_headless(&mut game, &mut left, &mut right, MAX_MATCH_TICKS)
+                .expect("match finishes");
+
+            let stats = &game.stats;
+            assert_eq!(stats.rallies, (game.player_score + game.ai_score) as u32);
+            assert_eq!(stats.left.points, game.player_score as u32);
+            assert_eq!(stats.right.points, game.ai_score as u32);
+            for side in [Side::Left, Side::Right] {
+                let side = stats.side(side);
+                assert_eq!(side.edge_hits + side.centre_hits, side.paddle_hits);
+                assert!(side.points_on_serve <= side.points);
+            }
+            assert!(stats.longest_rally as f32 >= stats.average_rally());
+            assert!(stats.total_hits() > 0);
+        }
+
+        #[test]
+        fn statistics_survive_save_and_restore() {
+            let mut game = Game::with_seed(3);
+            hit_left_paddle(&mut game, 0.0);
+            let state = game.snapshot();
+
+            let mut restored = Game::with_seed(99);
+            restored.restore(pong::save::SaveState::from_json(&state.to_json().unwrap()).unwrap());
+            assert_eq!(restored.stats, game.stats);
+        }
+
+        #[test]
+        fn statistics_export_to_json() {
+            let mut game = Game::with_seed(4);
+            hit_left_paddle(&mut game, 36.0);
+
+            let dir = std::env::temp_dir().join(format!("pong_stats_test_{}", std::process::id()));
+            let path = game.stats.export(&dir).unwrap();
+            // A second export straight away gets its own file, and its own events file
+            let again = game.stats.export(&dir).unwrap();
+            assert_ne!(again, path);
+            assert_ne!(
+                pong::analytics::events_path(&again),
+                pong::analytics::events_path(&path)
+            );
+            let json = std::fs::read_to_string(&path).unwrap();
+            assert_eq!(std::fs::read_to_string(&again).unwrap(), json);
+            std::fs::remove_dir_all(&dir).unwrap();
+
+            let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(value["left"]["edge_hits"], 1);
+            assert!(value["time_played"].as_f64().unwrap() > 0.0);
+            let parsed: MatchStats = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed, game.stats);
+        }
+
+        #[test]
+        fn summary_rows_format_values() {
+            let stats = MatchStats {
+                rallies: 4,
+                longest_rally: 9,
+                time_played: 125.4,
+                ..MatchStats::default()
+            };
+            let rows = summary_rows(&stats);
+            let row = |label: &str| rows.iter().find(|row| row.0 == label).unwrap().clone();
+
+            assert_eq!(row("Time played").1, "2:05");
+            assert_eq!(row("Longest rally").1, "9");
+            assert_eq!(
+                row("Paddle hits"),
+                ("Paddle hits", "0".to_owned(), "0".to_owned())
+            );
+            assert_eq!(format_duration(59.9), "0:59");
+        }
+    }
+
+    // Text and HUD tests: the pixel font, measuring, alignment and anchored layout
+    mod text_and_hud {
+        use super::args;
+        use macroquad::prelude::*;
+        use pong::cli::parse_args;
+        use pong::hud::{format_clock, Anchor, Layout, HINT_SIZE, LINE_GAP, MIN_TEXT_PIXELS};
+        use pong::settings::Settings;
+        use pong::text::{self, Align, PIXELS_PER_EM};
+        use pong::view::Viewport;
+        use pong::{WINDOW_HEIGHT, WINDOW_WIDTH};
+        use std::io::ErrorKind;
+
+        fn close(a: f32, b: f32) -> bool {
+            (a - b).abs() < 0.001
+        }
+
+        #[test]
+        fn the_pixel_font_covers_printable_ascii() {
+            assert_eq!(text::pixel_glyph(' '), [0; 8]);
+            for c in '!'..='~' {
+                let glyph = text::pixel_glyph(c);
+                assert!(glyph.iter().any(|&row| row != 0), "{:?} is blank", c);
+                assert!(glyph.iter().all(|&row| row < 0x20), "{:?} is too wide", c);
+            }
+            // Only commas and the like reach below the baseline
+            assert_eq!(text::pixel_glyph('A')[7], 0);
+            assert_ne!(text::pixel_glyph(',')[7], 0);
+            assert_eq!(text::pixel_glyph('é'), text::pixel_glyph('?'));
+        }
+
+        #[test]
+        fn text_is_measured_and_aligned_exactly() {
+            // With the pixel font a size of PIXELS_PER_EM draws one unit per pixel
+            let one = text::measure("A", PIXELS_PER_EM);
+            assert_eq!((one.width, one.height, one.offset_y), (5.0, 7.0, 7.0));
+            assert_eq!(text::measure("AB", PIXELS_PER_EM).width, 11.0);
+            assert_eq!(text::measure("", 20.0).width, 0.0);
+            let double = text::measure("Pong", PIXELS_PER_EM * 2.0);
+            assert_eq!(
+                double.width,
+                2.0 * text::measure("Pong", PIXELS_PER_EM).width
+            );
+
+            let width = text::measure("centred", 20.0).width;
+            assert!(close(
+                text::aligned_x("centred", 400.0, 20.0, Align::Center),
+                400.0 - width / 2.0
+            ));
+            assert!(close(
+                text::aligned_x("centred", 400.0, 20.0, Align::Right),
+                400.0 - width
+            ));
+            assert_eq!(text::aligned_x("centred", 400.0, 20.0, Align::Left), 400.0);
+
+            assert!(matches!(text::load("pixel"), Ok(text::TextFont::Pixel)));
+            assert_eq!(
+                text::load("comic").err().unwrap().kind(),
+                ErrorKind::InvalidInput
+            );
+            assert_eq!(
+                text::load("/no/such/font.ttf").err().unwrap().kind(),
+                ErrorKind::NotFound
+            );
+        }
+
+        #[test]
+        fn anchors_place_text_inside_the_margins() {
+            let layout = Layout::new(&Viewport::fit(WINDOW_WIDTH, WINDOW_HEIGHT));
+            let dimensions = text::measure("Score 10", 24.0);
+            let margin = layout.margin;
+
+            let top_left = layout.place(Anchor::TopLeft, dimensions, Vec2::ZERO);
+            assert_eq!(top_left, vec2(margin, margin + dimensions.offset_y));
+
+            // Centred text has equal space either side, whatever the string
+            let center = layout.place(Anchor::Center, dimensions, Vec2::ZERO);
+            assert!(close(center.x * 2.0 + dimensions.width, WINDOW_WIDTH));
+            let top = center.y - dimensions.offset_y;
+            assert!(close(top * 2.0 + dimensions.height, WINDOW_HEIGHT));
+
+            let bottom_right = layout.place(Anchor::BottomRight, dimensions, vec2(0.0, -30.0));
+            assert!(close(
+                bottom_right.x + dimensions.width,
+                WINDOW_WIDTH - margin
+            ));
+            assert!(close(
+                bottom_right.y - dimensions.offset_y + dimensions.height,
+                WINDOW_HEIGHT - margin - 30.0
+            ));
+        }
+
+        #[test]
+        fn lines_stack_evenly_and_columns_fit_their_widest_text() {
+            let layout = Layout::new(&Viewport::fit(WINDOW_WIDTH, WINDOW_HEIGHT));
+
+            // Every line of one size is as tall as any other, whatever its letters
+            let short = layout.line_box("a", 40.0, 100.0, 20.0, Align::Left);
+            let tall = layout.line_box("Wj|", 40.0, 100.0, 20.0, Align::Left);
+            assert_eq!(short.h, tall.h);
+            assert_eq!(short.h, layout.line_height(20.0));
+            assert_eq!(
+                layout.line_box("", 40.0, 100.0, 20.0, Align::Left).h,
+                short.h
+            );
+
+            // Right-aligned values end on the column's edge
+            let value = layout.line_box("1234", 500.0, 100.0, 20.0, Align::Right);
+            assert!(close(value.right(), 500.0));
+            assert_eq!(value.w, text::measure("1234", 20.0).width);
+
+            let widest = text::measure("Longest rally", 20.0).width;
+            assert_eq!(
+                layout.column_width(["Hits", "Longest rally", ""], 20.0),
+                widest
+            );
+            assert_eq!(layout.column_width([], 20.0), 0.0);
+
+            // Hint lines above the instructions are a line and a gap apart
+            let step = layout.line_height(HINT_SIZE) + LINE_GAP;
+            assert_eq!(layout.lines_up(0), Vec2::ZERO);
+            assert!(close(layout.lines_up(2).y, -2.0 * step));
+        }
+
+        #[test]
+        fn small_windows_keep_text_readable() {
+            let full = Layout::new(&Viewport::fit(WINDOW_WIDTH, WINDOW_HEIGHT));
+            assert_eq!(full.text_size(20.0), 20.0);
+            assert_eq!(full.text_size(4.0), MIN_TEXT_PIXELS);
+
+            // At a quarter size, 20 units would be 5 pixels tall on screen
+            let small = Layout::new(&Viewport::fit(WINDOW_WIDTH / 4.0, WINDOW_HEIGHT / 4.0));
+            assert!(close(small.text_size(20.0) * small.scale, MIN_TEXT_PIXELS));
+            assert_eq!(small.text_size(72.0), 72.0);
+
+            assert_eq!(format_clock(0.0), "0:00");
+            assert_eq!(format_clock(75.9), "1:15");
+            assert_eq!(format_clock(-3.0), "0:00");
+        }
+
+        #[test]
+        fn the_font_is_a_setting() {
+            assert_eq!(Settings::default().font, "pixel");
+            let settings =
+                Settings::from_json(r#"{"version": 1, "font": "fonts/retro.ttf"}"#).unwrap();
+            assert_eq!(settings.font, "fonts/retro.ttf");
+
+            let options = parse_args(args(&["--font", "default"])).unwrap();
+            assert_eq!(options.font.as_deref(), Some("default"));
+            assert!(parse_args(args(&["--font", "mono.otf"])).is_ok());
+            assert!(parse_args(args(&["--font", "comic"])).is_err());
+        }
+    }
+
+    // Theme tests: built-in palettes, theme files, settings and selection
+    mod themes {
+        use super::args;
+        use macroquad::prelude::*;
+        use pong::cli::parse_args;
+        use pong::settings::Settings;
+        use pong::theme::{self, Theme, THEME_NAMES};
+        use pong::Side;
+        use std::io::ErrorKind;
+
+        #[test]
+        fn builtin_themes_are_complete() {
+            for name in THEME_NAMES.iter().filter(|&&name| name != "custom") {
+                let theme = Theme::builtin(name).unwrap();
+                assert_ne!(theme.text, theme.background, "{}", name);
+                assert_ne!(theme.ball, theme.background, "{}", name);
+            }
+            assert_eq!(Theme::default(), Theme::CLASSIC);
+            assert!(Theme::builtin("sepia").is_none());
+
+            // Two-player modes can tell the paddles apart
+            let contrast = Theme::HIGH_CONTRAST;
+            assert_ne!(contrast.paddle(Side::Left), contrast.paddle(Side::Right));
+            assert_eq!(Theme::NEON.paddle(Side::Right), Theme::NEON.right_paddle);
+        }
+
+        #[test]
+        fn theme_files_use_hex_colours() {
+            let json = r##"{
+        "version": 1,
+        "background": "#101020",
+        "left_paddle": "#ff0000",
+        "ball": "#00ff0080"
+    }"##;
+            let custom = Theme::from_json(json).unwrap();
+            assert_eq!(custom.background, Color::from_rgba(0x10, 0x10, 0x20, 255));
+            assert_eq!(custom.left_paddle, Color::new(1.0, 0.0, 0.0, 1.0));
+            assert_eq!(custom.ball, Color::from_rgba(0, 255, 0, 0x80));
+            // Anything left out comes from the classic theme
+            assert_eq!(custom.right_paddle, Theme::CLASSIC.right_paddle);
+            assert_eq!(custom.text_dim, Theme::CLASSIC.text_dim);
+
+            let builtins = [
+                Theme::CLASSIC,
+                Theme::NEON,
+                Theme::AMBER,
+                Theme::HIGH_CONTRAST,
+            ];
+            for theme in builtins.into_iter().chain([custom]) {
+                assert_eq!(Theme::from_json(&theme.to_json().unwrap()).unwrap(), theme);
+            }
+
+            let bad = [
+                r#"{"version": 1, "ball": "green"}"#,
+                r##"{"version": 1, "ball": "#12345"}"##,
+                r##"{"version": 1, "ball": "#+12345"}"##,
+                r#"{"version": 2}"#,
+            ];
+            for json in bad {
+                assert_eq!(
+                    Theme::from_json(json).unwrap_err().kind(),
+                    ErrorKind::InvalidData,
+                    "{}",
+                    json
+                );
+            }
+        }
+
+        #[test]
+        fn themes_resolve_by_name_or_file() {
+            let dir = std::env::temp_dir().join(format!("pong_theme_test_{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::env::set_var("PONG_DATA_DIR", &dir);
+
+            assert_eq!(theme::resolve("amber").unwrap(), Theme::AMBER);
+            assert_eq!(
+                theme::resolve("sepia").unwrap_err().kind(),
+                ErrorKind::InvalidInput
+            );
+            assert_eq!(
+                theme::resolve("custom").unwrap_err().kind(),
+                ErrorKind::NotFound
+            );
+
+            // Cycling skips `custom` until there is a theme file
+            assert_eq!(theme::next_theme_name("classic"), "neon");
+            assert_eq!(theme::next_theme_name("high_contrast"), "classic");
+            let file = dir.join("theme.json");
+            std::fs::write(&file, Theme::NEON.to_json().unwrap()).unwrap();
+            assert_eq!(theme::next_theme_name("high_contrast"), "custom");
+            assert_eq!(theme::next_theme_name("custom"), "classic");
+            assert_eq!(theme::resolve("custom").unwrap(), Theme::NEON);
+            assert_eq!(theme::resolve(file.to_str().unwrap()).unwrap(), Theme::NEON);
+
+            theme::set_active(Theme::AMBER);
+            assert_eq!(theme::active(), Theme::AMBER);
+        }
+
+        #[test]
+        fn the_theme_is_a_setting() {
+            let path = std::env::temp_dir()
+                .join(format!("pong_settings_test_{}.json", std::process::id()));
+            assert_eq!(Settings::load(&path).unwrap(), Settings::default());
+            assert_eq!(Settings::default().theme, "classic");
+
+            let settings = Settings {
+                theme: "neon".to_owned(),
+                ..Settings::default()
+            };
+            settings.write_to(&path).unwrap();
+            assert_eq!(Settings::load(&path).unwrap(), settings);
+            assert_eq!(
+                Settings::from_json(r#"{"version": 1}"#).unwrap(),
+                Settings::default()
+            );
+            assert!(Settings::from_json(r#"{"version": 9, "theme": "neon"}"#).is_err());
+
+            let options = parse_args(args(&["--theme", "high_contrast"])).unwrap();
+            assert_eq!(options.theme.as_deref(), Some("high_contrast"));
+            assert!(parse_args(args(&["--theme", "mine.json"])).is_ok());
+            assert!(parse_args(args(&["--theme", "sepia"])).is_err());
+        }
+    }
+
+    // Terminal renderer tests: the pixel canvas, cell diffing, colour fallback and held keys
+    mod terminal_renderer {
+        use super::args;
+        use pong::cli::parse_args;
+
+        #[test]
+        fn the_tui_flag_needs_the_feature_and_a_local_match() {
+            let parsed = parse_args(args(&["--tui", "--difficulty", "hard"]));
+            if cfg!(feature = "tui") {
+                assert!(parsed.unwrap().tui);
+            } else {
+                assert!(parsed.unwrap_err().contains("--features tui"));
+            }
+            assert!(parse_args(args(&["--tui", "--spectate", "127.0.0.1:7000"])).is_err());
+            assert!(parse_args(args(&["--tui", "--broadcast", "127.0.0.1:7000"])).is_err());
+            assert!(!parse_args(args(&[])).unwrap().tui);
+        }
+
+        #[cfg(feature = "tui")]
+        mod terminal {
+            use macroquad::prelude::*;
+            use pong::theme::Theme;
+            use pong::tui::{ansi256, render, Canvas, Cell, ColorMode, HeldKeys, Screen, KEY_HOLD};
+            use pong::{Game, PaddleCommand};
+            use std::time::{Duration, Instant};
+
+            #[test]
+            fn rectangles_cover_pixel_centres_and_never_vanish() {
+                let mut canvas = Canvas::new(10, 10, [0; 3]);
+                canvas.fill_rect(2.0, 2.0, 3.0, 2.0, WHITE);
+                let lit = |canvas: &Canvas| {
+                    (0..10)
+                        .flat_map(|y| (0..10).map(move |x| (x, y)))
+                        .filter(|&(x, y)| canvas.get(x, y) != [0; 3])
+                        .count()
+                };
+                assert_eq!(lit(&canvas), 6);
+                assert_eq!(canvas.get(2, 2), [255; 3]);
+                assert_eq!(canvas.get(5, 2), [0; 3]);
+
+                // A sliver still lights the pixel under it, and the edges clip
+                let mut canvas = Canvas::new(10, 10, [0; 3]);
+                canvas.fill_rect(4.6, 7.1, 0.2, 0.2, WHITE);
+                assert_eq!(lit(&canvas), 1);
+                assert_eq!(canvas.get(4, 7), [255; 3]);
+                canvas.fill_rect(-5.0, 8.0, 30.0, 30.0, WHITE.with_alpha(0.5));
+                assert_eq!(canvas.get(0, 9), [128; 3]);
+            }
+
+            #[test]
+            fn the_court_is_letterboxed_with_paddles_on_their_sides() {
+                let game = Game::with_seed(3);
+                let theme = Theme {
+                    background: DARKBLUE,
+                    left_paddle: RED,
+                    right_paddle: GREEN,
+                    ball: YELLOW,
+                    ..Theme::CLASSIC
+                };
+                // Too wide for 4:3, so there are bars left and right
+                let canvas = render(&game, &theme, 100, 60);
+                assert_eq!(canvas.get(0, 30), [0; 3]);
+                assert_eq!(canvas.get(99, 30), [0; 3]);
+                assert_eq!(canvas.get(15, 59), pong::tui::rgb(DARKBLUE));
+
+                let columns_with = |color: Color| {
+                    let color = pong::tui::rgb(color);
+                    (0..canvas.width)
+                        .filter(|&x| (0..canvas.height).any(|y| canvas.get(x, y) == color))
+                        .collect::<Vec<_>>()
+                };
+                let left = columns_with(RED);
+                let right = columns_with(GREEN);
+                assert!(!left.is_empty() && left.iter().all(|&x| x < 30));
+                assert!(!right.is_empty() && right.iter().all(|&x| x > 70));
+                assert!(!columns_with(YELLOW).is_empty());
+            }
+
+            #[test]
+            fn only_changed_cells_are_redrawn() {
+                let blank = Cell {
+                    top: [0; 3],
+                    bottom: [0; 3],
+                };
+                let mut cells = vec![blank; 20 * 5];
+                let mut screen = Screen::new(ColorMode::TrueColor);
+                let blocks = |output: &[u8]| String::from_utf8_lossy(output).matches('▀').count();
+
+                let mut output = Vec::new();
+                screen.draw(&mut output, 20, &cells, "0 - 0").unwrap();
+                assert_eq!(blocks(&output), 100);
+
+                let mut output = Vec::new();
+                screen.draw(&mut output, 20, &cells, "0 - 0").unwrap();
+                assert!(output.is_empty());
+
+                cells[42].top = [255, 0, 0];
+                let mut output = Vec::new();
+                screen.draw(&mut output, 20, &cells, "1 - 0").unwrap();
+                assert_eq!(blocks(&output), 1);
+                assert!(String::from_utf8_lossy(&output).contains("1 - 0"));
+
+                // A new size redraws everything
+                let mut output = Vec::new();
+                screen.draw(&mut output, 25, &cells, "1 - 0").unwrap();
+                assert_eq!(blocks(&output), 100);
+            }
+
+            #[test]
+            fn colours_fall_back_to_the_256_colour_palette() {
+                assert_eq!(
+                    ColorMode::from_colorterm(Some("truecolor")),
+                    ColorMode::TrueColor
+                );
+                assert_eq!(
+                    ColorMode::from_colorterm(Some("24bit")),
+                    ColorMode::TrueColor
+                );
+                assert_eq!(ColorMode::from_colorterm(None), ColorMode::Ansi256);
+                assert_eq!(ansi256([0, 0, 0]), 16);
+                assert_eq!(ansi256([255, 255, 255]), 231);
+                assert_eq!(ansi256([255, 0, 0]), 196);
+                assert_eq!(ansi256([0, 0, 255]), 21);
+                assert!((232..=255).contains(&ansi256([128, 128, 128])));
+            }
+
+            #[test]
+            fn keys_are_held_until_released_or_until_the_hold_runs_out() {
+                let start = Instant::now();
+                let later = start + KEY_HOLD + Duration::from_millis(1);
+
+                let mut keys = HeldKeys::new(false);
+                keys.press(PaddleCommand::Up, start);
+                assert_eq!(keys.command(start), PaddleCommand::Up);
+                assert_eq!(keys.command(later), PaddleCommand::Stay);
+                keys.press(PaddleCommand::Down, start);
+                assert_eq!(keys.command(start), PaddleCommand::Down);
+
+                let mut keys = HeldKeys::new(true);
+                keys.press(PaddleCommand::Up, start);
+                assert_eq!(keys.command(later), PaddleCommand::Up);
+                keys.release(PaddleCommand::Up);
+                assert_eq!(keys.command(later), PaddleCommand::Stay);
+            }
+        }
+    }
+
+    // Window scaling tests: letterboxing, coordinate conversion and the HiDPI camera
+    mod window_scaling {
+        use macroquad::prelude::*;
+        use pong::view::Viewport;
+        use pong::{WINDOW_HEIGHT, WINDOW_WIDTH};
+
+        fn close(a: Vec2, b: Vec2) -> bool {
+            (a - b).length() < 0.001
+        }
+
+        #[test]
+        fn the_court_fills_a_window_of_its_own_size() {
+            let viewport = Viewport::fit(WINDOW_WIDTH, WINDOW_HEIGHT);
+            assert_eq!((viewport.x, viewport.y, viewport.scale), (0.0, 0.0, 1.0));
+            let point = vec2(123.0, 456.0);
+            assert!(close(viewport.to_logical(point), point));
+        }
+
+        #[test]
+        fn other_window_shapes_get_bars() {
+            // Wider than the court: bars left and right
+            let wide = Viewport::fit(WINDOW_WIDTH * 3.0, WINDOW_HEIGHT * 2.0);
+            assert_eq!(wide.scale, 2.0);
+            assert_eq!(wide.x, WINDOW_WIDTH / 2.0);
+            assert_eq!(wide.y, 0.0);
+
+            // Taller than the court: bars above and below
+            let tall = Viewport::fit(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT);
+            assert_eq!(tall.scale, 0.5);
+            assert_eq!(tall.x, 0.0);
+            assert_eq!(tall.y, WINDOW_HEIGHT / 4.0);
+            assert_eq!(tall.height(), WINDOW_HEIGHT / 2.0);
+
+            // A minimised window never produces a degenerate scale
+            let minimised = Viewport::fit(0.0, 0.0);
+            assert!(minimised.scale > 0.0);
+            assert!(minimised.to_logical(vec2(10.0, 10.0)).is_finite());
+        }
+
+        #[test]
+        fn window_and_logical_positions_convert_both_ways() {
+            let viewport = Viewport::fit(1920.0, 1080.0);
+            let centre = vec2(WINDOW_WIDTH / 2.0, WINDOW_HEIGHT / 2.0);
+            assert!(close(viewport.to_window(centre), vec2(960.0, 540.0)));
+
+            let point = vec2(37.0, 512.0);
+            assert!(close(viewport.to_logical(viewport.to_window(point)), point));
+            assert!(viewport.contains(vec2(960.0, 10.0)));
+            assert!(!viewport.contains(vec2(5.0, 540.0)));
+        }
+
+        #[test]
+        fn the_camera_renders_at_framebuffer_resolution() {
+            let viewport = Viewport::fit(WINDOW_WIDTH * 2.0, WINDOW_HEIGHT * 3.0);
+            let camera = viewport.camera(2.0);
+            let (x, y, width, height) = camera.viewport.unwrap();
+            // The court is scaled by 2 with half a court of bar below it; pixels are twice the points
+            assert_eq!((x, width), (0, (WINDOW_WIDTH * 4.0) as i32));
+            assert_eq!(
+                (y, height),
+                (WINDOW_HEIGHT as i32, (WINDOW_HEIGHT * 4.0) as i32)
+            );
+
+            // Logical corners land on the corners of clip space, top left first
+            let matrix = camera.matrix();
+            let top_left = matrix.transform_point3(vec3(0.0, 0.0, 0.0));
+            let bottom_right = matrix.transform_point3(vec3(WINDOW_WIDTH, WINDOW_HEIGHT, 0.0));
+            assert!(close(top_left.truncate(), vec2(-1.0, 1.0)));
+            assert!(close(bottom_right.truncate(), vec2(1.0, -1.0)));
+        }
+    }
+}